@@ -872,8 +872,8 @@ impl MainState {
             Some(0)
         };
 
-        // TODO: call each_non_dead with visible region (add method to viewport)
-        universe.each_non_dead_full(visibility, &mut |col, row, state| {
+        // TODO: call non_dead_cells with visible region (add method to viewport)
+        for (col, row, state) in universe.non_dead_cells_full(visibility) {
             let color = if self.uni_draw_params.player_id >= 0 {
                 self.color_settings.get_color(Some(state))
             } else {
@@ -888,7 +888,7 @@ impl MainState {
 
                 main_spritebatch.add(p);
             }
-        });
+        }
 
         let mut insert_mode = None;
         GameArea::widget_from_screen_and_id(&self.ui_layout, Screen::Run, &self.static_node_ids.game_area_id).map(
@@ -24,9 +24,9 @@ use super::{
 use crate::{config::Config, constants::*, viewport::ZoomDirection};
 use conway::{
     error::ConwayError,
-    grids::{BitGrid, CharGrid, Rotation},
+    grids::{BitGrid, CharGrid, Rotation, Transform},
     rle::Pattern,
-    universe::{BigBang, CellState, PlayerBuilder, Region, Universe},
+    universe::{BigBang, BlendMode, CellState, PlayerBuilder, Region, Universe},
     ConwayResult,
 };
 use ggez::graphics::Rect;
@@ -126,50 +126,14 @@ fn init_patterns(uni: &mut Universe) -> ConwayResult<()> {
     let _pat = Pattern("10$10b16W$10bW14bW$10bW14bW$10bW14bW$10bW14bW$10bW14bW$10bW14bW$10bW14bW$10bW14bW$10bW$10bW$10bW$10b16W48$100b2A5b2A$100b2A5b2A2$104b2A$104b2A5$122b2Ab2A$121bA5bA$121bA6bA2b2A$121b3A3bA3b2A$126bA!".to_owned());
 
     // Simkin glider gun
-    uni.toggle(100, 70, 0)?;
-    uni.toggle(100, 71, 0)?;
-    uni.toggle(101, 70, 0)?;
-    uni.toggle(101, 71, 0)?;
-
-    uni.toggle(104, 73, 0)?;
-    uni.toggle(104, 74, 0)?;
-    uni.toggle(105, 73, 0)?;
-    uni.toggle(105, 74, 0)?;
-
-    uni.toggle(107, 70, 0)?;
-    uni.toggle(107, 71, 0)?;
-    uni.toggle(108, 70, 0)?;
-    uni.toggle(108, 71, 0)?;
+    let gun = Pattern("2o5b2o$2o5b2o2$4b2o$4b2o5$22b2ob2o$21bo5bo$21bo6bo2b2o$21b3o3bo3b2o$26bo!".to_owned());
+    uni.drop_pattern(0, 100, 70, &gun, BlendMode::Or)?;
 
     /* eater
-    uni.toggle(120, 87, 0)?;
-    uni.toggle(120, 88, 0)?;
-    uni.toggle(121, 87, 0)?;
-    uni.toggle(121, 89, 0)?;
-    uni.toggle(122, 89, 0)?;
-    uni.toggle(123, 89, 0)?;
-    uni.toggle(123, 90, 0)?;
+    let eater = Pattern("2o$o$b3o$3bo!".to_owned());
+    uni.drop_pattern(0, 120, 87, &eater, BlendMode::Or)?;
     */
 
-    uni.toggle(121, 80, 0)?;
-    uni.toggle(121, 81, 0)?;
-    uni.toggle(121, 82, 0)?;
-    uni.toggle(122, 79, 0)?;
-    uni.toggle(122, 82, 0)?;
-    uni.toggle(123, 79, 0)?;
-    uni.toggle(123, 82, 0)?;
-    uni.toggle(125, 79, 0)?;
-    uni.toggle(126, 79, 0)?;
-    uni.toggle(126, 83, 0)?;
-    uni.toggle(127, 80, 0)?;
-    uni.toggle(127, 82, 0)?;
-    uni.toggle(128, 81, 0)?;
-
-    uni.toggle(131, 81, 0)?;
-    uni.toggle(131, 82, 0)?;
-    uni.toggle(132, 81, 0)?;
-    uni.toggle(132, 82, 0)?;
-
     //Wall in player 0 area!
     let bw = 5; // buffer width
 
@@ -343,22 +307,27 @@ impl GameArea {
 
         if evt.shift_pressed && game_area_state.arrow_input != (0, 0) {
             if let Some((ref mut grid, ref mut width, ref mut height)) = game_area_state.insert_mode {
-                let rotation = match game_area_state.arrow_input {
-                    (-1, 0) => Some(Rotation::CCW),
-                    (1, 0) => Some(Rotation::CW),
-                    (0, 0) => unreachable!(),
+                let transform = match game_area_state.arrow_input {
+                    (-1, 0) => Some(Transform::Rotate(Rotation::CCW)),
+                    (1, 0) => Some(Transform::Rotate(Rotation::CW)),
+                    (0, -1) => Some(Transform::FlipVertically),
+                    (0, 1) => Some(Transform::FlipHorizontally),
                     _ => None, // do nothing in this case
                 };
-                if let Some(rotation) = rotation {
-                    grid.rotate(*width, *height, rotation).unwrap_or_else(|e| {
-                        error!("Failed to rotate pattern {:?}: {:?}", rotation, e);
-                    });
-                    // reverse the stored width and height
-                    let (new_width, new_height) = (*height, *width);
-                    *width = new_width;
-                    *height = new_height;
+                if let Some(transform) = transform {
+                    match grid.transformed(*width, *height, transform) {
+                        Ok(new_grid) => {
+                            *grid = new_grid;
+                            let (new_width, new_height) = transform.new_size(*width, *height);
+                            *width = new_width;
+                            *height = new_height;
+                        }
+                        Err(e) => {
+                            error!("Failed to transform pattern {:?}: {:?}", transform, e);
+                        }
+                    }
                 } else {
-                    info!("Ignoring Shift-<Up/Down>");
+                    info!("Ignoring Shift-arrow input {:?}", game_area_state.arrow_input);
                 }
             }
         }
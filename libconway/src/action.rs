@@ -0,0 +1,52 @@
+/*  Copyright 2020 the Conwayste Developers.
+ *
+ *  This file is part of libconway.
+ *
+ *  libconway is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  libconway is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with libconway.  If not, see <http://www.gnu.org/licenses/>. */
+
+//! Player actions scheduled for a given generation (see `Universe::schedule`). Instead of
+//! changing the latest generation right away, the server and the clients queue the actions of all
+//! players and `Universe::next` applies them together, so that everyone agrees on the outcome of
+//! simultaneous inputs.
+
+use serde::{Deserialize, Serialize};
+
+use crate::rle::Pattern;
+use crate::universe::Region;
+
+/// A change a player makes to the universe.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Action {
+    /// Toggles a cell, as with `Universe::drop_pattern` and `BlendMode::Xor`.
+    Toggle { col: usize, row: usize },
+    /// Brings the live cells of `pattern` to life for the player, with the top-left cell of the
+    /// pattern at (`col`, `row`), as with `BlendMode::Or`. Parts of the pattern beyond the edges of
+    /// the universe are wrapped around or dropped according to its `Topology`.
+    DropPattern {
+        pattern: Pattern,
+        col:     usize,
+        row:     usize,
+    },
+    /// Kills the cells in `region` that don't belong to other players, as with
+    /// `Universe::clear_area`.
+    ClearArea { region: Region },
+}
+
+/// An `Action` of a player, waiting to be applied to generation `gen` once it is computed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ScheduledAction {
+    pub gen:       usize,
+    pub player_id: usize,
+    pub action:    Action,
+}
@@ -0,0 +1,210 @@
+/*  Copyright 2020 the Conwayste Developers.
+ *
+ *  This file is part of libconway.
+ *
+ *  libconway is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  libconway is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with libconway.  If not, see <http://www.gnu.org/licenses/>. */
+
+//! Per-cell age and activity, optionally kept by a `Universe` (see
+//! `Universe::set_activity_window`). The age of a cell is the number of generations it has been
+//! alive in a row, and its activity is the number of times it was born or died in the last few
+//! generations. Clients can use them to color cells and draw hot zones, and servers to detect
+//! games where nothing happens anymore.
+
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::grids::{BitGrid, CharGrid, SetOperation};
+use crate::universe::Region;
+
+/// Keeps the age and activity of each cell of a universe, updated as generations are computed.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct ActivityTracker {
+    width:   usize,
+    window:  usize,
+    ages:    Vec<u32>,          // row-major
+    counts:  Vec<u32>,          // number of changes in `changes` for each cell (row-major)
+    changes: VecDeque<BitGrid>, // cells that changed in each of the last `window` generations, oldest first
+}
+
+impl ActivityTracker {
+    /// Creates a tracker for a universe of `width` by `height` cells, counting the changes in the
+    /// last `window` generations. All ages start at 0.
+    pub(crate) fn new(width: usize, height: usize, window: usize) -> Self {
+        assert!(window > 0, "activity window must be at least 1");
+        ActivityTracker {
+            width,
+            window,
+            ages: vec![0; width * height],
+            counts: vec![0; width * height],
+            changes: VecDeque::with_capacity(window),
+        }
+    }
+
+    pub(crate) fn window(&self) -> usize {
+        self.window
+    }
+
+    /// Returns whether this tracker fits a universe of `width` by `height` cells and is
+    /// consistent, as it may not be when read from a corrupted snapshot. `grid_is_valid` checks
+    /// the dimensions of a grid of changes.
+    pub(crate) fn is_valid(&self, width: usize, height: usize, grid_is_valid: impl Fn(&BitGrid) -> bool) -> bool {
+        if self.width != width
+            || self.window == 0
+            || self.ages.len() != width * height
+            || self.counts.len() != width * height
+            || self.changes.len() > self.window
+            || !self.changes.iter().all(grid_is_valid)
+        {
+            return false;
+        }
+        let mut counts = vec![0; width * height];
+        for changed in &self.changes {
+            changed.each_set(|col, row| counts[row * width + col] += 1);
+        }
+        counts == self.counts
+    }
+
+    /// Records that the live cells went from `prev` to `next` over `generations` generations (1,
+    /// except when a client skips generations). Cells alive in both are assumed to have stayed
+    /// alive, and all changes are counted in the last generation.
+    pub(crate) fn record(&mut self, prev: &BitGrid, next: &BitGrid, generations: usize) {
+        for _ in 1..generations.min(self.window + 1) {
+            self.push(BitGrid::new(next.width_in_words(), next.height()));
+        }
+        let mut changed = prev.clone();
+        changed.combine(next, SetOperation::Xor);
+        self.push(changed.clone());
+
+        let (width, ages) = (self.width, &mut self.ages);
+        let elapsed = saturating_u32(generations);
+        next.each_set(|col, row| {
+            let age = &mut ages[row * width + col];
+            *age = if prev.is_set(col, row) {
+                age.saturating_add(elapsed)
+            } else {
+                1
+            };
+        });
+        changed.combine(next, SetOperation::AndNot);
+        changed.each_set(|col, row| ages[row * width + col] = 0); // died
+    }
+
+    /// Adds the changes of one generation, dropping the oldest generation if the window is full.
+    fn push(&mut self, changed: BitGrid) {
+        let (width, counts) = (self.width, &mut self.counts);
+        if self.changes.len() == self.window {
+            let oldest = self.changes.pop_front().unwrap();
+            oldest.each_set(|col, row| counts[row * width + col] -= 1);
+        }
+        changed.each_set(|col, row| counts[row * width + col] += 1);
+        self.changes.push_back(changed);
+    }
+
+    /// Returns the ages and activity of the cells in `region`, which must be inside the universe.
+    /// Cells not set in `visible` are reported with an age and activity of 0.
+    pub(crate) fn map(&self, region: Region, visible: &BitGrid) -> ActivityMap {
+        let mut ages = Vec::with_capacity(region.width() * region.height());
+        let mut changes = Vec::with_capacity(region.width() * region.height());
+        for row in region.top() as usize..=region.bottom() as usize {
+            for col in region.left() as usize..=region.right() as usize {
+                let (age, count) = if visible.is_set(col, row) {
+                    (self.ages[row * self.width + col], self.counts[row * self.width + col])
+                } else {
+                    (0, 0)
+                };
+                ages.push(age);
+                changes.push(count);
+            }
+        }
+        ActivityMap {
+            region,
+            generations: self.changes.len(),
+            ages,
+            changes,
+        }
+    }
+}
+
+fn saturating_u32(value: usize) -> u32 {
+    if value > u32::max_value() as usize {
+        u32::max_value()
+    } else {
+        value as u32
+    }
+}
+
+/// The age and activity of the cells in a region, as returned by `Universe::activity`. Cells are
+/// addressed by their column and row in the universe.
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub struct ActivityMap {
+    region:      Region,
+    generations: usize,
+    ages:        Vec<u32>, // row-major within `region`
+    changes:     Vec<u32>, // row-major within `region`
+}
+
+impl ActivityMap {
+    /// The part of the universe this map covers.
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    /// The number of generations the activity counts cover. This is the activity window, or less
+    /// if fewer generations have been computed since tracking started.
+    pub fn generations(&self) -> usize {
+        self.generations
+    }
+
+    fn index(&self, col: usize, row: usize) -> usize {
+        assert!(
+            self.region.contains(col as isize, row as isize),
+            "cell ({}, {}) is outside the activity map",
+            col,
+            row
+        );
+        (row - self.region.top() as usize) * self.region.width() + col - self.region.left() as usize
+    }
+
+    /// Returns the number of generations the cell has been alive in a row, or 0 if it is dead or
+    /// hidden.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the cell is outside `region()`.
+    pub fn age(&self, col: usize, row: usize) -> u32 {
+        self.ages[self.index(col, row)]
+    }
+
+    /// Returns the number of times the cell was born or died in the last `generations()`
+    /// generations, or 0 if it is hidden.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the cell is outside `region()`.
+    pub fn changes(&self, col: usize, row: usize) -> u32 {
+        self.changes[self.index(col, row)]
+    }
+
+    /// The largest age of a cell in the map.
+    pub fn max_age(&self) -> u32 {
+        self.ages.iter().cloned().max().unwrap_or(0)
+    }
+
+    /// The number of births and deaths in the map in the last `generations()` generations. A
+    /// universe whose total is 0 over a full window has stopped changing.
+    pub fn total_changes(&self) -> usize {
+        self.changes.iter().map(|&count| count as usize).sum()
+    }
+}
@@ -0,0 +1,183 @@
+/*  Copyright 2020 the Conwayste Developers.
+ *
+ *  This file is part of libconway.
+ *
+ *  libconway is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  libconway is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with libconway.  If not, see <http://www.gnu.org/licenses/>. */
+
+//! Classifying patterns as still lifes, oscillators or spaceships by running them forward.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::error::{ConwayError, ConwayResult};
+use crate::grids::BitGrid;
+use crate::rle::Pattern;
+use crate::rule::Rule;
+use crate::universe::{BigBang, Region, Topology};
+
+/// Largest number of empty cells around the pattern in the universe used for the analysis. A
+/// pattern that grows into this border before repeating is classified as `PatternClass::Unknown`.
+const MAX_MARGIN: usize = 128;
+
+/// What a pattern turns out to be when it is run forward.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum PatternClass {
+    /// All cells die.
+    DiesOut,
+    /// The pattern never changes.
+    StillLife,
+    /// The pattern returns to the same state in the same position every `period` generations.
+    Oscillator { period: usize },
+    /// The pattern returns to the same state every `period` generations, moved `dx` columns to
+    /// the right and `dy` rows down (left and up for negative values).
+    Spaceship {
+        period: usize,
+        dx:     isize,
+        dy:     isize,
+    },
+    /// The pattern did not repeat within the number of generations that were run, or it grew too
+    /// large. This is typical of chaotic patterns and of guns and other patterns that grow forever.
+    Unknown,
+}
+
+impl fmt::Display for PatternClass {
+    /// Describes the class briefly, for example `P2 oscillator`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PatternClass::DiesOut => write!(f, "dies out"),
+            PatternClass::StillLife => write!(f, "still life"),
+            PatternClass::Oscillator { period } => write!(f, "P{} oscillator", period),
+            PatternClass::Spaceship { period, dx, dy } => write!(f, "P{} spaceship moving ({}, {})", period, dx, dy),
+            PatternClass::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// The result of analyzing a pattern with `analyze`.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub struct Analysis {
+    pub class:      PatternClass,
+    /// For `PatternClass::DiesOut`, the generation at which no cells are left. For still lifes,
+    /// oscillators and spaceships, the generation at which the pattern first reached the state
+    /// that repeats, which is 0 if the pattern itself is a still life, oscillator or spaceship
+    /// (rather than, say, a pattern that settles into an oscillator). 0 for `PatternClass::Unknown`.
+    pub generation: usize,
+}
+
+/// A state of a pattern, regardless of position: its width, height and cells.
+type StateKey = (usize, usize, Vec<Vec<u64>>);
+
+/// Runs the live cells of `grid` forward for up to `max_generations` generations according to
+/// `rule` in an otherwise empty universe, and classifies the pattern according to the first
+/// repeated state (ignoring position). The evolution is done by a `Universe` with
+/// `Topology::Plane`, large enough that the edges have no effect on the result.
+///
+/// # Errors
+///
+/// Returns `ConwayError::InvalidData` if `rule` has birth on 0 neighbors, since then no pattern
+/// is surrounded by empty cells after the first generation, or if it is a Generations rule, since
+/// only live cells are compared.
+pub fn analyze(grid: &BitGrid, rule: Rule, max_generations: usize) -> ConwayResult<Analysis> {
+    if rule.is_born(0) {
+        return Err(ConwayError::InvalidData {
+            reason: format!("cannot analyze patterns in rule {}, which has B0", rule),
+        });
+    }
+    if rule.dying_states() > 0 {
+        return Err(ConwayError::InvalidData {
+            reason: format!("cannot analyze patterns in Generations rule {}", rule),
+        });
+    }
+    let (pattern, bbox) = match grid.cropped() {
+        Some(cropped) => cropped,
+        None => {
+            return Ok(Analysis {
+                class:      PatternClass::DiesOut,
+                generation: 0,
+            })
+        }
+    };
+
+    // Cells can spread by at most one cell per generation.
+    let margin = (max_generations + 1).min(MAX_MARGIN);
+    let (width, height) = (bbox.width() + 2 * margin, bbox.height() + 2 * margin);
+    let mut uni = BigBang::new()
+        .width(width)
+        .height(height)
+        .history(2)
+        .rule(rule)
+        .topology(Topology::Plane)
+        .birth()?;
+    uni.copy_from_bit_grid(
+        &pattern,
+        Region::new(margin as isize, margin as isize, bbox.width(), bbox.height()),
+        None,
+    );
+
+    // For each state seen so far, the generation and the position of its top-left corner.
+    let mut seen: HashMap<StateKey, (usize, isize, isize)> = HashMap::new();
+    for generation in 0..=max_generations {
+        if generation > 0 {
+            uni.next();
+        }
+        let (cells, bbox) = match uni.latest_cells().cropped() {
+            Some(cropped) => cropped,
+            None => {
+                return Ok(Analysis {
+                    class: PatternClass::DiesOut,
+                    generation,
+                })
+            }
+        };
+        let touches_edge = bbox.left() == 0
+            || bbox.top() == 0
+            || bbox.right() as usize == width - 1
+            || bbox.bottom() as usize == height - 1;
+        if touches_edge {
+            break; // the next generation may depend on the cells beyond the edge
+        }
+        let key = (bbox.width(), bbox.height(), cells.0);
+        if let Some(&(prev_generation, prev_left, prev_top)) = seen.get(&key) {
+            let period = generation - prev_generation;
+            let (dx, dy) = (bbox.left() - prev_left, bbox.top() - prev_top);
+            let class = if dx != 0 || dy != 0 {
+                PatternClass::Spaceship { period, dx, dy }
+            } else if period == 1 {
+                PatternClass::StillLife
+            } else {
+                PatternClass::Oscillator { period }
+            };
+            return Ok(Analysis {
+                class,
+                generation: prev_generation,
+            });
+        }
+        seen.insert(key, (generation, bbox.left(), bbox.top()));
+    }
+    Ok(Analysis {
+        class:      PatternClass::Unknown,
+        generation: 0,
+    })
+}
+
+/// Like `analyze`, but for the pattern described by `pattern`.
+///
+/// # Errors
+///
+/// Returns `ConwayError::InvalidData` if `pattern` can't be parsed, or for the reasons listed
+/// for `analyze`.
+pub fn analyze_pattern(pattern: &Pattern, rule: Rule, max_generations: usize) -> ConwayResult<Analysis> {
+    let (width, height) = pattern.calc_size()?;
+    analyze(&pattern.to_new_bit_grid(width, height)?, rule, max_generations)
+}
@@ -0,0 +1,144 @@
+/*  Copyright 2020 the Conwayste Developers.
+ *
+ *  This file is part of libconway.
+ *
+ *  libconway is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  libconway is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with libconway.  If not, see <http://www.gnu.org/licenses/>. */
+
+//! Placement budgets, which limit how many cells a player can bring to life, and how often (see
+//! `PlayerBuilder::budget`). Without a budget, a player can fill their writable area at any time,
+//! so competitive games come down to who pastes the biggest gun first.
+
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{ConwayError, ConwayResult};
+
+/// Limits on the cells a player can bring to life with `Universe::toggle`, `Universe::set`,
+/// `Universe::drop_pattern` and scheduled actions. Each cell brought to life costs one cell of the
+/// budget, and each of these operations that brings cells to life counts as one placement.
+#[derive(Serialize, Deserialize, Eq, PartialEq, Copy, Clone, Debug)]
+pub struct Budget {
+    pub capacity:   usize,                  // most cells the player can have saved up
+    pub refill:     usize,                  // cells added with each generation
+    pub rate_limit: Option<(usize, usize)>, // at most this many placements in this many generations
+}
+
+impl Budget {
+    /// A budget of at most `capacity` cells, which starts full and gains `refill` cells with each
+    /// generation.
+    pub fn new(capacity: usize, refill: usize) -> Self {
+        Budget {
+            capacity,
+            refill,
+            rate_limit: None,
+        }
+    }
+
+    /// Also allows at most `placements` placements in any `generations` consecutive generations.
+    /// Not limited by default.
+    pub fn rate_limit(mut self, placements: usize, generations: usize) -> Self {
+        self.rate_limit = Some((placements, generations));
+        self
+    }
+
+    pub(crate) fn check(&self) -> ConwayResult<()> {
+        if let Some((_, 0)) = self.rate_limit {
+            return Err(ConwayError::InvalidData {
+                reason: "rate limit must span at least 1 generation".to_owned(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// The state of the budget of one player.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct BudgetTracker {
+    budget:     Budget,
+    available:  usize,
+    placements: VecDeque<usize>, // generations of the recent placements, oldest first
+}
+
+impl BudgetTracker {
+    pub(crate) fn new(budget: Budget) -> Self {
+        BudgetTracker {
+            budget,
+            available: budget.capacity,
+            placements: VecDeque::new(),
+        }
+    }
+
+    /// Returns an error if the budget is invalid (see `Budget::check`) or more cells are
+    /// available than it can hold, as in a corrupted snapshot.
+    pub(crate) fn check(&self) -> ConwayResult<()> {
+        self.budget.check()?;
+        if self.available > self.budget.capacity {
+            return Err(ConwayError::InvalidData {
+                reason: format!(
+                    "{} cells available in a budget of {} cells",
+                    self.available, self.budget.capacity
+                ),
+            });
+        }
+        Ok(())
+    }
+
+    pub(crate) fn budget(&self) -> Budget {
+        self.budget
+    }
+
+    pub(crate) fn available(&self) -> usize {
+        self.available
+    }
+
+    /// Adds the refill of `generations` generations, up to the capacity.
+    pub(crate) fn replenish(&mut self, generations: usize) {
+        let refill = self.budget.refill.saturating_mul(generations);
+        self.available = self.budget.capacity.min(self.available.saturating_add(refill));
+    }
+
+    /// Returns an error with the reason if a placement of `cells` cells at generation `gen` is
+    /// over budget.
+    pub(crate) fn check_placement(&mut self, cells: usize, gen: usize) -> Result<(), String> {
+        if cells > self.available {
+            return Err(format!("needs {} cells, but only {} are left", cells, self.available));
+        }
+        if let Some((placements, generations)) = self.budget.rate_limit {
+            while let Some(&placed) = self.placements.front() {
+                if placed + generations > gen {
+                    break;
+                }
+                self.placements.pop_front();
+            }
+            if self.placements.len() >= placements {
+                return Err(format!(
+                    "already placed {} times in the last {} generations",
+                    self.placements.len(),
+                    generations
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Records a placement of `cells` cells at generation `gen`, which must have been checked
+    /// with `check_placement`.
+    pub(crate) fn place(&mut self, cells: usize, gen: usize) {
+        self.available -= cells;
+        if self.budget.rate_limit.is_some() {
+            self.placements.push_back(gen);
+        }
+    }
+}
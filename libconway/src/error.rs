@@ -15,6 +15,8 @@
  *  You should have received a copy of the GNU General Public License
  *  along with libconway.  If not, see <http://www.gnu.org/licenses/>. */
 
+use std::{error, fmt};
+
 custom_error! {pub ConwayError
     InvalidData {reason: String} = "ConwayError->InvalidData->{reason}",
     AccessDenied{reason: String} = "ConwayError->AccessDenied->{reason}"
@@ -53,3 +55,64 @@ impl PartialEq for ConwayError {
         }
     }
 }
+
+/// Error returned by `Universe::drop_pattern` and `Universe::clear_area`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlacementError {
+    /// The request is invalid, for example because of an unknown player_id or a pattern that
+    /// can't be parsed.
+    InvalidData { reason: String },
+    /// The player may not write to these cells, given as (col, row). Cells beyond an edge of a
+    /// universe that doesn't wrap there are listed with their coordinates outside the universe.
+    CellsDenied { cells: Vec<(isize, isize)> },
+    /// The player's budget doesn't allow the placement; see `Universe::set_budget`.
+    OverBudget { reason: String },
+}
+
+/// The largest number of denied cells listed in the message of a `PlacementError`.
+const MAX_LISTED_CELLS: usize = 8;
+
+impl fmt::Display for PlacementError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PlacementError::InvalidData { ref reason } => write!(f, "PlacementError->InvalidData->{}", reason),
+            PlacementError::OverBudget { ref reason } => write!(f, "PlacementError->OverBudget->{}", reason),
+            PlacementError::CellsDenied { ref cells } => {
+                write!(f, "PlacementError->CellsDenied->{} cell(s):", cells.len())?;
+                for &(col, row) in cells.iter().take(MAX_LISTED_CELLS) {
+                    write!(f, " ({}, {})", col, row)?;
+                }
+                if cells.len() > MAX_LISTED_CELLS {
+                    write!(f, " ...")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl error::Error for PlacementError {
+}
+
+impl From<ConwayError> for PlacementError {
+    fn from(err: ConwayError) -> PlacementError {
+        match err {
+            ConwayError::InvalidData { reason } | ConwayError::AccessDenied { reason } => {
+                PlacementError::InvalidData { reason }
+            }
+        }
+    }
+}
+
+/// Denied cells and placements over budget become a `ConwayError::AccessDenied` error.
+impl From<PlacementError> for ConwayError {
+    fn from(err: PlacementError) -> ConwayError {
+        match err {
+            PlacementError::InvalidData { reason } => ConwayError::InvalidData { reason },
+            PlacementError::OverBudget { reason } => ConwayError::AccessDenied { reason },
+            PlacementError::CellsDenied { .. } => ConwayError::AccessDenied {
+                reason: err.to_string(),
+            },
+        }
+    }
+}
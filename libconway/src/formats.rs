@@ -0,0 +1,794 @@
+/*  Copyright 2020 the Conwayste Developers.
+ *
+ *  This file is part of libconway.
+ *
+ *  libconway is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  libconway is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with libconway.  If not, see <http://www.gnu.org/licenses/>. */
+
+//! Reading and writing two-state patterns in the file formats commonly used by other Life
+//! programs. For Conwayste's multi-state extension of RLE, use `rle::PatternFile` directly.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::str::FromStr;
+
+use crate::error::{ConwayError, ConwayResult};
+use crate::grids::{BitGrid, BitOperation, CharGrid};
+use crate::rle::{HeaderLine, Pattern, PatternFile};
+use crate::rule::Rule;
+use crate::universe::CellState;
+
+/// Largest width or height of a pattern that can be created or read. This is well beyond the size
+/// of an arena, while keeping the cells of a pattern from a small untrusted file to a few megabytes.
+pub const MAX_PATTERN_SIZE: usize = 4096;
+
+/// Maximum length of a line of cells in a Life 1.05 file.
+const LIFE_105_MAX_LINE_LENGTH: usize = 80;
+
+/// Level of the leaf nodes of a Macrocell file, which are 8x8 cells.
+const MACROCELL_LEAF_LEVEL: u32 = 3;
+
+/// Deepest node allowed in a Macrocell file.
+const MACROCELL_MAX_LEVEL: u32 = 62;
+
+/// Pattern file formats.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum PatternFormat {
+    /// Run Length Encoded, as described in the `rle` module. Only the `b` and `o` states are
+    /// supported by `LifePattern`.
+    Rle,
+    /// Plaintext (`.cells`): `!` comment lines followed by rows of `.` (dead) and `O` (alive).
+    Plaintext,
+    /// Life 1.05: `#P` blocks of rows of `.` (dead) and `*` (alive).
+    Life105,
+    /// Life 1.06: one line with the coordinates of each live cell.
+    Life106,
+    /// Golly's Macrocell (`.mc`): a quadtree with 8x8 leaves, as used by HashLife.
+    Macrocell,
+}
+
+impl PatternFormat {
+    /// Guesses the format of a pattern file from its contents. Returns `None` if the contents
+    /// do not look like any of the supported formats.
+    pub fn detect(text: &str) -> Option<PatternFormat> {
+        let first_line = text.lines().map(|line| line.trim()).find(|line| !line.is_empty())?;
+        if first_line.starts_with("[M2]") {
+            return Some(PatternFormat::Macrocell);
+        }
+        if first_line.starts_with("#Life 1.05") {
+            return Some(PatternFormat::Life105);
+        }
+        if first_line.starts_with("#Life 1.06") {
+            return Some(PatternFormat::Life106);
+        }
+        if first_line.starts_with('!') {
+            return Some(PatternFormat::Plaintext);
+        }
+        let first_non_comment = text
+            .lines()
+            .map(|line| line.trim())
+            .find(|line| !line.is_empty() && !line.starts_with('#'))?;
+        if first_non_comment.starts_with('x') && first_non_comment.contains('=') {
+            return Some(PatternFormat::Rle);
+        }
+        let is_plaintext = text
+            .lines()
+            .all(|line| line.trim_end().chars().all(|ch| plaintext_cell(ch).is_some()));
+        if is_plaintext {
+            return Some(PatternFormat::Plaintext);
+        }
+        None
+    }
+}
+
+/// A two-state pattern, read from or to be written to a pattern file in any `PatternFormat`.
+///
+/// The top-left cell of the pattern is at column 0 and row 0. Formats that place cells relative to
+/// an origin (Life 1.05, Life 1.06 and Macrocell) are shifted so that the pattern starts at the
+/// top-left corner of the bounding box of its live cells.
+#[derive(Debug, PartialEq, Clone)]
+pub struct LifePattern {
+    /// Name of the pattern. Only RLE (`#N`) and plaintext (`!Name:`) files have a name.
+    pub name:     Option<String>,
+    /// Comments about the pattern.
+    pub comments: Vec<String>,
+    /// The rule, if specified. Plaintext and Life 1.06 files can't specify a rule.
+    pub rule:     Option<Rule>,
+    width:        usize,
+    height:       usize,
+    cells:        BitGrid,
+}
+
+impl LifePattern {
+    /// Creates an empty pattern of the specified size.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ConwayError::InvalidData` if `width` or `height` is too large.
+    pub fn new(width: usize, height: usize) -> ConwayResult<LifePattern> {
+        if width > MAX_PATTERN_SIZE || height > MAX_PATTERN_SIZE {
+            return Err(ConwayError::InvalidData {
+                reason: format!(
+                    "pattern of size {}x{} is larger than the maximum of {}x{}",
+                    width, height, MAX_PATTERN_SIZE, MAX_PATTERN_SIZE
+                ),
+            });
+        }
+        Ok(LifePattern {
+            name: None,
+            comments: vec![],
+            rule: None,
+            width,
+            height,
+            cells: BitGrid::new(((width + 63) / 64).max(1), height.max(1)),
+        })
+    }
+
+    /// Parses a pattern file, detecting its format with `PatternFormat::detect`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ConwayError::InvalidData` if the format is not recognized or the file can't be
+    /// parsed.
+    pub fn parse(text: &str) -> ConwayResult<LifePattern> {
+        let format = PatternFormat::detect(text).ok_or_else(|| ConwayError::InvalidData {
+            reason: "unrecognized pattern format".to_owned(),
+        })?;
+        LifePattern::parse_as(text, format)
+    }
+
+    /// Parses a pattern file in the specified format.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ConwayError::InvalidData` if the file can't be parsed.
+    pub fn parse_as(text: &str, format: PatternFormat) -> ConwayResult<LifePattern> {
+        match format {
+            PatternFormat::Rle => parse_rle(text),
+            PatternFormat::Plaintext => parse_plaintext(text),
+            PatternFormat::Life105 => parse_life_105(text),
+            PatternFormat::Life106 => parse_life_106(text),
+            PatternFormat::Macrocell => parse_macrocell(text),
+        }
+    }
+
+    /// Writes this pattern in the specified format. Information that can't be represented in
+    /// that format, such as the name of the pattern in a Life 1.06 file, is left out.
+    pub fn write(&self, format: PatternFormat) -> String {
+        match format {
+            PatternFormat::Rle => self.write_rle(),
+            PatternFormat::Plaintext => self.write_plaintext(),
+            PatternFormat::Life105 => self.write_life_105(),
+            PatternFormat::Life106 => self.write_life_106(),
+            PatternFormat::Macrocell => self.write_macrocell(),
+        }
+    }
+
+    /// Creates a pattern from all of `grid` as seen by the player specified by `visibility`, or
+    /// a fog-less view if `visibility.is_none()`. Cells belonging to players are alive; walls and
+    /// fog are dead.
+    pub fn from_grid<G: CharGrid>(grid: &G, visibility: Option<usize>) -> ConwayResult<LifePattern> {
+        let mut pattern = LifePattern::new(grid.width(), grid.height())?;
+        for row in 0..grid.height() {
+            let mut col = 0;
+            while col < grid.width() {
+                let (run, ch) = grid.get_run(col, row, visibility);
+                if let Some(CellState::Alive(_)) = CellState::from_char(ch) {
+                    pattern
+                        .cells
+                        .modify_row_span(row, col, col + run - 1, BitOperation::Set);
+                }
+                col += run;
+            }
+        }
+        Ok(pattern)
+    }
+
+    /// Writes all cells of this pattern to `grid` as `o` (alive) or `b` (dead), starting at the
+    /// top-left corner. See `Pattern::to_grid` for how `visibility` is used.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ConwayError::InvalidData` if the pattern does not fit in `grid`.
+    pub fn to_grid<G: CharGrid>(&self, grid: &mut G, visibility: Option<usize>) -> ConwayResult<()> {
+        if self.width > grid.width() || self.height > grid.height() {
+            return Err(ConwayError::InvalidData {
+                reason: format!(
+                    "pattern of size {}x{} does not fit in grid of size {}x{}",
+                    self.width,
+                    self.height,
+                    grid.width(),
+                    grid.height()
+                ),
+            });
+        }
+        for row in 0..self.height {
+            for col in 0..self.width {
+                let ch = if self.is_alive(col, row) { 'o' } else { 'b' };
+                grid.write_at_position(col, row, ch, visibility);
+            }
+        }
+        Ok(())
+    }
+
+    /// Width in cells
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Height in cells
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The cells of this pattern. The grid may be wider than the pattern, since its width is a
+    /// multiple of 64; the extra cells are always dead.
+    pub fn cells(&self) -> &BitGrid {
+        &self.cells
+    }
+
+    /// Whether the cell at `(col, row)` is alive. Cells outside of the pattern are dead.
+    pub fn is_alive(&self, col: usize, row: usize) -> bool {
+        if col >= self.width || row >= self.height {
+            return false;
+        }
+        self.cells.is_set(col, row)
+    }
+
+    /// Makes the cell at `(col, row)` alive.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `(col, row)` is outside of the pattern.
+    pub fn set_alive(&mut self, col: usize, row: usize) {
+        assert!(col < self.width && row < self.height, "cell out of bounds");
+        self.cells[row][col / 64] |= 1 << (63 - col % 64);
+    }
+
+    /// Creates a pattern just large enough to contain `cells`, given as `(x, y)` coordinates
+    /// relative to an arbitrary origin.
+    fn from_coordinates(cells: &[(i64, i64)]) -> ConwayResult<LifePattern> {
+        if cells.is_empty() {
+            return LifePattern::new(0, 0);
+        }
+        let min_x = cells.iter().map(|&(x, _)| x).min().unwrap();
+        let max_x = cells.iter().map(|&(x, _)| x).max().unwrap();
+        let min_y = cells.iter().map(|&(_, y)| y).min().unwrap();
+        let max_y = cells.iter().map(|&(_, y)| y).max().unwrap();
+        let size = |min: i64, max: i64| (max as i128 - min as i128 + 1).min(MAX_PATTERN_SIZE as i128 + 1) as usize;
+        let mut pattern = LifePattern::new(size(min_x, max_x), size(min_y, max_y))?;
+        for &(x, y) in cells {
+            pattern.set_alive((x - min_x) as usize, (y - min_y) as usize);
+        }
+        Ok(pattern)
+    }
+
+    /// The live cells of row `row` as `.` and `alive_ch`, without trailing dead cells.
+    fn row_chars(&self, row: usize, cols: std::ops::Range<usize>, alive_ch: char) -> String {
+        let mut line: String = cols
+            .map(|col| if self.is_alive(col, row) { alive_ch } else { '.' })
+            .collect();
+        let trimmed_len = line.trim_end_matches('.').len();
+        line.truncate(trimmed_len);
+        line
+    }
+
+    fn write_rle(&self) -> String {
+        let mut pattern = self.cells.to_pattern(None).0.replace("\r\n", "");
+        if self.height == 0 {
+            pattern = "!".to_owned();
+        }
+        let mut file = PatternFile {
+            comment_lines: vec![],
+            header_line:   HeaderLine {
+                x:    self.width,
+                y:    self.height,
+                rule: None,
+            },
+            pattern:       Pattern(pattern),
+        };
+        if let Some(ref name) = self.name {
+            file = file.with_name(name);
+        }
+        for comment in &self.comments {
+            file = file.with_comment(comment);
+        }
+        if let Some(rule) = self.rule {
+            file = file.with_rule(rule);
+        }
+        file.to_string()
+    }
+
+    fn write_plaintext(&self) -> String {
+        let mut text = String::new();
+        if let Some(ref name) = self.name {
+            writeln!(text, "!Name: {}", name).unwrap();
+        }
+        for comment in &self.comments {
+            for line in comment.lines() {
+                writeln!(text, "!{}", line).unwrap();
+            }
+        }
+        // Rows are written in full so that the size of the pattern is kept.
+        for row in 0..self.height {
+            let line: String = (0..self.width)
+                .map(|col| if self.is_alive(col, row) { 'O' } else { '.' })
+                .collect();
+            writeln!(text, "{}", line).unwrap();
+        }
+        text
+    }
+
+    fn write_life_105(&self) -> String {
+        let mut text = "#Life 1.05\n".to_owned();
+        for comment in &self.comments {
+            for line in comment.lines() {
+                writeln!(text, "#D {}", line).unwrap();
+            }
+        }
+        match self.rule {
+            None => {}
+            Some(rule) if rule.is_conway() => text.push_str("#N\n"),
+            Some(rule) => {
+                text.push_str("#R ");
+                for n in rule.survival_counts() {
+                    write!(text, "{}", n).unwrap();
+                }
+                text.push('/');
+                for n in rule.birth_counts() {
+                    write!(text, "{}", n).unwrap();
+                }
+                text.push('\n');
+            }
+        }
+        // One block for each band of columns narrow enough to fit on a line, skipping empty rows
+        // at the top and bottom of the band, and empty bands. Coordinates are relative to the
+        // center of the pattern.
+        let (center_x, center_y) = ((self.width / 2) as i64, (self.height / 2) as i64);
+        for left in (0..self.width).step_by(LIFE_105_MAX_LINE_LENGTH) {
+            let cols = left..(left + LIFE_105_MAX_LINE_LENGTH).min(self.width);
+            let lines: Vec<String> = (0..self.height)
+                .map(|row| self.row_chars(row, cols.clone(), '*'))
+                .collect();
+            let top = match lines.iter().position(|line| !line.is_empty()) {
+                Some(top) => top,
+                None => continue,
+            };
+            let bottom = lines.iter().rposition(|line| !line.is_empty()).unwrap();
+            writeln!(text, "#P {} {}", left as i64 - center_x, top as i64 - center_y).unwrap();
+            for line in &lines[top..=bottom] {
+                writeln!(text, "{}", if line.is_empty() { "." } else { line }).unwrap();
+            }
+        }
+        text
+    }
+
+    fn write_life_106(&self) -> String {
+        let mut text = "#Life 1.06\n".to_owned();
+        self.cells.each_set(|col, row| {
+            writeln!(text, "{} {}", col, row).unwrap();
+        });
+        text
+    }
+
+    fn write_macrocell(&self) -> String {
+        let mut text = "[M2] (libconway)\n".to_owned();
+        writeln!(text, "#R {}", self.rule.unwrap_or_else(Rule::conway)).unwrap();
+        for comment in &self.comments {
+            for line in comment.lines() {
+                writeln!(text, "#C {}", line).unwrap();
+            }
+        }
+        let mut level = MACROCELL_LEAF_LEVEL;
+        while (1usize << level) < self.width.max(self.height) {
+            level += 1;
+        }
+        let mut writer = MacrocellWriter {
+            pattern:    self,
+            leaf_ids:   HashMap::new(),
+            branch_ids: HashMap::new(),
+            lines:      vec![],
+        };
+        writer.node(level, 0, 0);
+        for line in writer.lines {
+            writeln!(text, "{}", line).unwrap();
+        }
+        text
+    }
+}
+
+/// Whether a character in a plaintext file is an alive cell (`Some(true)`), a dead cell
+/// (`Some(false)`), or not valid (`None`).
+fn plaintext_cell(ch: char) -> Option<bool> {
+    match ch {
+        'O' | 'o' | '*' => Some(true),
+        '.' => Some(false),
+        _ => None,
+    }
+}
+
+fn parse_rle(text: &str) -> ConwayResult<LifePattern> {
+    let file = PatternFile::from_str(text)?;
+    let (pat_width, pat_height) = file.pattern.calc_size()?;
+    let mut pattern = LifePattern::new(file.width().max(pat_width), file.height().max(pat_height))?;
+    file.pattern.to_grid(&mut pattern.cells, None)?;
+    // The size in the header line is used unless the live cells don't fit in it.
+    let (width, height) = match pattern.cells.bounding_box() {
+        Some(bbox) => (
+            file.width().max(bbox.right() as usize + 1),
+            file.height().max(bbox.bottom() as usize + 1),
+        ),
+        None => (file.width(), file.height()),
+    };
+    pattern.width = width;
+    pattern.height = height;
+    pattern.name = file.name().map(|name| name.to_owned());
+    pattern.comments = file.comments().iter().map(|c| (*c).to_owned()).collect();
+    if file.header_line.rule.is_some() {
+        pattern.rule = Some(file.rule()?);
+    }
+    Ok(pattern)
+}
+
+fn parse_plaintext(text: &str) -> ConwayResult<LifePattern> {
+    let mut name = None;
+    let mut comments = vec![];
+    let mut rows = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim_end();
+        if let Some(comment) = line.strip_prefix('!') {
+            if let Some(pattern_name) = comment.strip_prefix("Name:") {
+                name = Some(pattern_name.trim().to_owned());
+            } else {
+                comments.push(comment.trim().to_owned());
+            }
+            continue;
+        }
+        let mut row = vec![];
+        for ch in line.chars() {
+            row.push(plaintext_cell(ch).ok_or_else(|| ConwayError::InvalidData {
+                reason: format!("unexpected character {:?} on line {}", ch, i + 1),
+            })?);
+        }
+        rows.push(row);
+    }
+    // blank lines at the end of the file are not part of the pattern
+    while let Some(true) = rows.last().map(|row| row.is_empty()) {
+        rows.pop();
+    }
+    let width = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+    let mut pattern = LifePattern::new(width, rows.len())?;
+    for (row, cells) in rows.iter().enumerate() {
+        for (col, &alive) in cells.iter().enumerate() {
+            if alive {
+                pattern.set_alive(col, row);
+            }
+        }
+    }
+    pattern.name = name;
+    pattern.comments = comments;
+    Ok(pattern)
+}
+
+fn parse_coordinate(s: Option<&str>, line_num: usize) -> ConwayResult<i64> {
+    s.and_then(|s| s.parse().ok()).ok_or_else(|| ConwayError::InvalidData {
+        reason: format!("expected two coordinates on line {}", line_num),
+    })
+}
+
+fn parse_life_105(text: &str) -> ConwayResult<LifePattern> {
+    let mut comments = vec![];
+    let mut rule = None;
+    let mut cells = vec![];
+    let mut block: Option<(i64, i64)> = None; // position of the next row of the current block
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.starts_with("#D") || line.starts_with("#C") {
+            comments.push(line[2..].trim().to_owned());
+        } else if line == "#N" {
+            rule = Some(Rule::conway());
+        } else if let Some(rule_str) = line.strip_prefix("#R") {
+            rule = Some(Rule::from_str(rule_str)?);
+        } else if let Some(position) = line.strip_prefix("#P") {
+            let mut coords = position.split_whitespace();
+            let x = parse_coordinate(coords.next(), i + 1)?;
+            let y = parse_coordinate(coords.next(), i + 1)?;
+            block = Some((x, y));
+        } else if line.starts_with('#') || line.is_empty() {
+            // unsupported or empty line
+        } else {
+            let (x, y) = block.ok_or_else(|| ConwayError::InvalidData {
+                reason: format!("cells before the first #P line on line {}", i + 1),
+            })?;
+            for (col, ch) in line.chars().enumerate() {
+                match ch {
+                    '*' => cells.push((x + col as i64, y)),
+                    '.' => {}
+                    _ => {
+                        return Err(ConwayError::InvalidData {
+                            reason: format!("unexpected character {:?} on line {}", ch, i + 1),
+                        });
+                    }
+                }
+            }
+            block = Some((x, y + 1));
+        }
+    }
+    let mut pattern = LifePattern::from_coordinates(&cells)?;
+    pattern.comments = comments;
+    pattern.rule = rule;
+    Ok(pattern)
+}
+
+fn parse_life_106(text: &str) -> ConwayResult<LifePattern> {
+    let mut cells = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut coords = line.split_whitespace();
+        let x = parse_coordinate(coords.next(), i + 1)?;
+        let y = parse_coordinate(coords.next(), i + 1)?;
+        cells.push((x, y));
+    }
+    LifePattern::from_coordinates(&cells)
+}
+
+/// A node of a Macrocell file.
+enum MacrocellNode {
+    /// Coordinates of the live cells of a leaf, relative to its top-left corner.
+    Leaf(Vec<(i64, i64)>),
+    /// Level and children (NW, NE, SW, SE; 0 means empty) of a non-leaf node.
+    Branch(u32, [usize; 4]),
+}
+
+fn parse_macrocell(text: &str) -> ConwayResult<LifePattern> {
+    let invalid = |reason: String| ConwayError::InvalidData { reason };
+    let mut comments = vec![];
+    let mut rule = None;
+    let mut nodes: Vec<MacrocellNode> = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.starts_with("[M2]") {
+            // format and version
+        } else if let Some(rule_str) = line.strip_prefix("#R") {
+            rule = Some(Rule::from_str(rule_str)?);
+        } else if line.starts_with("#C") || line.starts_with("#D") {
+            comments.push(line[2..].trim().to_owned());
+        } else if line.starts_with('#') || line.is_empty() {
+            // unsupported or empty line
+        } else if line.starts_with(|ch: char| ch.is_ascii_digit()) {
+            let numbers = line
+                .split_whitespace()
+                .map(|n| n.parse::<usize>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| invalid(format!("invalid node on line {}", i + 1)))?;
+            if numbers.len() != 5 {
+                return Err(invalid(format!("expected 5 numbers on line {}", i + 1)));
+            }
+            let level = numbers[0] as u32;
+            if !(1..=MACROCELL_MAX_LEVEL).contains(&level) {
+                return Err(invalid(format!("unsupported level {} on line {}", level, i + 1)));
+            }
+            let children = [numbers[1], numbers[2], numbers[3], numbers[4]];
+            for &child in &children {
+                let child_ok = match nodes.get(child.wrapping_sub(1)) {
+                    _ if child == 0 || level == 1 => true,
+                    Some(MacrocellNode::Leaf(_)) => level == MACROCELL_LEAF_LEVEL + 1,
+                    Some(MacrocellNode::Branch(child_level, _)) => *child_level == level - 1,
+                    None => false,
+                };
+                if !child_ok {
+                    return Err(invalid(format!("invalid child {} on line {}", child, i + 1)));
+                }
+            }
+            nodes.push(MacrocellNode::Branch(level, children));
+        } else {
+            let (mut x, mut y) = (0, 0);
+            let mut cells = vec![];
+            for ch in line.chars() {
+                match ch {
+                    '.' => x += 1,
+                    '*' => {
+                        cells.push((x, y));
+                        x += 1;
+                    }
+                    '$' => {
+                        x = 0;
+                        y += 1;
+                    }
+                    _ => {
+                        return Err(invalid(format!("unexpected character {:?} on line {}", ch, i + 1)));
+                    }
+                }
+                if x > 8 || y > 8 {
+                    return Err(invalid(format!("leaf larger than 8x8 on line {}", i + 1)));
+                }
+            }
+            nodes.push(MacrocellNode::Leaf(cells));
+        }
+    }
+
+    // The root is the last node. Its bounding box is found first, so that a small file can't
+    // describe a pattern that is too large to expand.
+    let root = nodes.len();
+    let mut pattern = match macrocell_bounds(&nodes, root, &mut HashMap::new()) {
+        Some((min_x, min_y, max_x, max_y)) => {
+            let size = |min: i128, max: i128| (max - min + 1).min(MAX_PATTERN_SIZE as i128 + 1) as usize;
+            let mut pattern = LifePattern::new(size(min_x, max_x), size(min_y, max_y))?;
+            macrocell_cells(&nodes, root, -min_x as i64, -min_y as i64, &mut pattern);
+            pattern
+        }
+        None => LifePattern::new(0, 0)?,
+    };
+    pattern.comments = comments;
+    pattern.rule = rule;
+    Ok(pattern)
+}
+
+/// Bounding box `(min_x, min_y, max_x, max_y)` of live cells in a Macrocell file.
+type Bounds = (i128, i128, i128, i128);
+
+/// Smallest bounding box containing `bounds` (if any) and the box `other`.
+fn union_bounds(bounds: Option<Bounds>, other: Bounds) -> Bounds {
+    match bounds {
+        None => other,
+        Some((min_x, min_y, max_x, max_y)) => (
+            min_x.min(other.0),
+            min_y.min(other.1),
+            max_x.max(other.2),
+            max_y.max(other.3),
+        ),
+    }
+}
+
+/// The bounding box of the live cells of node `id` relative to its top-left corner, or `None` if
+/// there are none. Results are memoized in `memo`, since nodes are shared.
+fn macrocell_bounds(nodes: &[MacrocellNode], id: usize, memo: &mut HashMap<usize, Option<Bounds>>) -> Option<Bounds> {
+    if id == 0 {
+        return None;
+    }
+    if let Some(&result) = memo.get(&id) {
+        return result;
+    }
+    let mut result = None;
+    match nodes[id - 1] {
+        MacrocellNode::Leaf(ref cells) => {
+            for &(x, y) in cells {
+                let (x, y) = (x as i128, y as i128);
+                result = Some(union_bounds(result, (x, y, x, y)));
+            }
+        }
+        MacrocellNode::Branch(1, children) => {
+            for (i, &child) in children.iter().enumerate() {
+                if child != 0 {
+                    let (x, y) = ((i % 2) as i128, (i / 2) as i128);
+                    result = Some(union_bounds(result, (x, y, x, y)));
+                }
+            }
+        }
+        MacrocellNode::Branch(level, children) => {
+            let half = 1i128 << (level - 1);
+            for (i, &child) in children.iter().enumerate() {
+                if let Some((min_x, min_y, max_x, max_y)) = macrocell_bounds(nodes, child, memo) {
+                    let (dx, dy) = ((i % 2) as i128 * half, (i / 2) as i128 * half);
+                    result = Some(union_bounds(result, (min_x + dx, min_y + dy, max_x + dx, max_y + dy)));
+                }
+            }
+        }
+    }
+    memo.insert(id, result);
+    result
+}
+
+/// Makes the live cells of node `id` alive in `pattern`, with the top-left corner of the node at
+/// `(x, y)`, which may be outside of the pattern. Empty nodes are skipped.
+fn macrocell_cells(nodes: &[MacrocellNode], id: usize, x: i64, y: i64, pattern: &mut LifePattern) {
+    if id == 0 {
+        return;
+    }
+    match nodes[id - 1] {
+        MacrocellNode::Leaf(ref cells) => {
+            for &(cell_x, cell_y) in cells {
+                pattern.set_alive((x + cell_x) as usize, (y + cell_y) as usize);
+            }
+        }
+        MacrocellNode::Branch(1, children) => {
+            for (i, &child) in children.iter().enumerate() {
+                if child != 0 {
+                    pattern.set_alive((x + (i % 2) as i64) as usize, (y + (i / 2) as i64) as usize);
+                }
+            }
+        }
+        MacrocellNode::Branch(level, children) => {
+            let half = 1i64 << (level - 1);
+            for (i, &child) in children.iter().enumerate() {
+                macrocell_cells(
+                    nodes,
+                    child,
+                    x + (i % 2) as i64 * half,
+                    y + (i / 2) as i64 * half,
+                    pattern,
+                );
+            }
+        }
+    }
+}
+
+/// Builds the lines of a Macrocell file, reusing identical nodes.
+struct MacrocellWriter<'a> {
+    pattern:    &'a LifePattern,
+    leaf_ids:   HashMap<[u8; 8], usize>,
+    branch_ids: HashMap<(u32, [usize; 4]), usize>,
+    lines:      Vec<String>,
+}
+
+impl<'a> MacrocellWriter<'a> {
+    /// Writes the node of the specified level with its top-left corner at `(col, row)`, and any
+    /// children not written yet. Returns its ID, or 0 if it is empty.
+    fn node(&mut self, level: u32, col: usize, row: usize) -> usize {
+        if col >= self.pattern.width || row >= self.pattern.height {
+            return 0;
+        }
+        if level == MACROCELL_LEAF_LEVEL {
+            let mut leaf = [0u8; 8];
+            for (y, leaf_row) in leaf.iter_mut().enumerate() {
+                for x in 0..8 {
+                    if self.pattern.is_alive(col + x, row + y) {
+                        *leaf_row |= 1 << x;
+                    }
+                }
+            }
+            if leaf == [0; 8] {
+                return 0;
+            }
+            if let Some(&id) = self.leaf_ids.get(&leaf) {
+                return id;
+            }
+            let last_row = leaf.iter().rposition(|&r| r != 0).unwrap();
+            let mut line = String::new();
+            for &leaf_row in &leaf[..=last_row] {
+                let width = 8 - leaf_row.leading_zeros() as usize;
+                for x in 0..width {
+                    line.push(if leaf_row & (1 << x) != 0 { '*' } else { '.' });
+                }
+                line.push('$');
+            }
+            self.lines.push(line);
+            let id = self.lines.len();
+            self.leaf_ids.insert(leaf, id);
+            return id;
+        }
+        let half = 1 << (level - 1);
+        let children = [
+            self.node(level - 1, col, row),
+            self.node(level - 1, col + half, row),
+            self.node(level - 1, col, row + half),
+            self.node(level - 1, col + half, row + half),
+        ];
+        if children == [0; 4] {
+            return 0;
+        }
+        if let Some(&id) = self.branch_ids.get(&(level, children)) {
+            return id;
+        }
+        self.lines.push(format!(
+            "{} {} {} {} {}",
+            level, children[0], children[1], children[2], children[3]
+        ));
+        let id = self.lines.len();
+        self.branch_ids.insert((level, children), id);
+        id
+    }
+}
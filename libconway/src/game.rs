@@ -0,0 +1,350 @@
+/*  Copyright 2020 the Conwayste Developers.
+ *
+ *  This file is part of libconway.
+ *
+ *  libconway is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  libconway is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with libconway.  If not, see <http://www.gnu.org/licenses/>. */
+
+//! Win conditions. A `Game` runs a `Universe` and evaluates a `GameMode` after each generation, so
+//! that the server and single-player games decide the outcome with exactly the same rules.
+
+use crate::error::{ConwayError, ConwayResult};
+use crate::grids::CharGrid;
+use crate::universe::{Region, Universe};
+
+/// Whether a game is over and, if so, how it ended. Teammates (see `PlayerBuilder::team`) win or
+/// lose together, so a game won by a team ends with `TeamWinner`, and a game won by a player who
+/// is not on a team ends with `Winner`.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum GameStatus {
+    InProgress,
+    Winner(usize),     // player_id of the winner
+    TeamWinner(usize), // team of the winners
+    Tie,
+}
+
+impl GameStatus {
+    /// Returns true if the game has ended, with a winner or a tie.
+    pub fn is_finished(self) -> bool {
+        self != GameStatus::InProgress
+    }
+}
+
+/// The rules that decide when a game ends and who wins.
+pub trait GameMode {
+    /// Checks that the mode can be used with `uni`, for example that it has the right number of
+    /// players. Called once, when the `Game` is created.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ConwayError::InvalidData` if the mode can't be used with `uni`.
+    fn check(&self, _uni: &Universe) -> ConwayResult<()> {
+        Ok(())
+    }
+
+    /// Evaluates the latest generation of `uni`. Called once after each generation until the
+    /// result is not `GameStatus::InProgress`; modes may keep track of earlier generations.
+    fn evaluate(&mut self, uni: &Universe) -> GameStatus;
+}
+
+/// A `Universe` together with the `GameMode` deciding the outcome of the game played in it.
+pub struct Game {
+    universe: Universe,
+    mode:     Box<dyn GameMode>,
+    status:   GameStatus,
+}
+
+impl Game {
+    /// Creates a game in `universe` following the rules of `mode`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from `GameMode::check` if `mode` can't be used with `universe`.
+    pub fn new(universe: Universe, mode: Box<dyn GameMode>) -> ConwayResult<Game> {
+        mode.check(&universe)?;
+        Ok(Game {
+            universe,
+            mode,
+            status: GameStatus::InProgress,
+        })
+    }
+
+    pub fn universe(&self) -> &Universe {
+        &self.universe
+    }
+
+    /// Gives access to the universe, for example to let players toggle cells. Stepping it with
+    /// `Universe::next` bypasses the game mode; use `Game::next` instead.
+    pub fn universe_mut(&mut self) -> &mut Universe {
+        &mut self.universe
+    }
+
+    pub fn status(&self) -> GameStatus {
+        self.status
+    }
+
+    /// Computes the next generation and evaluates the game mode on it. Once the game is finished,
+    /// the universe is no longer advanced and the final status is returned.
+    pub fn next(&mut self) -> GameStatus {
+        if !self.status.is_finished() {
+            self.universe.next();
+            self.status = self.mode.evaluate(&self.universe);
+        }
+        self.status
+    }
+}
+
+/// A team, or a player who is not on a team. Game modes compare sides rather than players, so
+/// that teammates don't compete with each other.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+enum Side {
+    Player(usize), // player_id
+    Team(usize),
+}
+
+impl Side {
+    fn of(uni: &Universe, player_id: usize) -> Side {
+        match uni.team(player_id) {
+            Some(team) => Side::Team(team),
+            None => Side::Player(player_id),
+        }
+    }
+}
+
+/// Returns the side of every player in the game, without duplicates, in order of player_id.
+fn sides(uni: &Universe) -> Vec<Side> {
+    let mut sides = vec![];
+    for player_id in (0..uni.num_players()).filter(|&player_id| uni.has_player(player_id)) {
+        let side = Side::of(uni, player_id);
+        if !sides.contains(&side) {
+            sides.push(side);
+        }
+    }
+    sides
+}
+
+/// Returns the winner if `sides` has exactly one side (ignoring duplicates), a tie if it has more
+/// than one, or `GameStatus::InProgress` if it is empty.
+fn status_from_candidates(sides: &[Side]) -> GameStatus {
+    match sides.first() {
+        None => GameStatus::InProgress,
+        Some(&side) if sides.iter().any(|&other| other != side) => GameStatus::Tie,
+        Some(&Side::Player(player_id)) => GameStatus::Winner(player_id),
+        Some(&Side::Team(team)) => GameStatus::TeamWinner(team),
+    }
+}
+
+/// Returns an error unless `uni` has at least two players.
+fn check_multiplayer(uni: &Universe, mode_name: &str) -> ConwayResult<()> {
+    if uni.num_players() < 2 {
+        return Err(ConwayError::InvalidData {
+            reason: format!("{} needs at least two players, not {}", mode_name, uni.num_players()),
+        });
+    }
+    Ok(())
+}
+
+/// The last player (or team) with live cells wins. If the last players lose their cells in the
+/// same generation, it's a tie.
+#[derive(Debug, Clone, Default)]
+pub struct LastSurvivor;
+
+impl GameMode for LastSurvivor {
+    fn check(&self, uni: &Universe) -> ConwayResult<()> {
+        check_multiplayer(uni, "LastSurvivor")
+    }
+
+    fn evaluate(&mut self, uni: &Universe) -> GameStatus {
+        let survivors: Vec<Side> = (0..uni.num_players())
+            .filter(|&player_id| !uni.latest_player_cells(player_id).is_empty())
+            .map(|player_id| Side::of(uni, player_id))
+            .collect();
+        match status_from_candidates(&survivors) {
+            GameStatus::InProgress => GameStatus::Tie, // nobody survived
+            GameStatus::Tie => GameStatus::InProgress, // more than one side survived
+            status => status,
+        }
+    }
+}
+
+/// The player (or team) with the most live cells after a fixed number of generations wins. The
+/// cells of teammates are counted together. If several players share the highest count, it's a
+/// tie.
+#[derive(Debug, Clone)]
+pub struct MostCells {
+    generations: usize,
+}
+
+impl MostCells {
+    /// The game ends once `generations` generations have been computed, that is, at generation
+    /// `generations + 1`.
+    pub fn new(generations: usize) -> Self {
+        MostCells { generations }
+    }
+}
+
+impl GameMode for MostCells {
+    fn evaluate(&mut self, uni: &Universe) -> GameStatus {
+        if uni.latest_gen() <= self.generations {
+            return GameStatus::InProgress;
+        }
+        let sides = sides(uni);
+        let mut counts = vec![0; sides.len()];
+        for player_id in 0..uni.num_players() {
+            if let Some(i) = sides.iter().position(|&side| side == Side::of(uni, player_id)) {
+                counts[i] += uni.latest_player_cells(player_id).popcount();
+            }
+        }
+        let most = counts.iter().cloned().max().unwrap_or(0);
+        let leaders: Vec<Side> = (0..sides.len())
+            .filter(|&i| counts[i] == most)
+            .map(|i| sides[i])
+            .collect();
+        match status_from_candidates(&leaders) {
+            GameStatus::InProgress => GameStatus::Tie, // no players
+            status => status,
+        }
+    }
+}
+
+/// The first player to get a live cell into the base of a player on another side wins, along with
+/// the player's team; the bases of teammates don't count. If several sides do so in the same
+/// generation, it's a tie. The bases of players removed with `Universe::remove_player`
+/// don't count.
+#[derive(Debug, Clone)]
+pub struct ReachBase {
+    bases: Vec<Region>, // indexed by player_id
+}
+
+impl ReachBase {
+    /// `bases` has the base region of each player, indexed by player_id.
+    pub fn new(bases: Vec<Region>) -> Self {
+        ReachBase { bases }
+    }
+}
+
+impl GameMode for ReachBase {
+    fn check(&self, uni: &Universe) -> ConwayResult<()> {
+        check_multiplayer(uni, "ReachBase")?;
+        if self.bases.len() != uni.num_players() {
+            return Err(ConwayError::InvalidData {
+                reason: format!(
+                    "expected a base for each of the {} players, but got {}",
+                    uni.num_players(),
+                    self.bases.len()
+                ),
+            });
+        }
+        Ok(())
+    }
+
+    fn evaluate(&mut self, uni: &Universe) -> GameStatus {
+        let bases = &self.bases;
+        let reached: Vec<Side> = (0..uni.num_players())
+            .filter(|&player_id| {
+                let cells = uni.latest_player_cells(player_id);
+                let side = Side::of(uni, player_id);
+                bases.iter().enumerate().any(|(owner, &base)| {
+                    uni.has_player(owner) && Side::of(uni, owner) != side && cells.popcount_in_region(base) > 0
+                })
+            })
+            .map(|player_id| Side::of(uni, player_id))
+            .collect();
+        status_from_candidates(&reached)
+    }
+}
+
+/// A cell marked as the flag of a player, for `CaptureTheFlag`.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub struct Flag {
+    pub col:   usize,
+    pub row:   usize,
+    pub owner: usize, // player_id of the player defending this flag
+}
+
+/// A player captures a flag of a player on another side by keeping live cells of its side on the
+/// flag for a number of consecutive generations, and the first side to capture a flag wins. If
+/// several players capture flags in the same generation, it's a tie. The flags of players removed
+/// with `Universe::remove_player` can't be captured.
+#[derive(Debug, Clone)]
+pub struct CaptureTheFlag {
+    flags:            Vec<Flag>,
+    hold_generations: usize,
+    holders:          Vec<Option<(Side, usize)>>, // for each flag, the side on it and for how many generations
+}
+
+impl CaptureTheFlag {
+    /// `hold_generations` is how many consecutive generations a player must hold a flag to capture
+    /// it; with 1, a flag is captured as soon as another player's cell is on it.
+    pub fn new(flags: Vec<Flag>, hold_generations: usize) -> Self {
+        let holders = vec![None; flags.len()];
+        CaptureTheFlag {
+            flags,
+            hold_generations,
+            holders,
+        }
+    }
+}
+
+impl GameMode for CaptureTheFlag {
+    fn check(&self, uni: &Universe) -> ConwayResult<()> {
+        check_multiplayer(uni, "CaptureTheFlag")?;
+        if self.hold_generations == 0 {
+            return Err(ConwayError::InvalidData {
+                reason: "hold_generations must be at least 1".to_owned(),
+            });
+        }
+        for flag in &self.flags {
+            if flag.owner >= uni.num_players() {
+                return Err(ConwayError::InvalidData {
+                    reason: format!("flag owner {} is not a valid player_id", flag.owner),
+                });
+            }
+            if flag.col >= uni.width() || flag.row >= uni.height() {
+                return Err(ConwayError::InvalidData {
+                    reason: format!("flag at ({}, {}) is outside the universe", flag.col, flag.row),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn evaluate(&mut self, uni: &Universe) -> GameStatus {
+        let mut captured_by = vec![];
+        for (flag, holder) in self.flags.iter().zip(self.holders.iter_mut()) {
+            if !uni.has_player(flag.owner) {
+                *holder = None;
+                continue;
+            }
+            let flag_region = Region::new(flag.col as isize, flag.row as isize, 1, 1);
+            let owner_side = Side::of(uni, flag.owner);
+            let opt_side = (0..uni.num_players())
+                .find(|&player_id| {
+                    Side::of(uni, player_id) != owner_side
+                        && uni.latest_player_cells(player_id).popcount_in_region(flag_region) > 0
+                })
+                .map(|player_id| Side::of(uni, player_id));
+            *holder = match (opt_side, *holder) {
+                (Some(side), Some((prev_side, count))) if side == prev_side => Some((side, count + 1)),
+                (Some(side), _) => Some((side, 1)),
+                (None, _) => None,
+            };
+            if let Some((side, count)) = *holder {
+                if count >= self.hold_generations && !captured_by.contains(&side) {
+                    captured_by.push(side);
+                }
+            }
+        }
+        status_from_candidates(&captured_by)
+    }
+}
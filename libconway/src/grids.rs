@@ -15,8 +15,12 @@
  *  You should have received a copy of the GNU General Public License
  *  along with libconway.  If not, see <http://www.gnu.org/licenses/>. */
 
+use crate::error::{ConwayError, ConwayResult};
 use crate::rle::Pattern;
 use crate::universe::Region;
+use serde::de::{self, Deserializer};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
 use std::cmp;
 use std::error::Error;
 use std::ops::{Index, IndexMut};
@@ -28,16 +32,110 @@ pub enum BitOperation {
     Toggle,
 }
 
+/// Bitwise operations between two `BitGrid`s, as performed by `BitGrid::combine`.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum SetOperation {
+    And,
+    Or,
+    Xor,
+    AndNot, // clear the bits that are set in the other grid
+}
+
 /// Defines a rotation.
 #[derive(Eq, PartialEq, Debug, Clone, Copy)]
 pub enum Rotation {
-    CW,  // clockwise
-    CCW, // counter-clockwise
+    CW,   // clockwise
+    CCW,  // counter-clockwise
+    Half, // 180 degrees
+}
+
+/// Defines a transformation that moves each cell of a pattern to a new position, possibly changing
+/// the dimensions of the pattern.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum Transform {
+    Rotate(Rotation),
+    FlipHorizontally, // mirror left to right
+    FlipVertically,   // mirror top to bottom
+    Transpose,        // swap rows and columns
+}
+
+impl Transform {
+    /// Returns the `(width, height)` of the result of transforming a `width` by `height` pattern.
+    pub fn new_size(self, width: usize, height: usize) -> (usize, usize) {
+        match self {
+            Transform::Rotate(Rotation::CW) | Transform::Rotate(Rotation::CCW) | Transform::Transpose => {
+                (height, width)
+            }
+            Transform::Rotate(Rotation::Half) | Transform::FlipHorizontally | Transform::FlipVertically => {
+                (width, height)
+            }
+        }
+    }
+
+    /// Returns the new `(col, row)` of the cell at `(col, row)` in a `width` by `height` pattern.
+    pub fn apply(self, col: usize, row: usize, width: usize, height: usize) -> (usize, usize) {
+        match self {
+            Transform::Rotate(Rotation::CW) => (height - row - 1, col),
+            Transform::Rotate(Rotation::CCW) => (row, width - col - 1),
+            Transform::Rotate(Rotation::Half) => (width - col - 1, height - row - 1),
+            Transform::FlipHorizontally => (width - col - 1, row),
+            Transform::FlipVertically => (col, height - row - 1),
+            Transform::Transpose => (row, col),
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct BitGrid(pub Vec<Vec<u64>>);
 
+/// How a `BitGrid` is written in human-readable formats such as JSON.
+#[derive(Serialize, Deserialize)]
+struct BitGridRle {
+    width_in_words: usize,
+    height:         usize,
+    rle:            String,
+}
+
+/// In human-readable formats, a `BitGrid` is written as an RLE pattern plus its dimensions.
+/// Otherwise, the words are written as they are.
+impl Serialize for BitGrid {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            BitGridRle {
+                width_in_words: self.width_in_words(),
+                height:         self.height(),
+                rle:            self.to_pattern(None).0,
+            }
+            .serialize(serializer)
+        } else {
+            self.0.serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for BitGrid {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            let grid_rle = BitGridRle::deserialize(deserializer)?;
+            let pattern = Pattern(grid_rle.rle);
+            let (width, height) = pattern.calc_size().map_err(|e| de::Error::custom(e.to_string()))?;
+            if width > grid_rle.width_in_words * 64 || height > grid_rle.height {
+                return Err(de::Error::custom(format!(
+                    "{}x{} pattern does not fit in BitGrid of {} words by {} rows",
+                    width, height, grid_rle.width_in_words, grid_rle.height
+                )));
+            }
+            let mut grid = BitGrid::new(grid_rle.width_in_words, grid_rle.height);
+            pattern
+                .to_grid(&mut grid, None)
+                .map_err(|e| de::Error::custom(e.to_string()))?;
+            Ok(grid)
+        } else {
+            Ok(BitGrid(Vec::deserialize(deserializer)?))
+        }
+    }
+}
+
 impl BitGrid {
     /// Creates a new zero-initialized BitGrid of given dimensions.
     ///
@@ -102,6 +200,28 @@ impl BitGrid {
         }
     }
 
+    /// Sets, clears, or toggles the bits from `col_left` to `col_right` (inclusive) in `row`.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if `row` or `col_right` are out of range, or if `col_left` is
+    /// greater than `col_right`.
+    pub fn modify_row_span(&mut self, row: usize, col_left: usize, col_right: usize, op: BitOperation) {
+        assert!(col_left <= col_right);
+        let word_left = col_left / 64;
+        let word_right = col_right / 64;
+        for word_col in word_left..=word_right {
+            let mut mask = u64::max_value();
+            if word_col == word_left {
+                mask &= u64::max_value() >> (col_left % 64);
+            }
+            if word_col == word_right {
+                mask &= u64::max_value() << (63 - col_right % 64);
+            }
+            self.modify_bits_in_word(row, word_col, mask, op);
+        }
+    }
+
     /// Returns `Some(`smallest region containing every 1 bit`)`, or `None` if there are no 1 bits.
     pub fn bounding_box(&self) -> Option<Region> {
         let (width, height) = (self.width(), self.height());
@@ -160,41 +280,132 @@ impl BitGrid {
     /// The bits are copied using an `|=` operation, so 1 bits in the destination will not ever be
     /// cleared.
     pub fn copy(src: &BitGrid, dst: &mut BitGrid, dst_region: Region) {
-        let dst_left = cmp::max(0, dst_region.left()) as usize;
-        let dst_right = cmp::min(dst.width() as isize - 1, dst_region.right()) as usize;
-        let dst_top = cmp::max(0, dst_region.top()) as usize;
-        let dst_bottom = cmp::min(dst.height() as isize - 1, dst_region.bottom()) as usize;
-        if dst_left > dst_right || dst_top > dst_bottom {
-            // nothing to do because both dimensions aren't positive
-            return;
+        dst.combine_in_region(src, dst_region, SetOperation::Or);
+    }
+
+    /// Combines every bit of this grid with the bit at the same position in `other`. Bits beyond
+    /// the edges of `other` are treated as 0.
+    pub fn combine(&mut self, other: &BitGrid, op: SetOperation) {
+        let region = self.region();
+        self.combine_in_region(other, region, op);
+    }
+
+    /// Combines the bits of this grid in `dst_region` with the bits of `src`, with the top-left
+    /// corner of `src` at the top-left corner of `dst_region`. Bits outside of `dst_region` are
+    /// not modified, and `dst_region` is clipped to this grid. Bits of `src` beyond its edges are
+    /// treated as 0, so for example `SetOperation::And` clears any bits in the part of
+    /// `dst_region` that `src` does not cover.
+    pub fn combine_in_region(&mut self, src: &BitGrid, dst_region: Region, op: SetOperation) {
+        let clipped = match self.clip(dst_region) {
+            Some(clipped) => clipped,
+            None => return, // nothing to do because both dimensions aren't positive
+        };
+        for row in clipped.top() as usize..=clipped.bottom() as usize {
+            let src_row = row as isize - dst_region.top();
+            for word_col in clipped.left() as usize / 64..=clipped.right() as usize / 64 {
+                let mask = BitGrid::word_mask(word_col, clipped);
+                let src_word = src.word_at(src_row, (word_col * 64) as isize - dst_region.left());
+                let word = &mut self.0[row][word_col];
+                match op {
+                    SetOperation::And => *word &= src_word | !mask,
+                    SetOperation::Or => *word |= src_word & mask,
+                    SetOperation::Xor => *word ^= src_word & mask,
+                    SetOperation::AndNot => *word &= !(src_word & mask),
+                }
+            }
         }
+    }
 
-        for src_row in 0..src.height() {
-            let dst_row = src_row + dst_top;
-            if dst_row > dst_bottom {
-                break;
+    /// Returns the number of 1 bits.
+    pub fn popcount(&self) -> usize {
+        self.0
+            .iter()
+            .map(|row| row.iter().map(|word| word.count_ones() as usize).sum::<usize>())
+            .sum()
+    }
+
+    /// Returns the number of 1 bits in `region`. Parts of `region` outside of this grid are
+    /// ignored.
+    pub fn popcount_in_region(&self, region: Region) -> usize {
+        let clipped = match self.clip(region) {
+            Some(clipped) => clipped,
+            None => return 0,
+        };
+        let mut count = 0;
+        for row in clipped.top() as usize..=clipped.bottom() as usize {
+            for word_col in clipped.left() as usize / 64..=clipped.right() as usize / 64 {
+                count += (self.0[row][word_col] & BitGrid::word_mask(word_col, clipped)).count_ones() as usize;
             }
-            let mut src_col = 0; // word-aligned
-            while src_col < src.width() {
-                let dst_col = src_col + dst_left; // not word-aligned
-                if dst_col > dst_right {
-                    break;
-                }
-                let dst_word_idx = dst_col / 64;
-                let shift = dst_col - dst_word_idx * 64; // right shift amount
-                let mut word = src[src_row][src_col / 64];
-                // clear bits that would be beyond dst_right
-                if dst_right - dst_col + 1 < 64 {
-                    let mask = !((1u64 << (64 - (dst_right - dst_col + 1))) - 1);
-                    word &= mask;
-                }
-                dst[dst_row][dst_word_idx] |= word >> shift;
-                if shift > 0 && dst_word_idx + 1 < dst.width_in_words() {
-                    dst[dst_row][dst_word_idx + 1] |= word << (64 - shift);
+        }
+        count
+    }
+
+    /// Returns true if there are no 1 bits.
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(|row| row.iter().all(|&word| word == 0))
+    }
+
+    /// Returns true if every bit in `region` is the same in this grid and `other`, with both grids
+    /// positioned at `(0, 0)`. Bits beyond the edges of either grid are treated as 0.
+    pub fn eq_in_region(&self, other: &BitGrid, region: Region) -> bool {
+        let clipped = match self.clip(region) {
+            Some(clipped) => clipped,
+            None => return true,
+        };
+        // bits of `other` beyond the edges of this grid must be 0
+        if other.popcount_in_region(region) != other.popcount_in_region(clipped) {
+            return false;
+        }
+        for row in clipped.top() as usize..=clipped.bottom() as usize {
+            for word_col in clipped.left() as usize / 64..=clipped.right() as usize / 64 {
+                let mask = BitGrid::word_mask(word_col, clipped);
+                let other_word = other.word_at(row as isize, (word_col * 64) as isize);
+                if (self.0[row][word_col] ^ other_word) & mask != 0 {
+                    return false;
                 }
-                src_col += 64;
             }
         }
+        true
+    }
+
+    /// Returns the part of `region` that is inside this grid, or `None` if there is no such part.
+    fn clip(&self, region: Region) -> Option<Region> {
+        self.region().intersection(region)
+    }
+
+    /// Returns the mask of the bits of word `word_col` (in any row) that are in the columns of
+    /// `region`, which must not have negative coordinates.
+    fn word_mask(word_col: usize, region: Region) -> u64 {
+        let word_left = word_col as isize * 64;
+        let left = cmp::max(region.left(), word_left) - word_left;
+        let right = cmp::min(region.right(), word_left + 63) - word_left;
+        if left > right {
+            return 0;
+        }
+        (u64::max_value() >> left) & (u64::max_value() << (63 - right))
+    }
+
+    /// Returns the 64 bits of `row` starting at column `col`, where either may be outside of this
+    /// grid. Bits outside of this grid are 0.
+    fn word_at(&self, row: isize, col: isize) -> u64 {
+        if row < 0 || row as usize >= self.height() {
+            return 0;
+        }
+        let words = &self.0[row as usize];
+        let word = |idx: isize| {
+            if idx < 0 || idx as usize >= words.len() {
+                0
+            } else {
+                words[idx as usize]
+            }
+        };
+        let word_col = col.div_euclid(64);
+        let shift = col.rem_euclid(64);
+        if shift == 0 {
+            word(word_col)
+        } else {
+            (word(word_col) << shift) | (word(word_col + 1) >> (64 - shift))
+        }
     }
 
     /// Get a Region of the same size as the BitGrid.
@@ -202,6 +413,15 @@ impl BitGrid {
         Region::new(0, 0, self.width(), self.height())
     }
 
+    /// Returns true if the bit for the cell at (`col`, `row`) is set (1).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the cell is outside of the grid.
+    pub fn is_set(&self, col: usize, row: usize) -> bool {
+        (self.0[row][col / 64] >> (63 - col % 64)) & 1 == 1
+    }
+
     /// Clear this BitGrid.
     pub fn clear(&mut self) {
         for row in &mut self.0 {
@@ -213,20 +433,38 @@ impl BitGrid {
 
     /// Calls callback on each bit that is set (1). Callback receives (col, row).
     pub fn each_set<F: FnMut(usize, usize)>(&self, mut callback: F) {
-        for row in 0..self.height() {
-            let mut col = 0;
-            for col_idx in 0..self.width_in_words() {
-                let word = self.0[row][col_idx];
-                for shift in (0..64).rev() {
-                    if (word >> shift) & 1 == 1 {
-                        callback(col, row);
-                    }
-                    col += 1;
-                }
-            }
+        for (col, row) in self.iter_set() {
+            callback(col, row);
         }
     }
 
+    /// Returns an iterator over the bits that are set (1), as (col, row), in row-major order. Words
+    /// with no bits set are skipped without looking at their bits.
+    pub fn iter_set(&self) -> SetBits<'_> {
+        SetBits {
+            grid:     self,
+            row:      0,
+            word_col: 0,
+            word:     self.0.first().and_then(|row| row.first()).cloned().unwrap_or(0),
+        }
+    }
+
+    /// Returns a new grid containing the pattern with top-left corner at `(0, 0)` in this grid and
+    /// lower right corner at `(width - 1, height - 1)`, transformed. The new grid is just large
+    /// enough for the transformed pattern, which has its top-left corner at `(0, 0)`; its
+    /// dimensions are swapped for rotations by 90 degrees and transpositions.
+    ///
+    /// # Errors
+    ///
+    /// An error is returned if the width or height are out of range.
+    pub fn transformed(&self, width: usize, height: usize, transform: Transform) -> ConwayResult<BitGrid> {
+        self.check_size(width, height)?;
+        let (new_width, new_height) = transform.new_size(width, height);
+        Ok(self.map_bits(width, height, new_width, new_height, |col, row| {
+            Some(transform.apply(col, row, width, height))
+        }))
+    }
+
     /// Rotates pattern with top-left corner at `(0,0)` in the grid and lower right corner at
     /// `(width - 1, height - 1)` in the specified direction. This may change the dimensions of the
     /// grid.
@@ -234,49 +472,130 @@ impl BitGrid {
     /// # Errors
     ///
     /// An error is returned if the width or height are out of range.
+    #[deprecated(note = "use `transformed` with `Transform::Rotate`")]
     pub fn rotate(&mut self, width: usize, height: usize, rotation: Rotation) -> Result<(), Box<dyn Error>> {
+        *self = self.transformed(width, height, Transform::Rotate(rotation))?;
+        Ok(())
+    }
+
+    /// Returns a new grid containing the pattern with top-left corner at `(0, 0)` in this grid and
+    /// lower right corner at `(width - 1, height - 1)`, with every cell moved `dx` columns to the
+    /// right and `dy` rows down (left and up for negative values). Cells moved past an edge of the
+    /// pattern wrap around to the opposite edge.
+    ///
+    /// # Errors
+    ///
+    /// An error is returned if the width or height are out of range.
+    pub fn shifted(&self, width: usize, height: usize, dx: isize, dy: isize) -> ConwayResult<BitGrid> {
+        self.check_size(width, height)?;
+        Ok(self.map_bits(width, height, width, height, |col, row| {
+            Some((
+                (col as isize + dx).rem_euclid(width as isize) as usize,
+                (row as isize + dy).rem_euclid(height as isize) as usize,
+            ))
+        }))
+    }
+
+    /// Returns a new grid that is just large enough to contain the `bounding_box` of this grid,
+    /// with the top-left corner of the bounding box moved to `(0, 0)`, along with the bounding box.
+    /// Returns `None` if there are no 1 bits.
+    pub fn cropped(&self) -> Option<(BitGrid, Region)> {
+        let bbox = self.bounding_box()?;
+        let (left, top) = (bbox.left() as usize, bbox.top() as usize);
+        let cropped = self.map_bits(self.width(), self.height(), bbox.width(), bbox.height(), |col, row| {
+            Some((col - left, row - top))
+        });
+        Some((cropped, bbox))
+    }
+
+    /// Returns a new grid containing the pattern with top-left corner at `(0, 0)` in this grid and
+    /// lower right corner at `(width - 1, height - 1)`, with the specified number of empty cells
+    /// added on each side.
+    ///
+    /// # Errors
+    ///
+    /// An error is returned if the width or height are out of range.
+    pub fn padded(
+        &self,
+        width: usize,
+        height: usize,
+        left: usize,
+        top: usize,
+        right: usize,
+        bottom: usize,
+    ) -> ConwayResult<BitGrid> {
+        self.check_size(width, height)?;
+        Ok(self.map_bits(
+            width,
+            height,
+            left + width + right,
+            top + height + bottom,
+            |col, row| Some((col + left, row + top)),
+        ))
+    }
+
+    /// Returns an error if a `width` by `height` pattern doesn't fit in this grid.
+    fn check_size(&self, width: usize, height: usize) -> ConwayResult<()> {
         if width > self.width() || height > self.height() {
-            return Err(format!(
-                "Expected passed-in width={} and height={} to be less than grid width={} and height={}",
-                width,
-                height,
-                self.width(),
-                self.height()
-            )
-            .into());
+            return Err(ConwayError::InvalidData {
+                reason: format!(
+                    "Expected passed-in width={} and height={} to be less than grid width={} and height={}",
+                    width,
+                    height,
+                    self.width(),
+                    self.height()
+                ),
+            });
         }
-        let new_width_in_words = (self.height() - 1) / 64 + 1; // number of words needed for this many cells
-        let new_height = self.width();
-        let mut new = BitGrid::new(new_width_in_words, new_height);
-        for row in 0..height {
-            let new_col = match rotation {
-                Rotation::CCW => row,
-                Rotation::CW => height - row - 1,
-            };
-            let new_col_idx = new_col / 64; // the column index in the new grid
-            let mut col = 0;
-            'rowloop: for col_idx in 0..self.width_in_words() {
-                let word = self.0[row][col_idx];
-                for shift in (0..64).rev() {
-                    if col >= width {
-                        break 'rowloop;
-                    }
-                    let new_row = match rotation {
-                        Rotation::CCW => width - col - 1,
-                        Rotation::CW => col,
-                    };
-                    if (word >> shift) & 1 == 1 {
-                        let new_shift = 63 - (new_col - new_col_idx * 64);
-                        // copy this bit to new but rotated
-                        new.0[new_row][new_col_idx] |= 1 << new_shift;
-                    }
-                    col += 1;
-                }
+        Ok(())
+    }
+
+    /// Creates a grid large enough for `new_width` by `new_height` cells, with each 1 bit among the
+    /// top-left `width` by `height` cells of this grid moved to the position returned by `new_pos`.
+    /// Bits for which `new_pos` returns `None` are dropped.
+    fn map_bits<F>(&self, width: usize, height: usize, new_width: usize, new_height: usize, new_pos: F) -> BitGrid
+    where
+        F: Fn(usize, usize) -> Option<(usize, usize)>,
+    {
+        let mut new = BitGrid::new(cmp::max(1, (new_width + 63) / 64), cmp::max(1, new_height));
+        self.each_set(|col, row| {
+            if col >= width || row >= height {
+                return;
+            }
+            if let Some((new_col, new_row)) = new_pos(col, row) {
+                new.0[new_row][new_col / 64] |= 1 << (63 - new_col % 64);
             }
+        });
+        new
+    }
+}
+
+/// Iterator over the set bits of a `BitGrid`; see `BitGrid::iter_set`.
+pub struct SetBits<'a> {
+    grid:     &'a BitGrid,
+    row:      usize,
+    word_col: usize,
+    word:     u64, // bits of the word at (row, word_col) not returned yet
+}
+
+impl<'a> Iterator for SetBits<'a> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<(usize, usize)> {
+        while self.word == 0 {
+            self.word_col += 1;
+            if self.word_col >= self.grid.width_in_words() {
+                self.word_col = 0;
+                self.row += 1;
+            }
+            if self.row >= self.grid.height() {
+                return None;
+            }
+            self.word = self.grid.0[self.row][self.word_col];
         }
-        // replace self with new
-        self.0 = new.0;
-        Ok(())
+        let offset = self.word.leading_zeros() as usize;
+        self.word &= !(1 << (63 - offset));
+        Some((self.word_col * 64 + offset, self.row))
     }
 }
 
@@ -0,0 +1,505 @@
+/*  Copyright 2020 the Conwayste Developers.
+ *
+ *  This file is part of libconway.
+ *
+ *  libconway is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  libconway is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with libconway.  If not, see <http://www.gnu.org/licenses/>. */
+
+use std::cmp;
+use std::collections::HashMap;
+
+use crate::error::{ConwayError, ConwayResult};
+use crate::grids::BitGrid;
+use crate::rule::Rule;
+use crate::universe::Region;
+
+/// Index of a node in `HashLife::nodes`.
+type NodeId = u32;
+
+const DEAD: NodeId = 0;
+const ALIVE: NodeId = 1;
+
+/// Level of the smallest root node. A root must be at least level 2 to be stepped.
+const MIN_ROOT_LEVEL: u8 = 3;
+
+/// Largest supported step is 2^MAX_STEP_LOG2 generations, so that cell coordinates stay within
+/// the range of an `isize`.
+pub const MAX_STEP_LOG2: u8 = 56;
+
+/// A square quadtree node of side 2^`level` cells. Level 0 nodes are single cells; the children
+/// of all other nodes are one level lower.
+#[derive(Copy, Clone, Debug)]
+struct Node {
+    level:      u8,
+    nw:         NodeId,
+    ne:         NodeId,
+    sw:         NodeId,
+    se:         NodeId,
+    population: u64,
+}
+
+/// A memoized quadtree ("HashLife") engine for fast-forwarding large, sparse patterns by
+/// 2^k generations at a time. Identical subpatterns are stored once, and the future of each
+/// is computed once, so repetitive or mostly empty patterns can be evolved millions of
+/// generations ahead far faster than with `Universe::next`.
+///
+/// # Restrictions
+///
+/// The engine only knows which cells are alive, on an unbounded plane. Compared to a `Universe`:
+///
+/// * Cells have no owner. Converting back into a `Universe` leaves every cell unowned.
+/// * Walls, fog, and unknown cells are not simulated. Walls and unknown cells are only taken into
+///   account when converting back (see `Universe::copy_from_hash_life`).
+/// * The universe's `Topology` is ignored; patterns are not wrapped or cut off at the edges of
+///   the universe while evolving here.
+/// * Rules with birth on 0 neighbors (B0) are not supported, since empty space would not stay
+///   empty. Neither are Generations rules, since cells have only two states here.
+///
+/// Memory use grows with the number of distinct subpatterns encountered; create a new engine to
+/// release it.
+pub struct HashLife {
+    rule:       Rule,
+    nodes:      Vec<Node>,
+    interned:   HashMap<[NodeId; 4], NodeId>,
+    results:    HashMap<(NodeId, u8), NodeId>, // (node, log2 of generations) -> center of node afterwards
+    empty:      Vec<NodeId>,                   // empty node at each level, indexed by level
+    root:       NodeId,
+    generation: u64,
+}
+
+impl HashLife {
+    /// Creates an empty engine that evolves cells according to `rule`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ConwayError::InvalidData` if `rule` has birth on 0 neighbors or is a Generations
+    /// rule.
+    pub fn new(rule: Rule) -> ConwayResult<HashLife> {
+        if rule.is_born(0) {
+            return Err(ConwayError::InvalidData {
+                reason: format!("HashLife does not support rules with birth on 0 neighbors ({})", rule),
+            });
+        }
+        if rule.dying_states() > 0 {
+            return Err(ConwayError::InvalidData {
+                reason: format!("HashLife does not support Generations rules ({})", rule),
+            });
+        }
+        let leaf = |population| Node {
+            level: 0,
+            nw: DEAD,
+            ne: DEAD,
+            sw: DEAD,
+            se: DEAD,
+            population,
+        };
+        let mut life = HashLife {
+            rule,
+            nodes: vec![leaf(0), leaf(1)],
+            interned: HashMap::new(),
+            results: HashMap::new(),
+            empty: vec![DEAD],
+            root: DEAD,
+            generation: 0,
+        };
+        life.root = life.empty(MIN_ROOT_LEVEL);
+        Ok(life)
+    }
+
+    /// Creates an engine containing the 1 bits of `grid`, where the cell at `(col, row)` in the
+    /// grid is at the same coordinates in the engine.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ConwayError::InvalidData` if `rule` has birth on 0 neighbors or is a Generations
+    /// rule.
+    pub fn from_bit_grid(grid: &BitGrid, rule: Rule) -> ConwayResult<HashLife> {
+        let mut life = HashLife::new(rule)?;
+        grid.each_set(|col, row| life.set_cell(col as isize, row as isize, true));
+        Ok(life)
+    }
+
+    /// Returns a new `BitGrid` containing the live cells inside `region`. The cell at the top-left
+    /// corner of `region` is at `(0, 0)` in the grid.
+    pub fn to_bit_grid(&self, region: Region) -> BitGrid {
+        let width_in_words = (cmp::max(region.width(), 1) - 1) / 64 + 1;
+        let mut grid = BitGrid::new(width_in_words, region.height());
+        self.each_live_cell_in(region, |col, row| {
+            let col = (col - region.left()) as usize;
+            let row = (row - region.top()) as usize;
+            grid[row][col / 64] |= 1 << (63 - (col % 64));
+        });
+        grid
+    }
+
+    /// The rule that cells evolve according to.
+    pub fn rule(&self) -> Rule {
+        self.rule
+    }
+
+    /// Number of generations this engine has advanced since it was created.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Number of live cells.
+    pub fn population(&self) -> u64 {
+        self.nodes[self.root as usize].population
+    }
+
+    /// Returns true if the cell at (`col`, `row`) is alive.
+    pub fn get_cell(&self, col: isize, row: isize) -> bool {
+        let (root_left, root_top) = self.root_origin();
+        let size = self.side(self.root);
+        let (mut x, mut y) = (
+            col.wrapping_sub(root_left) as usize,
+            row.wrapping_sub(root_top) as usize,
+        );
+        if x >= size || y >= size {
+            return false;
+        }
+        let mut node = self.root;
+        while self.nodes[node as usize].level > 0 {
+            let half = self.side(node) / 2;
+            let n = self.nodes[node as usize];
+            node = match (x >= half, y >= half) {
+                (false, false) => n.nw,
+                (true, false) => n.ne,
+                (false, true) => n.sw,
+                (true, true) => n.se,
+            };
+            x %= half;
+            y %= half;
+        }
+        node == ALIVE
+    }
+
+    /// Sets the cell at (`col`, `row`) to be alive if `alive` is true, or dead otherwise.
+    pub fn set_cell(&mut self, col: isize, row: isize, alive: bool) {
+        loop {
+            let (root_left, root_top) = self.root_origin();
+            let size = self.side(self.root) as isize;
+            if col >= root_left && col < root_left + size && row >= root_top && row < root_top + size {
+                let root = self.root;
+                self.root = self.set_cell_in(root, (col - root_left) as usize, (row - root_top) as usize, alive);
+                return;
+            }
+            self.expand();
+        }
+    }
+
+    /// Calls `callback` with (`col`, `row`) for each live cell, in no particular order.
+    pub fn each_live_cell<F: FnMut(isize, isize)>(&self, mut callback: F) {
+        let (root_left, root_top) = self.root_origin();
+        self.each_live_cell_rec(self.root, root_left, root_top, None, &mut callback);
+    }
+
+    /// Calls `callback` with (`col`, `row`) for each live cell inside `region`, in no particular
+    /// order.
+    pub fn each_live_cell_in<F: FnMut(isize, isize)>(&self, region: Region, mut callback: F) {
+        if region.width() == 0 || region.height() == 0 {
+            return;
+        }
+        let (root_left, root_top) = self.root_origin();
+        self.each_live_cell_rec(self.root, root_left, root_top, Some(region), &mut callback);
+    }
+
+    /// Returns `Some(`smallest region containing every live cell`)`, or `None` if there are no
+    /// live cells.
+    pub fn bounding_box(&self) -> Option<Region> {
+        let mut bounds: Option<(isize, isize, isize, isize)> = None;
+        self.each_live_cell(|col, row| {
+            bounds = Some(match bounds {
+                None => (col, row, col, row),
+                Some((left, top, right, bottom)) => (
+                    cmp::min(left, col),
+                    cmp::min(top, row),
+                    cmp::max(right, col),
+                    cmp::max(bottom, row),
+                ),
+            });
+        });
+        bounds.map(|(left, top, right, bottom)| {
+            Region::new(left, top, (right - left + 1) as usize, (bottom - top + 1) as usize)
+        })
+    }
+
+    /// Advances by `generations` generations, in as few steps of 2^k generations as possible.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `generations` is 2^(`MAX_STEP_LOG2` + 1) or more.
+    pub fn step(&mut self, generations: u64) {
+        for log2 in 0..64u8 {
+            if (generations >> log2) & 1 == 1 {
+                self.step_pow2(log2);
+            }
+        }
+    }
+
+    /// Advances by 2^`log2_generations` generations in a single step.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `log2_generations` is greater than `MAX_STEP_LOG2`.
+    pub fn step_pow2(&mut self, log2_generations: u8) {
+        assert!(
+            log2_generations <= MAX_STEP_LOG2,
+            "cannot step more than 2^{} generations at a time",
+            MAX_STEP_LOG2
+        );
+        // The pattern can grow by at most one cell per generation in each direction, so make sure
+        // it's inside the center quarter of a root large enough to hold it afterwards.
+        loop {
+            let level = self.nodes[self.root as usize].level;
+            if level >= log2_generations + 3 {
+                let root = self.root;
+                let inner = self.center(root);
+                let inner = self.center(inner);
+                if self.nodes[inner as usize].population == self.population() {
+                    break;
+                }
+            }
+            self.expand();
+        }
+        let root = self.root;
+        self.root = self.step_node(root, log2_generations);
+        self.generation += 1u64 << log2_generations;
+        self.shrink();
+    }
+
+    /// Coordinates of the top-left cell of the root node, which is always centered on the origin.
+    fn root_origin(&self) -> (isize, isize) {
+        let half = (self.side(self.root) / 2) as isize;
+        (-half, -half)
+    }
+
+    /// Length of a side of `node`, in cells.
+    fn side(&self, node: NodeId) -> usize {
+        1 << self.nodes[node as usize].level
+    }
+
+    /// Returns the node with the given children, creating it if it doesn't exist yet.
+    fn join(&mut self, nw: NodeId, ne: NodeId, sw: NodeId, se: NodeId) -> NodeId {
+        let key = [nw, ne, sw, se];
+        if let Some(&id) = self.interned.get(&key) {
+            return id;
+        }
+        let population = key.iter().fold(0u64, |sum, &child| {
+            sum.saturating_add(self.nodes[child as usize].population)
+        });
+        let id = self.nodes.len() as NodeId;
+        self.nodes.push(Node {
+            level: self.nodes[nw as usize].level + 1,
+            nw,
+            ne,
+            sw,
+            se,
+            population,
+        });
+        self.interned.insert(key, id);
+        id
+    }
+
+    /// Returns the node of the given level with no live cells.
+    fn empty(&mut self, level: u8) -> NodeId {
+        while self.empty.len() <= level as usize {
+            let e = self.empty[self.empty.len() - 1];
+            let bigger = self.join(e, e, e, e);
+            self.empty.push(bigger);
+        }
+        self.empty[level as usize]
+    }
+
+    /// Doubles the size of the root, keeping the pattern centered.
+    fn expand(&mut self) {
+        let root = self.nodes[self.root as usize];
+        let e = self.empty(root.level - 1);
+        let nw = self.join(e, e, e, root.nw);
+        let ne = self.join(e, e, root.ne, e);
+        let sw = self.join(e, root.sw, e, e);
+        let se = self.join(root.se, e, e, e);
+        self.root = self.join(nw, ne, sw, se);
+    }
+
+    /// Halves the size of the root while no live cells would be lost.
+    fn shrink(&mut self) {
+        while self.nodes[self.root as usize].level > MIN_ROOT_LEVEL {
+            let root = self.root;
+            let inner = self.center(root);
+            if self.nodes[inner as usize].population != self.population() {
+                break;
+            }
+            self.root = inner;
+        }
+    }
+
+    fn set_cell_in(&mut self, node: NodeId, x: usize, y: usize, alive: bool) -> NodeId {
+        let n = self.nodes[node as usize];
+        if n.level == 0 {
+            return if alive { ALIVE } else { DEAD };
+        }
+        let half = 1 << (n.level - 1);
+        let (mut nw, mut ne, mut sw, mut se) = (n.nw, n.ne, n.sw, n.se);
+        match (x >= half, y >= half) {
+            (false, false) => nw = self.set_cell_in(nw, x, y, alive),
+            (true, false) => ne = self.set_cell_in(ne, x - half, y, alive),
+            (false, true) => sw = self.set_cell_in(sw, x, y - half, alive),
+            (true, true) => se = self.set_cell_in(se, x - half, y - half, alive),
+        }
+        self.join(nw, ne, sw, se)
+    }
+
+    fn each_live_cell_rec<F: FnMut(isize, isize)>(
+        &self,
+        node: NodeId,
+        left: isize,
+        top: isize,
+        region: Option<Region>,
+        callback: &mut F,
+    ) {
+        let n = self.nodes[node as usize];
+        if n.population == 0 {
+            return;
+        }
+        let size = self.side(node) as isize;
+        if let Some(region) = region {
+            if left > region.right()
+                || top > region.bottom()
+                || left + size <= region.left()
+                || top + size <= region.top()
+            {
+                return;
+            }
+        }
+        if n.level == 0 {
+            callback(left, top);
+            return;
+        }
+        let half = size / 2;
+        self.each_live_cell_rec(n.nw, left, top, region, callback);
+        self.each_live_cell_rec(n.ne, left + half, top, region, callback);
+        self.each_live_cell_rec(n.sw, left, top + half, region, callback);
+        self.each_live_cell_rec(n.se, left + half, top + half, region, callback);
+    }
+
+    /// The node one level lower, centered inside `node`.
+    fn center(&mut self, node: NodeId) -> NodeId {
+        let n = self.nodes[node as usize];
+        let (nw, ne, sw, se) = (
+            self.nodes[n.nw as usize],
+            self.nodes[n.ne as usize],
+            self.nodes[n.sw as usize],
+            self.nodes[n.se as usize],
+        );
+        self.join(nw.se, ne.sw, sw.ne, se.nw)
+    }
+
+    /// The node at the same level as `west` and `east`, centered between them.
+    fn horizontal_center(&mut self, west: NodeId, east: NodeId) -> NodeId {
+        let (w, e) = (self.nodes[west as usize], self.nodes[east as usize]);
+        self.join(w.ne, e.nw, w.se, e.sw)
+    }
+
+    /// The node at the same level as `north` and `south`, centered between them.
+    fn vertical_center(&mut self, north: NodeId, south: NodeId) -> NodeId {
+        let (n, s) = (self.nodes[north as usize], self.nodes[south as usize]);
+        self.join(n.sw, n.se, s.nw, s.ne)
+    }
+
+    /// Returns the center of `node` (one level lower) after 2^`log2_generations` generations.
+    /// The level of `node` must be at least `log2_generations + 2`.
+    fn step_node(&mut self, node: NodeId, log2_generations: u8) -> NodeId {
+        let n = self.nodes[node as usize];
+        debug_assert!(n.level >= log2_generations + 2);
+        if n.population == 0 {
+            return self.empty(n.level - 1);
+        }
+        if let Some(&result) = self.results.get(&(node, log2_generations)) {
+            return result;
+        }
+        let result = if n.level == 2 {
+            self.step_level_2(node)
+        } else {
+            // nine overlapping nodes one level lower, covering `node`
+            let n01 = self.horizontal_center(n.nw, n.ne);
+            let n10 = self.vertical_center(n.nw, n.sw);
+            let n11 = self.center(node);
+            let n12 = self.vertical_center(n.ne, n.se);
+            let n21 = self.horizontal_center(n.sw, n.se);
+            let nine = [n.nw, n01, n.ne, n10, n11, n12, n.sw, n21, n.se];
+
+            // At full speed, both halves of the step advance by half the generations. Otherwise,
+            // the first half is just a shift to the center, and the second does all the work.
+            let full_speed = log2_generations == n.level - 2;
+            let mut r = [DEAD; 9];
+            for (i, &sub) in nine.iter().enumerate() {
+                r[i] = if full_speed {
+                    self.step_node(sub, log2_generations - 1)
+                } else {
+                    self.center(sub)
+                };
+            }
+            let second_log2 = if full_speed {
+                log2_generations - 1
+            } else {
+                log2_generations
+            };
+            let quads = [
+                self.join(r[0], r[1], r[3], r[4]),
+                self.join(r[1], r[2], r[4], r[5]),
+                self.join(r[3], r[4], r[6], r[7]),
+                self.join(r[4], r[5], r[7], r[8]),
+            ];
+            let mut out = [DEAD; 4];
+            for (i, &quad) in quads.iter().enumerate() {
+                out[i] = self.step_node(quad, second_log2);
+            }
+            self.join(out[0], out[1], out[2], out[3])
+        };
+        self.results.insert((node, log2_generations), result);
+        result
+    }
+
+    /// Returns the center 2x2 cells of a 4x4 `node` after one generation.
+    fn step_level_2(&mut self, node: NodeId) -> NodeId {
+        let mut cells = [[false; 4]; 4];
+        let n = self.nodes[node as usize];
+        for (quad_idx, &quad) in [n.nw, n.ne, n.sw, n.se].iter().enumerate() {
+            let q = self.nodes[quad as usize];
+            for (cell_idx, &cell) in [q.nw, q.ne, q.sw, q.se].iter().enumerate() {
+                let row = (quad_idx / 2) * 2 + cell_idx / 2;
+                let col = (quad_idx % 2) * 2 + cell_idx % 2;
+                cells[row][col] = cell == ALIVE;
+            }
+        }
+        let mut next = [DEAD; 4];
+        for (i, (row, col)) in [(1, 1), (1, 2), (2, 1), (2, 2)].iter().cloned().enumerate() {
+            let mut neighbors = 0;
+            for neighbor_row in row - 1..=row + 1 {
+                for neighbor_col in col - 1..=col + 1 {
+                    if (neighbor_row, neighbor_col) != (row, col) && cells[neighbor_row][neighbor_col] {
+                        neighbors += 1;
+                    }
+                }
+            }
+            let alive = if cells[row][col] {
+                self.rule.survives(neighbors)
+            } else {
+                self.rule.is_born(neighbors)
+            };
+            next[i] = if alive { ALIVE } else { DEAD };
+        }
+        self.join(next[0], next[1], next[2], next[3])
+    }
+}
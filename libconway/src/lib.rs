@@ -21,14 +21,33 @@ extern crate env_logger;
 #[macro_use]
 extern crate custom_error;
 
+pub mod action;
+pub mod activity;
+pub mod analysis;
+pub mod budget;
+pub mod diffcodec;
 pub mod error;
+pub mod formats;
+pub mod game;
 pub mod grids;
+pub mod hashlife;
 pub mod rle;
+pub mod rule;
+pub mod snapshot;
 pub mod universe;
 
-pub use error::{ConwayError, ConwayResult};
+pub use action::{Action, ScheduledAction};
+pub use activity::ActivityMap;
+pub use analysis::{Analysis, PatternClass};
+pub use budget::Budget;
+pub use error::{ConwayError, ConwayResult, PlacementError};
 
-pub use grids::Rotation;
+pub use formats::{LifePattern, PatternFormat, MAX_PATTERN_SIZE};
+pub use game::{Game, GameMode, GameStatus};
+pub use grids::{Rotation, SetOperation, Transform};
+pub use hashlife::HashLife;
+pub use rule::Rule;
+pub use snapshot::SnapshotFormat;
 
 #[cfg(test)]
 pub mod tests;
@@ -27,7 +27,7 @@ fn main() {
         true,
         16,
         2,
-        vec![Region::new(40, 6, 16, 8), Region::new(60, 16, 8, 8)],
+        vec![Region::new(40, 6, 16, 8).into(), Region::new(60, 16, 8, 8).into()],
         16,
     )
     .unwrap();
@@ -18,10 +18,20 @@
 const MAX_NUMBER: usize = 50000;
 pub const NO_OP_CHAR: char = '"';
 
+/// Maximum length of a line of pattern data written to an RLE file.
+const MAX_LINE_LENGTH: usize = 70;
+
+/// Golly's letters for the states 1 to 24 of multi-state rules. Higher states are written as one
+/// of the prefixes `p` to `y` followed by one of these letters.
+const GOLLY_STATE_LETTERS: &str = "ABCDEFGHIJKLMNOPQRSTUVWX";
+
 use crate::error::{ConwayError, ConwayResult};
-use crate::grids::{BitGrid, CharGrid};
+use crate::grids::{BitGrid, CharGrid, Transform};
+use crate::rule::Rule;
+use crate::universe::CellState;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
+use std::fmt;
 use std::str::FromStr;
 
 /// This contains just the RLE pattern string. For example: "4bobo$7b3o!"
@@ -29,6 +39,33 @@ use std::str::FromStr;
 pub struct Pattern(pub String);
 
 /// Represents the contents of a RLE file.
+///
+/// Parsing is done with `FromStr`, and writing with `Display`, so that
+/// `PatternFile::from_str(&file.to_string())` gives back the same `PatternFile` as long as the
+/// pattern does not contain line breaks (which is the case for any `PatternFile` that was parsed
+/// or created by `PatternFile::from_grid`).
+///
+/// # Multi-state extension
+///
+/// Besides the standard `b` (dead) and `o` (alive) states, Conwayste uses the characters defined
+/// by `CellState::to_char` to describe whole arenas:
+///
+/// * `A` through `V`: alive cell belonging to player 0 through 21
+/// * `W`: wall
+/// * `?`: fog (a cell that is not visible to the player the pattern was written for)
+/// * lowercase letters other than `b` and `o`: dying cell of a Generations rule (see
+///   `CellState::Dying`)
+///
+/// Other Life programs will not understand patterns containing these characters.
+///
+/// # Generations rules
+///
+/// If the header line has a Generations rule, the pattern data in the file uses Golly's
+/// multi-state letters instead: `.` for dead cells, `A` for live cells, and `B` and up for the
+/// dying states (`pA` and so on after `X`). These are converted to and from the characters above
+/// when parsing and writing, so `pattern` always uses the characters above. Since the uppercase
+/// letters are taken, such files can't describe player-owned cells or walls; they are written as
+/// live and dead cells, respectively.
 #[derive(Debug, PartialEq, Clone)]
 pub struct PatternFile {
     pub comment_lines: Vec<String>,
@@ -63,6 +100,161 @@ impl PatternFile {
     pub fn to_grid<G: CharGrid>(&self, grid: &mut G, visibility: Option<usize>) -> ConwayResult<()> {
         self.pattern.to_grid(grid, visibility)
     }
+
+    /// The rule specified in the header line, or B3/S23 if there is none.
+    pub fn rule(&self) -> ConwayResult<Rule> {
+        self.header_line.parsed_rule()
+    }
+
+    /// Creates a `PatternFile` containing all of `grid` as seen by the player specified by
+    /// `visibility`, or a fog-less view if `visibility.is_none()`. There are no comment lines
+    /// and no rule in the header line.
+    pub fn from_grid<G: CharGrid>(grid: &G, visibility: Option<usize>) -> PatternFile {
+        let pattern = grid.to_pattern(visibility).0.replace("\r\n", "");
+        PatternFile {
+            comment_lines: vec![],
+            header_line:   HeaderLine {
+                x:    grid.width(),
+                y:    grid.height(),
+                rule: None,
+            },
+            pattern:       Pattern(pattern),
+        }
+    }
+
+    /// Sets the name of the pattern (the `#N` line).
+    pub fn with_name(self, name: &str) -> PatternFile {
+        self.with_single_comment_line('N', name)
+    }
+
+    /// Sets the author of the pattern (the `#O` line).
+    pub fn with_author(self, author: &str) -> PatternFile {
+        self.with_single_comment_line('O', author)
+    }
+
+    /// Adds a comment (one `#C` line per line of `comment`).
+    pub fn with_comment(mut self, comment: &str) -> PatternFile {
+        for line in comment.lines() {
+            self.comment_lines.push(format!("#C {}", line));
+        }
+        self
+    }
+
+    /// Sets the rule in the header line.
+    pub fn with_rule(mut self, rule: Rule) -> PatternFile {
+        self.header_line.rule = Some(rule.to_string());
+        self
+    }
+
+    /// The name of the pattern, from the `#N` line.
+    pub fn name(&self) -> Option<&str> {
+        self.comment_lines_of_type(&['N']).next()
+    }
+
+    /// The author of the pattern, from the `#O` line.
+    pub fn author(&self) -> Option<&str> {
+        self.comment_lines_of_type(&['O']).next()
+    }
+
+    /// The comments about the pattern, from the `#C` and `#c` lines.
+    pub fn comments(&self) -> Vec<&str> {
+        self.comment_lines_of_type(&['C', 'c']).collect()
+    }
+
+    /// Replaces any `#<line_type>` lines with a single one containing `text`, which is put on one
+    /// line if necessary.
+    fn with_single_comment_line(mut self, line_type: char, text: &str) -> PatternFile {
+        let prefix = format!("#{}", line_type);
+        self.comment_lines.retain(|line| !line.starts_with(&prefix));
+        let text = text.lines().collect::<Vec<_>>().join(" ");
+        // the #N line goes first, followed by the #O line
+        let idx = if line_type == 'N' {
+            0
+        } else {
+            self.comment_lines
+                .iter()
+                .take_while(|line| line.starts_with("#N"))
+                .count()
+        };
+        self.comment_lines.insert(idx, format!("{} {}", prefix, text));
+        self
+    }
+
+    /// The contents of the comment lines that start with `#` and one of `line_types`.
+    fn comment_lines_of_type<'a>(&'a self, line_types: &'a [char]) -> impl Iterator<Item = &'a str> + 'a {
+        self.comment_lines.iter().filter_map(move |line| {
+            let mut chars = line.chars();
+            if chars.next() == Some('#') && chars.next().map_or(false, |ch| line_types.contains(&ch)) {
+                Some(line[2..].trim())
+            } else {
+                None
+            }
+        })
+    }
+}
+
+impl fmt::Display for PatternFile {
+    /// Writes the contents of an RLE file: the comment lines, the header line, and the pattern,
+    /// wrapped so that no line is longer than 70 characters. Line breaks are not put in the middle
+    /// of a run.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for line in &self.comment_lines {
+            writeln!(f, "{}", line)?;
+        }
+        writeln!(f, "{}", self.header_line)?;
+
+        let is_generations = self.rule().map(|rule| rule.dying_states() > 0).unwrap_or(false);
+        let data = if is_generations {
+            to_golly_states(&self.pattern.0)
+        } else {
+            self.pattern.0.clone()
+        };
+        let mut line_len = 0;
+        let mut run = String::new();
+        for ch in data.chars() {
+            if ch == '\r' || ch == '\n' {
+                continue;
+            }
+            run.push(ch);
+            if ch.is_ascii_digit() || (is_generations && ('p'..='y').contains(&ch)) {
+                continue; // the run continues until the character after the number or state prefix
+            }
+            if line_len > 0 && line_len + run.len() > MAX_LINE_LENGTH {
+                writeln!(f)?;
+                line_len = 0;
+            }
+            write!(f, "{}", run)?;
+            line_len += run.len();
+            run.clear();
+        }
+        write!(f, "{}", run)?;
+        writeln!(f)
+    }
+}
+
+impl fmt::Display for HeaderLine {
+    /// Writes the header line, for example `x = 3, y = 3, rule = B3/S23`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "x = {}, y = {}", self.x, self.y)?;
+        if let Some(ref rule) = self.rule {
+            write!(f, ", rule = {}", rule)?;
+        }
+        Ok(())
+    }
+}
+
+impl HeaderLine {
+    /// Parses the `rule` field, returning B3/S23 if it is absent.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ConwayError::InvalidData` if the rule is not a valid Life-like rule.
+    pub fn parsed_rule(&self) -> ConwayResult<Rule> {
+        match self.rule {
+            Some(ref rule_str) => Rule::from_str(rule_str),
+            None => Ok(Rule::default()),
+        }
+    }
 }
 
 impl FromStr for PatternFile {
@@ -114,9 +306,15 @@ impl FromStr for PatternFile {
         for line in pattern_lines {
             pattern.push_str(line);
         }
+        let header_line = opt_header_line.unwrap();
+        if let Ok(rule) = header_line.parsed_rule() {
+            if rule.dying_states() > 0 {
+                pattern = from_golly_states(&pattern, rule)?;
+            }
+        }
         Ok(PatternFile {
             comment_lines,
-            header_line: opt_header_line.unwrap(),
+            header_line,
             pattern: Pattern(pattern),
         })
     }
@@ -153,6 +351,68 @@ impl FromStr for HeaderLine {
     }
 }
 
+/// Converts pattern data written with Golly's multi-state letters into the characters used by
+/// `Pattern` (see `PatternFile`). `b` and `o` are accepted for dead and live cells as well.
+fn from_golly_states(data: &str, rule: Rule) -> ConwayResult<String> {
+    let invalid = |reason: String| Err(ConwayError::InvalidData { reason });
+    let mut result = String::with_capacity(data.len());
+    let mut chars = data.chars();
+    while let Some(ch) = chars.next() {
+        let state = match ch {
+            '.' => 0,
+            'A'..='X' => GOLLY_STATE_LETTERS.find(ch).unwrap() + 1,
+            'p'..='y' => match chars.next() {
+                Some(letter @ 'A'..='X') => {
+                    (ch as usize - 'p' as usize + 1) * GOLLY_STATE_LETTERS.len()
+                        + GOLLY_STATE_LETTERS.find(letter).unwrap()
+                        + 1
+                }
+                _ => return invalid(format!("expected a state letter after prefix {:?}", ch)),
+            },
+            _ => {
+                result.push(ch);
+                continue;
+            }
+        };
+        if state >= rule.states() {
+            return invalid(format!("state {} is out of range for rule {}", state, rule));
+        }
+        result.push(match state {
+            0 => CellState::Dead.to_char(),
+            1 => CellState::Alive(None).to_char(),
+            _ => CellState::Dying(state as u8 - 1).to_char(),
+        });
+    }
+    Ok(result)
+}
+
+/// Converts pattern data written with the characters used by `Pattern` into Golly's multi-state
+/// letters. Player-owned cells become live cells, and walls become dead cells.
+fn to_golly_states(data: &str) -> String {
+    let mut result = String::with_capacity(data.len());
+    for ch in data.chars() {
+        let state = match CellState::from_char(ch) {
+            Some(CellState::Dead) | Some(CellState::Wall) => 0,
+            Some(CellState::Alive(_)) => 1,
+            Some(CellState::Dying(n)) => n as usize + 1,
+            Some(CellState::Fog) | None => {
+                result.push(ch);
+                continue;
+            }
+        };
+        if state == 0 {
+            result.push('.');
+            continue;
+        }
+        let letters = GOLLY_STATE_LETTERS.len();
+        if state > letters {
+            result.push((b'p' + ((state - 1) / letters - 1) as u8) as char);
+        }
+        result.push(GOLLY_STATE_LETTERS.chars().nth((state - 1) % letters).unwrap());
+    }
+    result
+}
+
 fn digits_to_number(digits: &Vec<char>) -> ConwayResult<usize> {
     use ConwayError::*;
     let mut result = 0;
@@ -314,3 +574,178 @@ impl Pattern {
         Ok(())
     }
 }
+
+impl Pattern {
+    /// Returns a new pattern with the transformation applied. The size of this pattern is
+    /// determined by `calc_size`, and the result has the transformed size, including any dead
+    /// cells at its right and bottom edges. Cells skipped with `NO_OP_CHAR` become dead cells.
+    pub fn transformed(&self, transform: Transform) -> ConwayResult<Pattern> {
+        let matrix = CharMatrix::from_pattern(self)?;
+        let (width, height) = (matrix.width(), matrix.height());
+        let (new_width, new_height) = transform.new_size(width, height);
+        Ok(matrix
+            .map_cells(new_width, new_height, |col, row| {
+                transform.apply(col, row, width, height)
+            })
+            .to_sized_pattern())
+    }
+
+    /// Returns a new pattern of the same size with every cell moved `dx` columns to the right and
+    /// `dy` rows down (left and up for negative values). Cells moved past an edge wrap around to
+    /// the opposite edge.
+    pub fn shifted(&self, dx: isize, dy: isize) -> ConwayResult<Pattern> {
+        let matrix = CharMatrix::from_pattern(self)?;
+        let (width, height) = (matrix.width(), matrix.height());
+        Ok(matrix
+            .map_cells(width, height, |col, row| {
+                (
+                    (col as isize + dx).rem_euclid(width as isize) as usize,
+                    (row as isize + dy).rem_euclid(height as isize) as usize,
+                )
+            })
+            .to_sized_pattern())
+    }
+
+    /// Returns a new pattern containing only the smallest rectangle that contains every cell that
+    /// is not dead. If all cells are dead, the result is an empty pattern (`!`).
+    pub fn cropped(&self) -> ConwayResult<Pattern> {
+        let matrix = CharMatrix::from_pattern(self)?;
+        let mut bounds: Option<(usize, usize, usize, usize)> = None;
+        for (row, chars) in matrix.rows.iter().enumerate() {
+            for (col, &ch) in chars.iter().enumerate() {
+                if ch != 'b' {
+                    bounds = Some(match bounds {
+                        None => (col, row, col, row),
+                        Some((left, top, right, bottom)) => {
+                            (left.min(col), top.min(row), right.max(col), bottom.max(row))
+                        }
+                    });
+                }
+            }
+        }
+        Ok(match bounds {
+            Some((left, top, right, bottom)) => matrix
+                .map_cells(right - left + 1, bottom - top + 1, |col, row| (col - left, row - top))
+                .to_sized_pattern(),
+            None => Pattern("!".to_owned()),
+        })
+    }
+
+    /// Returns a new pattern with the specified number of dead cells added on each side.
+    pub fn padded(&self, left: usize, top: usize, right: usize, bottom: usize) -> ConwayResult<Pattern> {
+        let matrix = CharMatrix::from_pattern(self)?;
+        let (width, height) = (matrix.width(), matrix.height());
+        Ok(matrix
+            .map_cells(left + width + right, top + height + bottom, |col, row| {
+                (col + left, row + top)
+            })
+            .to_sized_pattern())
+    }
+}
+
+/// A pattern decoded into one character per cell, so that it can be transformed.
+struct CharMatrix {
+    width: usize,
+    rows:  Vec<Vec<char>>,
+}
+
+impl CharMatrix {
+    /// Creates a matrix of dead cells.
+    fn new(width: usize, height: usize) -> CharMatrix {
+        CharMatrix {
+            width,
+            rows: vec![vec!['b'; width]; height],
+        }
+    }
+
+    /// Decodes `pattern` into a matrix of the size returned by `calc_size`.
+    fn from_pattern(pattern: &Pattern) -> ConwayResult<CharMatrix> {
+        let (width, height) = pattern.calc_size()?;
+        let mut matrix = CharMatrix::new(width, height);
+        pattern.to_grid(&mut matrix, None)?;
+        Ok(matrix)
+    }
+
+    /// Creates a `new_width` by `new_height` matrix, with each cell that is not dead moved to the
+    /// position returned by `new_pos`.
+    fn map_cells<F>(&self, new_width: usize, new_height: usize, new_pos: F) -> CharMatrix
+    where
+        F: Fn(usize, usize) -> (usize, usize),
+    {
+        let mut new = CharMatrix::new(new_width, new_height);
+        for (row, chars) in self.rows.iter().enumerate() {
+            for (col, &ch) in chars.iter().enumerate() {
+                if ch != 'b' {
+                    let (new_col, new_row) = new_pos(col, row);
+                    new.rows[new_row][new_col] = ch;
+                }
+            }
+        }
+        new
+    }
+
+    /// Encodes this matrix as a pattern. Unlike `CharGrid::to_pattern`, dead cells at the end of
+    /// the last row are kept, so that `calc_size` returns the size of this matrix.
+    fn to_sized_pattern(&self) -> Pattern {
+        fn push(result: &mut String, run_len: usize, ch: char) {
+            if run_len > 1 {
+                result.push_str(&run_len.to_string());
+            }
+            result.push(ch);
+        }
+
+        let mut result = String::new();
+        let mut line_ends_buffered = 0;
+        for row in 0..self.height() {
+            let mut runs = vec![];
+            let mut col = 0;
+            while col < self.width() {
+                let (run_len, ch) = self.get_run(col, row, None);
+                runs.push((run_len, ch));
+                col += run_len;
+            }
+            if row + 1 < self.height() && runs.last().map(|&(_, ch)| ch) == Some('b') {
+                runs.pop();
+            }
+            if runs.is_empty() {
+                line_ends_buffered += 1;
+                continue;
+            }
+            if line_ends_buffered > 0 {
+                push(&mut result, line_ends_buffered, '$');
+            }
+            for (run_len, ch) in runs {
+                push(&mut result, run_len, ch);
+            }
+            line_ends_buffered = 1;
+        }
+        result.push('!');
+        Pattern(result)
+    }
+}
+
+impl CharGrid for CharMatrix {
+    fn write_at_position(&mut self, col: usize, row: usize, ch: char, _visibility: Option<usize>) {
+        self.rows[row][col] = ch;
+    }
+
+    fn is_valid(ch: char) -> bool {
+        ch == NO_OP_CHAR || CellState::from_char(ch).is_some()
+    }
+
+    /// Width in cells
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Height in cells
+    fn height(&self) -> usize {
+        self.rows.len()
+    }
+
+    fn get_run(&self, col: usize, row: usize, _visibility: Option<usize>) -> (usize, char) {
+        let chars = &self.rows[row][col..];
+        let ch = chars[0];
+        (chars.iter().take_while(|&&c| c == ch).count(), ch)
+    }
+}
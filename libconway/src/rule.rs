@@ -0,0 +1,346 @@
+/*  Copyright 2020 the Conwayste Developers.
+ *
+ *  This file is part of libconway.
+ *
+ *  libconway is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  libconway is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with libconway.  If not, see <http://www.gnu.org/licenses/>. */
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::de::{self, Deserializer};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{ConwayError, ConwayResult};
+
+/// Maximum number of live neighbors a cell can have in the Moore neighborhood.
+const MAX_NEIGHBORS: u8 = 8;
+
+/// Maximum number of states of a Generations rule, limited by the characters available for dying
+/// cells (see `CellState::Dying`).
+pub const MAX_GENERATIONS_STATES: usize = 26;
+
+/// An outer-totalistic rule, such as B3/S23 (Conway's Game of Life), B36/S23 (HighLife) or
+/// B3678/S34678 (Day & Night).
+///
+/// Bit `n` of `birth` is set if a dead cell with `n` live neighbors comes to life, and bit `n` of
+/// `survival` is set if a live cell with `n` live neighbors stays alive.
+///
+/// Rules of the "Generations" family, such as B2/S/C3 (Brian's Brain) or B2/S345/C4 (Star Wars),
+/// have more than two states. A live cell that does not survive starts dying instead of becoming
+/// dead right away: it goes through `states - 2` dying states, one per generation, before it is
+/// dead. Dying cells don't count as live neighbors, and can't come to life.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub struct Rule {
+    birth:    u16,
+    survival: u16,
+    states:   u8, // 2 for Life-like rules
+}
+
+impl Rule {
+    /// Creates a rule from lists of neighbor counts for birth and survival.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ConwayError::InvalidData` if any neighbor count is greater than 8.
+    pub fn new(birth: &[u8], survival: &[u8]) -> ConwayResult<Rule> {
+        Ok(Rule {
+            birth:    Rule::counts_to_mask(birth)?,
+            survival: Rule::counts_to_mask(survival)?,
+            states:   2,
+        })
+    }
+
+    /// Creates a rule of the Generations family, in which cells have `states` states: dead, alive,
+    /// and `states - 2` dying states. With 2 states, this is the same as `Rule::new`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ConwayError::InvalidData` if any neighbor count is greater than 8, or if `states`
+    /// is less than 2 or greater than `MAX_GENERATIONS_STATES`.
+    pub fn generations(birth: &[u8], survival: &[u8], states: usize) -> ConwayResult<Rule> {
+        if states < 2 || states > MAX_GENERATIONS_STATES {
+            return Err(ConwayError::InvalidData {
+                reason: format!(
+                    "number of states {} is out of range in rule (expected 2 to {})",
+                    states, MAX_GENERATIONS_STATES
+                ),
+            });
+        }
+        let mut rule = Rule::new(birth, survival)?;
+        rule.states = states as u8;
+        Ok(rule)
+    }
+
+    /// Conway's Game of Life, B3/S23.
+    pub fn conway() -> Rule {
+        Rule {
+            birth:    1 << 3,
+            survival: (1 << 2) | (1 << 3),
+            states:   2,
+        }
+    }
+
+    /// Returns true if this is B3/S23.
+    #[inline]
+    pub fn is_conway(&self) -> bool {
+        *self == Rule::conway()
+    }
+
+    /// Returns true if a dead cell with `neighbors` live neighbors comes to life.
+    pub fn is_born(&self, neighbors: u8) -> bool {
+        neighbors <= MAX_NEIGHBORS && (self.birth >> neighbors) & 1 == 1
+    }
+
+    /// Returns true if a live cell with `neighbors` live neighbors stays alive.
+    pub fn survives(&self, neighbors: u8) -> bool {
+        neighbors <= MAX_NEIGHBORS && (self.survival >> neighbors) & 1 == 1
+    }
+
+    /// Number of states a cell can be in: 2 for Life-like rules, more for Generations rules.
+    pub fn states(&self) -> usize {
+        self.states as usize
+    }
+
+    /// Number of dying states a cell goes through after it stops surviving; 0 for Life-like
+    /// rules.
+    pub fn dying_states(&self) -> usize {
+        self.states as usize - 2
+    }
+
+    /// Neighbor counts that cause a birth, in ascending order.
+    pub fn birth_counts(&self) -> Vec<u8> {
+        (0..=MAX_NEIGHBORS).filter(|&n| self.is_born(n)).collect()
+    }
+
+    /// Neighbor counts that allow a live cell to survive, in ascending order.
+    pub fn survival_counts(&self) -> Vec<u8> {
+        (0..=MAX_NEIGHBORS).filter(|&n| self.survives(n)).collect()
+    }
+
+    fn counts_to_mask(counts: &[u8]) -> ConwayResult<u16> {
+        let mut mask = 0;
+        for &n in counts {
+            if n > MAX_NEIGHBORS {
+                return Err(ConwayError::InvalidData {
+                    reason: format!("neighbor count {} is out of range in rule", n),
+                });
+            }
+            mask |= 1 << n;
+        }
+        Ok(mask)
+    }
+
+    /// Bit-parallel version of this rule for 64 cells at a time. Same as
+    /// `Universe::next_single_gen` but works for any `Rule`, at the cost of some speed. Each
+    /// argument is a word of cells; `center` holds the cells being computed, and the rest are its
+    /// neighbors in the respective directions.
+    pub fn next_single_gen(
+        &self,
+        nw: u64,
+        n: u64,
+        ne: u64,
+        w: u64,
+        center: u64,
+        e: u64,
+        sw: u64,
+        s: u64,
+        se: u64,
+    ) -> u64 {
+        let neighbors = [
+            (nw << 63) | (n >> 1),
+            n,
+            (n << 1) | (ne >> 63),
+            (w << 63) | (center >> 1),
+            (center << 1) | (e >> 63),
+            (sw << 63) | (s >> 1),
+            s,
+            (s << 1) | (se >> 63),
+        ];
+
+        // Bit-sliced counter: count[i] holds bit i of each cell's neighbor count.
+        let mut count = [0u64; 4];
+        for &neighbor in neighbors.iter() {
+            let mut carry = neighbor;
+            for count_bit in count.iter_mut() {
+                let next_carry = *count_bit & carry;
+                *count_bit ^= carry;
+                carry = next_carry;
+            }
+        }
+
+        let mut born = 0;
+        let mut survive = 0;
+        for total in 0..=MAX_NEIGHBORS {
+            let in_birth = self.is_born(total);
+            let in_survival = self.survives(total);
+            if !in_birth && !in_survival {
+                continue;
+            }
+            let mut equals_total = u64::MAX;
+            for (i, count_bit) in count.iter().enumerate() {
+                if (total >> i) & 1 == 1 {
+                    equals_total &= *count_bit;
+                } else {
+                    equals_total &= !*count_bit;
+                }
+            }
+            if in_birth {
+                born |= equals_total;
+            }
+            if in_survival {
+                survive |= equals_total;
+            }
+        }
+        (!center & born) | (center & survive)
+    }
+}
+
+impl Default for Rule {
+    fn default() -> Self {
+        Rule::conway()
+    }
+}
+
+impl fmt::Display for Rule {
+    /// Formats the rule in B/S notation, for example `B36/S23`, with the number of states added
+    /// for Generations rules, for example `B2/S345/C4`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "B")?;
+        for n in self.birth_counts() {
+            write!(f, "{}", n)?;
+        }
+        write!(f, "/S")?;
+        for n in self.survival_counts() {
+            write!(f, "{}", n)?;
+        }
+        if self.states > 2 {
+            write!(f, "/C{}", self.states)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Rule {
+    type Err = ConwayError;
+
+    /// Parses a rule in any of the common notations for Life-like and Generations rules:
+    ///
+    /// * B/S notation, case-insensitive, with or without a slash: `B36/S23`, `b3s23`, `S23/B3`
+    /// * S/B notation, as used by older Life programs: `23/3`
+    /// * either of these with the number of states of a Generations rule: `B2/S345/C4` (or `G4`
+    ///   instead of `C4`), `345/2/4`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use ConwayError::*;
+        let rule_str: String = s.chars().filter(|ch| !ch.is_whitespace()).collect();
+        let rule_str = rule_str.to_uppercase();
+        let invalid = |detail: &str| InvalidData {
+            reason: format!("invalid rule {:?}: {}", s, detail),
+        };
+
+        let mut birth: Option<Vec<u8>> = None;
+        let mut survival: Option<Vec<u8>> = None;
+        let mut states: Option<String> = None;
+        if rule_str.contains('B') || rule_str.contains('S') {
+            // B/S notation; the sections may come in any order.
+            let mut current: Option<&mut Vec<u8>> = None;
+            let mut in_states = false;
+            for ch in rule_str.chars() {
+                if in_states && ch.is_ascii_digit() {
+                    states.as_mut().unwrap().push(ch);
+                    continue;
+                }
+                in_states = false;
+                match ch {
+                    'C' | 'G' => {
+                        if states.is_some() {
+                            return Err(invalid("more than one C section"));
+                        }
+                        states = Some(String::new());
+                        in_states = true;
+                        current = None;
+                    }
+                    'B' => {
+                        if birth.is_some() {
+                            return Err(invalid("more than one B section"));
+                        }
+                        current = Some(birth.get_or_insert_with(Vec::new));
+                    }
+                    'S' => {
+                        if survival.is_some() {
+                            return Err(invalid("more than one S section"));
+                        }
+                        current = Some(survival.get_or_insert_with(Vec::new));
+                    }
+                    '/' => current = None,
+                    '0'..='8' => {
+                        if let Some(counts) = current.as_mut() {
+                            counts.push(ch as u8 - b'0');
+                        } else {
+                            return Err(invalid("neighbor count outside of B or S section"));
+                        }
+                    }
+                    _ => return Err(invalid(&format!("unexpected character {:?}", ch))),
+                }
+            }
+        } else {
+            // S/B notation, possibly followed by the number of states
+            let mut parts: Vec<&str> = rule_str.split('/').collect();
+            if parts.len() == 3 {
+                states = parts.pop().map(|part| part.to_owned());
+            }
+            if parts.len() != 2 {
+                return Err(invalid("expected B/S or S/B notation"));
+            }
+            let mut halves = vec![];
+            for part in parts {
+                let mut counts = vec![];
+                for ch in part.chars() {
+                    match ch {
+                        '0'..='8' => counts.push(ch as u8 - b'0'),
+                        _ => return Err(invalid(&format!("unexpected character {:?}", ch))),
+                    }
+                }
+                halves.push(counts);
+            }
+            birth = halves.pop();
+            survival = halves.pop();
+        }
+
+        let states = match states {
+            Some(states) => states
+                .parse::<usize>()
+                .map_err(|_| invalid(&format!("invalid number of states {:?}", states)))?,
+            None => 2,
+        };
+        match (birth, survival) {
+            (Some(birth), Some(survival)) => Rule::generations(&birth, &survival, states),
+            _ => Err(invalid("both birth and survival sections are required")),
+        }
+    }
+}
+
+/// Rules are serialized in B/S notation.
+impl Serialize for Rule {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Rule {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let rule_str = String::deserialize(deserializer)?;
+        Rule::from_str(&rule_str).map_err(|e| de::Error::custom(e.to_string()))
+    }
+}
@@ -0,0 +1,126 @@
+/*  Copyright 2020 the Conwayste Developers.
+ *
+ *  This file is part of libconway.
+ *
+ *  libconway is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  libconway is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with libconway.  If not, see <http://www.gnu.org/licenses/>. */
+
+use serde::{Deserialize, Serialize};
+
+use crate::action::ScheduledAction;
+use crate::activity::ActivityTracker;
+use crate::budget::BudgetTracker;
+use crate::error::{ConwayError, ConwayResult};
+use crate::rule::Rule;
+use crate::universe::{GenState, Topology, WritableArea};
+
+/// Version of the snapshot format written by `Universe::save_snapshot`. Bump this whenever
+/// `UniverseSnapshot` or anything it contains changes in an incompatible way.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Binary snapshots start with these bytes, so that they can be told apart from JSON snapshots.
+const BINARY_MAGIC: &[u8] = b"CONWAYSS";
+
+/// Encodings of a `Universe` snapshot.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum SnapshotFormat {
+    /// Compact encoding, for example for crash recovery.
+    Binary,
+    /// JSON, with each `BitGrid` written as an RLE pattern so that it can be read (and edited) by
+    /// a person, for example in a bug report.
+    Json,
+}
+
+/// Everything needed to recreate a `Universe`. This is only meant to be built and taken apart by
+/// `Universe::save_snapshot` and `Universe::load_snapshot`.
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct UniverseSnapshot {
+    pub version:         u32, // must be first; see `SnapshotVersion`
+    pub width:           usize,
+    pub height:          usize,
+    pub generation:      usize,
+    pub num_players:     usize,
+    pub state_index:     usize,
+    pub gen_states:      Vec<GenState>,
+    pub player_writable: Vec<WritableArea>,
+    pub teams:           Vec<Option<usize>>,
+    pub vacant:          Vec<bool>,
+    pub fog_radius:      usize,
+    pub rule:            Rule,
+    pub topology:        Topology,
+    pub activity:        Option<ActivityTracker>,
+    pub scheduled:       Vec<ScheduledAction>,
+    pub applied:         Vec<ScheduledAction>,
+    pub budgets:         Vec<Vec<Option<BudgetTracker>>>,
+}
+
+/// Just the version of a `UniverseSnapshot`, so it can be checked before the rest is decoded.
+#[derive(Deserialize)]
+struct SnapshotVersion {
+    version: u32,
+}
+
+impl UniverseSnapshot {
+    /// Encodes this snapshot in the specified format.
+    pub fn encode(&self, format: SnapshotFormat) -> ConwayResult<Vec<u8>> {
+        match format {
+            SnapshotFormat::Binary => {
+                let mut data = BINARY_MAGIC.to_vec();
+                bincode::serialize_into(&mut data, self).map_err(|e| ConwayError::InvalidData {
+                    reason: format!("failed to encode binary snapshot: {}", e),
+                })?;
+                Ok(data)
+            }
+            SnapshotFormat::Json => serde_json::to_vec_pretty(self).map_err(|e| ConwayError::InvalidData {
+                reason: format!("failed to encode JSON snapshot: {}", e),
+            }),
+        }
+    }
+
+    /// Decodes a snapshot, detecting whether it is binary or JSON.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ConwayError::InvalidData` if `data` can't be decoded or was written by an
+    /// incompatible version of this library.
+    pub fn decode(data: &[u8]) -> ConwayResult<UniverseSnapshot> {
+        if data.starts_with(BINARY_MAGIC) {
+            let data = &data[BINARY_MAGIC.len()..];
+            let invalid = |e: bincode::Error| ConwayError::InvalidData {
+                reason: format!("failed to decode binary snapshot: {}", e),
+            };
+            let version: SnapshotVersion = bincode::deserialize(data).map_err(invalid)?;
+            check_version(version.version)?;
+            bincode::deserialize(data).map_err(invalid)
+        } else {
+            let invalid = |e: serde_json::Error| ConwayError::InvalidData {
+                reason: format!("failed to decode JSON snapshot: {}", e),
+            };
+            let version: SnapshotVersion = serde_json::from_slice(data).map_err(invalid)?;
+            check_version(version.version)?;
+            serde_json::from_slice(data).map_err(invalid)
+        }
+    }
+}
+
+fn check_version(version: u32) -> ConwayResult<()> {
+    if version != SNAPSHOT_VERSION {
+        return Err(ConwayError::InvalidData {
+            reason: format!(
+                "unsupported snapshot version {} (expected {})",
+                version, SNAPSHOT_VERSION
+            ),
+        });
+    }
+    Ok(())
+}
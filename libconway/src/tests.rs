@@ -16,11 +16,16 @@
  *  along with libconway.  If not, see <http://www.gnu.org/licenses/>. */
 
 mod universe_tests {
+    use crate::action::Action;
+    use crate::budget::Budget;
     use crate::error::ConwayError::*;
-    use crate::grids::CharGrid;
-    use crate::rle::Pattern;
+    use crate::error::{ConwayError, PlacementError};
+    use crate::grids::{BitGrid, BitOperation, CharGrid};
+    use crate::rle::{Pattern, PatternFile};
+    use crate::snapshot::SnapshotFormat;
     use crate::universe::test_helpers::*;
     use crate::universe::*;
+    use std::str::FromStr;
 
     #[test]
     fn new_universe_with_valid_dims() {
@@ -49,7 +54,7 @@ mod universe_tests {
         bigbang = bigbang.width(255);
 
         let uni_result1 = bigbang.birth();
-        assert!(uni_result1.is_err());
+        assert!(uni_result1.is_ok());
 
         bigbang = bigbang.width(256).height(0);
         let uni_result2 = bigbang.birth();
@@ -444,521 +449,2148 @@ mod universe_tests {
     }
 
     #[test]
-    fn universe_writable_returns_true_when_writable() {
-        let uni = generate_test_universe_with_default_params(UniType::Client);
-        let player0 = 0;
-        assert!(uni.writable(100, 70, player0).unwrap());
-    }
+    fn next_with_highlife_rule_births_on_six() {
+        let player = PlayerBuilder::new(Region::new(0, 0, 64, 64));
+        let mut life = BigBang::new().width(64).height(64).add_player(player).birth().unwrap();
+        let player = PlayerBuilder::new(Region::new(0, 0, 64, 64));
+        let mut highlife = BigBang::new()
+            .width(64)
+            .height(64)
+            .rule("B36/S23".parse().unwrap())
+            .add_player(player)
+            .birth()
+            .unwrap();
+        assert_eq!(highlife.rule().to_string(), "B36/S23");
 
-    #[test]
-    fn universe_writable_returns_false_when_not_writable() {
-        let uni = generate_test_universe_with_default_params(UniType::Client);
-        let player1 = 1;
-        assert!(!uni.writable(100, 70, player1).unwrap());
+        // (20, 20) has six live neighbors
+        for &(col, row) in &[(19, 19), (20, 19), (21, 19), (19, 21), (20, 21), (21, 21)] {
+            life.toggle(col, row, 0).unwrap();
+            highlife.toggle(col, row, 0).unwrap();
+        }
+        life.next();
+        highlife.next();
+        assert_eq!(life.get_cell_state(20, 20, None), CellState::Dead);
+        assert_eq!(highlife.get_cell_state(20, 20, None), CellState::Alive(None));
+        assert_eq!(highlife.get_cell_state(20, 20, Some(0)), CellState::Alive(Some(0)));
     }
-}
-
-mod genstate_tests {
-    use crate::grids::CharGrid;
-    use crate::rle::Pattern;
-    use crate::universe::test_helpers::*;
 
     #[test]
-    fn gen_state_get_run_simple() {
-        let mut genstate = make_gen_state();
+    fn next_with_non_conway_rule_respects_walls() {
+        let player = PlayerBuilder::new(Region::new(0, 0, 64, 64));
+        let mut uni = BigBang::new()
+            .width(64)
+            .height(64)
+            .rule("B2/S".parse().unwrap()) // Seeds
+            .add_player(player)
+            .birth()
+            .unwrap();
+        uni.toggle(10, 10, 0).unwrap();
+        uni.toggle(11, 10, 0).unwrap();
+        uni.set_unchecked(10, 9, CellState::Wall);
+        uni.next();
+        assert_eq!(uni.get_cell_state(10, 10, None), CellState::Dead);
+        assert_eq!(uni.get_cell_state(10, 9, None), CellState::Dead); // wall cells are never alive
+        assert_eq!(uni.get_cell_state(11, 9, None), CellState::Alive(None));
+        assert_eq!(uni.get_cell_state(10, 11, Some(0)), CellState::Alive(Some(0)));
+    }
 
-        Pattern("o!".to_owned()).to_grid(&mut genstate, None).unwrap();
-        assert_eq!(genstate.get_run(0, 0, None), (1, 'o'));
+    fn make_topology_universe(topology: Topology) -> Universe {
+        let player = PlayerBuilder::new(Region::new(0, 0, 64, 64));
+        test_big_bang(64, 64, vec![player])
+            .fog_radius(4)
+            .topology(topology)
+            .birth()
+            .unwrap()
     }
 
     #[test]
-    fn gen_state_get_run_wall() {
-        let mut genstate = make_gen_state();
-
-        Pattern("4W!".to_owned()).to_grid(&mut genstate, None).unwrap();
-        assert_eq!(genstate.get_run(0, 0, None), (4, 'W'));
+    fn next_on_plane_does_not_wrap() {
+        let mut torus = make_topology_universe(Topology::Torus);
+        let mut plane = make_topology_universe(Topology::Plane);
+        assert_eq!(torus.topology(), Topology::Torus);
+        assert_eq!(plane.topology(), Topology::Plane);
+        // vertical blinker on the left edge
+        for row in 10..13 {
+            torus.toggle(0, row, 0).unwrap();
+            plane.toggle(0, row, 0).unwrap();
+        }
+        torus.next();
+        plane.next();
+        assert_eq!(torus.get_cell_state(63, 11, None), CellState::Alive(None));
+        assert_eq!(plane.get_cell_state(63, 11, None), CellState::Dead);
+        assert_eq!(plane.get_cell_state(0, 11, Some(0)), CellState::Alive(Some(0)));
+        assert_eq!(plane.get_cell_state(1, 11, Some(0)), CellState::Alive(Some(0)));
+        assert_eq!(plane.get_cell_state(0, 10, None), CellState::Dead);
     }
 
     #[test]
-    fn gen_state_get_run_wall_blank_in_front() {
-        let mut genstate = make_gen_state();
-
-        Pattern("12b4W!".to_owned()).to_grid(&mut genstate, None).unwrap();
-        assert_eq!(genstate.get_run(12, 0, None), (4, 'W'));
+    fn next_on_cylinder_wraps_only_horizontally() {
+        let mut uni = make_topology_universe(Topology::Cylinder);
+        // horizontal blinker on the top edge
+        for col in 10..13 {
+            uni.toggle(col, 0, 0).unwrap();
+        }
+        // vertical blinker on the left edge
+        for row in 10..13 {
+            uni.toggle(0, row, 0).unwrap();
+        }
+        uni.next();
+        assert_eq!(uni.get_cell_state(11, 63, None), CellState::Dead);
+        assert_eq!(uni.get_cell_state(11, 0, None), CellState::Alive(None));
+        assert_eq!(uni.get_cell_state(11, 1, None), CellState::Alive(None));
+        assert_eq!(uni.get_cell_state(63, 11, Some(0)), CellState::Alive(Some(0)));
     }
 
     #[test]
-    fn gen_state_get_run_alternating_owned_unowned() {
-        let mut genstate = make_gen_state();
-
-        Pattern("15o3A2B9o!".to_owned()).to_grid(&mut genstate, None).unwrap();
-        assert_eq!(genstate.get_run(0, 0, None), (15, 'o'));
-        assert_eq!(genstate.get_run(15, 0, None), (3, 'A'));
-        assert_eq!(genstate.get_run(15 + 3, 0, None), (2, 'B'));
-        assert_eq!(genstate.get_run(15 + 3 + 2, 0, None), (9, 'o'));
+    fn next_on_klein_bottle_mirrors_across_top_edge() {
+        let mut uni = make_topology_universe(Topology::KleinBottle);
+        // horizontal blinker on the top edge
+        for col in 10..13 {
+            uni.toggle(col, 0, 0).unwrap();
+        }
+        uni.next();
+        assert_eq!(uni.get_cell_state(11, 63, None), CellState::Dead);
+        assert_eq!(uni.get_cell_state(63 - 11, 63, Some(0)), CellState::Alive(Some(0)));
+        assert_eq!(uni.get_cell_state(11, 1, None), CellState::Alive(None));
+        uni.next();
+        for col in 10..13 {
+            assert_eq!(uni.get_cell_state(col, 0, None), CellState::Alive(None));
+        }
     }
 
     #[test]
-    fn gen_state_get_run_player1_no_fog() {
-        let mut genstate = make_gen_state();
+    fn fog_clearing_respects_topology() {
+        fn fog_at(uni: &Universe, col: usize, row: usize) -> bool {
+            let mut is_fog = false;
+            uni.each_non_dead(
+                Region::new(col as isize, row as isize, 1, 1),
+                Some(0),
+                &mut |_, _, state| {
+                    is_fog = state == CellState::Fog;
+                },
+            );
+            is_fog
+        }
 
-        let write_pattern_as = Some(1); // avoid clearing fog for players other than player 1
-        Pattern("o!".to_owned())
-            .to_grid(&mut genstate, write_pattern_as)
-            .unwrap();
-        let visibility = Some(1); // as player 1
-        assert_eq!(genstate.get_run(0, 0, visibility), (1, 'o'));
+        for &(topology, expect_fog) in &[(Topology::Torus, false), (Topology::Plane, true)] {
+            let player0 = PlayerBuilder::new(Region::new(0, 0, 8, 8));
+            let player1 = PlayerBuilder::new(Region::new(32, 32, 8, 8));
+            let mut uni = BigBang::new()
+                .width(64)
+                .height(64)
+                .fog_radius(4)
+                .topology(topology)
+                .add_players(vec![player0, player1])
+                .birth()
+                .unwrap();
+            assert!(fog_at(&uni, 63, 2));
+            // vertical blinker near the left edge becomes horizontal, and (0, 2) is born
+            for row in 1..4 {
+                uni.toggle(1, row, 0).unwrap();
+            }
+            uni.next();
+            assert_eq!(uni.get_cell_state(0, 2, Some(0)), CellState::Alive(Some(0)));
+            assert_eq!(fog_at(&uni, 63, 2), expect_fog);
+            assert!(fog_at(&uni, 60, 2));
+        }
     }
 
     #[test]
-    fn gen_state_diff_simple1() {
-        let gs0 = make_gen_state();
-        let mut gs1 = make_gen_state();
-        Pattern("o!".to_owned()).to_grid(&mut gs1, None).unwrap();
-
-        let gsdiff = gs0.diff(&gs1, None);
-        assert_eq!(gsdiff.pattern.0.len(), 659);
-        let mut gsdiff_pattern_iter = gsdiff.pattern.0.split('$');
-        assert_eq!(gsdiff_pattern_iter.next().unwrap(), "o255\"");
-        assert_eq!(gsdiff_pattern_iter.next().unwrap(), "256\"");
-        assert_eq!(gsdiff_pattern_iter.next().unwrap(), "256\"");
-        // if you keep doing this, you'll eventually get a string containing \r\n
+    fn each_non_dead_with_region_across_edge() {
+        for (topology, expected) in vec![(Topology::Torus, vec![(63, 5)]), (Topology::Plane, vec![])] {
+            let mut uni = make_topology_universe(topology);
+            uni.toggle(63, 5, 0).unwrap();
+            let mut found = vec![];
+            uni.each_non_dead(Region::new(-2, 4, 4, 3), None, &mut |col, row, _| {
+                found.push((col, row))
+            });
+            assert_eq!(found, expected);
+        }
     }
 
     #[test]
-    fn gen_state_diff_and_restore_simple1() {
-        let gs0 = make_gen_state();
-        let mut gs1 = make_gen_state();
-        let visibility = None;
-        Pattern("o!".to_owned()).to_grid(&mut gs1, visibility).unwrap();
-
-        let gsdiff = gs0.diff(&gs1, visibility);
-
-        let mut new_gs = make_gen_state();
-
-        gsdiff.pattern.to_grid(&mut new_gs, visibility).unwrap();
-        assert_eq!(new_gs, gs1);
+    fn non_dead_cells_matches_each_non_dead() {
+        let mut uni = generate_test_universe_with_default_params(UniType::Server);
+        uni.toggle(16, 15, 1).unwrap();
+        uni.toggle(16, 16, 1).unwrap();
+        uni.toggle(16, 17, 1).unwrap();
+        for col in 60..70 {
+            uni.set_unchecked(col, 12, CellState::Wall);
+        }
+        for &(region, visibility) in &[
+            (uni.region(), None),
+            (uni.region(), Some(0)),
+            (uni.region(), Some(1)),
+            (Region::new(15, 16, 50, 1), Some(1)),
+            (Region::new(65, 0, 3, 80), None),
+            (Region::new(-10, -10, 30, 30), Some(1)), // across the edges of the torus
+        ] {
+            let mut expected = vec![];
+            uni.each_non_dead(region, visibility, &mut |col, row, state| {
+                expected.push((col, row, state))
+            });
+            let found: Vec<_> = uni.non_dead_cells(region, visibility).collect();
+            assert_eq!(found, expected, "region {:?}, visibility {:?}", region, visibility);
+        }
+        assert_eq!(
+            uni.non_dead_cells(Region::new(15, 16, 50, 1), Some(1))
+                .collect::<Vec<_>>(),
+            vec![(16, 16, CellState::Alive(Some(1)))]
+        );
     }
-}
-
-mod region_tests {
-    use crate::universe::*;
 
     #[test]
-    fn region_with_valid_dims() {
-        let region = Region::new(1, 10, 100, 200);
-
-        assert_eq!(region.left(), 1);
-        assert_eq!(region.top(), 10);
-        assert_eq!(region.height(), 200);
-        assert_eq!(region.width(), 100);
-        assert_eq!(region.right(), 100);
-        assert_eq!(region.bottom(), 209);
+    fn non_dead_cells_supports_early_exit() {
+        let mut uni = generate_test_universe_with_default_params(UniType::Server);
+        uni.toggle(16, 15, 1).unwrap();
+        uni.toggle(16, 16, 1).unwrap();
+        let mut iter = uni.non_dead_cells_full(None);
+        assert_eq!(iter.next(), Some((16, 15, CellState::Alive(Some(1)))));
+        assert_eq!(iter.next(), Some((16, 16, CellState::Alive(Some(1)))));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next(), None);
     }
 
     #[test]
-    fn region_with_valid_dims_negative_top_and_left() {
-        let region = Region::new(-1, -10, 100, 200);
+    fn copy_from_bit_grid_across_edge() {
+        let grid = Pattern("2o$2o!".to_owned()).to_new_bit_grid(2, 2).unwrap();
+        for (topology, mut expected) in vec![
+            (Topology::Torus, vec![(0, 0), (63, 0), (0, 63), (63, 63)]),
+            (Topology::Cylinder, vec![(0, 0), (63, 0)]),
+            (Topology::KleinBottle, vec![(0, 0), (63, 0), (0, 63), (63, 63)]),
+            (Topology::Plane, vec![(0, 0)]),
+        ] {
+            let mut uni = make_topology_universe(topology);
+            uni.copy_from_bit_grid(&grid, Region::new(-1, -1, 2, 2), Some(0));
+            let mut found = vec![];
+            uni.each_non_dead_full(Some(0), &mut |col, row, state| {
+                assert_eq!(state, CellState::Alive(Some(0)));
+                found.push((col, row));
+            });
+            found.sort();
+            expected.sort();
+            assert_eq!(found, expected, "topology {:?}", topology);
+        }
+    }
 
-        assert_eq!(region.left(), -1);
-        assert_eq!(region.top(), -10);
-        assert_eq!(region.height(), 200);
-        assert_eq!(region.width(), 100);
-        assert_eq!(region.right(), 98);
-        assert_eq!(region.bottom(), 189);
+    fn make_narrow_universe(topology: Topology, width: usize) -> Universe {
+        let player = PlayerBuilder::new(Region::new(0, 0, width, 80));
+        test_big_bang(width, 80, vec![player])
+            .fog_radius(4)
+            .topology(topology)
+            .birth()
+            .unwrap()
     }
 
-    #[test]
-    #[should_panic]
-    fn region_with_bad_dims_panics() {
-        Region::new(0, 0, 0, 0);
+    fn live_cells(uni: &Universe) -> Vec<(usize, usize)> {
+        let mut found = vec![];
+        uni.each_non_dead_full(None, &mut |col, row, _| found.push((col, row)));
+        found.sort();
+        found
     }
 
     #[test]
-    fn region_contains_a_valid_sub_region() {
-        let region1 = Region::new(1, 10, 100, 200);
-        let region2 = Region::new(-100, -200, 100, 200);
-
-        assert!(region1.contains(50, 50));
-        assert!(region2.contains(-50, -50));
+    fn non_dead_cells_ignores_unused_bits() {
+        let mut uni = make_narrow_universe(Topology::Torus, 100);
+        uni.toggle(99, 3, 0).unwrap();
+        assert_eq!(
+            uni.non_dead_cells_full(Some(0)).collect::<Vec<_>>(),
+            vec![(99, 3, CellState::Alive(Some(0)))]
+        );
     }
 
     #[test]
-    fn region_does_not_contain_sub_region() {
-        let region1 = Region::new(1, 10, 100, 200);
-        let region2 = Region::new(-100, -200, 100, 200);
-
-        assert!(!region1.contains(-50, -50));
-        assert!(!region2.contains(50, 50));
+    fn next_with_width_not_multiple_of_64_wraps_at_width() {
+        for &width in &[100, 37] {
+            let mut uni = make_narrow_universe(Topology::Torus, width);
+            // vertical blinker on the right edge
+            for row in 10..13 {
+                uni.toggle(width - 1, row, 0).unwrap();
+            }
+            uni.next();
+            assert_eq!(live_cells(&uni), vec![(0, 11), (width - 2, 11), (width - 1, 11)]);
+            uni.next();
+            assert_eq!(
+                live_cells(&uni),
+                vec![(width - 1, 10), (width - 1, 11), (width - 1, 12)]
+            );
+        }
     }
 
     #[test]
-    fn region_no_intersection() {
-        let region1 = Region::new(1, 10, 100, 200);
-        let region2 = Region::new(-100, -200, 100, 200);
-        assert_eq!(region1.intersection(region2), None);
-        assert_eq!(region2.intersection(region1), None);
+    fn next_with_width_not_multiple_of_64_on_plane() {
+        let mut uni = make_narrow_universe(Topology::Plane, 100);
+        // vertical blinker on the right edge loses its cell beyond the edge
+        for row in 10..13 {
+            uni.toggle(99, row, 0).unwrap();
+        }
+        uni.next();
+        assert_eq!(live_cells(&uni), vec![(98, 11), (99, 11)]);
     }
 
     #[test]
-    fn region_intersection_with_self() {
-        let region1 = Region::new(1, 10, 100, 200);
-        assert_eq!(region1.intersection(region1), Some(region1));
+    fn next_with_width_not_multiple_of_64_on_klein_bottle() {
+        let mut uni = make_narrow_universe(Topology::KleinBottle, 100);
+        // horizontal blinker on the top edge
+        for col in 10..13 {
+            uni.toggle(col, 0, 0).unwrap();
+        }
+        uni.next();
+        assert_eq!(live_cells(&uni), vec![(11, 0), (11, 1), (100 - 1 - 11, 79)]);
     }
 
     #[test]
-    fn region_intersection_overlap() {
-        let region1 = Region::new(1, 10, 100, 200);
-        let region2 = Region::new(90, 120, 100, 200);
-        assert_eq!(region1.intersection(region2), Some(Region::new(90, 120, 11, 90)));
+    fn fog_clearing_with_width_not_multiple_of_64() {
+        let player0 = PlayerBuilder::new(Region::new(90, 0, 10, 8));
+        let player1 = PlayerBuilder::new(Region::new(40, 40, 8, 8));
+        let mut uni = BigBang::new()
+            .width(100)
+            .height(80)
+            .fog_radius(4)
+            .add_players(vec![player0, player1])
+            .birth()
+            .unwrap();
+        // vertical blinker on the right edge becomes horizontal, and (0, 2) is born
+        for row in 1..4 {
+            uni.toggle(99, row, 0).unwrap();
+        }
+        uni.next();
+        assert_eq!(uni.get_cell_state(0, 2, Some(0)), CellState::Alive(Some(0)));
+        let mut fog_cols = vec![];
+        uni.each_non_dead(Region::new(0, 2, 100, 1), Some(0), &mut |col, _, state| {
+            if state == CellState::Fog {
+                fog_cols.push(col);
+            }
+        });
+        assert!(!fog_cols.contains(&0));
+        assert!(!fog_cols.contains(&2));
+        assert!(fog_cols.contains(&50));
+        assert_eq!(fog_cols.len(), 100 - 10 - 4); // writable region, plus fog cleared around (0, 2)
     }
 
     #[test]
-    fn region_no_intersection_overlap_one_dim() {
-        let region1 = Region::new(0, 0, 2, 2);
-        let region2 = Region::new(3, 0, 2, 2);
-        assert_eq!(region1.intersection(region2), None);
+    fn to_pattern_with_width_not_multiple_of_64() {
+        let mut uni = make_narrow_universe(Topology::Torus, 70);
+        uni.toggle(69, 0, 0).unwrap();
+        assert_eq!(uni.to_pattern(None).0, "69bA!".to_owned());
+        assert_eq!(format!("{}", uni).lines().next().unwrap().len(), 70);
+
+        let client = BigBang::new()
+            .width(70)
+            .height(2)
+            .server_mode(false)
+            .add_player(PlayerBuilder::new(Region::new(0, 0, 70, 2)))
+            .birth()
+            .unwrap();
+        let mut fog_count = 0;
+        client.each_non_dead_full(Some(0), &mut |_, _, state| {
+            assert_eq!(state, CellState::Fog);
+            fog_count += 1;
+        });
+        assert_eq!(fog_count, 70 * 2);
     }
-}
-
-mod cellstate_tests {
-    use crate::universe::*;
 
     #[test]
-    fn cell_states_as_char() {
-        let dead = CellState::Dead;
-        let alive = CellState::Alive(None);
-        let player1 = CellState::Alive(Some(1));
-        let player2 = CellState::Alive(Some(2));
-        let wall = CellState::Wall;
-        let fog = CellState::Fog;
+    fn stats_counts_cells_per_player() {
+        let mut uni = generate_test_universe_with_default_params(UniType::Server);
+        // vertical blinker as player 1
+        for row in 15..18 {
+            uni.toggle(16, row, 1).unwrap();
+        }
+        // block as player 0, with the left half outside its writable region
+        for &(col, row) in &[(99, 70), (100, 70), (99, 71), (100, 71)] {
+            uni.set_unchecked(col, row, CellState::Alive(Some(0)));
+        }
+        uni.set_unchecked(200, 100, CellState::Alive(None));
+        uni.set_unchecked(150, 5, CellState::Wall);
 
-        assert_eq!(dead.to_char(), 'b');
-        assert_eq!(alive.to_char(), 'o');
-        assert_eq!(player1.to_char(), 'B');
-        assert_eq!(player2.to_char(), 'C');
-        assert_eq!(wall.to_char(), 'W');
-        assert_eq!(fog.to_char(), '?');
+        let stats = uni.stats(None);
+        assert_eq!(stats.generation, 1);
+        assert_eq!(
+            stats.players,
+            vec![
+                CellStats {
+                    live:             4,
+                    inside_writable:  2,
+                    outside_writable: 2,
+                    births:           0,
+                    deaths:           0,
+                    bounding_box:     Some(Region::new(99, 70, 2, 2)),
+                },
+                CellStats {
+                    live:             3,
+                    inside_writable:  3,
+                    outside_writable: 0,
+                    births:           0,
+                    deaths:           0,
+                    bounding_box:     Some(Region::new(16, 15, 1, 3)),
+                },
+            ]
+        );
+        assert_eq!(stats.unowned.live, 1);
+        assert_eq!(stats.unowned.outside_writable, 1);
+        assert_eq!(stats.walls, 1);
+
+        // player 1's blinker and the cells far from player 0's are in player 0's fog
+        let stats = uni.stats(Some(0));
+        assert_eq!(stats.players[0].live, 4);
+        assert_eq!(stats.players[1].live, 0);
+        assert_eq!(stats.unowned.live, 0);
+        assert_eq!(stats.walls, 0);
+
+        uni.next();
+        let stats = uni.stats(None);
+        assert_eq!(stats.generation, 2);
+        assert_eq!(stats.players[0].live, 4);
+        assert_eq!((stats.players[0].births, stats.players[0].deaths), (0, 0));
+        assert_eq!(stats.players[1].live, 3);
+        assert_eq!((stats.players[1].births, stats.players[1].deaths), (2, 2));
+        assert_eq!(stats.players[1].bounding_box, Some(Region::new(15, 16, 3, 1)));
+        assert_eq!(stats.unowned.live, 0);
+        assert_eq!(stats.unowned.deaths, 1);
+        assert_eq!(stats.unowned.bounding_box, None);
     }
-}
 
-mod grid_tests {
-    use crate::grids::*;
-    use crate::rle::Pattern;
-    use crate::universe::Region;
+    fn is_fog(uni: &Universe, col: usize, row: usize, player_id: usize) -> bool {
+        let mut fog = false;
+        uni.each_non_dead(
+            Region::new(col as isize, row as isize, 1, 1),
+            Some(player_id),
+            &mut |_, _, state| {
+                fog = state == CellState::Fog;
+            },
+        );
+        fog
+    }
 
     #[test]
-    fn height_works() {
-        let grid = BitGrid::new(2, 5);
-        assert_eq!(grid.height(), 5);
+    fn writable_area_of_several_regions() {
+        // L-shaped base
+        let player0 = PlayerBuilder::new(Region::new(0, 0, 10, 30)).add_region(Region::new(10, 20, 20, 10));
+        let player1 = PlayerBuilder::new(Region::new(40, 0, 24, 64));
+        let mut uni = BigBang::new()
+            .width(64)
+            .height(64)
+            .add_players(vec![player0, player1])
+            .birth()
+            .unwrap();
+        assert!(uni.writable(5, 5, 0).unwrap());
+        assert!(uni.writable(25, 25, 0).unwrap());
+        assert!(!uni.writable(25, 5, 0).unwrap());
+        assert!(uni.toggle(25, 5, 0).is_err());
+        uni.toggle(25, 25, 0).unwrap();
+        assert_eq!(uni.get_cell_state(25, 25, Some(0)), CellState::Alive(Some(0)));
+        // fog is cleared on both parts of the L only
+        assert!(!is_fog(&uni, 5, 5, 0));
+        assert!(!is_fog(&uni, 15, 25, 0));
+        assert!(is_fog(&uni, 25, 5, 0));
+
+        // copying as a player only writes into the player's area
+        let mut src = BitGrid::new(1, 30);
+        src.modify_region(Region::new(0, 0, 30, 30), BitOperation::Set);
+        uni.copy_from_bit_grid(&src, Region::new(0, 0, 30, 30), Some(0));
+        let stats = uni.stats(None);
+        assert_eq!(stats.players[0].live, 10 * 30 + 20 * 10);
+        assert_eq!(stats.players[0].outside_writable, 0);
     }
 
     #[test]
-    fn width_works() {
-        let grid = BitGrid::new(2, 5);
-        assert_eq!(grid.width(), 2 * 64);
+    fn writable_area_from_mask() {
+        // two islands in the corners
+        let mut mask = BitGrid::new(1, 64);
+        mask.modify_region(Region::new(0, 0, 8, 8), BitOperation::Set);
+        mask.modify_region(Region::new(56, 56, 8, 8), BitOperation::Set);
+        let player0 = PlayerBuilder::new(mask.clone());
+        let player1 = PlayerBuilder::new(Region::new(20, 20, 20, 20));
+        let mut uni = BigBang::new()
+            .width(64)
+            .height(64)
+            .add_players(vec![player0, player1])
+            .birth()
+            .unwrap();
+        assert!(uni.writable(7, 7, 0).unwrap());
+        assert!(uni.writable(60, 60, 0).unwrap());
+        assert!(!uni.writable(8, 8, 0).unwrap());
+        uni.toggle(60, 60, 0).unwrap();
+        assert!(uni.toggle(30, 30, 0).is_err());
+        assert!(!is_fog(&uni, 2, 2, 0));
+        assert!(is_fog(&uni, 30, 2, 0));
+
+        // a mask must match the universe's dimensions
+        let result = BigBang::new()
+            .width(128)
+            .height(64)
+            .add_player(PlayerBuilder::new(mask))
+            .birth();
+        match result {
+            Err(InvalidData { .. }) => {}
+            Err(e) => panic!("unexpected error {}", e),
+            Ok(_) => panic!("expected an error"),
+        }
     }
 
     #[test]
-    fn create_valid_empty_bitgrid() {
-        let height = 11;
-        let width_in_words = 10;
-        let grid = BitGrid::new(width_in_words, height);
+    fn writable_area_is_bounded_by_universe_width() {
+        // a mask with bits set past the width of the universe is rejected
+        let mut mask = BitGrid::new(2, 10);
+        mask.modify_region(mask.region(), BitOperation::Set);
+        match test_big_bang(100, 10, vec![PlayerBuilder::new(mask.clone())]).birth() {
+            Err(InvalidData { .. }) => {}
+            Err(e) => panic!("unexpected error {}", e),
+            Ok(_) => panic!("expected an error"),
+        }
 
-        assert_eq!(grid[0][0], 0);
-        assert_eq!(grid[height - 1][width_in_words - 1], 0);
+        mask.modify_region(Region::new(100, 0, 28, 10), BitOperation::Clear);
+        let mut uni = test_big_bang(100, 10, vec![PlayerBuilder::new(mask)]).birth().unwrap();
+        assert!(uni.writable(99, 0, 0).unwrap());
+        assert!(!uni.writable(120, 0, 0).unwrap());
+        uni.set(120, 0, CellState::Alive(Some(0)), 0);
+        assert_eq!(uni.to_pattern(None).0, "!".to_owned());
 
-        for x in 0..height {
-            for y in 0..width_in_words {
-                assert_eq!(grid[x][y], 0);
-            }
-        }
+        // likewise for a region reaching past the edge of the universe
+        let uni = test_big_bang(100, 10, vec![PlayerBuilder::new(Region::new(0, 0, 200, 10))])
+            .birth()
+            .unwrap();
+        assert!(uni.writable(99, 9, 0).unwrap());
+        assert!(!uni.writable(120, 0, 0).unwrap());
     }
 
     #[test]
-    #[should_panic]
-    fn create_bitgrid_with_invalid_dims() {
-        let height = 0;
-        let width_in_words = 0;
-        let _ = BitGrid::new(width_in_words, height);
+    fn add_player_to_running_universe() {
+        let mut uni = generate_test_universe_with_default_params(UniType::Server);
+        uni.toggle(16, 15, 1).unwrap();
+        for _ in 0..3 {
+            uni.next();
+        }
+        let player_id = uni.add_player(PlayerBuilder::new(Region::new(150, 0, 50, 50))).unwrap();
+        assert_eq!(player_id, 2);
+        assert_eq!(uni.num_players(), 3);
+        for col in 160..163 {
+            uni.toggle(col, 10, player_id).unwrap();
+        }
+        assert!(uni.toggle(10, 10, player_id).is_err());
+        assert!(is_fog(&uni, 10, 10, player_id));
+
+        // the history stays usable
+        let latest = uni.latest_gen();
+        assert!(uni.diff(latest - 2, latest, Some(player_id)).is_some());
+        uni.next();
+        assert_eq!(uni.stats(None).players[player_id].live, 3);
+        assert_eq!(
+            uni.get_cell_state(161, 9, Some(player_id)),
+            CellState::Alive(Some(player_id))
+        );
     }
 
     #[test]
-    fn set_cell_bits_within_a_bitgrid() {
-        let height = 10;
-        let width_in_words = 10;
-        let mut grid = BitGrid::new(width_in_words, height);
-
-        for x in 0..height {
-            for y in 0..width_in_words {
-                assert_eq!(grid[x][y], 0);
+    fn remove_player_with_each_policy() {
+        for &policy in &[LeavePolicy::Unowned, LeavePolicy::Die, LeavePolicy::Wall] {
+            let mut uni = generate_test_universe_with_default_params(UniType::Server);
+            for &(col, row) in &[(100, 70), (101, 70), (100, 71), (101, 71)] {
+                uni.toggle(col, row, 0).unwrap();
             }
-        }
+            uni.toggle(10, 10, 1).unwrap();
+            uni.next();
 
-        grid.modify_bits_in_word(height / 2, width_in_words / 2, 1 << 63, BitOperation::Set);
-        assert_eq!(grid[height / 2][width_in_words / 2] >> 63, 1);
+            uni.remove_player(0, policy).unwrap();
+            assert_eq!(uni.num_players(), 2);
+            assert!(!uni.has_player(0));
+            assert!(uni.has_player(1));
+            // player 1 keeps its player_id
+            assert!(uni.writable(10, 10, 1).unwrap());
+            assert!(uni.toggle(100, 70, 0).is_err());
+
+            let mut states = vec![];
+            uni.each_non_dead(Region::new(100, 70, 1, 1), None, &mut |_, _, state| states.push(state));
+            let expected = match policy {
+                LeavePolicy::Unowned => vec![CellState::Alive(None)],
+                LeavePolicy::Die => vec![],
+                LeavePolicy::Wall => vec![CellState::Wall],
+            };
+            assert_eq!(states, expected);
+
+            // the history was updated too
+            let latest = uni.latest_gen();
+            assert!(uni.diff(latest - 1, latest, None).is_some());
+            uni.next();
+        }
 
-        grid.modify_bits_in_word(height - 1, width_in_words - 1, 1 << 63, BitOperation::Set);
-        assert_eq!(grid[height - 1][width_in_words - 1] >> 63, 1);
+        let mut uni = generate_test_universe_with_default_params(UniType::Server);
+        match uni.remove_player(2, LeavePolicy::Die) {
+            Err(InvalidData { .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        uni.remove_player(1, LeavePolicy::Die).unwrap();
+        match uni.remove_player(1, LeavePolicy::Die) {
+            Err(InvalidData { .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
     }
 
     #[test]
-    fn clear_cell_bits_within_a_bitgrid() {
-        let height = 10;
-        let width_in_words = 10;
-        let mut grid = BitGrid::new(width_in_words, height);
+    fn add_player_reuses_vacant_player_id() {
+        let mut uni = generate_test_universe_with_default_params(UniType::Server);
+        uni.toggle(100, 70, 0).unwrap();
+        uni.toggle(10, 10, 1).unwrap();
+        uni.remove_player(0, LeavePolicy::Unowned).unwrap();
+        let player_id = uni.add_player(PlayerBuilder::new(Region::new(150, 0, 50, 50))).unwrap();
+        assert_eq!(player_id, 0);
+        assert_eq!(uni.num_players(), 2);
+        assert!(uni.has_player(0));
+        // the new player has none of the cells of the player who left, and a new writable area
+        assert_eq!(uni.get_cell_state(100, 70, None), CellState::Alive(None));
+        assert!(is_fog(&uni, 100, 70, 0));
+        assert!(uni.toggle(100, 71, 0).is_err());
+        uni.toggle(160, 10, 0).unwrap();
+        assert_eq!(uni.get_cell_state(10, 10, Some(1)), CellState::Alive(Some(1)));
 
-        for x in 0..height {
-            for y in 0..width_in_words {
-                grid[x][y] = u64::max_value();
-            }
-        }
-
-        grid.modify_bits_in_word(height / 2, width_in_words / 2, 1 << 63, BitOperation::Clear);
-        assert_eq!(grid[height / 2][width_in_words / 2] >> 63, 0);
-
-        grid.modify_bits_in_word(height - 1, width_in_words - 1, 1 << 63, BitOperation::Clear);
-        assert_eq!(grid[height - 1][width_in_words - 1] >> 63, 0);
+        assert_eq!(
+            uni.add_player(PlayerBuilder::new(Region::new(150, 50, 50, 50)))
+                .unwrap(),
+            2
+        );
     }
 
     #[test]
-    fn toggle_cell_bits_within_a_bitgrid() {
-        let height = 10;
-        let width_in_words = 10;
-        let mut grid = BitGrid::new(width_in_words, height);
+    fn players_are_limited_to_max_players() {
+        let players = |count: usize| -> Vec<PlayerBuilder> {
+            (0..count)
+                .map(|player_id| PlayerBuilder::new(Region::new(player_id as isize * 4, 0, 4, 4)))
+                .collect()
+        };
+        let birth = |count: usize| BigBang::new().width(128).height(16).add_players(players(count)).birth();
+        assert!(birth(MAX_PLAYERS + 1).is_err());
 
-        for x in 0..height {
-            for y in 0..width_in_words {
-                grid[x][y] = u64::max_value();
-            }
+        let mut uni = birth(MAX_PLAYERS - 1).unwrap();
+        assert_eq!(
+            uni.add_player(PlayerBuilder::new(Region::new(0, 8, 4, 4))).unwrap(),
+            MAX_PLAYERS - 1
+        );
+        match uni.add_player(PlayerBuilder::new(Region::new(4, 8, 4, 4))) {
+            Err(InvalidData { .. }) => {}
+            other => panic!("unexpected result {:?}", other),
         }
+        uni.toggle(1, 9, MAX_PLAYERS - 1).unwrap();
+        assert!(uni.to_pattern(None).0.contains('V'));
 
-        grid.modify_bits_in_word(height / 2, width_in_words / 2, 1 << 63, BitOperation::Toggle);
-        assert_eq!(grid[height / 2][width_in_words / 2] >> 63, 0);
+        // a vacant player_id can be given to a new player
+        uni.remove_player(3, LeavePolicy::Die).unwrap();
+        assert_eq!(uni.add_player(PlayerBuilder::new(Region::new(4, 8, 4, 4))).unwrap(), 3);
+    }
 
-        grid.modify_bits_in_word(height / 2, width_in_words / 2, 1 << 63, BitOperation::Toggle);
-        assert_eq!(grid[height / 2][width_in_words / 2] >> 63, 1);
+    fn make_team_universe(team_play: bool) -> Universe {
+        let mut player0 = PlayerBuilder::new(Region::new(0, 0, 32, 32));
+        let mut player1 = PlayerBuilder::new(Region::new(64, 0, 32, 32));
+        if team_play {
+            player0 = player0.team(7);
+            player1 = player1.team(7);
+        }
+        let player2 = PlayerBuilder::new(Region::new(0, 32, 128, 32));
+        test_big_bang(128, 64, vec![player0, player1, player2]).birth().unwrap()
     }
 
     #[test]
-    fn fill_region_within_a_bit_grid() {
-        let height = 10;
-        let width_in_words = 10;
-
-        let region1_w = 7;
-        let region1_h = 7;
-        let region2_w = 3;
-        let region2_h = 3;
-        let region3_h = 4;
-        let region3_w = 4;
-
-        let mut grid = BitGrid::new(width_in_words, height);
-        let region1 = Region::new(0, 0, region1_w, region1_h);
-        let region2 = Region::new(0, 0, region2_w, region2_h);
-        let region3 = Region::new(region2_w as isize, region2_h as isize, region3_w, region3_h);
-
-        grid.modify_region(region1, BitOperation::Set);
-
-        for y in 0..region1_w {
-            assert_eq!(grid[y][0], 0xFE00000000000000);
+    fn teammates_share_fog() {
+        let mut uni = make_team_universe(true);
+        assert_eq!(uni.team(0), Some(7));
+        assert_eq!(uni.team(2), None);
+        assert_eq!(uni.teammates(1), vec![0, 1]);
+        assert_eq!(uni.teammates(2), vec![2]);
+
+        // each teammate sees the other's writable region, but not the opponent's
+        assert!(!is_fog(&uni, 70, 10, 0));
+        assert!(!is_fog(&uni, 10, 10, 1));
+        assert!(is_fog(&uni, 10, 40, 0));
+        assert!(is_fog(&uni, 10, 10, 2));
+
+        // a blinker in the opponent's region clears the fog around it for both teammates
+        for row in 39..42 {
+            uni.set_unchecked(48, row, CellState::Alive(Some(1)));
         }
+        assert!(!is_fog(&uni, 48, 40, 0));
+        uni.next();
+        assert!(!is_fog(&uni, 46, 40, 0));
+        assert!(!is_fog(&uni, 46, 40, 1));
+        assert!(!is_fog(&uni, 46, 40, 2)); // in player 2's own writable region
+
+        // teams are kept in snapshots
+        let data = uni.save_snapshot(SnapshotFormat::Json).unwrap();
+        let loaded = Universe::load_snapshot(&data).unwrap();
+        assert_eq!(loaded.teammates(0), vec![0, 1]);
+    }
 
-        grid.modify_region(region2, BitOperation::Clear);
-        for y in 0..region2_w {
-            assert_eq!(grid[y][0], 0x1E00000000000000);
+    #[test]
+    fn teammates_do_not_contest_cells() {
+        for &team_play in &[false, true] {
+            let mut uni = make_team_universe(team_play);
+            // horizontal blinker made of cells of players 0 and 1
+            uni.set_unchecked(10, 10, CellState::Alive(Some(0)));
+            uni.set_unchecked(11, 10, CellState::Alive(Some(1)));
+            uni.set_unchecked(12, 10, CellState::Alive(Some(1)));
+            uni.next();
+            if team_play {
+                // the surviving cell keeps its owner, and the new cells go to the lowest player_id
+                assert_eq!(uni.get_cell_state(11, 10, Some(1)), CellState::Alive(Some(1)));
+                assert_eq!(uni.get_cell_state(11, 9, Some(0)), CellState::Alive(Some(0)));
+                assert_eq!(uni.get_cell_state(11, 11, Some(0)), CellState::Alive(Some(0)));
+            } else {
+                for row in 9..12 {
+                    assert_eq!(uni.get_cell_state(11, row, None), CellState::Alive(None));
+                    assert_eq!(uni.get_cell_state(11, row, Some(0)), CellState::Dead);
+                    assert_eq!(uni.get_cell_state(11, row, Some(1)), CellState::Dead);
+                }
+            }
         }
+    }
 
-        grid.modify_region(region3, BitOperation::Toggle);
-        for x in region2_w..region3_w {
-            for y in region2_h..region3_h {
-                assert_eq!(grid[x][y], 0);
+    #[test]
+    fn checksum_detects_changes() {
+        let mut uni_a = generate_test_universe_with_default_params(UniType::Server);
+        let mut uni_b = generate_test_universe_with_default_params(UniType::Server);
+        for uni in &mut [&mut uni_a, &mut uni_b] {
+            // glider
+            for &(col, row) in &[(16, 15), (17, 16), (15, 17), (16, 17), (17, 17)] {
+                uni.toggle(col, row, 1).unwrap();
             }
+            uni.next();
         }
+        assert_eq!(uni_a.checksum(2, None), uni_b.checksum(2, None));
+        assert_eq!(uni_a.checksum(1, Some(0)), uni_b.checksum(1, Some(0)));
+        assert_ne!(uni_a.checksum(1, None), uni_a.checksum(2, None));
+        assert_eq!(uni_a.checksum(3, None), None);
+
+        // a change in player 0's fog only shows up without visibility
+        let (before, before_player0) = (uni_b.checksum(2, None), uni_b.checksum(2, Some(0)));
+        uni_b.toggle(40, 40, 1).unwrap();
+        assert_ne!(uni_b.checksum(2, None), before);
+        assert_eq!(uni_b.checksum(2, Some(0)), before_player0);
+        assert_ne!(uni_b.checksum(2, Some(1)), uni_a.checksum(2, Some(1)));
     }
 
     #[test]
-    fn bounding_box_of_empty_bit_grid_is_none() {
-        let grid = BitGrid::new(1, 1);
-        assert_eq!(grid.bounding_box(), None);
+    fn checksum_matches_between_server_and_client() {
+        let mut s_uni = generate_test_universe_with_default_params(UniType::Server);
+        let mut c_uni = generate_test_universe_with_default_params(UniType::Client);
+        for &(col, row) in &[(16, 15), (17, 16), (15, 17), (16, 17), (17, 17)] {
+            s_uni.toggle(col, row, 1).unwrap();
+        }
+        s_uni.toggle(110, 75, 0).unwrap();
+        for _ in 0..4 {
+            s_uni.next();
+        }
+        for &player_id in &[0, 1] {
+            let mut c_uni = generate_test_universe_with_default_params(UniType::Client);
+            let diff = s_uni.diff(0, 5, Some(player_id)).unwrap();
+            c_uni.apply(&diff, Some(player_id)).unwrap();
+            assert_eq!(c_uni.checksum(5, Some(player_id)), s_uni.checksum(5, Some(player_id)));
+        }
+        // the client's view differs from the full view
+        let diff = s_uni.diff(0, 5, Some(1)).unwrap();
+        c_uni.apply(&diff, Some(1)).unwrap();
+        assert_ne!(c_uni.checksum(5, Some(1)), s_uni.checksum(5, None));
     }
 
     #[test]
-    fn bounding_box_of_empty_bit_grid_is_none2() {
-        let grid = BitGrid::new(2, 5);
-        assert_eq!(grid.bounding_box(), None);
+    fn checksum_is_stable() {
+        let mut uni = generate_test_universe_with_default_params(UniType::Server);
+        uni.toggle(16, 15, 1).unwrap();
+        uni.set_unchecked(30, 30, CellState::Wall);
+        // the same on every platform; only change this along with the checksum function
+        assert_eq!(uni.checksum(1, None), Some(6210073603099718535));
     }
 
     #[test]
-    fn bounding_box_for_single_off() {
-        let grid = Pattern("b!".to_owned()).to_new_bit_grid(1, 1).unwrap();
-        assert_eq!(grid.bounding_box(), None);
+    fn history_lists_retained_generations_in_order() {
+        let mut uni = generate_test_universe_with_default_params(UniType::Server);
+        assert_eq!(uni.history().map(|(gen, _)| gen).collect::<Vec<_>>(), vec![1]);
+        for _ in 0..GEN_BUFSIZE + 3 {
+            uni.next();
+        }
+        let gens: Vec<usize> = uni.history().map(|(gen, _)| gen).collect();
+        let latest = uni.latest_gen();
+        assert_eq!(gens, ((latest + 1 - GEN_BUFSIZE)..=latest).collect::<Vec<_>>());
     }
 
     #[test]
-    fn bounding_box_for_single_on() {
-        let grid = Pattern("o!".to_owned()).to_new_bit_grid(1, 1).unwrap();
-        assert_eq!(grid.bounding_box(), Some(Region::new(0, 0, 1, 1)));
+    fn rewind_to_discards_later_generations() {
+        let mut uni = generate_test_universe_with_default_params(UniType::Server);
+        for &(col, row) in &[(16, 15), (17, 16), (15, 17), (16, 17), (17, 17)] {
+            uni.toggle(col, row, 1).unwrap();
+        }
+        for _ in 0..4 {
+            uni.next();
+        }
+        let checksum2 = uni.checksum(2, None);
+        uni.rewind_to(2).unwrap();
+        assert_eq!(uni.latest_gen(), 2);
+        assert_eq!(uni.history().map(|(gen, _)| gen).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(uni.checksum(2, None), checksum2);
+        assert_eq!(uni.checksum(3, None), None);
+
+        // running again gives the same generations
+        let mut other = generate_test_universe_with_default_params(UniType::Server);
+        for &(col, row) in &[(16, 15), (17, 16), (15, 17), (16, 17), (17, 17)] {
+            other.toggle(col, row, 1).unwrap();
+        }
+        for _ in 0..4 {
+            other.next();
+        }
+        uni.next();
+        uni.next();
+        assert_eq!(uni.latest_gen(), 4);
+        assert_eq!(uni.checksum(4, None), other.checksum(4, None));
+
+        assert!(uni.rewind_to(5).is_err());
+        assert!(uni.rewind_to(0).is_err());
+        assert_eq!(uni.latest_gen(), 4);
     }
 
     #[test]
-    fn bounding_box_for_single_line_complicated() {
-        let grid = Pattern("15b87o!".to_owned()).to_new_bit_grid(15 + 87, 1).unwrap();
-        assert_eq!(grid.bounding_box(), Some(Region::new(15, 0, 87, 1)));
+    fn rewind_to_restarts_activity_tracking() {
+        let mut uni = generate_test_universe_with_default_params(UniType::Server);
+        uni.set_activity_window(Some(4));
+        uni.toggle(16, 15, 1).unwrap();
+        uni.toggle(17, 15, 1).unwrap();
+        uni.toggle(18, 15, 1).unwrap();
+        uni.next();
+        uni.next();
+        let region = Region::new(10, 10, 10, 10);
+        assert!(uni.activity(region, None).unwrap().total_changes() > 0);
+        uni.rewind_to(2).unwrap();
+        assert_eq!(uni.activity_window(), Some(4));
+        let activity = uni.activity(region, None).unwrap();
+        assert_eq!(activity.generations(), 0);
+        assert_eq!(activity.total_changes(), 0);
     }
 
     #[test]
-    fn bounding_box_for_single_line_complicated2() {
-        let grid = Pattern("82b87o!".to_owned()).to_new_bit_grid(82 + 87, 1).unwrap();
-        assert_eq!(grid.bounding_box(), Some(Region::new(82, 0, 87, 1)));
+    fn fork_leaves_original_unchanged() {
+        let mut uni = generate_test_universe_with_default_params(UniType::Server);
+        for &(col, row) in &[(16, 15), (17, 16), (15, 17), (16, 17), (17, 17)] {
+            uni.toggle(col, row, 1).unwrap();
+        }
+        for _ in 0..3 {
+            uni.next();
+        }
+        let mut fork = uni.fork(2).unwrap();
+        assert_eq!(fork.latest_gen(), 2);
+        assert_eq!(uni.latest_gen(), 4);
+        assert_eq!(fork.checksum(2, None), uni.checksum(2, None));
+
+        // changes to the fork don't affect the original
+        let checksum2 = uni.checksum(2, None);
+        fork.toggle(40, 40, 1).unwrap();
+        assert_ne!(fork.checksum(2, None), checksum2);
+        assert_eq!(uni.checksum(2, None), checksum2);
+        fork.next();
+        assert_eq!(fork.latest_gen(), 3);
+        assert_eq!(uni.latest_gen(), 4);
+
+        let latest = uni.fork(4).unwrap();
+        assert_eq!(latest.checksum(4, None), uni.checksum(4, None));
+        assert!(uni.fork(5).is_err());
+    }
+
+    fn block() -> Pattern {
+        Pattern("2o$2o!".to_owned())
     }
 
     #[test]
-    fn bounding_box_for_multi_line_complicated() {
-        let grid = Pattern("4$15b87o!".to_owned()).to_new_bit_grid(15 + 87, 5).unwrap();
-        assert_eq!(grid.bounding_box(), Some(Region::new(15, 4, 87, 1)));
+    fn drop_pattern_or_brings_cells_to_life() {
+        let mut uni = generate_test_universe_with_default_params(UniType::Server);
+        assert_eq!(uni.drop_pattern(1, 10, 10, &block(), BlendMode::Or), Ok(4));
+        for &(col, row) in &[(10, 10), (11, 10), (10, 11), (11, 11)] {
+            assert_eq!(uni.get_cell_state(col, row, Some(1)), CellState::Alive(Some(1)));
+        }
+        assert_eq!(uni.drop_pattern(1, 10, 10, &block(), BlendMode::Or), Ok(0));
+        // unowned cells can be taken over
+        uni.set_unchecked(50, 50, CellState::Alive(None));
+        assert_eq!(
+            uni.drop_pattern(1, 50, 50, &Pattern("o!".to_owned()), BlendMode::Or),
+            Ok(1)
+        );
+        assert_eq!(uni.get_cell_state(50, 50, Some(1)), CellState::Alive(Some(1)));
     }
 
     #[test]
-    fn bounding_box_for_multi_line_complicated2() {
-        let grid = Pattern("4$15b87o$10b100o$25b3o!".to_owned())
-            .to_new_bit_grid(110, 7)
+    fn drop_pattern_replace_and_xor() {
+        let mut uni = generate_test_universe_with_default_params(UniType::Server);
+        uni.drop_pattern(1, 10, 10, &block(), BlendMode::Or).unwrap();
+        let diagonal = Pattern("o$bo!".to_owned());
+        assert_eq!(uni.drop_pattern(1, 10, 10, &diagonal, BlendMode::Replace), Ok(2));
+        assert_eq!(uni.get_cell_state(10, 10, None), CellState::Alive(None));
+        assert_eq!(uni.get_cell_state(11, 10, None), CellState::Dead);
+        assert_eq!(uni.get_cell_state(10, 11, None), CellState::Dead);
+        assert_eq!(uni.get_cell_state(11, 11, None), CellState::Alive(None));
+
+        assert_eq!(
+            uni.drop_pattern(1, 10, 10, &Pattern("2o!".to_owned()), BlendMode::Xor),
+            Ok(2)
+        );
+        assert_eq!(uni.get_cell_state(10, 10, None), CellState::Dead);
+        assert_eq!(uni.get_cell_state(11, 10, Some(1)), CellState::Alive(Some(1)));
+        assert_eq!(uni.get_cell_state(11, 11, None), CellState::Alive(None));
+    }
+
+    #[test]
+    fn drop_pattern_lists_denied_cells() {
+        let mut uni = generate_test_universe_with_default_params(UniType::Server);
+        // straddles the right edge of player 1's writable area
+        assert_eq!(
+            uni.drop_pattern(1, 79, 10, &block(), BlendMode::Or),
+            Err(PlacementError::CellsDenied {
+                cells: vec![(80, 10), (80, 11)],
+            })
+        );
+        assert_eq!(uni.get_cell_state(79, 10, None), CellState::Dead);
+
+        uni.set_unchecked(30, 30, CellState::Wall);
+        uni.set_unchecked(41, 41, CellState::Alive(Some(0)));
+        assert_eq!(
+            uni.drop_pattern(1, 30, 30, &block(), BlendMode::Or),
+            Err(PlacementError::CellsDenied { cells: vec![(30, 30)] })
+        );
+        let err = uni.drop_pattern(1, 40, 40, &block(), BlendMode::Replace).unwrap_err();
+        assert_eq!(err, PlacementError::CellsDenied { cells: vec![(41, 41)] });
+        assert_eq!(uni.get_cell_state(40, 40, None), CellState::Dead);
+        match ConwayError::from(err) {
+            AccessDenied { reason } => assert!(reason.contains("(41, 41)")),
+            other => panic!("expected AccessDenied, got {:?}", other),
+        }
+
+        // cells that are beyond the edge of a universe that doesn't wrap
+        let mut uni = BigBang::new()
+            .width(64)
+            .height(32)
+            .topology(Topology::Plane)
+            .add_player(PlayerBuilder::new(Region::new(0, 0, 64, 32)))
+            .birth()
             .unwrap();
-        assert_eq!(grid.bounding_box(), Some(Region::new(10, 4, 100, 3)));
+        assert_eq!(
+            uni.drop_pattern(0, 63, 31, &Pattern("2o!".to_owned()), BlendMode::Or),
+            Err(PlacementError::CellsDenied { cells: vec![(64, 31)] })
+        );
     }
 
     #[test]
-    fn copy_simple() {
-        let grid = Pattern("64o$64o!".to_owned()).to_new_bit_grid(64, 2).unwrap();
-        let mut grid2 = BitGrid::new(1, 2); // 64x2
-        let dst_region = Region::new(0, 0, 32, 3);
-        BitGrid::copy(&grid, &mut grid2, dst_region);
-        assert_eq!(grid2.to_pattern(None).0, "32o$32o!".to_owned());
+    fn drop_pattern_rejects_invalid_data() {
+        let mut uni = generate_test_universe_with_default_params(UniType::Server);
+        match uni.drop_pattern(2, 10, 10, &block(), BlendMode::Or) {
+            Err(PlacementError::InvalidData { .. }) => {}
+            other => panic!("expected InvalidData, got {:?}", other),
+        }
+        match uni.drop_pattern(1, 10, 10, &Pattern("2o$2x!".to_owned()), BlendMode::Or) {
+            Err(PlacementError::InvalidData { .. }) => {}
+            other => panic!("expected InvalidData, got {:?}", other),
+        }
     }
 
     #[test]
-    fn copy_out_of_bounds() {
-        let grid = Pattern("64o$64o!".to_owned()).to_new_bit_grid(64, 2).unwrap();
-        let mut grid2 = BitGrid::new(1, 2); // 64x2
-        let dst_region = Region::new(17, 3, 32, 5);
-        BitGrid::copy(&grid, &mut grid2, dst_region);
-        assert_eq!(grid2.to_pattern(None).0, "!".to_owned());
+    fn clear_area_kills_cells_not_owned_by_others() {
+        let mut uni = generate_test_universe_with_default_params(UniType::Server);
+        uni.drop_pattern(1, 10, 10, &block(), BlendMode::Or).unwrap();
+        uni.set_unchecked(13, 13, CellState::Alive(None));
+        uni.set_unchecked(14, 14, CellState::Alive(Some(0)));
+        uni.set_unchecked(15, 15, CellState::Wall);
+        assert_eq!(uni.clear_area(1, Region::new(10, 10, 6, 6)), Ok(5));
+        assert_eq!(uni.get_cell_state(10, 10, None), CellState::Dead);
+        assert_eq!(uni.get_cell_state(13, 13, None), CellState::Dead);
+        assert_eq!(uni.get_cell_state(14, 14, Some(0)), CellState::Alive(Some(0)));
+        assert_eq!(uni.writable(15, 15, 1), Ok(false)); // still a wall
     }
 
     #[test]
-    fn copy_simple2() {
-        let grid = Pattern("64o$64o!".to_owned()).to_new_bit_grid(64, 2).unwrap();
-        let mut grid2 = BitGrid::new(1, 2); // 64x2
-        let dst_region = Region::new(16, 1, 32, 3);
-        BitGrid::copy(&grid, &mut grid2, dst_region);
-        assert_eq!(grid2.to_pattern(None).0, "$16b32o!".to_owned());
+    fn clear_area_outside_writable_area_clears_nothing() {
+        let mut uni = generate_test_universe_with_default_params(UniType::Server);
+        uni.drop_pattern(1, 78, 10, &block(), BlendMode::Or).unwrap();
+        assert_eq!(
+            uni.clear_area(1, Region::new(78, 10, 3, 1)),
+            Err(PlacementError::CellsDenied { cells: vec![(80, 10)] })
+        );
+        assert_eq!(uni.get_cell_state(78, 10, Some(1)), CellState::Alive(Some(1)));
+        assert!(uni.clear_area(3, Region::new(78, 10, 2, 2)).is_err());
     }
 
     #[test]
-    fn copy_for_multi_line_complicated() {
-        let grid = Pattern("4$b87o$3b100o$3o!".to_owned()).to_new_bit_grid(110, 7).unwrap();
-        let mut grid2 = BitGrid::new(2, 10); // 128x10
-        let dst_region = Region::new(2, 3, 64, 5);
-        BitGrid::copy(&grid, &mut grid2, dst_region);
-        assert_eq!(grid2.to_pattern(None).0, "7$3b63o!".to_owned());
+    fn budget_limits_toggles() {
+        let mut uni = generate_test_universe_with_default_params(UniType::Server);
+        assert_eq!(uni.available_cells(1), None);
+        uni.set_budget(1, Some(Budget::new(2, 1))).unwrap();
+        assert_eq!(uni.budget(1), Some(Budget::new(2, 1)));
+        uni.toggle(10, 10, 1).unwrap();
+        uni.toggle(11, 10, 1).unwrap();
+        assert_eq!(uni.available_cells(1), Some(0));
+        match uni.toggle(12, 10, 1) {
+            Err(AccessDenied { .. }) => {}
+            other => panic!("expected AccessDenied, got {:?}", other),
+        }
+        assert_eq!(uni.get_cell_state(12, 10, None), CellState::Dead);
+        // killing a cell is free
+        assert_eq!(uni.toggle(11, 10, 1), Ok(CellState::Dead));
+        uni.next();
+        assert_eq!(uni.available_cells(1), Some(1));
+        assert_eq!(uni.toggle(12, 10, 1), Ok(CellState::Alive(Some(1))));
+        // player 0 has no budget
+        assert_eq!(uni.available_cells(0), None);
+        uni.toggle(110, 75, 0).unwrap();
     }
 
     #[test]
-    fn copy_for_multi_line_complicated2() {
-        let grid = Pattern("4$b87o$3b100o$3o!".to_owned()).to_new_bit_grid(110, 7).unwrap();
-        let mut grid2 = BitGrid::new(2, 10); // 128x10
-        let dst_region = Region::new(2, 3, 65, 5);
-        BitGrid::copy(&grid, &mut grid2, dst_region);
-        assert_eq!(grid2.to_pattern(None).0, "7$3b64o!".to_owned());
+    fn budget_refill_is_capped() {
+        let mut uni = generate_test_universe_with_default_params(UniType::Server);
+        uni.set_budget(1, Some(Budget::new(5, 2))).unwrap();
+        uni.toggle(10, 10, 1).unwrap();
+        assert_eq!(uni.available_cells(1), Some(4));
+        uni.next();
+        assert_eq!(uni.available_cells(1), Some(5));
     }
 
     #[test]
-    fn copy_for_multi_line_complicated3() {
-        let grid = Pattern("4$b87o$3b100o$3o!".to_owned()).to_new_bit_grid(110, 7).unwrap();
-        let mut grid2 = BitGrid::new(1, 10); // 64x10
-        let dst_region = Region::new(2, 3, 5, 5);
-        BitGrid::copy(&grid, &mut grid2, dst_region);
-        assert_eq!(grid2.to_pattern(None).0, "7$3b4o!".to_owned());
+    fn budget_rate_limit() {
+        let mut uni = generate_test_universe_with_default_params(UniType::Server);
+        uni.set_budget(1, Some(Budget::new(100, 0).rate_limit(1, 3))).unwrap();
+        uni.toggle(10, 10, 1).unwrap(); // generation 1
+        assert!(uni.toggle(20, 20, 1).is_err());
+        uni.next();
+        uni.next();
+        assert!(uni.toggle(20, 20, 1).is_err()); // generation 3
+        uni.next();
+        assert!(uni.toggle(20, 20, 1).is_ok()); // generation 4
+        assert!(uni.set_budget(1, Some(Budget::new(100, 0).rate_limit(1, 0))).is_err());
     }
 
     #[test]
-    #[should_panic]
-    fn modify_region_with_a_negative_region_panics() {
-        let height = 10;
-        let width_in_words = 10;
+    fn budget_limits_set_and_drop_pattern() {
+        let mut uni = generate_test_universe_with_default_params(UniType::Server);
+        uni.set_budget(1, Some(Budget::new(3, 0))).unwrap();
+        match uni.drop_pattern(1, 10, 10, &block(), BlendMode::Or) {
+            Err(err @ PlacementError::OverBudget { .. }) => match ConwayError::from(err) {
+                AccessDenied { .. } => {}
+                other => panic!("expected AccessDenied, got {:?}", other),
+            },
+            other => panic!("expected OverBudget, got {:?}", other),
+        }
+        assert_eq!(uni.get_cell_state(10, 10, None), CellState::Dead);
+        assert_eq!(uni.available_cells(1), Some(3));
 
-        let mut grid = BitGrid::new(width_in_words, height);
-        let region_neg = Region::new(-1, -1, 1, 1);
-        grid.modify_region(region_neg, BitOperation::Set);
+        assert_eq!(
+            uni.drop_pattern(1, 10, 10, &Pattern("2o!".to_owned()), BlendMode::Or),
+            Ok(2)
+        );
+        // only the new cell counts
+        assert_eq!(
+            uni.drop_pattern(1, 10, 10, &Pattern("3o!".to_owned()), BlendMode::Or),
+            Ok(1)
+        );
+        assert_eq!(uni.available_cells(1), Some(0));
+        uni.set(20, 20, CellState::Alive(Some(1)), 1);
+        assert_eq!(uni.get_cell_state(20, 20, None), CellState::Dead);
     }
 
     #[test]
-    fn get_run_for_single_on() {
-        let grid = Pattern("o!".to_owned()).to_new_bit_grid(1, 1).unwrap();
-        assert_eq!(grid.get_run(0, 0, None), (1, 'o'));
+    fn claiming_unowned_cells_is_free() {
+        let mut uni = generate_test_universe_with_default_params(UniType::Server);
+        uni.set_budget(1, Some(Budget::new(1, 0))).unwrap();
+        for &(col, row) in &[(10, 10), (11, 10), (20, 20), (21, 20), (20, 21), (21, 21)] {
+            uni.set_unchecked(col, row, CellState::Alive(None));
+        }
+        assert_eq!(
+            uni.drop_pattern(1, 10, 10, &Pattern("3o!".to_owned()), BlendMode::Or),
+            Ok(3)
+        );
+        assert_eq!(uni.get_cell_state(10, 10, Some(1)), CellState::Alive(Some(1)));
+        assert_eq!(uni.available_cells(1), Some(0));
+
+        uni.schedule(2, 1, block_drop(20, 20)).unwrap();
+        uni.next();
+        assert_eq!(uni.get_cell_state(20, 20, Some(1)), CellState::Alive(Some(1)));
     }
 
     #[test]
-    fn get_run_for_single_off_then_on() {
-        let grid = Pattern("bo!".to_owned()).to_new_bit_grid(2, 1).unwrap();
-        assert_eq!(grid.get_run(0, 0, None), (1, 'b'));
+    fn scheduled_action_over_budget_is_skipped() {
+        let mut uni = generate_test_universe_with_default_params(UniType::Server);
+        uni.set_budget(1, Some(Budget::new(3, 0))).unwrap();
+        uni.schedule(2, 1, block_drop(10, 10)).unwrap();
+        uni.schedule(2, 1, Action::Toggle { col: 20, row: 20 }).unwrap();
+        uni.next();
+        assert_eq!(uni.get_cell_state(10, 10, None), CellState::Dead);
+        assert_eq!(uni.get_cell_state(20, 20, Some(1)), CellState::Alive(Some(1)));
+        assert_eq!(uni.available_cells(1), Some(2));
     }
 
     #[test]
-    fn get_run_for_single_off() {
-        let grid = Pattern("b!".to_owned()).to_new_bit_grid(1, 1).unwrap();
-        assert_eq!(grid.get_run(0, 0, None), (64, 'b'));
+    fn rewind_restores_budgets() {
+        let mut uni = generate_test_universe_with_default_params(UniType::Server);
+        uni.set_budget(1, Some(Budget::new(10, 1))).unwrap();
+        for col in 10..13 {
+            uni.toggle(col, 10, 1).unwrap();
+        }
+        uni.next();
+        uni.toggle(20, 20, 1).unwrap();
+        assert_eq!(uni.available_cells(1), Some(7)); // generation 2
+        uni.next();
+        assert_eq!(uni.available_cells(1), Some(8));
+
+        let fork = uni.fork(1).unwrap();
+        assert_eq!(fork.available_cells(1), Some(7));
+        assert_eq!(uni.available_cells(1), Some(8));
+
+        uni.rewind_to(2).unwrap();
+        assert_eq!(uni.available_cells(1), Some(7));
+        // computing generation 3 again refills the budget only once
+        uni.next();
+        assert_eq!(uni.available_cells(1), Some(8));
     }
 
     #[test]
-    fn get_run_nonzero_col_for_single_off() {
-        let grid = Pattern("b!".to_owned()).to_new_bit_grid(1, 1).unwrap();
-        assert_eq!(grid.get_run(23, 0, None), (64 - 23, 'b'));
+    fn budget_from_player_builder() {
+        let player = PlayerBuilder::new(Region::new(0, 0, 64, 32)).budget(Budget::new(10, 1));
+        let uni = BigBang::new().width(64).height(32).add_player(player).birth().unwrap();
+        assert_eq!(uni.available_cells(0), Some(10));
+
+        let player = PlayerBuilder::new(Region::new(0, 0, 64, 32)).budget(Budget::new(10, 1).rate_limit(1, 0));
+        assert!(BigBang::new().width(64).height(32).add_player(player).birth().is_err());
+    }
+
+    fn block_drop(col: usize, row: usize) -> Action {
+        Action::DropPattern {
+            pattern: Pattern("2o$2o!".to_owned()),
+            col,
+            row,
+        }
     }
 
     #[test]
-    fn get_run_nonzero_col_complicated() {
-        let grid = Pattern("15b87o!".to_owned()).to_new_bit_grid(15 + 87, 1).unwrap();
-        assert_eq!(grid.get_run(15, 0, None), (87, 'o'));
+    fn scheduled_toggle_applies_at_its_generation() {
+        let mut uni = generate_test_universe_with_default_params(UniType::Server);
+        uni.schedule(3, 1, Action::Toggle { col: 40, row: 40 }).unwrap();
+        assert_eq!(uni.scheduled_actions().len(), 1);
+        uni.next();
+        assert_eq!(uni.get_cell_state(40, 40, None), CellState::Dead);
+        uni.next();
+        assert_eq!(uni.get_cell_state(40, 40, Some(1)), CellState::Alive(Some(1)));
+        assert!(uni.scheduled_actions().is_empty());
+        uni.next(); // a lone cell dies
+        assert_eq!(uni.get_cell_state(40, 40, None), CellState::Dead);
     }
 
     #[test]
-    fn get_run_nonzero_col_multiline_complicated() {
-        let grid = Pattern("3$15b87o!".to_owned()).to_new_bit_grid(15 + 87, 4).unwrap();
-        assert_eq!(grid.get_run(15, 3, None), (87, 'o'));
+    fn schedule_rejects_invalid_actions() {
+        let mut uni = generate_test_universe_with_default_params(UniType::Server);
+        uni.next();
+        assert!(uni.schedule(2, 1, Action::Toggle { col: 40, row: 40 }).is_err());
+        assert!(uni.schedule(3, 2, Action::Toggle { col: 40, row: 40 }).is_err());
+        assert!(uni.schedule(3, 1, Action::Toggle { col: 256, row: 40 }).is_err());
+        let bad_pattern = Action::DropPattern {
+            pattern: Pattern("2o$2x!".to_owned()),
+            col:     10,
+            row:     10,
+        };
+        assert!(uni.schedule(3, 1, bad_pattern).is_err());
+        assert!(uni.scheduled_actions().is_empty());
     }
 
     #[test]
-    fn to_pattern_simple() {
-        let original_pattern = Pattern("bo!".to_owned());
-        let grid = original_pattern.to_new_bit_grid(2, 1).unwrap();
-        let pattern = grid.to_pattern(None);
-        assert_eq!(pattern, original_pattern);
+    fn scheduled_drop_and_clear() {
+        let mut uni = generate_test_universe_with_default_params(UniType::Server);
+        uni.schedule(2, 1, block_drop(10, 10)).unwrap();
+        uni.schedule(
+            4,
+            1,
+            Action::ClearArea {
+                region: Region::new(0, 0, 20, 20),
+            },
+        )
+        .unwrap();
+        uni.next();
+        for &(col, row) in &[(10, 10), (11, 10), (10, 11), (11, 11)] {
+            assert_eq!(uni.get_cell_state(col, row, Some(1)), CellState::Alive(Some(1)));
+        }
+        uni.next();
+        assert_eq!(uni.get_cell_state(11, 11, Some(1)), CellState::Alive(Some(1)));
+        uni.next();
+        for &(col, row) in &[(10, 10), (11, 10), (10, 11), (11, 11)] {
+            assert_eq!(uni.get_cell_state(col, row, None), CellState::Dead);
+        }
     }
 
     #[test]
-    fn to_pattern_nonzero_col_3empty_then_complicated() {
-        let original_pattern = Pattern("3$15b87o!".to_owned());
-        let grid = original_pattern.to_new_bit_grid(15 + 87, 4).unwrap();
-        let pattern = grid.to_pattern(None);
-        assert_eq!(pattern, original_pattern);
+    fn scheduled_drop_respects_writable_area() {
+        let mut uni = generate_test_universe_with_default_params(UniType::Server);
+        // straddles the right edge of player 1's writable area
+        uni.schedule(2, 1, block_drop(79, 10)).unwrap();
+        uni.next();
+        assert_eq!(uni.get_cell_state(79, 10, Some(1)), CellState::Alive(Some(1)));
+        assert_eq!(uni.get_cell_state(80, 10, None), CellState::Dead);
     }
 
     #[test]
-    fn to_pattern_nonzero_col_veryverycomplicated() {
-        let original_pattern = Pattern("b2o23b2o21b$b2o23bo22b$24bobo22b$15b2o7b2o23b$2o13bobo31b$2o13bob2o30b$16b2o31b$16bo32b$44b2o3b$16bo27b2o3b$16b2o31b$2o13bob2o13bo3bo12b$2o13bobo13bo5bo7b2o2b$15b2o14bo13b2o2b$31b2o3bo12b$b2o30b3o13b$b2o46b$33b3o13b$31b2o3bo12b$31bo13b2o2b$31bo5bo7b2o2b$32bo3bo12b2$44b2o3b$44b2o3b5$37b2o10b$37bobo7b2o$39bo7b2o$37b3o9b$22bobo24b$21b3o25b$21b3o25b$21bo15b3o9b$25bobo11bo9b$21b2o4bo9bobo9b$16b2o4bo3b2o9b2o10b$15bobo6bo24b$15bo33b$14b2o!".to_owned());
-        let grid = original_pattern.to_new_bit_grid(49, 43).unwrap();
-        let pattern = grid.to_pattern(None);
-        assert_eq!(pattern.0.as_str(), "b2o23b2o$b2o23bo$24bobo$15b2o7b2o$2o13bobo$2o13bob2o$16b2o$16bo$44b2o$\r\n16bo27b2o$16b2o$2o13bob2o13bo3bo$2o13bobo13bo5bo7b2o$15b2o14bo13b2o$\r\n31b2o3bo$b2o30b3o$b2o$33b3o$31b2o3bo$31bo13b2o$31bo5bo7b2o$32bo3bo2$\r\n44b2o$44b2o5$37b2o$37bobo7b2o$39bo7b2o$37b3o$22bobo$21b3o$21b3o$21bo\r\n15b3o$25bobo11bo$21b2o4bo9bobo$16b2o4bo3b2o9b2o$15bobo6bo$15bo$14b2o!");
-        for line in pattern.0.as_str().lines() {
-            assert!(line.len() <= 70);
+    fn contested_cells_are_left_unchanged() {
+        let mut unis = vec![];
+        for &reverse in &[false, true] {
+            let mut uni = generate_test_universe_with_default_params(UniType::Server);
+            let mut actions = vec![
+                (0, block_drop(110, 75)),
+                (1, Action::Toggle { col: 111, row: 76 }),
+                (1, Action::Toggle { col: 20, row: 20 }),
+            ];
+            if reverse {
+                actions.reverse();
+            }
+            for (player_id, action) in actions {
+                uni.schedule(2, player_id, action).unwrap();
+            }
+            uni.next();
+            assert_eq!(uni.get_cell_state(110, 75, Some(0)), CellState::Alive(Some(0)));
+            assert_eq!(uni.get_cell_state(111, 76, None), CellState::Dead);
+            assert_eq!(uni.get_cell_state(20, 20, Some(1)), CellState::Alive(Some(1)));
+            unis.push(uni);
         }
+        assert_eq!(unis[0].checksum(2, None), unis[1].checksum(2, None));
     }
 
     #[test]
-    fn get_run_empty() {
-        let grid = BitGrid::new(1, 1);
-        let pattern = grid.to_pattern(None);
-        assert_eq!(pattern.0.as_str(), "!");
+    fn remove_player_drops_scheduled_actions() {
+        let mut uni = generate_test_universe_with_default_params(UniType::Server);
+        uni.schedule(2, 0, block_drop(110, 75)).unwrap();
+        uni.schedule(2, 1, Action::Toggle { col: 20, row: 20 }).unwrap();
+        uni.remove_player(0, LeavePolicy::Die).unwrap();
+        assert_eq!(uni.scheduled_actions().len(), 1);
+        assert_eq!(uni.scheduled_actions()[0].player_id, 1);
+        uni.next();
+        assert_eq!(uni.get_cell_state(20, 20, Some(1)), CellState::Alive(Some(1)));
+        assert_eq!(uni.get_cell_state(110, 75, None), CellState::Dead);
+    }
+
+    #[test]
+    fn late_action_after_rewind_matches_timely_action() {
+        let mut timely = generate_test_universe_with_default_params(UniType::Server);
+        timely.schedule(3, 0, block_drop(100, 70)).unwrap();
+        timely.schedule(3, 1, block_drop(30, 30)).unwrap();
+        for _ in 0..4 {
+            timely.next();
+        }
+
+        let mut late = generate_test_universe_with_default_params(UniType::Server);
+        late.schedule(3, 0, block_drop(100, 70)).unwrap();
+        for _ in 0..4 {
+            late.next();
+        }
+        assert!(late.scheduled_actions().is_empty());
+        // the action of player 1 arrives after generation 3 was computed
+        late.rewind_to(2).unwrap();
+        assert_eq!(late.scheduled_actions().len(), 1); // the action of player 0 is scheduled again
+        late.schedule(3, 1, block_drop(30, 30)).unwrap();
+        late.next();
+        late.next();
+        assert_eq!(late.latest_gen(), 4);
+        assert_eq!(late.checksum(4, None), timely.checksum(4, None));
+        assert_eq!(late.get_cell_state(100, 70, Some(0)), CellState::Alive(Some(0)));
+
+        // rewinding past the generation of an action that was dropped from the history doesn't
+        // bring it back
+        for _ in 0..GEN_BUFSIZE {
+            late.next();
+        }
+        let oldest = late.latest_gen() + 1 - GEN_BUFSIZE;
+        late.rewind_to(oldest).unwrap();
+        assert!(late.scheduled_actions().is_empty());
+    }
+
+    #[test]
+    fn client_drops_actions_included_in_applied_diff() {
+        let mut s_uni = generate_test_universe_with_default_params(UniType::Server);
+        let mut c_uni = generate_test_universe_with_default_params(UniType::Client);
+        c_uni.apply(&s_uni.diff(0, 1, Some(1)).unwrap(), Some(1)).unwrap();
+        for uni in &mut [&mut s_uni, &mut c_uni] {
+            uni.schedule(3, 1, Action::Toggle { col: 40, row: 40 }).unwrap();
+        }
+        for _ in 0..3 {
+            s_uni.next();
+        }
+        // the client skips generations 2 and 3, which has the toggle
+        c_uni.apply(&s_uni.diff(1, 4, Some(1)).unwrap(), Some(1)).unwrap();
+        assert!(c_uni.scheduled_actions().is_empty());
+        assert_eq!(c_uni.checksum(4, Some(1)), s_uni.checksum(4, Some(1)));
+        s_uni.next();
+        c_uni.next();
+        assert_eq!(c_uni.latest_gen(), 5);
+        assert_eq!(c_uni.checksum(5, Some(1)), s_uni.checksum(5, Some(1)));
+        assert_eq!(c_uni.get_cell_state(40, 40, Some(1)), CellState::Dead);
+
+        // rewinding the client to generation 1 schedules the toggle again
+        c_uni.rewind_to(1).unwrap();
+        for _ in 0..4 {
+            c_uni.next();
+        }
+        assert_eq!(c_uni.checksum(5, Some(1)), s_uni.checksum(5, Some(1)));
+    }
+
+    /// A universe following Brian's Brain (B2/S/C3), with one player who can write anywhere.
+    fn make_generations_universe(is_server: bool) -> Universe {
+        test_big_bang(64, 32, vec![PlayerBuilder::new(Region::new(0, 0, 64, 32))])
+            .server_mode(is_server)
+            .rule(crate::rule::Rule::generations(&[2], &[], 3).unwrap())
+            .birth()
+            .unwrap()
+    }
+
+    #[test]
+    fn generations_cells_decay() {
+        let mut uni = make_generations_universe(true);
+        uni.toggle(10, 10, 0).unwrap();
+        uni.toggle(11, 10, 0).unwrap();
+        uni.next();
+        assert_eq!(uni.get_cell_state(10, 10, None), CellState::Dying(1));
+        assert_eq!(uni.get_cell_state(11, 10, None), CellState::Dying(1));
+        for &(col, row) in &[(10, 9), (11, 9), (10, 11), (11, 11)] {
+            assert_eq!(uni.get_cell_state(col, row, None), CellState::Alive(None));
+        }
+        let mut non_dead = vec![];
+        uni.each_non_dead_full(None, &mut |col, row, state| non_dead.push((col, row, state)));
+        assert_eq!(non_dead.len(), 6);
+        assert!(non_dead.contains(&(10, 10, CellState::Dying(1))));
+
+        uni.next();
+        // the dying cells are dead now, and could not come to life while dying
+        assert_eq!(uni.get_cell_state(10, 10, None), CellState::Dead);
+        assert_eq!(uni.get_cell_state(11, 10, None), CellState::Dead);
+        assert_eq!(uni.get_cell_state(10, 9, None), CellState::Dying(1));
+    }
+
+    #[test]
+    fn generations_dying_states_advance() {
+        let rule = crate::rule::Rule::generations(&[], &[], 5).unwrap(); // nothing survives
+        let mut uni = BigBang::new().width(64).height(32).rule(rule).birth().unwrap();
+        uni.set_unchecked(5, 5, CellState::Alive(None));
+        for n in 1..=3 {
+            uni.next();
+            assert_eq!(uni.get_cell_state(5, 5, None), CellState::Dying(n));
+        }
+        uni.next();
+        assert_eq!(uni.get_cell_state(5, 5, None), CellState::Dead);
+    }
+
+    #[test]
+    fn generations_set_and_toggle_dying_cells() {
+        let mut uni = make_generations_universe(true);
+        uni.set_unchecked(3, 3, CellState::Dying(1));
+        assert_eq!(uni.get_cell_state(3, 3, None), CellState::Dying(1));
+        assert_eq!(uni.toggle(3, 3, 0).unwrap(), CellState::Dead);
+        assert_eq!(uni.get_cell_state(3, 3, None), CellState::Dead);
+
+        uni.set_unchecked(4, 4, CellState::Dying(1));
+        uni.set(4, 4, CellState::Alive(Some(0)), 0);
+        assert_eq!(uni.get_cell_state(4, 4, None), CellState::Alive(None));
+        uni.next();
+        assert_eq!(uni.get_cell_state(4, 4, None), CellState::Dying(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "Tried to set dying state 1, but the rule has 0")]
+    fn set_dying_state_in_life_like_universe_panics() {
+        let mut uni = generate_test_universe_with_default_params(UniType::Server);
+        uni.set_unchecked(3, 3, CellState::Dying(1));
+    }
+
+    #[test]
+    fn generations_client_matches_server() {
+        let mut s_uni = make_generations_universe(true);
+        for &(col, row) in &[(10, 10), (11, 10), (30, 20), (30, 21)] {
+            s_uni.toggle(col, row, 0).unwrap();
+        }
+        for _ in 0..3 {
+            s_uni.next();
+        }
+        let mut c_uni = make_generations_universe(false);
+        c_uni.apply(&s_uni.diff(0, 3, Some(0)).unwrap(), Some(0)).unwrap();
+        c_uni.apply(&s_uni.diff(3, 4, Some(0)).unwrap(), Some(0)).unwrap();
+        assert_eq!(c_uni.checksum(4, Some(0)), s_uni.checksum(4, Some(0)));
+        for &(col, row) in &[(10, 8), (11, 8), (10, 7), (28, 20)] {
+            assert_eq!(
+                c_uni.get_cell_state(col, row, None),
+                s_uni.get_cell_state(col, row, None)
+            );
+        }
+        assert_eq!(c_uni.get_cell_state(10, 8, None), CellState::Dying(1));
+    }
+
+    #[test]
+    fn load_pattern_file_rejects_missing_dying_states() {
+        let mut uni = generate_test_universe_with_default_params(UniType::Server);
+        let file = PatternFile::from_str("x = 2, y = 1, rule = B2/S/C3\nAB!").unwrap();
+        assert!(uni.load_pattern_file(&file, None).is_err());
+
+        let mut uni = make_generations_universe(true);
+        uni.load_pattern_file(&file, None).unwrap();
+        assert_eq!(uni.get_cell_state(0, 0, None), CellState::Alive(None));
+        assert_eq!(uni.get_cell_state(1, 0, None), CellState::Dying(1));
+    }
+
+    #[test]
+    fn activity_tracks_age_and_changes() {
+        let mut uni = generate_test_universe_with_default_params(UniType::Server);
+        assert_eq!(uni.activity(uni.region(), None), None);
+        uni.set_activity_window(Some(4));
+        assert_eq!(uni.activity_window(), Some(4));
+        // blinker
+        for &(col, row) in &[(16, 15), (16, 16), (16, 17)] {
+            uni.toggle(col, row, 1).unwrap();
+        }
+        for _ in 0..6 {
+            uni.next();
+        }
+        let map = uni.activity(Region::new(10, 10, 10, 10), None).unwrap();
+        assert_eq!(map.generations(), 4);
+        assert_eq!(map.age(16, 16), 6); // center stays alive
+        assert_eq!(map.age(16, 15), 1); // born again in the latest generation
+        assert_eq!(map.age(15, 16), 0);
+        assert_eq!(map.changes(16, 15), 4);
+        assert_eq!(map.changes(15, 16), 4);
+        assert_eq!(map.changes(16, 16), 0);
+        assert_eq!(map.max_age(), 6);
+        assert_eq!(map.total_changes(), 16);
+
+        // hidden in player 0's fog
+        let map = uni.activity(Region::new(10, 10, 10, 10), Some(0)).unwrap();
+        assert_eq!((map.age(16, 16), map.total_changes()), (0, 0));
+        let map = uni.activity(Region::new(10, 10, 10, 10), Some(1)).unwrap();
+        assert_eq!((map.age(16, 16), map.total_changes()), (6, 16));
+
+        uni.set_activity_window(None);
+        assert_eq!(uni.activity(uni.region(), None), None);
+    }
+
+    #[test]
+    fn activity_region_is_clipped_to_universe() {
+        let uni = BigBang::new().width(64).height(32).activity_window(8).birth().unwrap();
+        let map = uni.activity(Region::new(-5, 20, 10, 20), None).unwrap();
+        assert_eq!(map.region(), Region::new(0, 20, 5, 12));
+        assert_eq!(map.generations(), 0);
+        assert_eq!(uni.activity(Region::new(64, 0, 10, 10), None), None);
+    }
+
+    #[test]
+    fn activity_window_of_zero_is_rejected() {
+        assert!(BigBang::new().activity_window(0).birth().is_err());
+    }
+
+    #[test]
+    fn activity_stops_in_still_life() {
+        let mut uni = generate_test_universe_with_default_params(UniType::Server);
+        uni.set_activity_window(Some(3));
+        // block
+        for &(col, row) in &[(20, 20), (21, 20), (20, 21), (21, 21)] {
+            uni.toggle(col, row, 1).unwrap();
+        }
+        uni.next();
+        let map = uni.activity(uni.region(), None).unwrap();
+        assert_eq!(map.total_changes(), 0); // set before tracking the first generation
+        for _ in 0..3 {
+            uni.next();
+        }
+        let map = uni.activity(uni.region(), None).unwrap();
+        assert_eq!((map.generations(), map.total_changes()), (3, 0));
+        assert_eq!(map.age(20, 20), 4);
+    }
+
+    #[test]
+    fn activity_on_client_counts_applied_diffs() {
+        let mut s_uni = generate_test_universe_with_default_params(UniType::Server);
+        let mut c_uni = generate_test_universe_with_default_params(UniType::Client);
+        c_uni.set_activity_window(Some(4));
+        for &(col, row) in &[(16, 15), (16, 16), (16, 17)] {
+            s_uni.toggle(col, row, 1).unwrap();
+        }
+        s_uni.next();
+        c_uni.apply(&s_uni.diff(0, 2, Some(1)).unwrap(), Some(1)).unwrap();
+        let map = c_uni.activity(c_uni.region(), Some(1)).unwrap();
+        assert_eq!((map.age(16, 16), map.age(15, 16), map.total_changes()), (1, 1, 3));
+
+        s_uni.next();
+        s_uni.next();
+        c_uni.apply(&s_uni.diff(2, 4, Some(1)).unwrap(), Some(1)).unwrap();
+        let map = c_uni.activity(c_uni.region(), Some(1)).unwrap();
+        assert_eq!(map.generations(), 3);
+        assert_eq!((map.age(16, 16), map.age(15, 16)), (3, 3));
+        assert_eq!(map.total_changes(), 3);
+    }
+
+    #[test]
+    fn universe_writable_returns_true_when_writable() {
+        let uni = generate_test_universe_with_default_params(UniType::Client);
+        let player0 = 0;
+        assert!(uni.writable(100, 70, player0).unwrap());
+    }
+
+    #[test]
+    fn universe_writable_returns_false_when_not_writable() {
+        let uni = generate_test_universe_with_default_params(UniType::Client);
+        let player1 = 1;
+        assert!(!uni.writable(100, 70, player1).unwrap());
+    }
+}
+
+mod genstate_tests {
+    use crate::grids::CharGrid;
+    use crate::rle::Pattern;
+    use crate::universe::test_helpers::*;
+
+    #[test]
+    fn gen_state_get_run_simple() {
+        let mut genstate = make_gen_state();
+
+        Pattern("o!".to_owned()).to_grid(&mut genstate, None).unwrap();
+        assert_eq!(genstate.get_run(0, 0, None), (1, 'o'));
+    }
+
+    #[test]
+    fn gen_state_get_run_wall() {
+        let mut genstate = make_gen_state();
+
+        Pattern("4W!".to_owned()).to_grid(&mut genstate, None).unwrap();
+        assert_eq!(genstate.get_run(0, 0, None), (4, 'W'));
+    }
+
+    #[test]
+    fn gen_state_get_run_wall_blank_in_front() {
+        let mut genstate = make_gen_state();
+
+        Pattern("12b4W!".to_owned()).to_grid(&mut genstate, None).unwrap();
+        assert_eq!(genstate.get_run(12, 0, None), (4, 'W'));
+    }
+
+    #[test]
+    fn gen_state_get_run_alternating_owned_unowned() {
+        let mut genstate = make_gen_state();
+
+        Pattern("15o3A2B9o!".to_owned()).to_grid(&mut genstate, None).unwrap();
+        assert_eq!(genstate.get_run(0, 0, None), (15, 'o'));
+        assert_eq!(genstate.get_run(15, 0, None), (3, 'A'));
+        assert_eq!(genstate.get_run(15 + 3, 0, None), (2, 'B'));
+        assert_eq!(genstate.get_run(15 + 3 + 2, 0, None), (9, 'o'));
+    }
+
+    #[test]
+    fn gen_state_get_run_player1_no_fog() {
+        let mut genstate = make_gen_state();
+
+        let write_pattern_as = Some(1); // avoid clearing fog for players other than player 1
+        Pattern("o!".to_owned())
+            .to_grid(&mut genstate, write_pattern_as)
+            .unwrap();
+        let visibility = Some(1); // as player 1
+        assert_eq!(genstate.get_run(0, 0, visibility), (1, 'o'));
+    }
+
+    #[test]
+    fn gen_state_diff_simple1() {
+        let gs0 = make_gen_state();
+        let mut gs1 = make_gen_state();
+        Pattern("o!".to_owned()).to_grid(&mut gs1, None).unwrap();
+
+        let gsdiff = gs0.diff(&gs1, None);
+        assert_eq!(gsdiff.pattern.0.len(), 659);
+        let mut gsdiff_pattern_iter = gsdiff.pattern.0.split('$');
+        assert_eq!(gsdiff_pattern_iter.next().unwrap(), "o255\"");
+        assert_eq!(gsdiff_pattern_iter.next().unwrap(), "256\"");
+        assert_eq!(gsdiff_pattern_iter.next().unwrap(), "256\"");
+        // if you keep doing this, you'll eventually get a string containing \r\n
+    }
+
+    #[test]
+    fn gen_state_diff_and_restore_simple1() {
+        let gs0 = make_gen_state();
+        let mut gs1 = make_gen_state();
+        let visibility = None;
+        Pattern("o!".to_owned()).to_grid(&mut gs1, visibility).unwrap();
+
+        let gsdiff = gs0.diff(&gs1, visibility);
+
+        let mut new_gs = make_gen_state();
+
+        gsdiff.pattern.to_grid(&mut new_gs, visibility).unwrap();
+        assert_eq!(new_gs, gs1);
+    }
+}
+
+mod region_tests {
+    use crate::universe::*;
+
+    #[test]
+    fn region_with_valid_dims() {
+        let region = Region::new(1, 10, 100, 200);
+
+        assert_eq!(region.left(), 1);
+        assert_eq!(region.top(), 10);
+        assert_eq!(region.height(), 200);
+        assert_eq!(region.width(), 100);
+        assert_eq!(region.right(), 100);
+        assert_eq!(region.bottom(), 209);
+    }
+
+    #[test]
+    fn region_with_valid_dims_negative_top_and_left() {
+        let region = Region::new(-1, -10, 100, 200);
+
+        assert_eq!(region.left(), -1);
+        assert_eq!(region.top(), -10);
+        assert_eq!(region.height(), 200);
+        assert_eq!(region.width(), 100);
+        assert_eq!(region.right(), 98);
+        assert_eq!(region.bottom(), 189);
+    }
+
+    #[test]
+    #[should_panic]
+    fn region_with_bad_dims_panics() {
+        Region::new(0, 0, 0, 0);
+    }
+
+    #[test]
+    fn region_contains_a_valid_sub_region() {
+        let region1 = Region::new(1, 10, 100, 200);
+        let region2 = Region::new(-100, -200, 100, 200);
+
+        assert!(region1.contains(50, 50));
+        assert!(region2.contains(-50, -50));
+    }
+
+    #[test]
+    fn region_does_not_contain_sub_region() {
+        let region1 = Region::new(1, 10, 100, 200);
+        let region2 = Region::new(-100, -200, 100, 200);
+
+        assert!(!region1.contains(-50, -50));
+        assert!(!region2.contains(50, 50));
+    }
+
+    #[test]
+    fn region_no_intersection() {
+        let region1 = Region::new(1, 10, 100, 200);
+        let region2 = Region::new(-100, -200, 100, 200);
+        assert_eq!(region1.intersection(region2), None);
+        assert_eq!(region2.intersection(region1), None);
+    }
+
+    #[test]
+    fn region_intersection_with_self() {
+        let region1 = Region::new(1, 10, 100, 200);
+        assert_eq!(region1.intersection(region1), Some(region1));
+    }
+
+    #[test]
+    fn region_intersection_overlap() {
+        let region1 = Region::new(1, 10, 100, 200);
+        let region2 = Region::new(90, 120, 100, 200);
+        assert_eq!(region1.intersection(region2), Some(Region::new(90, 120, 11, 90)));
+    }
+
+    #[test]
+    fn region_no_intersection_overlap_one_dim() {
+        let region1 = Region::new(0, 0, 2, 2);
+        let region2 = Region::new(3, 0, 2, 2);
+        assert_eq!(region1.intersection(region2), None);
+    }
+}
+
+mod rule_tests {
+    use crate::error::ConwayError;
+    use crate::rule::Rule;
+    use std::str::FromStr;
+
+    #[test]
+    fn parse_bs_notation() {
+        let rule = Rule::from_str("B36/S23").unwrap();
+        assert_eq!(rule.birth_counts(), vec![3, 6]);
+        assert_eq!(rule.survival_counts(), vec![2, 3]);
+    }
+
+    #[test]
+    fn parse_lowercase_without_slash() {
+        assert_eq!(Rule::from_str("b3s23").unwrap(), Rule::conway());
+    }
+
+    #[test]
+    fn parse_survival_first() {
+        assert_eq!(Rule::from_str("S23/B3").unwrap(), Rule::conway());
+    }
+
+    #[test]
+    fn parse_sb_notation() {
+        assert_eq!(Rule::from_str("23/3").unwrap(), Rule::conway());
+        assert_eq!(
+            Rule::from_str("34678/3678").unwrap(),
+            Rule::new(&[3, 6, 7, 8], &[3, 4, 6, 7, 8]).unwrap()
+        );
+    }
+
+    #[test]
+    fn parse_empty_survival() {
+        let rule = Rule::from_str("B2/S").unwrap();
+        assert_eq!(rule.birth_counts(), vec![2]);
+        assert_eq!(rule.survival_counts(), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn parse_invalid_rules() {
+        assert!(Rule::from_str("B9/S23").is_err());
+        assert!(Rule::from_str("B3").is_err());
+        assert!(Rule::from_str("B3/S23/B4").is_err());
+        assert!(Rule::from_str("3").is_err());
+        assert!(Rule::from_str("B3/S2x").is_err());
+        assert!(Rule::from_str("").is_err());
+    }
+
+    #[test]
+    fn new_rejects_out_of_range_counts() {
+        assert_eq!(
+            Rule::new(&[3], &[9]),
+            Err(ConwayError::InvalidData {
+                reason: "neighbor count 9 is out of range in rule".to_owned(),
+            })
+        );
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for rule_str in &["B3/S23", "B36/S23", "B3678/S34678", "B2/S", "B/S012345678"] {
+            let rule = Rule::from_str(rule_str).unwrap();
+            assert_eq!(&rule.to_string(), rule_str);
+        }
+    }
+
+    #[test]
+    fn parse_generations_notation() {
+        let star_wars = Rule::generations(&[2], &[3, 4, 5], 4).unwrap();
+        assert_eq!(Rule::from_str("B2/S345/C4").unwrap(), star_wars);
+        assert_eq!(Rule::from_str("b2s345g4").unwrap(), star_wars);
+        assert_eq!(Rule::from_str("345/2/4").unwrap(), star_wars);
+        let brians_brain = Rule::from_str("/2/3").unwrap();
+        assert_eq!(brians_brain.states(), 3);
+        assert_eq!(brians_brain.dying_states(), 1);
+        assert_eq!(brians_brain.birth_counts(), vec![2]);
+        assert_eq!(Rule::from_str("B3/S23/C2").unwrap(), Rule::conway());
+        assert_eq!(Rule::from_str("B12/S/C26").unwrap().states(), 26);
+    }
+
+    #[test]
+    fn parse_invalid_generations_rules() {
+        assert!(Rule::from_str("B2/S/C").is_err());
+        assert!(Rule::from_str("B2/S/C1").is_err());
+        assert!(Rule::from_str("B2/S/C27").is_err());
+        assert!(Rule::from_str("B2/S/C3/C4").is_err());
+        assert!(Rule::from_str("345/2/x").is_err());
+        assert!(Rule::from_str("345/2/4/5").is_err());
+        assert_eq!(
+            Rule::generations(&[2], &[], 1),
+            Err(ConwayError::InvalidData {
+                reason: "number of states 1 is out of range in rule (expected 2 to 26)".to_owned(),
+            })
+        );
+    }
+
+    #[test]
+    fn display_generations_round_trips() {
+        for rule_str in &["B2/S/C3", "B2/S345/C4", "B12/S/C26"] {
+            let rule = Rule::from_str(rule_str).unwrap();
+            assert_eq!(&rule.to_string(), rule_str);
+        }
+        assert_eq!(Rule::from_str("345/2/4").unwrap().to_string(), "B2/S345/C4");
+    }
+
+    #[test]
+    fn default_is_conway() {
+        assert!(Rule::default().is_conway());
+        assert!(!Rule::from_str("B36/S23").unwrap().is_conway());
+    }
+}
+
+mod topology_tests {
+    use crate::universe::*;
+
+    #[test]
+    fn map_cell_inside_is_identity() {
+        for &topology in &[
+            Topology::Torus,
+            Topology::Plane,
+            Topology::Cylinder,
+            Topology::KleinBottle,
+        ] {
+            assert_eq!(topology.map_cell(5, 7, 64, 32), Some((5, 7)));
+        }
+    }
+
+    #[test]
+    fn map_cell_outside() {
+        assert_eq!(Topology::Torus.map_cell(-1, -1, 64, 32), Some((63, 31)));
+        assert_eq!(Topology::Plane.map_cell(-1, 0, 64, 32), None);
+        assert_eq!(Topology::Cylinder.map_cell(64, 3, 64, 32), Some((0, 3)));
+        assert_eq!(Topology::Cylinder.map_cell(3, 32, 64, 32), None);
+        assert_eq!(Topology::KleinBottle.map_cell(3, 32, 64, 32), Some((60, 0)));
+        assert_eq!(Topology::KleinBottle.map_cell(3, 64, 64, 32), Some((3, 0)));
+        // two crossings
+    }
+
+    #[test]
+    fn region_contains_across_edges() {
+        let region = Region::new(-2, -2, 4, 4);
+        assert!(Topology::Torus.region_contains(region, 63, 31, 64, 32));
+        assert!(!Topology::Plane.region_contains(region, 63, 31, 64, 32));
+        assert!(Topology::Cylinder.region_contains(region, 63, 1, 64, 32));
+        assert!(!Topology::Cylinder.region_contains(region, 63, 31, 64, 32));
+        // mirrored: (0, 31) is seen at (63, -1) from the top edge
+        assert!(Topology::KleinBottle.region_contains(Region::new(62, -2, 2, 2), 0, 31, 64, 32));
+        assert!(!Topology::KleinBottle.region_contains(Region::new(62, -2, 2, 2), 63, 31, 64, 32));
+    }
+
+    #[test]
+    fn region_contains_row_across_edges() {
+        let region = Region::new(0, 30, 4, 4);
+        assert!(Topology::Torus.region_contains_row(region, 1, 32));
+        assert!(!Topology::Torus.region_contains_row(region, 2, 32));
+        assert!(!Topology::Plane.region_contains_row(region, 1, 32));
+    }
+}
+
+mod cellstate_tests {
+    use crate::universe::*;
+
+    #[test]
+    fn cell_states_as_char() {
+        let dead = CellState::Dead;
+        let alive = CellState::Alive(None);
+        let player1 = CellState::Alive(Some(1));
+        let player2 = CellState::Alive(Some(2));
+        let wall = CellState::Wall;
+        let fog = CellState::Fog;
+
+        assert_eq!(dead.to_char(), 'b');
+        assert_eq!(alive.to_char(), 'o');
+        assert_eq!(player1.to_char(), 'B');
+        assert_eq!(player2.to_char(), 'C');
+        assert_eq!(wall.to_char(), 'W');
+        assert_eq!(fog.to_char(), '?');
+    }
+}
+
+mod grid_tests {
+    use crate::grids::*;
+    use crate::rle::Pattern;
+    use crate::universe::Region;
+
+    #[test]
+    fn height_works() {
+        let grid = BitGrid::new(2, 5);
+        assert_eq!(grid.height(), 5);
+    }
+
+    #[test]
+    fn width_works() {
+        let grid = BitGrid::new(2, 5);
+        assert_eq!(grid.width(), 2 * 64);
+    }
+
+    #[test]
+    fn create_valid_empty_bitgrid() {
+        let height = 11;
+        let width_in_words = 10;
+        let grid = BitGrid::new(width_in_words, height);
+
+        assert_eq!(grid[0][0], 0);
+        assert_eq!(grid[height - 1][width_in_words - 1], 0);
+
+        for x in 0..height {
+            for y in 0..width_in_words {
+                assert_eq!(grid[x][y], 0);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn create_bitgrid_with_invalid_dims() {
+        let height = 0;
+        let width_in_words = 0;
+        let _ = BitGrid::new(width_in_words, height);
+    }
+
+    #[test]
+    fn set_cell_bits_within_a_bitgrid() {
+        let height = 10;
+        let width_in_words = 10;
+        let mut grid = BitGrid::new(width_in_words, height);
+
+        for x in 0..height {
+            for y in 0..width_in_words {
+                assert_eq!(grid[x][y], 0);
+            }
+        }
+
+        grid.modify_bits_in_word(height / 2, width_in_words / 2, 1 << 63, BitOperation::Set);
+        assert_eq!(grid[height / 2][width_in_words / 2] >> 63, 1);
+
+        grid.modify_bits_in_word(height - 1, width_in_words - 1, 1 << 63, BitOperation::Set);
+        assert_eq!(grid[height - 1][width_in_words - 1] >> 63, 1);
+    }
+
+    #[test]
+    fn clear_cell_bits_within_a_bitgrid() {
+        let height = 10;
+        let width_in_words = 10;
+        let mut grid = BitGrid::new(width_in_words, height);
+
+        for x in 0..height {
+            for y in 0..width_in_words {
+                grid[x][y] = u64::max_value();
+            }
+        }
+
+        grid.modify_bits_in_word(height / 2, width_in_words / 2, 1 << 63, BitOperation::Clear);
+        assert_eq!(grid[height / 2][width_in_words / 2] >> 63, 0);
+
+        grid.modify_bits_in_word(height - 1, width_in_words - 1, 1 << 63, BitOperation::Clear);
+        assert_eq!(grid[height - 1][width_in_words - 1] >> 63, 0);
+    }
+
+    #[test]
+    fn toggle_cell_bits_within_a_bitgrid() {
+        let height = 10;
+        let width_in_words = 10;
+        let mut grid = BitGrid::new(width_in_words, height);
+
+        for x in 0..height {
+            for y in 0..width_in_words {
+                grid[x][y] = u64::max_value();
+            }
+        }
+
+        grid.modify_bits_in_word(height / 2, width_in_words / 2, 1 << 63, BitOperation::Toggle);
+        assert_eq!(grid[height / 2][width_in_words / 2] >> 63, 0);
+
+        grid.modify_bits_in_word(height / 2, width_in_words / 2, 1 << 63, BitOperation::Toggle);
+        assert_eq!(grid[height / 2][width_in_words / 2] >> 63, 1);
+    }
+
+    #[test]
+    fn fill_region_within_a_bit_grid() {
+        let height = 10;
+        let width_in_words = 10;
+
+        let region1_w = 7;
+        let region1_h = 7;
+        let region2_w = 3;
+        let region2_h = 3;
+        let region3_h = 4;
+        let region3_w = 4;
+
+        let mut grid = BitGrid::new(width_in_words, height);
+        let region1 = Region::new(0, 0, region1_w, region1_h);
+        let region2 = Region::new(0, 0, region2_w, region2_h);
+        let region3 = Region::new(region2_w as isize, region2_h as isize, region3_w, region3_h);
+
+        grid.modify_region(region1, BitOperation::Set);
+
+        for y in 0..region1_w {
+            assert_eq!(grid[y][0], 0xFE00000000000000);
+        }
+
+        grid.modify_region(region2, BitOperation::Clear);
+        for y in 0..region2_w {
+            assert_eq!(grid[y][0], 0x1E00000000000000);
+        }
+
+        grid.modify_region(region3, BitOperation::Toggle);
+        for x in region2_w..region3_w {
+            for y in region2_h..region3_h {
+                assert_eq!(grid[x][y], 0);
+            }
+        }
+    }
+
+    #[test]
+    fn bounding_box_of_empty_bit_grid_is_none() {
+        let grid = BitGrid::new(1, 1);
+        assert_eq!(grid.bounding_box(), None);
+    }
+
+    #[test]
+    fn bounding_box_of_empty_bit_grid_is_none2() {
+        let grid = BitGrid::new(2, 5);
+        assert_eq!(grid.bounding_box(), None);
+    }
+
+    #[test]
+    fn bounding_box_for_single_off() {
+        let grid = Pattern("b!".to_owned()).to_new_bit_grid(1, 1).unwrap();
+        assert_eq!(grid.bounding_box(), None);
+    }
+
+    #[test]
+    fn bounding_box_for_single_on() {
+        let grid = Pattern("o!".to_owned()).to_new_bit_grid(1, 1).unwrap();
+        assert_eq!(grid.bounding_box(), Some(Region::new(0, 0, 1, 1)));
+    }
+
+    #[test]
+    fn bounding_box_for_single_line_complicated() {
+        let grid = Pattern("15b87o!".to_owned()).to_new_bit_grid(15 + 87, 1).unwrap();
+        assert_eq!(grid.bounding_box(), Some(Region::new(15, 0, 87, 1)));
+    }
+
+    #[test]
+    fn bounding_box_for_single_line_complicated2() {
+        let grid = Pattern("82b87o!".to_owned()).to_new_bit_grid(82 + 87, 1).unwrap();
+        assert_eq!(grid.bounding_box(), Some(Region::new(82, 0, 87, 1)));
+    }
+
+    #[test]
+    fn bounding_box_for_multi_line_complicated() {
+        let grid = Pattern("4$15b87o!".to_owned()).to_new_bit_grid(15 + 87, 5).unwrap();
+        assert_eq!(grid.bounding_box(), Some(Region::new(15, 4, 87, 1)));
+    }
+
+    #[test]
+    fn bounding_box_for_multi_line_complicated2() {
+        let grid = Pattern("4$15b87o$10b100o$25b3o!".to_owned())
+            .to_new_bit_grid(110, 7)
+            .unwrap();
+        assert_eq!(grid.bounding_box(), Some(Region::new(10, 4, 100, 3)));
+    }
+
+    #[test]
+    fn copy_simple() {
+        let grid = Pattern("64o$64o!".to_owned()).to_new_bit_grid(64, 2).unwrap();
+        let mut grid2 = BitGrid::new(1, 2); // 64x2
+        let dst_region = Region::new(0, 0, 32, 3);
+        BitGrid::copy(&grid, &mut grid2, dst_region);
+        assert_eq!(grid2.to_pattern(None).0, "32o$32o!".to_owned());
+    }
+
+    #[test]
+    fn copy_out_of_bounds() {
+        let grid = Pattern("64o$64o!".to_owned()).to_new_bit_grid(64, 2).unwrap();
+        let mut grid2 = BitGrid::new(1, 2); // 64x2
+        let dst_region = Region::new(17, 3, 32, 5);
+        BitGrid::copy(&grid, &mut grid2, dst_region);
+        assert_eq!(grid2.to_pattern(None).0, "!".to_owned());
+    }
+
+    #[test]
+    fn copy_simple2() {
+        let grid = Pattern("64o$64o!".to_owned()).to_new_bit_grid(64, 2).unwrap();
+        let mut grid2 = BitGrid::new(1, 2); // 64x2
+        let dst_region = Region::new(16, 1, 32, 3);
+        BitGrid::copy(&grid, &mut grid2, dst_region);
+        assert_eq!(grid2.to_pattern(None).0, "$16b32o!".to_owned());
+    }
+
+    #[test]
+    fn copy_for_multi_line_complicated() {
+        let grid = Pattern("4$b87o$3b100o$3o!".to_owned()).to_new_bit_grid(110, 7).unwrap();
+        let mut grid2 = BitGrid::new(2, 10); // 128x10
+        let dst_region = Region::new(2, 3, 64, 5);
+        BitGrid::copy(&grid, &mut grid2, dst_region);
+        assert_eq!(grid2.to_pattern(None).0, "7$3b63o!".to_owned());
+    }
+
+    #[test]
+    fn copy_for_multi_line_complicated2() {
+        let grid = Pattern("4$b87o$3b100o$3o!".to_owned()).to_new_bit_grid(110, 7).unwrap();
+        let mut grid2 = BitGrid::new(2, 10); // 128x10
+        let dst_region = Region::new(2, 3, 65, 5);
+        BitGrid::copy(&grid, &mut grid2, dst_region);
+        assert_eq!(grid2.to_pattern(None).0, "7$3b64o!".to_owned());
+    }
+
+    #[test]
+    fn copy_for_multi_line_complicated3() {
+        let grid = Pattern("4$b87o$3b100o$3o!".to_owned()).to_new_bit_grid(110, 7).unwrap();
+        let mut grid2 = BitGrid::new(1, 10); // 64x10
+        let dst_region = Region::new(2, 3, 5, 5);
+        BitGrid::copy(&grid, &mut grid2, dst_region);
+        assert_eq!(grid2.to_pattern(None).0, "7$3b4o!".to_owned());
+    }
+
+    #[test]
+    #[should_panic]
+    fn modify_region_with_a_negative_region_panics() {
+        let height = 10;
+        let width_in_words = 10;
+
+        let mut grid = BitGrid::new(width_in_words, height);
+        let region_neg = Region::new(-1, -1, 1, 1);
+        grid.modify_region(region_neg, BitOperation::Set);
+    }
+
+    #[test]
+    fn get_run_for_single_on() {
+        let grid = Pattern("o!".to_owned()).to_new_bit_grid(1, 1).unwrap();
+        assert_eq!(grid.get_run(0, 0, None), (1, 'o'));
+    }
+
+    #[test]
+    fn get_run_for_single_off_then_on() {
+        let grid = Pattern("bo!".to_owned()).to_new_bit_grid(2, 1).unwrap();
+        assert_eq!(grid.get_run(0, 0, None), (1, 'b'));
+    }
+
+    #[test]
+    fn get_run_for_single_off() {
+        let grid = Pattern("b!".to_owned()).to_new_bit_grid(1, 1).unwrap();
+        assert_eq!(grid.get_run(0, 0, None), (64, 'b'));
+    }
+
+    #[test]
+    fn get_run_nonzero_col_for_single_off() {
+        let grid = Pattern("b!".to_owned()).to_new_bit_grid(1, 1).unwrap();
+        assert_eq!(grid.get_run(23, 0, None), (64 - 23, 'b'));
+    }
+
+    #[test]
+    fn get_run_nonzero_col_complicated() {
+        let grid = Pattern("15b87o!".to_owned()).to_new_bit_grid(15 + 87, 1).unwrap();
+        assert_eq!(grid.get_run(15, 0, None), (87, 'o'));
+    }
+
+    #[test]
+    fn get_run_nonzero_col_multiline_complicated() {
+        let grid = Pattern("3$15b87o!".to_owned()).to_new_bit_grid(15 + 87, 4).unwrap();
+        assert_eq!(grid.get_run(15, 3, None), (87, 'o'));
+    }
+
+    #[test]
+    fn to_pattern_simple() {
+        let original_pattern = Pattern("bo!".to_owned());
+        let grid = original_pattern.to_new_bit_grid(2, 1).unwrap();
+        let pattern = grid.to_pattern(None);
+        assert_eq!(pattern, original_pattern);
+    }
+
+    #[test]
+    fn to_pattern_nonzero_col_3empty_then_complicated() {
+        let original_pattern = Pattern("3$15b87o!".to_owned());
+        let grid = original_pattern.to_new_bit_grid(15 + 87, 4).unwrap();
+        let pattern = grid.to_pattern(None);
+        assert_eq!(pattern, original_pattern);
+    }
+
+    #[test]
+    fn to_pattern_nonzero_col_veryverycomplicated() {
+        let original_pattern = Pattern("b2o23b2o21b$b2o23bo22b$24bobo22b$15b2o7b2o23b$2o13bobo31b$2o13bob2o30b$16b2o31b$16bo32b$44b2o3b$16bo27b2o3b$16b2o31b$2o13bob2o13bo3bo12b$2o13bobo13bo5bo7b2o2b$15b2o14bo13b2o2b$31b2o3bo12b$b2o30b3o13b$b2o46b$33b3o13b$31b2o3bo12b$31bo13b2o2b$31bo5bo7b2o2b$32bo3bo12b2$44b2o3b$44b2o3b5$37b2o10b$37bobo7b2o$39bo7b2o$37b3o9b$22bobo24b$21b3o25b$21b3o25b$21bo15b3o9b$25bobo11bo9b$21b2o4bo9bobo9b$16b2o4bo3b2o9b2o10b$15bobo6bo24b$15bo33b$14b2o!".to_owned());
+        let grid = original_pattern.to_new_bit_grid(49, 43).unwrap();
+        let pattern = grid.to_pattern(None);
+        assert_eq!(pattern.0.as_str(), "b2o23b2o$b2o23bo$24bobo$15b2o7b2o$2o13bobo$2o13bob2o$16b2o$16bo$44b2o$\r\n16bo27b2o$16b2o$2o13bob2o13bo3bo$2o13bobo13bo5bo7b2o$15b2o14bo13b2o$\r\n31b2o3bo$b2o30b3o$b2o$33b3o$31b2o3bo$31bo13b2o$31bo5bo7b2o$32bo3bo2$\r\n44b2o$44b2o5$37b2o$37bobo7b2o$39bo7b2o$37b3o$22bobo$21b3o$21b3o$21bo\r\n15b3o$25bobo11bo$21b2o4bo9bobo$16b2o4bo3b2o9b2o$15bobo6bo$15bo$14b2o!");
+        for line in pattern.0.as_str().lines() {
+            assert!(line.len() <= 70);
+        }
+    }
+
+    #[test]
+    fn get_run_empty() {
+        let grid = BitGrid::new(1, 1);
+        let pattern = grid.to_pattern(None);
+        assert_eq!(pattern.0.as_str(), "!");
     }
 
     #[test]
@@ -973,301 +2605,1940 @@ mod grid_tests {
         }
     }
 
-    #[test]
-    fn bit_grid_rotate_horizontal_line_ccw() {
-        let pat = Pattern("10o!".to_owned());
-        let mut grid = pat.to_new_bit_grid(10, 1).unwrap();
-        grid.rotate(10, 1, Rotation::CCW).unwrap();
-        let pat_r = grid.to_pattern(None);
-        assert_eq!(pat_r, Pattern("o$o$o$o$o$o$o$o$o$o!".to_owned()));
+    #[test]
+    fn bit_grid_rotate_horizontal_line_ccw() {
+        let pat = Pattern("10o!".to_owned());
+        let grid = pat.to_new_bit_grid(10, 1).unwrap();
+        let pat_r = grid
+            .transformed(10, 1, Transform::Rotate(Rotation::CCW))
+            .unwrap()
+            .to_pattern(None);
+        assert_eq!(pat_r, Pattern("o$o$o$o$o$o$o$o$o$o!".to_owned()));
+    }
+
+    #[test]
+    fn bit_grid_rotate_horizontal_line_cw() {
+        let pat = Pattern("10o!".to_owned());
+        let grid = pat.to_new_bit_grid(10, 1).unwrap();
+        let pat_r = grid
+            .transformed(10, 1, Transform::Rotate(Rotation::CW))
+            .unwrap()
+            .to_pattern(None);
+        assert_eq!(pat_r, Pattern("o$o$o$o$o$o$o$o$o$o!".to_owned()));
+    }
+
+    #[test]
+    fn bit_grid_rotate_glider_cw() {
+        let pat = Pattern("bo$2bo$3o!".to_owned());
+        let grid = pat.to_new_bit_grid(3, 3).unwrap();
+        let pat_r = grid
+            .transformed(3, 3, Transform::Rotate(Rotation::CW))
+            .unwrap()
+            .to_pattern(None);
+        assert_eq!(pat_r, Pattern("o$obo$2o!".to_owned()));
+    }
+
+    #[test]
+    fn bit_grid_rotate_glider_half() {
+        let pat = Pattern("bo$2bo$3o!".to_owned());
+        let grid = pat.to_new_bit_grid(3, 3).unwrap();
+        let pat_r = grid
+            .transformed(3, 3, Transform::Rotate(Rotation::Half))
+            .unwrap()
+            .to_pattern(None);
+        assert_eq!(pat_r, Pattern("3o$o$bo!".to_owned()));
+    }
+
+    #[test]
+    fn bit_grid_rotate_rejects_size_out_of_range() {
+        let grid = Pattern("3o!".to_owned()).to_new_bit_grid(3, 1).unwrap();
+        assert!(grid.transformed(65, 1, Transform::Rotate(Rotation::CW)).is_err());
+        assert!(grid.transformed(3, 2, Transform::Rotate(Rotation::CW)).is_err());
+        assert!(grid.shifted(65, 1, 1, 0).is_err());
+        assert!(grid.padded(3, 2, 0, 0, 0, 0).is_err());
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn bit_grid_rotate_in_place() {
+        let mut grid = Pattern("bo$2bo$3o!".to_owned()).to_new_bit_grid(3, 3).unwrap();
+        grid.rotate(3, 3, Rotation::CW).unwrap();
+        assert_eq!(grid.to_pattern(None), Pattern("o$obo$2o!".to_owned()));
+        assert!(grid.rotate(65, 1, Rotation::CCW).is_err());
+    }
+
+    #[test]
+    fn bit_grid_transformed_rotate_cw() {
+        let grid = Pattern("bo$2bo$3o!".to_owned()).to_new_bit_grid(3, 3).unwrap();
+        let rotated = grid.transformed(3, 3, Transform::Rotate(Rotation::CW)).unwrap();
+        assert_eq!((rotated.width_in_words(), rotated.height()), (1, 3));
+        assert_eq!(rotated.to_pattern(None), Pattern("o$obo$2o!".to_owned()));
+    }
+
+    #[test]
+    fn bit_grid_transformed_flip() {
+        let grid = Pattern("bo$2bo$3o!".to_owned()).to_new_bit_grid(3, 3).unwrap();
+        let flipped = grid.transformed(3, 3, Transform::FlipHorizontally).unwrap();
+        assert_eq!((flipped.width_in_words(), flipped.height()), (1, 3));
+        assert_eq!(flipped.bounding_box(), Some(Region::new(0, 0, 3, 3)));
+        assert_eq!(flipped.to_pattern(None), Pattern("bo$o$3o!".to_owned()));
+
+        let flipped = grid.transformed(3, 3, Transform::FlipVertically).unwrap();
+        assert_eq!(flipped.to_pattern(None), Pattern("3o$2bo$bo!".to_owned()));
+    }
+
+    #[test]
+    fn bit_grid_transformed_flip_narrow_pattern() {
+        // a lightweight spaceship, 5 cells wide, stays in columns 0 to 4 when flipped
+        let grid = Pattern("bo2bo$o4b$o3bo$4o!".to_owned()).to_new_bit_grid(5, 4).unwrap();
+        let flipped = grid.transformed(5, 4, Transform::FlipHorizontally).unwrap();
+        assert_eq!(flipped.bounding_box(), Some(Region::new(0, 0, 5, 4)));
+        assert_eq!(flipped.to_pattern(None), Pattern("o2bo$4bo$o3bo$b4o!".to_owned()));
+        let flipped_back = flipped.transformed(5, 4, Transform::FlipHorizontally).unwrap();
+        assert_eq!(flipped_back, grid);
+    }
+
+    #[test]
+    fn bit_grid_transformed_transpose_wide() {
+        let grid = Pattern("100o$o!".to_owned()).to_new_bit_grid(100, 2).unwrap();
+        let transposed = grid.transformed(100, 2, Transform::Transpose).unwrap();
+        assert_eq!((transposed.width_in_words(), transposed.height()), (1, 100));
+        let mut expected = "2o".to_owned();
+        for _ in 1..100 {
+            expected.push_str("$o");
+        }
+        expected.push('!');
+        assert_eq!(transposed.to_pattern(None).0.replace("\r\n", ""), expected);
+        assert_eq!(transposed.transformed(2, 100, Transform::Transpose).unwrap(), grid);
+    }
+
+    #[test]
+    fn bit_grid_cropped_empty() {
+        let grid = BitGrid::new(1, 3);
+        assert!(grid.cropped().is_none());
+    }
+
+    #[test]
+    fn bit_grid_shifted_wraps_within_pattern() {
+        let grid = Pattern("o$bo!".to_owned()).to_new_bit_grid(2, 2).unwrap();
+        let shifted = grid.shifted(2, 2, -1, 0).unwrap();
+        assert_eq!((shifted.width_in_words(), shifted.height()), (1, 2));
+        assert_eq!(shifted.to_pattern(None), Pattern("bo$o!".to_owned()));
+        assert_eq!(shifted.shifted(2, 2, 65, 4).unwrap(), grid);
+
+        // a 5 cell wide pattern wraps at its width, not at the end of the word
+        let grid = Pattern("o!".to_owned()).to_new_bit_grid(5, 1).unwrap();
+        let shifted = grid.shifted(5, 1, -1, 0).unwrap();
+        assert_eq!(shifted.to_pattern(None), Pattern("4bo!".to_owned()));
+    }
+
+    #[test]
+    fn bit_grid_padded() {
+        let grid = Pattern("bo$2bo$3o!".to_owned()).to_new_bit_grid(3, 3).unwrap();
+        let padded = grid.padded(3, 3, 1, 2, 0, 1).unwrap();
+        assert_eq!((padded.width_in_words(), padded.height()), (1, 6));
+        assert_eq!(padded.to_pattern(None), Pattern("2$2bo$3bo$b3o!".to_owned()));
+    }
+
+    fn bit(grid: &BitGrid, col: isize, row: isize) -> bool {
+        if col < 0 || row < 0 || col as usize >= grid.width() || row as usize >= grid.height() {
+            return false;
+        }
+        grid.is_set(col as usize, row as usize)
+    }
+
+    #[test]
+    fn bit_grid_is_set() {
+        let grid = Pattern("bo$64bo!".to_owned()).to_new_bit_grid(65, 2).unwrap();
+        assert!(grid.is_set(1, 0));
+        assert!(grid.is_set(64, 1));
+        assert!(!grid.is_set(0, 0));
+        assert!(!grid.is_set(63, 1));
+    }
+
+    fn random_grid(width_in_words: usize, height: usize, seed: u64) -> BitGrid {
+        let mut grid = BitGrid::new(width_in_words, height);
+        let mut state = seed | 1;
+        for row in 0..height {
+            for word_col in 0..width_in_words {
+                // xorshift
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                grid[row][word_col] = state;
+            }
+        }
+        grid
+    }
+
+    #[test]
+    fn combine_whole_grid() {
+        let a = Pattern("2o$2o!".to_owned()).to_new_bit_grid(2, 2).unwrap();
+        let b = Pattern("bo$2o!".to_owned()).to_new_bit_grid(2, 2).unwrap();
+        for &(op, expected) in &[
+            (SetOperation::And, "bo$2o!"),
+            (SetOperation::Or, "2o$2o!"),
+            (SetOperation::Xor, "o!"),
+            (SetOperation::AndNot, "o!"),
+        ] {
+            let mut grid = a.clone();
+            grid.combine(&b, op);
+            assert_eq!(grid.to_pattern(None).0, expected, "{:?}", op);
+        }
+    }
+
+    #[test]
+    fn combine_in_region_offset() {
+        let mut grid = BitGrid::new(2, 4);
+        grid.modify_region(Region::new(60, 0, 8, 4), BitOperation::Set);
+        let src = Pattern("o$bo!".to_owned()).to_new_bit_grid(2, 2).unwrap();
+        grid.combine_in_region(&src, Region::new(62, 1, 3, 2), SetOperation::And);
+        // only bits in the region are affected; bits the source doesn't cover are cleared
+        assert_eq!(grid.to_pattern(None).0, "60b8o$60b3o2b3o$60b2obob3o$60b8o!");
+        assert_eq!(grid.popcount(), 8 + 6 + 6 + 8);
+    }
+
+    #[test]
+    fn combine_in_region_clipped() {
+        let mut grid = BitGrid::new(1, 2);
+        let src = Pattern("3o$3o!".to_owned()).to_new_bit_grid(3, 2).unwrap();
+        grid.combine_in_region(&src, Region::new(-1, -1, 3, 3), SetOperation::Or);
+        assert_eq!(grid.to_pattern(None).0, "2o!");
+        grid.combine_in_region(&src, Region::new(62, 1, 5, 5), SetOperation::Or);
+        assert_eq!(grid.to_pattern(None).0, "2o$62b2o!");
+        grid.combine_in_region(&src, Region::new(100, 0, 5, 5), SetOperation::And);
+        assert_eq!(grid.popcount(), 4);
+    }
+
+    #[test]
+    fn popcount_and_is_empty() {
+        let mut grid = BitGrid::new(2, 3);
+        assert!(grid.is_empty());
+        assert_eq!(grid.popcount(), 0);
+        grid.modify_region(Region::new(10, 1, 100, 2), BitOperation::Set);
+        assert!(!grid.is_empty());
+        assert_eq!(grid.popcount(), 200);
+        assert_eq!(grid.popcount_in_region(Region::new(0, 0, 64, 2)), 54);
+        assert_eq!(grid.popcount_in_region(Region::new(100, -5, 100, 100)), 20);
+        assert_eq!(grid.popcount_in_region(Region::new(200, 0, 10, 10)), 0);
+    }
+
+    #[test]
+    fn eq_in_region_works() {
+        let a = Pattern("o$bo$2bo!".to_owned()).to_new_bit_grid(3, 3).unwrap();
+        let b = Pattern("o$bo$3o!".to_owned()).to_new_bit_grid(3, 3).unwrap();
+        assert!(a.eq_in_region(&b, Region::new(0, 0, 3, 2)));
+        assert!(!a.eq_in_region(&b, Region::new(0, 0, 3, 3)));
+        assert!(a.eq_in_region(&b, Region::new(2, 0, 10, 10)));
+        assert!(a.eq_in_region(&b, Region::new(-10, -10, 5, 5)));
+
+        // bits beyond the edges of the first grid must be 0 in the second
+        let wide = Pattern("o$bo$66bo!".to_owned()).to_new_bit_grid(67, 3).unwrap();
+        assert!(a.eq_in_region(&wide, Region::new(0, 0, 100, 2)));
+        assert!(!a.eq_in_region(&wide, Region::new(0, 0, 100, 3)));
+        assert!(!wide.eq_in_region(&a, Region::new(0, 0, 100, 3)));
+    }
+
+    #[test]
+    fn set_operations_match_per_cell_operations() {
+        let regions = [
+            Region::new(0, 0, 128, 5),
+            Region::new(-70, -2, 100, 3),
+            Region::new(3, 1, 61, 2),
+            Region::new(63, 0, 2, 10),
+            Region::new(100, 4, 200, 1),
+        ];
+        for (i, &region) in regions.iter().enumerate() {
+            let dst = random_grid(2, 5, i as u64 * 3 + 1);
+            let src = random_grid(1 + i % 2, 3 + i % 3, i as u64 * 3 + 2);
+            for &op in &[
+                SetOperation::And,
+                SetOperation::Or,
+                SetOperation::Xor,
+                SetOperation::AndNot,
+            ] {
+                let mut result = dst.clone();
+                result.combine_in_region(&src, region, op);
+                for row in 0..5 {
+                    for col in 0..128 {
+                        let d = bit(&dst, col, row);
+                        let s = bit(&src, col - region.left(), row - region.top());
+                        let expected = if region.contains(col, row) {
+                            match op {
+                                SetOperation::And => d && s,
+                                SetOperation::Or => d || s,
+                                SetOperation::Xor => d != s,
+                                SetOperation::AndNot => d && !s,
+                            }
+                        } else {
+                            d
+                        };
+                        assert_eq!(
+                            bit(&result, col, row),
+                            expected,
+                            "{:?} {:?} ({}, {})",
+                            op,
+                            region,
+                            col,
+                            row
+                        );
+                    }
+                }
+            }
+            let mut expected_count = 0;
+            for row in 0..5 {
+                for col in 0..128 {
+                    if region.contains(col, row) && bit(&dst, col, row) {
+                        expected_count += 1;
+                    }
+                }
+            }
+            assert_eq!(dst.popcount_in_region(region), expected_count, "{:?}", region);
+        }
+    }
+
+    #[test]
+    fn transform_new_size() {
+        for &(transform, expected) in &[
+            (Transform::Rotate(Rotation::CW), (2, 3)),
+            (Transform::Rotate(Rotation::CCW), (2, 3)),
+            (Transform::Rotate(Rotation::Half), (3, 2)),
+            (Transform::FlipHorizontally, (3, 2)),
+            (Transform::FlipVertically, (3, 2)),
+            (Transform::Transpose, (2, 3)),
+        ] {
+            assert_eq!(transform.new_size(3, 2), expected);
+        }
+    }
+
+    #[test]
+    fn bit_grid_each_set1() {
+        let pat = Pattern("bo$2bo$3o!".to_owned());
+        let grid = pat.to_new_bit_grid(3, 3).unwrap();
+        let expected = vec![(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)];
+        let mut i = 0;
+        grid.each_set(|c, r| {
+            assert_eq!((c, r), expected[i]);
+            i += 1;
+        });
+        assert_eq!(i, expected.len());
+    }
+
+    #[test]
+    fn bit_grid_iter_set_skips_empty_words() {
+        let mut grid = BitGrid::new(4, 3);
+        for &(col, row) in &[(0, 0), (63, 0), (130, 1), (255, 2)] {
+            grid.modify_bits_in_word(row, col / 64, 1 << (63 - col % 64), BitOperation::Set);
+        }
+        let found: Vec<_> = grid.iter_set().collect();
+        assert_eq!(found, vec![(0, 0), (63, 0), (130, 1), (255, 2)]);
+        let mut each = vec![];
+        grid.each_set(|col, row| each.push((col, row)));
+        assert_eq!(each, found);
+    }
+
+    #[test]
+    fn bit_grid_iter_set_none_set() {
+        assert_eq!(BitGrid::new(2, 3).iter_set().next(), None);
+    }
+}
+
+mod rle_tests {
+    use crate::error::ConwayError;
+    use crate::grids::{BitGrid, CharGrid, Rotation, Transform};
+    use crate::rle::*;
+    use crate::rule::Rule;
+    use crate::universe::test_helpers::test_big_bang;
+    use crate::universe::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn one_line_parsing_works1() {
+        // Glider gun
+        let gun = Pattern(
+            "24bo$22bobo$12b2o6b2o12b2o$11bo3bo4b2o12b2o$2o8bo5bo3b2o$2o8bo3bob2o4bobo$10bo5bo7bo$11bo3bo$12b2o!"
+                .to_owned(),
+        )
+        .to_new_bit_grid(36, 9)
+        .unwrap();
+        assert_eq!(
+            gun[0][0],
+            0b0000000000000000000000001000000000000000000000000000000000000000
+        );
+        assert_eq!(
+            gun[1][0],
+            0b0000000000000000000000101000000000000000000000000000000000000000
+        );
+        assert_eq!(
+            gun[2][0],
+            0b0000000000001100000011000000000000110000000000000000000000000000
+        );
+        assert_eq!(
+            gun[3][0],
+            0b0000000000010001000011000000000000110000000000000000000000000000
+        );
+        assert_eq!(
+            gun[4][0],
+            0b1100000000100000100011000000000000000000000000000000000000000000
+        );
+        assert_eq!(
+            gun[5][0],
+            0b1100000000100010110000101000000000000000000000000000000000000000
+        );
+        assert_eq!(
+            gun[6][0],
+            0b0000000000100000100000001000000000000000000000000000000000000000
+        );
+        assert_eq!(
+            gun[7][0],
+            0b0000000000010001000000000000000000000000000000000000000000000000
+        );
+        assert_eq!(
+            gun[8][0],
+            0b0000000000001100000000000000000000000000000000000000000000000000
+        );
+    }
+
+    #[test]
+    fn multi_line_parsing_works1() {
+        // Glider gun with line break
+        let gun = Pattern("24bo$22bobo$12b2o6b2o\r\n12b2o$\r\n11bo3bo4b2o12b2o$2o8b\ro5bo3b2o$2o8bo3bob2o4b\nobo$10bo5bo7bo$11bo3bo$12b2o!".to_owned()).to_new_bit_grid(36, 9).unwrap();
+        assert_eq!(
+            gun[0][0],
+            0b0000000000000000000000001000000000000000000000000000000000000000
+        );
+        assert_eq!(
+            gun[1][0],
+            0b0000000000000000000000101000000000000000000000000000000000000000
+        );
+        assert_eq!(
+            gun[2][0],
+            0b0000000000001100000011000000000000110000000000000000000000000000
+        );
+        assert_eq!(
+            gun[3][0],
+            0b0000000000010001000011000000000000110000000000000000000000000000
+        );
+        assert_eq!(
+            gun[4][0],
+            0b1100000000100000100011000000000000000000000000000000000000000000
+        );
+        assert_eq!(
+            gun[5][0],
+            0b1100000000100010110000101000000000000000000000000000000000000000
+        );
+        assert_eq!(
+            gun[6][0],
+            0b0000000000100000100000001000000000000000000000000000000000000000
+        );
+        assert_eq!(
+            gun[7][0],
+            0b0000000000010001000000000000000000000000000000000000000000000000
+        );
+        assert_eq!(
+            gun[8][0],
+            0b0000000000001100000000000000000000000000000000000000000000000000
+        );
+    }
+
+    #[test]
+    fn parsing_with_new_row_rle_works() {
+        let pattern = Pattern("27bo$28bo$29bo$28bo$27bo$29b3o20$oo$bbo$bbo$3b4o!".to_owned())
+            .to_new_bit_grid(32, 29)
+            .unwrap();
+        assert_eq!(
+            pattern.0[0..=5],
+            [
+                [0b0000000000000000000000000001000000000000000000000000000000000000],
+                [0b0000000000000000000000000000100000000000000000000000000000000000],
+                [0b0000000000000000000000000000010000000000000000000000000000000000],
+                [0b0000000000000000000000000000100000000000000000000000000000000000],
+                [0b0000000000000000000000000001000000000000000000000000000000000000],
+                [0b0000000000000000000000000000011100000000000000000000000000000000]
+            ]
+        );
+        for row in 6..=24 {
+            assert_eq!(pattern.0[row][0], 0);
+        }
+        assert_eq!(
+            pattern.0[25..=28],
+            [
+                [0b1100000000000000000000000000000000000000000000000000000000000000],
+                [0b0010000000000000000000000000000000000000000000000000000000000000],
+                [0b0010000000000000000000000000000000000000000000000000000000000000],
+                [0b0001111000000000000000000000000000000000000000000000000000000000]
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_whole_file_works() {
+        let pat: PatternFile = PatternFile::from_str("#N Gosper glider gun\n#C This was the first gun discovered.\n#C As its name suggests, it was discovered by Bill Gosper.\nx = 36, y = 9, rule = B3/S23\n24bo$22bobo$12b2o6b2o12b2o$11bo3bo4b2o12b2o$2o8bo5bo3b2o$2o8bo3bob2o4b\nobo$10bo5bo7bo$11bo3bo$12b2o!\n").unwrap();
+        assert_eq!(pat.comment_lines.len(), 3);
+        assert_eq!(
+            pat.header_line,
+            HeaderLine {
+                x:    36,
+                y:    9,
+                rule: Some("B3/S23".to_owned()),
+            }
+        );
+        assert_eq!(
+            pat.pattern.0,
+            "24bo$22bobo$12b2o6b2o12b2o$11bo3bo4b2o12b2o$2o8bo5bo3b2o$2o8bo3bob2o4bobo$10bo5bo7bo$11bo3bo$12b2o!"
+        );
+    }
+
+    #[test]
+    fn parse_whole_file_works_with_crap_at_the_end() {
+        let pat: PatternFile = PatternFile::from_str("#N Gosper glider gun\n#C This was the first gun discovered.\n#C As its name suggests, it was discovered by Bill Gosper.\nx = 36, y = 9, rule = B3/S23\n24bo$22bobo$12b2o6b2o12b2o$11bo3bo4b2o12b2o$2o8bo5bo3b2o$2o8bo3bob2o4b\nobo$10bo5bo7bo$11bo3bo$12b2o!blah\n\nyaddayadda\n").unwrap();
+        assert_eq!(pat.comment_lines.len(), 3);
+        assert_eq!(
+            pat.header_line,
+            HeaderLine {
+                x:    36,
+                y:    9,
+                rule: Some("B3/S23".to_owned()),
+            }
+        );
+        assert_eq!(
+            pat.pattern.0,
+            "24bo$22bobo$12b2o6b2o12b2o$11bo3bo4b2o12b2o$2o8bo5bo3b2o$2o8bo3bob2o4bobo$10bo5bo7bo$11bo3bo$12b2o!"
+        );
+    }
+
+    #[test]
+    fn parse_whole_file_works_vacuum_cleaner() {
+        let pat: PatternFile = PatternFile::from_str("#N Vacuum (gun)\r\n#O Dieter Leithner\r\n#C A true period 46 double-barreled gun found on February 21, 1997.\r\n#C www.conwaylife.com/wiki/index.php?title=Vacuum_(gun)\r\nx = 49, y = 43, rule = b3/s23\r\nb2o23b2o21b$b2o23bo22b$24bobo22b$15b2o7b2o23b$2o13bobo31b$2o13bob2o30b\r\n$16b2o31b$16bo32b$44b2o3b$16bo27b2o3b$16b2o31b$2o13bob2o13bo3bo12b$2o\r\n13bobo13bo5bo7b2o2b$15b2o14bo13b2o2b$31b2o3bo12b$b2o30b3o13b$b2o46b$\r\n33b3o13b$31b2o3bo12b$31bo13b2o2b$31bo5bo7b2o2b$32bo3bo12b2$44b2o3b$44b\r\n2o3b5$37b2o10b$37bobo7b2o$39bo7b2o$37b3o9b$22bobo24b$21b3o25b$21b3o25b\r\n$21bo15b3o9b$25bobo11bo9b$21b2o4bo9bobo9b$16b2o4bo3b2o9b2o10b$15bobo6b\r\no24b$15bo33b$14b2o!").unwrap();
+        assert_eq!(pat.comment_lines.len(), 4);
+        assert_eq!(
+            pat.header_line,
+            HeaderLine {
+                x:    49,
+                y:    43,
+                rule: Some("B3/S23".to_owned().to_lowercase()),
+            }
+        );
+        assert_eq!(pat.pattern.0, "b2o23b2o21b$b2o23bo22b$24bobo22b$15b2o7b2o23b$2o13bobo31b$2o13bob2o30b$16b2o31b$16bo32b$44b2o3b$16bo27b2o3b$16b2o31b$2o13bob2o13bo3bo12b$2o13bobo13bo5bo7b2o2b$15b2o14bo13b2o2b$31b2o3bo12b$b2o30b3o13b$b2o46b$33b3o13b$31b2o3bo12b$31bo13b2o2b$31bo5bo7b2o2b$32bo3bo12b2$44b2o3b$44b2o3b5$37b2o10b$37bobo7b2o$39bo7b2o$37b3o9b$22bobo24b$21b3o25b$21b3o25b$21bo15b3o9b$25bobo11bo9b$21b2o4bo9bobo9b$16b2o4bo3b2o9b2o10b$15bobo6bo24b$15bo33b$14b2o!");
+        assert_eq!(
+            pat.to_new_bit_grid().unwrap(),
+            BitGrid(vec![
+                vec![0b0110000000000000000000000011000000000000000000000000000000000000],
+                vec![0b0110000000000000000000000010000000000000000000000000000000000000],
+                vec![0b0000000000000000000000001010000000000000000000000000000000000000],
+                vec![0b0000000000000001100000001100000000000000000000000000000000000000],
+                vec![0b1100000000000001010000000000000000000000000000000000000000000000],
+                vec![0b1100000000000001011000000000000000000000000000000000000000000000],
+                vec![0b0000000000000000110000000000000000000000000000000000000000000000],
+                vec![0b0000000000000000100000000000000000000000000000000000000000000000],
+                vec![0b0000000000000000000000000000000000000000000011000000000000000000],
+                vec![0b0000000000000000100000000000000000000000000011000000000000000000],
+                vec![0b0000000000000000110000000000000000000000000000000000000000000000],
+                vec![0b1100000000000001011000000000000010001000000000000000000000000000],
+                vec![0b1100000000000001010000000000000100000100000001100000000000000000],
+                vec![0b0000000000000001100000000000000100000000000001100000000000000000],
+                vec![0b0000000000000000000000000000000110001000000000000000000000000000],
+                vec![0b0110000000000000000000000000000001110000000000000000000000000000],
+                vec![0b0110000000000000000000000000000000000000000000000000000000000000],
+                vec![0b0000000000000000000000000000000001110000000000000000000000000000],
+                vec![0b0000000000000000000000000000000110001000000000000000000000000000],
+                vec![0b0000000000000000000000000000000100000000000001100000000000000000],
+                vec![0b0000000000000000000000000000000100000100000001100000000000000000],
+                vec![0b0000000000000000000000000000000010001000000000000000000000000000],
+                vec![0b0000000000000000000000000000000000000000000000000000000000000000],
+                vec![0b0000000000000000000000000000000000000000000011000000000000000000],
+                vec![0b0000000000000000000000000000000000000000000011000000000000000000],
+                vec![0b0000000000000000000000000000000000000000000000000000000000000000],
+                vec![0b0000000000000000000000000000000000000000000000000000000000000000],
+                vec![0b0000000000000000000000000000000000000000000000000000000000000000],
+                vec![0b0000000000000000000000000000000000000000000000000000000000000000],
+                vec![0b0000000000000000000000000000000000000110000000000000000000000000],
+                vec![0b0000000000000000000000000000000000000101000000011000000000000000],
+                vec![0b0000000000000000000000000000000000000001000000011000000000000000],
+                vec![0b0000000000000000000000000000000000000111000000000000000000000000],
+                vec![0b0000000000000000000000101000000000000000000000000000000000000000],
+                vec![0b0000000000000000000001110000000000000000000000000000000000000000],
+                vec![0b0000000000000000000001110000000000000000000000000000000000000000],
+                vec![0b0000000000000000000001000000000000000111000000000000000000000000],
+                vec![0b0000000000000000000000000101000000000001000000000000000000000000],
+                vec![0b0000000000000000000001100001000000000101000000000000000000000000],
+                vec![0b0000000000000000110000100011000000000110000000000000000000000000],
+                vec![0b0000000000000001010000001000000000000000000000000000000000000000],
+                vec![0b0000000000000001000000000000000000000000000000000000000000000000],
+                vec![0b0000000000000011000000000000000000000000000000000000000000000000]
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_whole_file_rule() {
+        let pat: PatternFile = PatternFile::from_str("x = 3, y = 3, rule = b36/s23\nbo$2bo$3o!\n").unwrap();
+        assert_eq!(pat.rule().unwrap(), Rule::from_str("B36/S23").unwrap());
+
+        let pat: PatternFile = PatternFile::from_str("x = 3, y = 3\nbo$2bo$3o!\n").unwrap();
+        assert_eq!(pat.rule().unwrap(), Rule::conway());
+
+        let pat: PatternFile = PatternFile::from_str("x = 3, y = 3, rule = bogus\nbo$2bo$3o!\n").unwrap();
+        assert!(pat.rule().is_err());
+    }
+
+    #[test]
+    fn calc_size1() {
+        // Glider gun
+        let gun_pat = Pattern(
+            "24bo$22bobo$12b2o6b2o12b2o$11bo3bo4b2o12b2o$2o8bo5bo3b2o$2o8bo3bob2o4bobo$10bo5bo7bo$11bo3bo$12b2o!"
+                .to_owned(),
+        );
+        let size_result = gun_pat.calc_size();
+        let size = size_result.unwrap();
+        assert_eq!(size, (36, 9));
+    }
+
+    #[test]
+    fn calc_size2() {
+        let pat = Pattern("o!".to_owned());
+        let size_result = pat.calc_size();
+        let size = size_result.unwrap();
+        assert_eq!(size, (1, 1));
+    }
+
+    #[test]
+    fn calc_size_fail1() {
+        let pat = Pattern("invalidpatternlol".to_owned());
+        let size_result = pat.calc_size();
+        let err = size_result.unwrap_err();
+        assert_eq!(
+            err,
+            ConwayError::InvalidData {
+                reason: "Premature termination at Some(16)".to_owned(),
+            }
+        );
+    }
+
+    #[test]
+    fn pattern_transformed_glider() {
+        let glider = Pattern("bo$2bo$3o!".to_owned());
+        for &(transform, expected) in &[
+            (Transform::Rotate(Rotation::CW), "o$obo$2ob!"),
+            (Transform::Rotate(Rotation::CCW), "b2o$obo$2bo!"),
+            (Transform::Rotate(Rotation::Half), "3o$o$bob!"),
+            (Transform::FlipHorizontally, "bo$o$3o!"),
+            (Transform::FlipVertically, "3o$2bo$bob!"),
+            (Transform::Transpose, "2bo$obo$b2o!"),
+        ] {
+            let transformed = glider.transformed(transform).unwrap();
+            assert_eq!(transformed.0, expected, "{:?}", transform);
+            assert_eq!(transformed.calc_size().unwrap(), (3, 3));
+        }
+    }
+
+    #[test]
+    fn pattern_transformed_keeps_size() {
+        let pat = Pattern("3o$o!".to_owned());
+        let rotated = pat.transformed(Transform::Rotate(Rotation::CW)).unwrap();
+        assert_eq!(rotated.0, "2o$bo$bo!");
+        assert_eq!(rotated.calc_size().unwrap(), (2, 3));
+
+        // dead cells at the edges count towards the size
+        let pat = Pattern("3b$bo!".to_owned());
+        let flipped = pat.transformed(Transform::FlipVertically).unwrap();
+        assert_eq!(flipped.0, "bo$3b!");
+        assert_eq!(flipped.calc_size().unwrap(), (3, 2));
+        assert_eq!(
+            flipped
+                .transformed(Transform::FlipVertically)
+                .unwrap()
+                .calc_size()
+                .unwrap(),
+            (3, 2)
+        );
+    }
+
+    #[test]
+    fn pattern_transformed_round_trips() {
+        let pat = Pattern("4bo$5bo$o4bo$b5o!".to_owned()); // LWSS
+        let mut rotated = pat.clone();
+        for _ in 0..4 {
+            rotated = rotated.transformed(Transform::Rotate(Rotation::CW)).unwrap();
+        }
+        assert_eq!(rotated, pat);
+        let transposed = pat.transformed(Transform::Transpose).unwrap();
+        let rotated_and_flipped = pat
+            .transformed(Transform::Rotate(Rotation::CW))
+            .unwrap()
+            .transformed(Transform::FlipHorizontally)
+            .unwrap();
+        assert_eq!(transposed, rotated_and_flipped);
+    }
+
+    #[test]
+    fn pattern_transformed_multi_state() {
+        let pat = Pattern("AW$bB!".to_owned());
+        assert_eq!(pat.transformed(Transform::Transpose).unwrap().0, "A$WB!");
+        assert!(Pattern("AX!".to_owned()).transformed(Transform::Transpose).is_err());
+    }
+
+    #[test]
+    fn pattern_shifted() {
+        let pat = Pattern("o2b!".to_owned());
+        assert_eq!(pat.shifted(1, 0).unwrap().0, "bob!");
+        assert_eq!(pat.shifted(-1, 0).unwrap().0, "2bo!");
+        let pat = Pattern("o$3b!".to_owned());
+        assert_eq!(pat.shifted(-1, 1).unwrap().0, "$2bo!");
+        assert_eq!(pat.shifted(-1, 1).unwrap().calc_size().unwrap(), (3, 2));
+    }
+
+    #[test]
+    fn pattern_cropped() {
+        assert_eq!(Pattern("3b$bo$3b!".to_owned()).cropped().unwrap().0, "o!");
+        assert_eq!(Pattern("5b$b2o$3bo!".to_owned()).cropped().unwrap().0, "2o$2bo!");
+        assert_eq!(Pattern("3b!".to_owned()).cropped().unwrap().0, "!");
+    }
+
+    #[test]
+    fn pattern_padded() {
+        let padded = Pattern("o!".to_owned()).padded(1, 2, 3, 0).unwrap();
+        assert_eq!(padded.0, "2$bo3b!");
+        assert_eq!(padded.calc_size().unwrap(), (5, 3));
+    }
+
+    #[test]
+    fn generations_file_uses_golly_letters() {
+        let text = "x = 4, y = 2, rule = B2/S/C26\nA.2B$.pAA!\n";
+        let file = PatternFile::from_str(text).unwrap();
+        assert_eq!(file.pattern.0, "ob2a$bzo!");
+        assert_eq!(file.pattern.calc_size().unwrap(), (4, 2));
+        assert_eq!(file.to_string(), text);
+
+        // state 25 is out of range with 3 states
+        assert!(PatternFile::from_str("x = 4, y = 2, rule = B2/S/C3\nA.2B$.pAA!").is_err());
+        assert!(PatternFile::from_str("x = 4, y = 2, rule = B2/S/C26\nA.2B$.p!").is_err());
+    }
+
+    #[test]
+    fn generations_file_writes_players_and_walls_as_cells() {
+        let file = PatternFile {
+            comment_lines: vec![],
+            header_line:   HeaderLine::from_str("x = 4, y = 1, rule = 345/2/4").unwrap(),
+            pattern:       Pattern("oAWc!".to_owned()),
+        };
+        assert_eq!(file.to_string(), "x = 4, y = 1, rule = 345/2/4\nAA.C!\n");
+        let parsed = PatternFile::from_str(&file.to_string()).unwrap();
+        assert_eq!(parsed.pattern.0, "oobc!");
+    }
+
+    #[test]
+    fn header_line_display() {
+        let header = HeaderLine::from_str("x = 3, y = 4, rule = B36/S23").unwrap();
+        assert_eq!(header.to_string(), "x = 3, y = 4, rule = B36/S23");
+        let header = HeaderLine::from_str("x=3,y=4").unwrap();
+        assert_eq!(header.to_string(), "x = 3, y = 4");
+    }
+
+    #[test]
+    fn write_whole_file() {
+        let mut grid = BitGrid::new(1, 3);
+        Pattern("bo$2bo$3o!".to_owned()).to_grid(&mut grid, None).unwrap();
+        let file = PatternFile::from_grid(&grid, None)
+            .with_comment("A small spaceship\nthat moves diagonally")
+            .with_author("Richard K. Guy")
+            .with_name("Glider")
+            .with_rule(Rule::conway());
+        assert_eq!(
+            file.to_string(),
+            "#N Glider\n#O Richard K. Guy\n#C A small spaceship\n#C that moves diagonally\n\
+             x = 64, y = 3, rule = B3/S23\nbo$2bo$3o!\n"
+        );
+        assert_eq!(file.name(), Some("Glider"));
+        assert_eq!(file.author(), Some("Richard K. Guy"));
+        assert_eq!(file.comments(), vec!["A small spaceship", "that moves diagonally"]);
+
+        let parsed = PatternFile::from_str(&file.to_string()).unwrap();
+        assert_eq!(parsed, file);
+        assert_eq!(parsed.to_string(), file.to_string());
+    }
+
+    #[test]
+    fn write_whole_file_replaces_name() {
+        let file = PatternFile::from_str("#N Old\n#C Comment\nx = 1, y = 1\no!\n")
+            .unwrap()
+            .with_name("New");
+        assert_eq!(file.name(), Some("New"));
+        assert_eq!(file.comment_lines, vec!["#N New".to_owned(), "#C Comment".to_owned()]);
+    }
+
+    #[test]
+    fn write_whole_file_wraps_lines() {
+        // rows of alternating cells make long patterns with runs of different lengths
+        let mut grid = BitGrid::new(4, 10);
+        for row in 0..10 {
+            for col in (row % 3..256).step_by(row % 4 + 2) {
+                grid.write_at_position(col, row, 'o', None);
+            }
+        }
+        let file = PatternFile::from_grid(&grid, None);
+        let text = file.to_string();
+        let lines: Vec<&str> = text.lines().collect();
+        assert!(lines.len() > 3);
+        for line in &lines {
+            assert!(line.len() <= 70, "line too long: {:?}", line);
+        }
+        for line in &lines[1..lines.len() - 1] {
+            // runs are not split, so lines end with a cell or end-of-row character
+            assert!(!line.ends_with(|ch: char| ch.is_ascii_digit()), "run split: {:?}", line);
+        }
+
+        let parsed = PatternFile::from_str(&text).unwrap();
+        assert_eq!(parsed, file);
+        let mut new_grid = BitGrid::new(4, 10);
+        parsed.pattern.to_grid(&mut new_grid, None).unwrap();
+        assert_eq!(new_grid, grid);
+    }
+
+    fn make_arena() -> Universe {
+        let mut uni = make_empty_arena();
+        for &(col, row) in &[(3, 2), (4, 3), (2, 4), (3, 4), (4, 4)] {
+            uni.toggle(col, row, 0).unwrap();
+            uni.toggle(col + 30, row + 10, 1).unwrap();
+        }
+        for row in 5..15 {
+            uni.set_unchecked(20, row, CellState::Wall);
+        }
+        uni.set_unchecked(10, 18, CellState::Alive(None));
+        uni
     }
 
     #[test]
-    fn bit_grid_rotate_horizontal_line_cw() {
-        let pat = Pattern("10o!".to_owned());
-        let mut grid = pat.to_new_bit_grid(10, 1).unwrap();
-        grid.rotate(10, 1, Rotation::CW).unwrap();
-        let pat_r = grid.to_pattern(None);
-        assert_eq!(pat_r, Pattern("o$o$o$o$o$o$o$o$o$o!".to_owned()));
+    fn multi_state_arena_round_trip() {
+        let uni = make_arena();
+        let text = uni.to_pattern_file(None).with_name("Arena").to_string();
+        assert!(text.contains("W"));
+        assert!(text.contains("A"));
+        assert!(text.contains("B"));
+        assert!(text.contains("x = 40, y = 20, rule = B3/S23\n"));
+
+        let file = PatternFile::from_str(&text).unwrap();
+        assert_eq!(file.name(), Some("Arena"));
+        let mut new_uni = make_empty_arena();
+        assert_ne!(new_uni.to_pattern(None), uni.to_pattern(None));
+        new_uni.load_pattern_file(&file, None).unwrap();
+        assert_eq!(new_uni.to_pattern(None), uni.to_pattern(None));
+    }
+
+    fn make_empty_arena() -> Universe {
+        let player0 = PlayerBuilder::new(Region::new(0, 0, 20, 20));
+        let player1 = PlayerBuilder::new(Region::new(20, 0, 20, 20));
+        test_big_bang(40, 20, vec![player0, player1])
+            .fog_radius(4)
+            .birth()
+            .unwrap()
     }
 
     #[test]
-    fn bit_grid_rotate_glider_cw() {
-        let pat = Pattern("bo$2bo$3o!".to_owned());
-        let mut grid = pat.to_new_bit_grid(3, 3).unwrap();
-        grid.rotate(3, 3, Rotation::CW).unwrap();
-        let pat_r = grid.to_pattern(None);
-        assert_eq!(pat_r, Pattern("o$obo$2o!".to_owned()));
+    fn multi_state_arena_with_fog_round_trip() {
+        let uni = make_arena();
+        let file = PatternFile::from_str(&uni.to_pattern_file(Some(0)).to_string()).unwrap();
+        assert!(file.pattern.0.contains('?'));
+        let mut new_uni = make_empty_arena();
+        new_uni.load_pattern_file(&file, Some(0)).unwrap();
+        assert_eq!(new_uni.to_pattern(Some(0)), uni.to_pattern(Some(0)));
     }
 
     #[test]
-    fn bit_grid_each_set1() {
-        let pat = Pattern("bo$2bo$3o!".to_owned());
-        let grid = pat.to_new_bit_grid(3, 3).unwrap();
-        let expected = vec![(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)];
-        let mut i = 0;
-        grid.each_set(|c, r| {
-            assert_eq!((c, r), expected[i]);
-            i += 1;
-        });
-        assert_eq!(i, expected.len());
+    fn load_pattern_file_errors() {
+        let mut uni = make_empty_arena();
+        let before = uni.to_pattern(None);
+        for (text, visibility) in &[
+            ("x = 41, y = 1\n41o!\n", None),   // too wide
+            ("x = 1, y = 21\n20$o!\n", None),  // too tall
+            ("x = 3, y = 1\n2oC!\n", None),    // player 2 does not exist
+            ("x = 3, y = 1\n2o?!\n", None),    // fog without player
+            ("x = 3, y = 1\n2oA!\n", Some(2)), // player 2 does not exist
+            ("x = 3, y = 1\n2oZ!\n", None),    // invalid character
+        ] {
+            let file = PatternFile::from_str(text).unwrap();
+            match uni.load_pattern_file(&file, *visibility) {
+                Err(ConwayError::InvalidData { .. }) => {}
+                other => panic!("unexpected result {:?} for {:?}", other, text),
+            }
+            assert_eq!(uni.to_pattern(None), before);
+        }
     }
 }
 
-mod rle_tests {
+mod hashlife_tests {
     use crate::error::ConwayError;
     use crate::grids::BitGrid;
-    use crate::rle::*;
+    use crate::hashlife::HashLife;
+    use crate::rle::Pattern;
+    use crate::rule::Rule;
+    use crate::universe::test_helpers::test_big_bang;
+    use crate::universe::*;
     use std::str::FromStr;
 
+    fn live_cells(life: &HashLife) -> Vec<(isize, isize)> {
+        let mut found = vec![];
+        life.each_live_cell(|col, row| found.push((col, row)));
+        found.sort();
+        found
+    }
+
+    fn live_cells_in_universe(uni: &Universe) -> Vec<(isize, isize)> {
+        let mut found = vec![];
+        uni.each_non_dead_full(None, &mut |col, row, _| found.push((col as isize, row as isize)));
+        found.sort();
+        found
+    }
+
+    /// Plane universe big enough that nothing reaches the edges during these tests.
+    fn make_plane_universe(rule: Rule) -> Universe {
+        test_big_bang(256, 256, vec![PlayerBuilder::new(Region::new(0, 0, 256, 256))])
+            .topology(Topology::Plane)
+            .rule(rule)
+            .birth()
+            .unwrap()
+    }
+
     #[test]
-    fn one_line_parsing_works1() {
-        // Glider gun
-        let gun = Pattern(
-            "24bo$22bobo$12b2o6b2o12b2o$11bo3bo4b2o12b2o$2o8bo5bo3b2o$2o8bo3bob2o4bobo$10bo5bo7bo$11bo3bo$12b2o!"
-                .to_owned(),
-        )
-        .to_new_bit_grid(36, 9)
-        .unwrap();
-        assert_eq!(
-            gun[0][0],
-            0b0000000000000000000000001000000000000000000000000000000000000000
-        );
-        assert_eq!(
-            gun[1][0],
-            0b0000000000000000000000101000000000000000000000000000000000000000
-        );
-        assert_eq!(
-            gun[2][0],
-            0b0000000000001100000011000000000000110000000000000000000000000000
-        );
-        assert_eq!(
-            gun[3][0],
-            0b0000000000010001000011000000000000110000000000000000000000000000
-        );
-        assert_eq!(
-            gun[4][0],
-            0b1100000000100000100011000000000000000000000000000000000000000000
-        );
+    fn blinker_oscillates() {
+        let mut life = HashLife::new(Rule::conway()).unwrap();
+        for col in -1..=1 {
+            life.set_cell(col, 0, true);
+        }
+        life.step(1);
+        assert_eq!(live_cells(&life), vec![(0, -1), (0, 0), (0, 1)]);
+        life.step_pow2(5);
+        assert_eq!(live_cells(&life), vec![(0, -1), (0, 0), (0, 1)]);
+        assert_eq!(life.generation(), 33);
+    }
+
+    #[test]
+    fn glider_far_away() {
+        let grid = Pattern("bo$2bo$3o!".to_owned()).to_new_bit_grid(3, 3).unwrap();
+        let mut life = HashLife::from_bit_grid(&grid, Rule::conway()).unwrap();
+        life.step(4 * 1_000_000);
+        assert_eq!(life.population(), 5);
+        assert_eq!(life.bounding_box(), Some(Region::new(1_000_000, 1_000_000, 3, 3)));
+        assert!(life.get_cell(1_000_001, 1_000_000));
+        assert!(!life.get_cell(1, 0));
+    }
+
+    #[test]
+    fn r_pentomino_stabilizes() {
+        let grid = Pattern("b2o$2o$bo!".to_owned()).to_new_bit_grid(3, 3).unwrap();
+        let mut life = HashLife::from_bit_grid(&grid, Rule::conway()).unwrap();
+        life.step(1103);
+        assert_eq!(life.population(), 116);
+        life.step(1 << 12);
+        assert_eq!(life.population(), 116);
+    }
+
+    #[test]
+    fn matches_universe_next() {
+        for rule_str in &["B3/S23", "B36/S23", "B3678/S34678"] {
+            let rule = Rule::from_str(rule_str).unwrap();
+            let mut uni = make_plane_universe(rule);
+            // pseudo-random soup in the middle of the universe
+            let mut seed: u32 = 12345;
+            for row in 112..144 {
+                for col in 112..144 {
+                    seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                    if (seed >> 16) & 1 == 1 {
+                        uni.toggle(col, row, 0).unwrap();
+                    }
+                }
+            }
+            let mut life = uni.to_hash_life().unwrap();
+            for _ in 0..50 {
+                uni.next();
+            }
+            life.step(50);
+            assert_eq!(live_cells(&life), live_cells_in_universe(&uni), "rule {}", rule_str);
+        }
+    }
+
+    #[test]
+    fn bit_grid_round_trip() {
+        let grid = Pattern("o2bo$b2o$66bo!".to_owned()).to_new_bit_grid(67, 3).unwrap();
+        let life = HashLife::from_bit_grid(&grid, Rule::conway()).unwrap();
+        assert_eq!(life.to_bit_grid(Region::new(0, 0, 67, 3)), grid);
+        let clipped = life.to_bit_grid(Region::new(1, 1, 2, 2));
+        let mut expected = BitGrid::new(1, 2);
+        expected[0][0] = 0b11 << 62;
+        assert_eq!(clipped, expected);
+    }
+
+    #[test]
+    fn copy_into_universe_clears_ownership_and_respects_walls() {
+        let mut uni = make_plane_universe(Rule::conway());
+        for col in 10..13 {
+            uni.toggle(col, 10, 0).unwrap();
+        }
+        uni.set_unchecked(11, 11, CellState::Wall);
+        let mut life = uni.to_hash_life().unwrap();
+        life.step(1);
+        uni.copy_from_hash_life(&life);
+        assert_eq!(uni.get_cell_state(11, 9, None), CellState::Alive(None));
+        assert_eq!(uni.get_cell_state(11, 9, Some(0)), CellState::Dead);
+        assert_eq!(uni.get_cell_state(11, 10, None), CellState::Alive(None));
+        assert_eq!(uni.get_cell_state(11, 11, None), CellState::Dead); // wall
+        assert_eq!(uni.get_cell_state(10, 10, None), CellState::Dead);
+    }
+
+    #[test]
+    fn rejects_birth_on_zero() {
+        let rule = Rule::from_str("B03/S23").unwrap();
         assert_eq!(
-            gun[5][0],
-            0b1100000000100010110000101000000000000000000000000000000000000000
+            HashLife::new(rule).err(),
+            Some(ConwayError::InvalidData {
+                reason: "HashLife does not support rules with birth on 0 neighbors (B03/S23)".to_owned(),
+            })
         );
+    }
+
+    #[test]
+    fn rejects_generations_rules() {
+        let rule = Rule::from_str("B2/S/C3").unwrap();
         assert_eq!(
-            gun[6][0],
-            0b0000000000100000100000001000000000000000000000000000000000000000
+            HashLife::new(rule).err(),
+            Some(ConwayError::InvalidData {
+                reason: "HashLife does not support Generations rules (B2/S/C3)".to_owned(),
+            })
         );
+    }
+}
+
+mod snapshot_tests {
+    use crate::action::Action;
+    use crate::budget::Budget;
+    use crate::error::ConwayError;
+    use crate::grids::{BitGrid, BitOperation, CharGrid};
+    use crate::rule::Rule;
+    use crate::snapshot::{SnapshotFormat, SNAPSHOT_VERSION};
+    use crate::universe::test_helpers::test_big_bang;
+    use crate::universe::*;
+    use std::str::FromStr;
+
+    fn make_universe() -> Universe {
+        let player0 = PlayerBuilder::new(Region::new(0, 0, 40, 40));
+        let player1 = PlayerBuilder::new(Region::new(60, 30, 40, 40));
+        let mut uni = test_big_bang(100, 70, vec![player0, player1])
+            .history(4)
+            .fog_radius(5)
+            .rule(Rule::from_str("B36/S23").unwrap())
+            .topology(Topology::KleinBottle)
+            .activity_window(3)
+            .birth()
+            .unwrap();
+        // glider for each player, and some walls
+        for &(col, row) in &[(11, 10), (12, 11), (10, 12), (11, 12), (12, 12)] {
+            uni.toggle(col, row, 0).unwrap();
+            uni.toggle(col + 70, row + 40, 1).unwrap();
+        }
+        for row in 20..30 {
+            uni.set_unchecked(50, row, CellState::Wall);
+        }
+        uni.schedule(6, 0, Action::Toggle { col: 30, row: 5 }).unwrap();
+        for _ in 0..6 {
+            uni.next();
+        }
+        // a budget that was used at the latest generation, and an action waiting for the next one
+        uni.set_budget(1, Some(Budget::new(10, 1).rate_limit(2, 5))).unwrap();
+        uni.toggle(95, 65, 1).unwrap();
+        uni.schedule(8, 0, Action::Toggle { col: 30, row: 30 }).unwrap();
+        uni
+    }
+
+    fn assert_same_universe(a: &Universe, b: &Universe) {
+        assert_eq!(a.latest_gen(), b.latest_gen());
+        assert_eq!(a.width(), b.width());
+        assert_eq!(a.height(), b.height());
+        assert_eq!(a.rule(), b.rule());
+        assert_eq!(a.topology(), b.topology());
+        for &visibility in &[None, Some(0), Some(1)] {
+            assert_eq!(a.to_pattern(visibility), b.to_pattern(visibility));
+        }
+    }
+
+    #[test]
+    fn round_trip_binary_and_json() {
+        for &format in &[SnapshotFormat::Binary, SnapshotFormat::Json] {
+            let mut uni = make_universe();
+            let data = uni.save_snapshot(format).unwrap();
+            let mut loaded = Universe::load_snapshot(&data).unwrap();
+            assert_same_universe(&uni, &loaded);
+
+            // history is restored too
+            let latest = uni.latest_gen();
+            assert_eq!(
+                uni.diff(latest - 3, latest, Some(1)),
+                loaded.diff(latest - 3, latest, Some(1))
+            );
+
+            // and so are the budgets, scheduled actions and activity
+            assert_eq!(loaded.budget(1), uni.budget(1));
+            assert_eq!(loaded.available_cells(1), Some(9));
+            assert_eq!(loaded.scheduled_actions(), uni.scheduled_actions());
+            assert_eq!(loaded.activity_window(), Some(3));
+            assert_eq!(loaded.activity(uni.region(), None), uni.activity(uni.region(), None));
+            for uni in &mut [&mut uni, &mut loaded] {
+                assert!(uni.toggle(96, 65, 1).is_ok());
+                assert!(uni.toggle(97, 65, 1).is_err()); // rate limited
+                uni.rewind_to(latest - 2).unwrap();
+                assert_eq!(uni.available_cells(1), Some(10));
+                assert_eq!(uni.scheduled_actions().len(), 2);
+            }
+
+            // and both keep evolving the same way
+            for _ in 0..4 {
+                uni.next();
+                loaded.next();
+            }
+            assert_same_universe(&uni, &loaded);
+        }
+    }
+
+    #[test]
+    fn round_trip_writable_areas() {
+        let mut mask = BitGrid::new(2, 70);
+        mask.modify_region(Region::new(60, 30, 10, 10), BitOperation::Set);
+        let player0 = PlayerBuilder::new(Region::new(0, 0, 10, 10)).add_region(Region::new(20, 20, 5, 5));
+        let player1 = PlayerBuilder::new(mask);
+        let uni = BigBang::new()
+            .width(100)
+            .height(70)
+            .add_players(vec![player0, player1])
+            .birth()
+            .unwrap();
+        for &format in &[SnapshotFormat::Binary, SnapshotFormat::Json] {
+            let loaded = Universe::load_snapshot(&uni.save_snapshot(format).unwrap()).unwrap();
+            for &(col, row, player_id, expected) in &[(22, 22, 0, true), (15, 15, 0, false), (65, 35, 1, true)] {
+                assert_eq!(loaded.writable(col, row, player_id).unwrap(), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn round_trip_generations_rule() {
+        let mut uni = BigBang::new()
+            .width(100)
+            .height(70)
+            .history(4)
+            .rule(Rule::from_str("B2/S345/C4").unwrap())
+            .birth()
+            .unwrap();
+        for &(col, row) in &[(10, 10), (11, 10), (10, 11), (30, 30), (31, 30)] {
+            uni.set_unchecked(col, row, CellState::Alive(None));
+        }
+        for _ in 0..3 {
+            uni.next();
+        }
+        for &format in &[SnapshotFormat::Binary, SnapshotFormat::Json] {
+            let mut loaded = Universe::load_snapshot(&uni.save_snapshot(format).unwrap()).unwrap();
+            assert_eq!(loaded.checksum(4, None), uni.checksum(4, None));
+            assert_eq!(loaded.next(), 5);
+        }
+    }
+
+    #[test]
+    fn json_is_human_readable() {
+        let uni = make_universe();
+        let json = String::from_utf8(uni.save_snapshot(SnapshotFormat::Json).unwrap()).unwrap();
+        assert!(json.contains("\"rule\": \"B36/S23\""));
+        assert!(json.contains("\"topology\": \"KleinBottle\""));
+        assert!(json.contains("\"rle\": \""));
+    }
+
+    #[test]
+    fn load_rejects_other_versions() {
+        let uni = make_universe();
+        let data = uni.save_snapshot(SnapshotFormat::Json).unwrap();
+        let mut value: serde_json::Value = serde_json::from_slice(&data).unwrap();
+        value["version"] = serde_json::Value::from(SNAPSHOT_VERSION + 1);
+        let data = serde_json::to_vec(&value).unwrap();
         assert_eq!(
-            gun[7][0],
-            0b0000000000010001000000000000000000000000000000000000000000000000
+            Universe::load_snapshot(&data).err(),
+            Some(ConwayError::InvalidData {
+                reason: format!(
+                    "unsupported snapshot version {} (expected {})",
+                    SNAPSHOT_VERSION + 1,
+                    SNAPSHOT_VERSION
+                ),
+            })
         );
+    }
+
+    #[test]
+    fn load_rejects_inconsistent_snapshot() {
+        let uni = make_universe();
+        let data = uni.save_snapshot(SnapshotFormat::Json).unwrap();
+        let mut value: serde_json::Value = serde_json::from_slice(&data).unwrap();
+        value["height"] = serde_json::Value::from(71);
+        let data = serde_json::to_vec(&value).unwrap();
+        assert!(Universe::load_snapshot(&data).is_err());
+
+        let mut value: serde_json::Value = serde_json::from_slice(&data).unwrap();
+        value["height"] = serde_json::Value::from(70);
+        value["player_writable"].as_array_mut().unwrap().pop();
+        let data = serde_json::to_vec(&value).unwrap();
+        assert!(Universe::load_snapshot(&data).is_err());
+    }
+
+    #[test]
+    fn load_rejects_garbage() {
+        assert!(Universe::load_snapshot(b"").is_err());
+        assert!(Universe::load_snapshot(b"{\"version\": 1}").is_err());
+        let data = make_universe().save_snapshot(SnapshotFormat::Binary).unwrap();
+        assert!(Universe::load_snapshot(&data[..data.len() / 2]).is_err());
+    }
+}
+
+mod formats_tests {
+    use crate::error::ConwayError;
+    use crate::formats::*;
+    use crate::grids::{BitGrid, CharGrid};
+    use crate::rule::Rule;
+    use crate::universe::*;
+    use std::str::FromStr;
+
+    const GLIDER_RLE: &str = "#N Glider\n#C A small spaceship\nx = 3, y = 3, rule = B3/S23\nbo$2bo$3o!\n";
+    const GLIDER_PLAINTEXT: &str = "!Name: Glider\n!A small spaceship\n.O\n..O\nOOO\n";
+    const GLIDER_LIFE_105: &str = "#Life 1.05\n#D A small spaceship\n#N\n#P -1 -1\n.*\n..*\n***\n";
+    const GLIDER_LIFE_106: &str = "#Life 1.06\n0 -1\n1 0\n-1 1\n0 1\n1 1\n";
+    const GLIDER_MACROCELL: &str = "[M2] (golly 3.3)\n#R B3/S23\n#C A small spaceship\n.*$..*$***$\n4 0 0 0 1\n";
+
+    fn assert_is_glider(pattern: &LifePattern) {
+        assert_eq!((pattern.width(), pattern.height()), (3, 3));
+        let mut cells = vec![];
+        pattern.cells().each_set(|col, row| cells.push((col, row)));
+        assert_eq!(cells, vec![(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)]);
+    }
+
+    /// A pattern wider than a Life 1.05 line and than one Macrocell leaf, with several identical
+    /// 8x8 blocks.
+    fn make_pattern() -> LifePattern {
+        let mut pattern = LifePattern::new(150, 37).unwrap();
+        for row in 0..37 {
+            for col in 0..150 {
+                if (col * 7 + row * 3) % 11 < 3 || (col / 8 + row / 8) % 5 == 0 {
+                    pattern.set_alive(col, row);
+                }
+            }
+        }
+        pattern.comments = vec!["first".to_owned(), "second".to_owned()];
+        pattern
+    }
+
+    #[test]
+    fn detect_formats() {
+        assert_eq!(PatternFormat::detect(GLIDER_RLE), Some(PatternFormat::Rle));
+        assert_eq!(PatternFormat::detect("x = 1, y = 1\no!"), Some(PatternFormat::Rle));
+        assert_eq!(PatternFormat::detect(GLIDER_PLAINTEXT), Some(PatternFormat::Plaintext));
+        assert_eq!(PatternFormat::detect(".O\n..O\nOOO\n"), Some(PatternFormat::Plaintext));
+        assert_eq!(PatternFormat::detect(GLIDER_LIFE_105), Some(PatternFormat::Life105));
+        assert_eq!(PatternFormat::detect(GLIDER_LIFE_106), Some(PatternFormat::Life106));
+        assert_eq!(PatternFormat::detect(GLIDER_MACROCELL), Some(PatternFormat::Macrocell));
+        assert_eq!(PatternFormat::detect("\n\n  [M2]\n"), Some(PatternFormat::Macrocell));
+        assert_eq!(PatternFormat::detect("hello world"), None);
+        assert_eq!(PatternFormat::detect(""), None);
+    }
+
+    #[test]
+    fn parse_glider_in_every_format() {
+        for text in &[
+            GLIDER_RLE,
+            GLIDER_PLAINTEXT,
+            GLIDER_LIFE_105,
+            GLIDER_LIFE_106,
+            GLIDER_MACROCELL,
+        ] {
+            let pattern = LifePattern::parse(text).unwrap();
+            assert_is_glider(&pattern);
+        }
+        let pattern = LifePattern::parse(GLIDER_RLE).unwrap();
+        assert_eq!(pattern.name, Some("Glider".to_owned()));
+        assert_eq!(pattern.comments, vec!["A small spaceship".to_owned()]);
+        assert_eq!(pattern.rule, Some(Rule::conway()));
+
+        let pattern = LifePattern::parse(GLIDER_PLAINTEXT).unwrap();
+        assert_eq!(pattern.name, Some("Glider".to_owned()));
+        assert_eq!(pattern.comments, vec!["A small spaceship".to_owned()]);
+        assert_eq!(pattern.rule, None);
+
+        let pattern = LifePattern::parse(GLIDER_LIFE_105).unwrap();
+        assert_eq!(pattern.comments, vec!["A small spaceship".to_owned()]);
+        assert_eq!(pattern.rule, Some(Rule::conway()));
+
+        let pattern = LifePattern::parse(GLIDER_MACROCELL).unwrap();
+        assert_eq!(pattern.comments, vec!["A small spaceship".to_owned()]);
+        assert_eq!(pattern.rule, Some(Rule::conway()));
+    }
+
+    #[test]
+    fn parse_life_105_rule_and_blocks() {
+        let text = "#Life 1.05\n#R 23/36\n#P 10 10\n*\n.\n*\n#P -5 10\n**\n";
+        let pattern = LifePattern::parse(text).unwrap();
+        assert_eq!(pattern.rule, Some(Rule::from_str("B36/S23").unwrap()));
+        assert_eq!((pattern.width(), pattern.height()), (16, 3));
+        assert!(pattern.is_alive(0, 0));
+        assert!(pattern.is_alive(1, 0));
+        assert!(pattern.is_alive(15, 0));
+        assert!(!pattern.is_alive(15, 1));
+        assert!(pattern.is_alive(15, 2));
+    }
+
+    #[test]
+    fn parse_macrocell_level_1_nodes() {
+        // a 2x2 block made of level 1 nodes, whose children are cell states
+        let text = "[M2]\n1 0 0 0 1\n1 0 0 1 0\n1 0 1 0 0\n1 1 0 0 0\n2 1 2 3 4\n";
+        let pattern = LifePattern::parse(text).unwrap();
+        assert_eq!((pattern.width(), pattern.height()), (2, 2));
+        assert!(pattern.is_alive(0, 0) && pattern.is_alive(1, 0) && pattern.is_alive(0, 1) && pattern.is_alive(1, 1));
+    }
+
+    #[test]
+    fn round_trip_every_format() {
+        let pattern = make_pattern();
+        for &format in &[
+            PatternFormat::Rle,
+            PatternFormat::Plaintext,
+            PatternFormat::Life105,
+            PatternFormat::Life106,
+            PatternFormat::Macrocell,
+        ] {
+            let text = pattern.write(format);
+            assert_eq!(PatternFormat::detect(&text), Some(format));
+            let parsed = LifePattern::parse(&text).unwrap();
+            assert_eq!(parsed.cells(), pattern.cells(), "{:?}", format);
+            assert_eq!((parsed.width(), parsed.height()), (150, 37), "{:?}", format);
+            if format != PatternFormat::Life106 {
+                assert_eq!(parsed.comments, pattern.comments, "{:?}", format);
+            }
+        }
+    }
+
+    #[test]
+    fn round_trip_name_and_rule() {
+        let mut pattern = LifePattern::parse(GLIDER_RLE).unwrap();
+        pattern.rule = Some(Rule::from_str("B36/S23").unwrap());
+        for &format in &[PatternFormat::Rle, PatternFormat::Life105, PatternFormat::Macrocell] {
+            let parsed = LifePattern::parse(&pattern.write(format)).unwrap();
+            assert_eq!(parsed.rule, pattern.rule, "{:?}", format);
+        }
+        for &format in &[PatternFormat::Rle, PatternFormat::Plaintext] {
+            let parsed = LifePattern::parse(&pattern.write(format)).unwrap();
+            assert_eq!(parsed.name, pattern.name, "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn macrocell_reuses_nodes() {
+        let mut pattern = LifePattern::new(64, 64).unwrap();
+        for row in 0..64 {
+            for col in 0..64 {
+                pattern.set_alive(col, row);
+            }
+        }
+        let text = pattern.write(PatternFormat::Macrocell);
+        // one leaf, and one node for each of the levels 4 through 6
         assert_eq!(
-            gun[8][0],
-            0b0000000000001100000000000000000000000000000000000000000000000000
+            text.lines()
+                .filter(|line| !line.starts_with('[') && !line.starts_with('#'))
+                .count(),
+            4
         );
+        assert_eq!(LifePattern::parse(&text).unwrap().cells(), pattern.cells());
+    }
+
+    #[test]
+    fn empty_patterns() {
+        let pattern = LifePattern::new(0, 0).unwrap();
+        for &format in &[
+            PatternFormat::Rle,
+            PatternFormat::Life105,
+            PatternFormat::Life106,
+            PatternFormat::Macrocell,
+        ] {
+            let parsed = LifePattern::parse(&pattern.write(format)).unwrap();
+            assert_eq!((parsed.width(), parsed.height()), (0, 0), "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn parse_errors() {
+        for &(text, format) in &[
+            ("hello world", None),
+            ("!Bad cell\n.O\n.X\n", Some(PatternFormat::Plaintext)),
+            ("#Life 1.05\n*\n", Some(PatternFormat::Life105)),
+            ("#Life 1.05\n#P 0 0\n*o*\n", Some(PatternFormat::Life105)),
+            ("#Life 1.06\n0\n", Some(PatternFormat::Life106)),
+            ("#Life 1.06\n0 0\n100000 0\n", Some(PatternFormat::Life106)),
+            ("[M2]\n*********$\n", Some(PatternFormat::Macrocell)),
+            ("[M2]\n.*$\n5 1 0 0 0\n", Some(PatternFormat::Macrocell)),
+            ("[M2]\n4 0 0 0 7\n", Some(PatternFormat::Macrocell)),
+        ] {
+            if let Some(format) = format {
+                assert_eq!(PatternFormat::detect(text), Some(format));
+            }
+            match LifePattern::parse(text) {
+                Err(ConwayError::InvalidData { .. }) => {}
+                other => panic!("unexpected result {:?} for {:?}", other, text),
+            }
+        }
+        // A tiny file describing cells far apart
+        let mut text = "[M2]\n*$\n4 1 0 0 0\n".to_owned();
+        for level in 5..=40 {
+            text.push_str(&format!("{} {} 0 0 {}\n", level, level - 3, level - 3));
+        }
+        match LifePattern::parse(&text) {
+            Err(ConwayError::InvalidData { .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn pattern_size_is_bounded() {
+        assert!(LifePattern::new(MAX_PATTERN_SIZE, 1).is_ok());
+        assert!(LifePattern::new(1, MAX_PATTERN_SIZE + 1).is_err());
+        let text = format!("#Life 1.06\n0 0\n{} 0\n", MAX_PATTERN_SIZE - 1);
+        assert_eq!(LifePattern::parse(&text).unwrap().width(), MAX_PATTERN_SIZE);
+        let text = format!("#Life 1.06\n0 0\n{} {}\n", MAX_PATTERN_SIZE, MAX_PATTERN_SIZE);
+        match LifePattern::parse(&text) {
+            Err(ConwayError::InvalidData { .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn from_and_to_grids() {
+        let player0 = PlayerBuilder::new(Region::new(0, 0, 20, 20));
+        let mut uni = BigBang::new()
+            .width(20)
+            .height(20)
+            .add_players(vec![player0])
+            .birth()
+            .unwrap();
+        uni.toggle(1, 0, 0).unwrap();
+        uni.set_unchecked(2, 1, CellState::Alive(None));
+        uni.set_unchecked(3, 3, CellState::Wall);
+        let pattern = LifePattern::from_grid(&uni, None).unwrap();
+        assert_eq!((pattern.width(), pattern.height()), (20, 20));
+        let mut cells = vec![];
+        pattern.cells().each_set(|col, row| cells.push((col, row)));
+        assert_eq!(cells, vec![(1, 0), (2, 1)]);
+
+        let glider = LifePattern::parse(GLIDER_LIFE_106).unwrap();
+        let mut grid = BitGrid::new(1, 3);
+        glider.to_grid(&mut grid, None).unwrap();
+        assert_eq!(grid.to_pattern(None).0, "bo$2bo$3o!");
+
+        let mut small_grid = BitGrid::new(1, 2);
+        match glider.to_grid(&mut small_grid, None) {
+            Err(ConwayError::InvalidData { .. }) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
+
+mod analysis_tests {
+    use crate::analysis::*;
+    use crate::error::ConwayError;
+    use crate::grids::BitGrid;
+    use crate::rle::Pattern;
+    use crate::rule::Rule;
+    use std::str::FromStr;
+
+    fn analyze_rle(rle: &str, max_generations: usize) -> Analysis {
+        analyze_pattern(&Pattern(rle.to_owned()), Rule::conway(), max_generations).unwrap()
+    }
+
+    #[test]
+    fn still_lifes() {
+        for rle in &["2o$2o!", "b2o$o2bo$b2o!", "2o$obo$bo!"] {
+            let analysis = analyze_rle(rle, 10);
+            assert_eq!(analysis.class, PatternClass::StillLife, "{}", rle);
+            assert_eq!(analysis.generation, 0);
+        }
+    }
+
+    #[test]
+    fn oscillators() {
+        assert_eq!(analyze_rle("3o!", 10).class, PatternClass::Oscillator { period: 2 });
+        assert_eq!(analyze_rle("b3o$3o!", 10).class, PatternClass::Oscillator { period: 2 });
+        // pentadecathlon
+        let analysis = analyze_rle("2bo4bo2b$2ob4ob2o$2bo4bo!", 100);
+        assert_eq!(analysis.class, PatternClass::Oscillator { period: 15 });
+        assert_eq!(analysis.generation, 0);
     }
 
     #[test]
-    fn multi_line_parsing_works1() {
-        // Glider gun with line break
-        let gun = Pattern("24bo$22bobo$12b2o6b2o\r\n12b2o$\r\n11bo3bo4b2o12b2o$2o8b\ro5bo3b2o$2o8bo3bob2o4b\nobo$10bo5bo7bo$11bo3bo$12b2o!".to_owned()).to_new_bit_grid(36, 9).unwrap();
-        assert_eq!(
-            gun[0][0],
-            0b0000000000000000000000001000000000000000000000000000000000000000
-        );
-        assert_eq!(
-            gun[1][0],
-            0b0000000000000000000000101000000000000000000000000000000000000000
-        );
-        assert_eq!(
-            gun[2][0],
-            0b0000000000001100000011000000000000110000000000000000000000000000
-        );
-        assert_eq!(
-            gun[3][0],
-            0b0000000000010001000011000000000000110000000000000000000000000000
-        );
-        assert_eq!(
-            gun[4][0],
-            0b1100000000100000100011000000000000000000000000000000000000000000
-        );
-        assert_eq!(
-            gun[5][0],
-            0b1100000000100010110000101000000000000000000000000000000000000000
-        );
+    fn spaceships() {
         assert_eq!(
-            gun[6][0],
-            0b0000000000100000100000001000000000000000000000000000000000000000
+            analyze_rle("bo$2bo$3o!", 10).class,
+            PatternClass::Spaceship {
+                period: 4,
+                dx:     1,
+                dy:     1,
+            }
         );
         assert_eq!(
-            gun[7][0],
-            0b0000000000010001000000000000000000000000000000000000000000000000
+            analyze_rle("3o$o$bo!", 10).class,
+            PatternClass::Spaceship {
+                period: 4,
+                dx:     -1,
+                dy:     -1,
+            }
         );
+        // lightweight spaceship
         assert_eq!(
-            gun[8][0],
-            0b0000000000001100000000000000000000000000000000000000000000000000
+            analyze_rle("bo2bo$o4b$o3bo$4o!", 10).class,
+            PatternClass::Spaceship {
+                period: 4,
+                dx:     -2,
+                dy:     0,
+            }
         );
     }
 
     #[test]
-    fn parsing_with_new_row_rle_works() {
-        let pattern = Pattern("27bo$28bo$29bo$28bo$27bo$29b3o20$oo$bbo$bbo$3b4o!".to_owned())
-            .to_new_bit_grid(32, 29)
-            .unwrap();
-        assert_eq!(
-            pattern.0[0..=5],
-            [
-                [0b0000000000000000000000000001000000000000000000000000000000000000],
-                [0b0000000000000000000000000000100000000000000000000000000000000000],
-                [0b0000000000000000000000000000010000000000000000000000000000000000],
-                [0b0000000000000000000000000000100000000000000000000000000000000000],
-                [0b0000000000000000000000000001000000000000000000000000000000000000],
-                [0b0000000000000000000000000000011100000000000000000000000000000000]
-            ]
-        );
-        for row in 6..=24 {
-            assert_eq!(pattern.0[row][0], 0);
+    fn patterns_that_settle() {
+        // T-tetromino becomes a traffic light
+        let analysis = analyze_rle("3o$bo!", 20);
+        assert_eq!(analysis.class, PatternClass::Oscillator { period: 2 });
+        assert!(analysis.generation > 0);
+
+        let analysis = analyze_rle("o!", 10);
+        assert_eq!(analysis.class, PatternClass::DiesOut);
+        assert_eq!(analysis.generation, 1);
+
+        let analysis = analyze(&BitGrid::new(1, 1), Rule::conway(), 10).unwrap();
+        assert_eq!(analysis.class, PatternClass::DiesOut);
+        assert_eq!(analysis.generation, 0);
+    }
+
+    #[test]
+    fn unknown_patterns() {
+        // R-pentomino takes 1103 generations to settle
+        assert_eq!(analyze_rle("b2o$2o$bo!", 100).class, PatternClass::Unknown);
+        // not enough generations to see the period
+        assert_eq!(analyze_rle("3o!", 1).class, PatternClass::Unknown);
+        // Gosper glider gun grows forever
+        let gun = "24bo$22bobo$12b2o6b2o12b2o$11bo3bo4b2o12b2o$2o8bo5bo3b2o$2o8bo3bob2o4bobo$10bo5bo7bo$11bo3bo$12b2o!";
+        assert_eq!(analyze_rle(gun, 300).class, PatternClass::Unknown);
+    }
+
+    #[test]
+    fn other_rules() {
+        // in HighLife, the blinker is still an oscillator
+        let highlife = Rule::from_str("B36/S23").unwrap();
+        let analysis = analyze_pattern(&Pattern("3o!".to_owned()), highlife, 10).unwrap();
+        assert_eq!(analysis.class, PatternClass::Oscillator { period: 2 });
+        // without survival, the block dies out
+        let no_survival = Rule::from_str("B3/S").unwrap();
+        let analysis = analyze_pattern(&Pattern("2o$2o!".to_owned()), no_survival, 10).unwrap();
+        assert_eq!(analysis.class, PatternClass::DiesOut);
+        assert_eq!(analysis.generation, 1);
+
+        let b0 = Rule::from_str("B03/S23").unwrap();
+        match analyze_pattern(&Pattern("3o!".to_owned()), b0, 10) {
+            Err(ConwayError::InvalidData { .. }) => {}
+            other => panic!("unexpected result {:?}", other),
         }
-        assert_eq!(
-            pattern.0[25..=28],
-            [
-                [0b1100000000000000000000000000000000000000000000000000000000000000],
-                [0b0010000000000000000000000000000000000000000000000000000000000000],
-                [0b0010000000000000000000000000000000000000000000000000000000000000],
-                [0b0001111000000000000000000000000000000000000000000000000000000000]
-            ]
-        );
+        let brians_brain = Rule::from_str("B2/S/C3").unwrap();
+        assert!(analyze_pattern(&Pattern("2o!".to_owned()), brians_brain, 10).is_err());
     }
 
     #[test]
-    fn parse_whole_file_works() {
-        let pat: PatternFile = PatternFile::from_str("#N Gosper glider gun\n#C This was the first gun discovered.\n#C As its name suggests, it was discovered by Bill Gosper.\nx = 36, y = 9, rule = B3/S23\n24bo$22bobo$12b2o6b2o12b2o$11bo3bo4b2o12b2o$2o8bo5bo3b2o$2o8bo3bob2o4b\nobo$10bo5bo7bo$11bo3bo$12b2o!\n").unwrap();
-        assert_eq!(pat.comment_lines.len(), 3);
+    fn display() {
+        assert_eq!(PatternClass::StillLife.to_string(), "still life");
+        assert_eq!(PatternClass::Oscillator { period: 23 }.to_string(), "P23 oscillator");
         assert_eq!(
-            pat.header_line,
-            HeaderLine {
-                x:    36,
-                y:    9,
-                rule: Some("B3/S23".to_owned()),
+            PatternClass::Spaceship {
+                period: 4,
+                dx:     1,
+                dy:     -1,
             }
-        );
-        assert_eq!(
-            pat.pattern.0,
-            "24bo$22bobo$12b2o6b2o12b2o$11bo3bo4b2o12b2o$2o8bo5bo3b2o$2o8bo3bob2o4bobo$10bo5bo7bo$11bo3bo$12b2o!"
+            .to_string(),
+            "P4 spaceship moving (1, -1)"
         );
     }
+}
+
+mod game_tests {
+    use crate::error::ConwayError;
+    use crate::game::*;
+    use crate::universe::test_helpers::test_big_bang;
+    use crate::universe::*;
+
+    fn make_universe(num_players: usize) -> Universe {
+        let players = (0..num_players)
+            .map(|player_id| PlayerBuilder::new(Region::new(player_id as isize * 32, 0, 32, 64)))
+            .collect();
+        test_big_bang(64, 64, players).birth().unwrap()
+    }
+
+    fn add_block(uni: &mut Universe, col: usize, row: usize, player_id: usize) {
+        for &(dx, dy) in &[(0, 0), (1, 0), (0, 1), (1, 1)] {
+            uni.set_unchecked(col + dx, row + dy, CellState::Alive(Some(player_id)));
+        }
+    }
 
     #[test]
-    fn parse_whole_file_works_with_crap_at_the_end() {
-        let pat: PatternFile = PatternFile::from_str("#N Gosper glider gun\n#C This was the first gun discovered.\n#C As its name suggests, it was discovered by Bill Gosper.\nx = 36, y = 9, rule = B3/S23\n24bo$22bobo$12b2o6b2o12b2o$11bo3bo4b2o12b2o$2o8bo5bo3b2o$2o8bo3bob2o4b\nobo$10bo5bo7bo$11bo3bo$12b2o!blah\n\nyaddayadda\n").unwrap();
-        assert_eq!(pat.comment_lines.len(), 3);
-        assert_eq!(
-            pat.header_line,
-            HeaderLine {
-                x:    36,
-                y:    9,
-                rule: Some("B3/S23".to_owned()),
-            }
-        );
-        assert_eq!(
-            pat.pattern.0,
-            "24bo$22bobo$12b2o6b2o12b2o$11bo3bo4b2o12b2o$2o8bo5bo3b2o$2o8bo3bob2o4bobo$10bo5bo7bo$11bo3bo$12b2o!"
-        );
+    fn last_survivor_wins() {
+        let mut uni = make_universe(2);
+        add_block(&mut uni, 10, 10, 0);
+        uni.set_unchecked(40, 10, CellState::Alive(Some(1)));
+        let mut game = Game::new(uni, Box::new(LastSurvivor)).unwrap();
+        assert_eq!(game.status(), GameStatus::InProgress);
+        assert_eq!(game.next(), GameStatus::Winner(0));
+        assert!(game.status().is_finished());
+        // the universe no longer advances
+        assert_eq!(game.next(), GameStatus::Winner(0));
+        assert_eq!(game.universe().latest_gen(), 2);
     }
 
     #[test]
-    fn parse_whole_file_works_vacuum_cleaner() {
-        let pat: PatternFile = PatternFile::from_str("#N Vacuum (gun)\r\n#O Dieter Leithner\r\n#C A true period 46 double-barreled gun found on February 21, 1997.\r\n#C www.conwaylife.com/wiki/index.php?title=Vacuum_(gun)\r\nx = 49, y = 43, rule = b3/s23\r\nb2o23b2o21b$b2o23bo22b$24bobo22b$15b2o7b2o23b$2o13bobo31b$2o13bob2o30b\r\n$16b2o31b$16bo32b$44b2o3b$16bo27b2o3b$16b2o31b$2o13bob2o13bo3bo12b$2o\r\n13bobo13bo5bo7b2o2b$15b2o14bo13b2o2b$31b2o3bo12b$b2o30b3o13b$b2o46b$\r\n33b3o13b$31b2o3bo12b$31bo13b2o2b$31bo5bo7b2o2b$32bo3bo12b2$44b2o3b$44b\r\n2o3b5$37b2o10b$37bobo7b2o$39bo7b2o$37b3o9b$22bobo24b$21b3o25b$21b3o25b\r\n$21bo15b3o9b$25bobo11bo9b$21b2o4bo9bobo9b$16b2o4bo3b2o9b2o10b$15bobo6b\r\no24b$15bo33b$14b2o!").unwrap();
-        assert_eq!(pat.comment_lines.len(), 4);
-        assert_eq!(
-            pat.header_line,
-            HeaderLine {
-                x:    49,
-                y:    43,
-                rule: Some("B3/S23".to_owned().to_lowercase()),
-            }
-        );
-        assert_eq!(pat.pattern.0, "b2o23b2o21b$b2o23bo22b$24bobo22b$15b2o7b2o23b$2o13bobo31b$2o13bob2o30b$16b2o31b$16bo32b$44b2o3b$16bo27b2o3b$16b2o31b$2o13bob2o13bo3bo12b$2o13bobo13bo5bo7b2o2b$15b2o14bo13b2o2b$31b2o3bo12b$b2o30b3o13b$b2o46b$33b3o13b$31b2o3bo12b$31bo13b2o2b$31bo5bo7b2o2b$32bo3bo12b2$44b2o3b$44b2o3b5$37b2o10b$37bobo7b2o$39bo7b2o$37b3o9b$22bobo24b$21b3o25b$21b3o25b$21bo15b3o9b$25bobo11bo9b$21b2o4bo9bobo9b$16b2o4bo3b2o9b2o10b$15bobo6bo24b$15bo33b$14b2o!");
-        assert_eq!(
-            pat.to_new_bit_grid().unwrap(),
-            BitGrid(vec![
-                vec![0b0110000000000000000000000011000000000000000000000000000000000000],
-                vec![0b0110000000000000000000000010000000000000000000000000000000000000],
-                vec![0b0000000000000000000000001010000000000000000000000000000000000000],
-                vec![0b0000000000000001100000001100000000000000000000000000000000000000],
-                vec![0b1100000000000001010000000000000000000000000000000000000000000000],
-                vec![0b1100000000000001011000000000000000000000000000000000000000000000],
-                vec![0b0000000000000000110000000000000000000000000000000000000000000000],
-                vec![0b0000000000000000100000000000000000000000000000000000000000000000],
-                vec![0b0000000000000000000000000000000000000000000011000000000000000000],
-                vec![0b0000000000000000100000000000000000000000000011000000000000000000],
-                vec![0b0000000000000000110000000000000000000000000000000000000000000000],
-                vec![0b1100000000000001011000000000000010001000000000000000000000000000],
-                vec![0b1100000000000001010000000000000100000100000001100000000000000000],
-                vec![0b0000000000000001100000000000000100000000000001100000000000000000],
-                vec![0b0000000000000000000000000000000110001000000000000000000000000000],
-                vec![0b0110000000000000000000000000000001110000000000000000000000000000],
-                vec![0b0110000000000000000000000000000000000000000000000000000000000000],
-                vec![0b0000000000000000000000000000000001110000000000000000000000000000],
-                vec![0b0000000000000000000000000000000110001000000000000000000000000000],
-                vec![0b0000000000000000000000000000000100000000000001100000000000000000],
-                vec![0b0000000000000000000000000000000100000100000001100000000000000000],
-                vec![0b0000000000000000000000000000000010001000000000000000000000000000],
-                vec![0b0000000000000000000000000000000000000000000000000000000000000000],
-                vec![0b0000000000000000000000000000000000000000000011000000000000000000],
-                vec![0b0000000000000000000000000000000000000000000011000000000000000000],
-                vec![0b0000000000000000000000000000000000000000000000000000000000000000],
-                vec![0b0000000000000000000000000000000000000000000000000000000000000000],
-                vec![0b0000000000000000000000000000000000000000000000000000000000000000],
-                vec![0b0000000000000000000000000000000000000000000000000000000000000000],
-                vec![0b0000000000000000000000000000000000000110000000000000000000000000],
-                vec![0b0000000000000000000000000000000000000101000000011000000000000000],
-                vec![0b0000000000000000000000000000000000000001000000011000000000000000],
-                vec![0b0000000000000000000000000000000000000111000000000000000000000000],
-                vec![0b0000000000000000000000101000000000000000000000000000000000000000],
-                vec![0b0000000000000000000001110000000000000000000000000000000000000000],
-                vec![0b0000000000000000000001110000000000000000000000000000000000000000],
-                vec![0b0000000000000000000001000000000000000111000000000000000000000000],
-                vec![0b0000000000000000000000000101000000000001000000000000000000000000],
-                vec![0b0000000000000000000001100001000000000101000000000000000000000000],
-                vec![0b0000000000000000110000100011000000000110000000000000000000000000],
-                vec![0b0000000000000001010000001000000000000000000000000000000000000000],
-                vec![0b0000000000000001000000000000000000000000000000000000000000000000],
-                vec![0b0000000000000011000000000000000000000000000000000000000000000000]
-            ])
-        );
+    fn last_survivor_tie_and_checks() {
+        let mut uni = make_universe(2);
+        uni.set_unchecked(10, 10, CellState::Alive(Some(0)));
+        uni.set_unchecked(40, 10, CellState::Alive(Some(1)));
+        let mut game = Game::new(uni, Box::new(LastSurvivor)).unwrap();
+        assert_eq!(game.next(), GameStatus::Tie);
+
+        match Game::new(make_universe(1), Box::new(LastSurvivor)) {
+            Err(ConwayError::InvalidData { .. }) => {}
+            Err(e) => panic!("unexpected error {}", e),
+            Ok(_) => panic!("expected an error"),
+        }
     }
 
     #[test]
-    fn calc_size1() {
-        // Glider gun
-        let gun_pat = Pattern(
+    fn most_cells_after_generations() {
+        let mut uni = make_universe(2);
+        add_block(&mut uni, 10, 10, 0);
+        for col in 40..43 {
+            uni.set_unchecked(col, 10, CellState::Alive(Some(1))); // blinker
+        }
+        let mut game = Game::new(uni, Box::new(MostCells::new(2))).unwrap();
+        assert_eq!(game.next(), GameStatus::InProgress);
+        assert_eq!(game.next(), GameStatus::Winner(0));
+        assert_eq!(game.universe().latest_gen(), 3);
+
+        let mut uni = make_universe(2);
+        add_block(&mut uni, 10, 10, 0);
+        add_block(&mut uni, 40, 10, 1);
+        let mut game = Game::new(uni, Box::new(MostCells::new(1))).unwrap();
+        assert_eq!(game.next(), GameStatus::Tie);
+    }
+
+    #[test]
+    fn reach_base_wins_on_entering_opponent_base() {
+        let bases = vec![Region::new(0, 0, 10, 10), Region::new(40, 0, 10, 10)];
+        let mut uni = make_universe(2);
+        add_block(&mut uni, 5, 5, 0); // in its own base
+        add_block(&mut uni, 20, 20, 1);
+        let mut game = Game::new(uni, Box::new(ReachBase::new(bases.clone()))).unwrap();
+        assert_eq!(game.next(), GameStatus::InProgress);
+        add_block(game.universe_mut(), 48, 9, 0); // top-left cell is in player 1's base
+        assert_eq!(game.next(), GameStatus::Winner(0));
+
+        match Game::new(make_universe(2), Box::new(ReachBase::new(bases[..1].to_vec()))) {
+            Err(ConwayError::InvalidData { .. }) => {}
+            Err(e) => panic!("unexpected error {}", e),
+            Ok(_) => panic!("expected an error"),
+        }
+    }
+
+    #[test]
+    fn capture_the_flag_requires_holding() {
+        let flags = vec![
+            Flag {
+                col:   10,
+                row:   10,
+                owner: 0,
+            },
+            Flag {
+                col:   40,
+                row:   10,
+                owner: 1,
+            },
+        ];
+        let mut uni = make_universe(2);
+        add_block(&mut uni, 40, 10, 0);
+        add_block(&mut uni, 10, 10, 0); // on its own flag, which doesn't count
+        let mut game = Game::new(uni, Box::new(CaptureTheFlag::new(flags.clone(), 2))).unwrap();
+        assert_eq!(game.next(), GameStatus::InProgress);
+        assert_eq!(game.next(), GameStatus::Winner(0));
+
+        let bad_flag = vec![Flag {
+            col:   64,
+            row:   0,
+            owner: 1,
+        }];
+        assert!(Game::new(make_universe(2), Box::new(CaptureTheFlag::new(bad_flag, 1))).is_err());
+        assert!(Game::new(make_universe(2), Box::new(CaptureTheFlag::new(flags, 0))).is_err());
+    }
+
+    /// A 2v2 universe: players 0 and 1 are on team 1, and players 2 and 3 are on team 2.
+    fn make_team_universe() -> Universe {
+        let players = (0..4)
+            .map(|player_id| {
+                PlayerBuilder::new(Region::new(player_id as isize * 16, 0, 16, 64)).team(1 + player_id / 2)
+            })
+            .collect();
+        test_big_bang(64, 64, players).birth().unwrap()
+    }
+
+    #[test]
+    fn last_survivor_team_wins_together() {
+        let mut uni = make_team_universe();
+        add_block(&mut uni, 2, 10, 0);
+        add_block(&mut uni, 18, 10, 1);
+        add_block(&mut uni, 34, 10, 2);
+        uni.set_unchecked(50, 10, CellState::Alive(Some(3)));
+        let mut game = Game::new(uni, Box::new(LastSurvivor)).unwrap();
+        // player 3 is gone, but its teammate is still alive
+        assert_eq!(game.next(), GameStatus::InProgress);
+        for &(col, row) in &[(34, 10), (35, 10), (34, 11), (35, 11)] {
+            game.universe_mut().set_unchecked(col, row, CellState::Dead);
+        }
+        // both members of team 1 are alive
+        assert_eq!(game.next(), GameStatus::TeamWinner(1));
+    }
+
+    #[test]
+    fn most_cells_counts_teammates_together() {
+        let mut uni = make_team_universe();
+        add_block(&mut uni, 2, 10, 0);
+        add_block(&mut uni, 34, 10, 2);
+        add_block(&mut uni, 34, 20, 2);
+        add_block(&mut uni, 50, 10, 3);
+        let mut game = Game::new(uni, Box::new(MostCells::new(1))).unwrap();
+        assert_eq!(game.next(), GameStatus::TeamWinner(2));
+
+        // a team against a player who is not on a team
+        let players = vec![
+            PlayerBuilder::new(Region::new(0, 0, 20, 64)).team(5),
+            PlayerBuilder::new(Region::new(20, 0, 20, 64)).team(5),
+            PlayerBuilder::new(Region::new(40, 0, 24, 64)),
+        ];
+        let mut uni = BigBang::new()
+            .width(64)
+            .height(64)
+            .add_players(players)
+            .birth()
+            .unwrap();
+        add_block(&mut uni, 2, 10, 0);
+        add_block(&mut uni, 22, 10, 1);
+        add_block(&mut uni, 42, 10, 2);
+        add_block(&mut uni, 42, 20, 2);
+        add_block(&mut uni, 42, 30, 2);
+        let mut game = Game::new(uni, Box::new(MostCells::new(1))).unwrap();
+        assert_eq!(game.next(), GameStatus::Winner(2));
+    }
+
+    #[test]
+    fn reach_base_ignores_bases_of_teammates() {
+        let bases = (0..4).map(|player_id| Region::new(player_id * 16, 0, 8, 8)).collect();
+        let mut uni = make_team_universe();
+        add_block(&mut uni, 18, 2, 0); // in the base of teammate 1
+        let mut game = Game::new(uni, Box::new(ReachBase::new(bases))).unwrap();
+        assert_eq!(game.next(), GameStatus::InProgress);
+        add_block(game.universe_mut(), 50, 2, 1); // in the base of player 3
+        assert_eq!(game.next(), GameStatus::TeamWinner(1));
+    }
+
+    #[test]
+    fn capture_the_flag_ignores_teammates() {
+        let flags = vec![Flag {
+            col:   20,
+            row:   20,
+            owner: 1,
+        }];
+        let mut uni = make_team_universe();
+        add_block(&mut uni, 20, 20, 0); // teammate on the flag
+        let mut game = Game::new(uni, Box::new(CaptureTheFlag::new(flags, 2))).unwrap();
+        assert_eq!(game.next(), GameStatus::InProgress);
+        assert_eq!(game.next(), GameStatus::InProgress);
+
+        // the two opponents take turns holding the flag, which counts as holding it together
+        for &(col, row) in &[(20, 20), (21, 20), (20, 21), (21, 21)] {
+            game.universe_mut().set_unchecked(col, row, CellState::Alive(Some(2)));
+        }
+        assert_eq!(game.next(), GameStatus::InProgress);
+        for &(col, row) in &[(20, 20), (21, 20), (20, 21), (21, 21)] {
+            game.universe_mut().set_unchecked(col, row, CellState::Alive(Some(3)));
+        }
+        assert_eq!(game.next(), GameStatus::TeamWinner(2));
+    }
+
+    #[test]
+    fn player_ids_stay_valid_after_a_player_leaves() {
+        let bases = vec![
+            Region::new(0, 0, 10, 10),
+            Region::new(20, 0, 10, 10),
+            Region::new(40, 0, 10, 10),
+        ];
+        let players = (0..3)
+            .map(|player_id| PlayerBuilder::new(Region::new(player_id * 20, 0, 20, 64)))
+            .collect();
+        let uni = test_big_bang(64, 64, players).birth().unwrap();
+        let mut game = Game::new(uni, Box::new(ReachBase::new(bases))).unwrap();
+        add_block(game.universe_mut(), 5, 30, 0);
+        add_block(game.universe_mut(), 45, 30, 2);
+        game.universe_mut().remove_player(1, LeavePolicy::Die).unwrap();
+        assert_eq!(game.next(), GameStatus::InProgress);
+        // player 2 reaches the base of player 0, not the base of the player who left
+        add_block(game.universe_mut(), 5, 5, 2);
+        assert_eq!(game.next(), GameStatus::Winner(2));
+
+        let flags = vec![Flag {
+            col:   10,
+            row:   10,
+            owner: 0,
+        }];
+        let mut game = Game::new(make_universe(2), Box::new(CaptureTheFlag::new(flags, 1))).unwrap();
+        game.universe_mut().remove_player(0, LeavePolicy::Die).unwrap();
+        add_block(game.universe_mut(), 10, 10, 1);
+        assert_eq!(game.next(), GameStatus::InProgress);
+    }
+}
+
+mod diffcodec_tests {
+    use crate::error::ConwayError;
+    use crate::grids::CharGrid;
+    use crate::rle::Pattern;
+    use crate::universe::test_helpers::*;
+    use crate::universe::*;
+
+    /// A server universe with a glider gun for player 1, a glider for player 0 and a wall.
+    fn make_server_universe() -> Universe {
+        let mut uni = generate_test_universe_with_default_params(UniType::Server);
+        let gun = Pattern(
             "24bo$22bobo$12b2o6b2o12b2o$11bo3bo4b2o12b2o$2o8bo5bo3b2o$2o8bo3bob2o4bobo$10bo5bo7bo$11bo3bo$12b2o!"
                 .to_owned(),
-        );
-        let size_result = gun_pat.calc_size();
-        let size = size_result.unwrap();
-        assert_eq!(size, (36, 9));
+        )
+        .to_new_bit_grid(36, 9)
+        .unwrap();
+        uni.copy_from_bit_grid(&gun, Region::new(5, 5, 36, 9), Some(1));
+        for &(col, row) in &[(111, 75), (112, 76), (110, 77), (111, 77), (112, 77)] {
+            uni.toggle(col, row, 0).unwrap();
+        }
+        for row in 40..60 {
+            uni.set_unchecked(60, row, CellState::Wall);
+        }
+        for _ in 0..30 {
+            uni.next();
+        }
+        uni
     }
 
     #[test]
-    fn calc_size2() {
-        let pat = Pattern("o!".to_owned());
-        let size_result = pat.calc_size();
-        let size = size_result.unwrap();
-        assert_eq!(size, (1, 1));
+    fn round_trip_matches_rle_path() {
+        let s_uni = make_server_universe();
+        let latest = s_uni.latest_gen();
+        for &visibility in &[None, Some(0), Some(1)] {
+            // start each client from the full state of an earlier generation
+            let full = s_uni.diff(0, latest - 4, visibility).unwrap();
+            let incremental = s_uni.diff(latest - 4, latest, visibility).unwrap();
+            let mut rle_client = generate_test_universe_with_default_params(UniType::Client);
+            let mut binary_client = generate_test_universe_with_default_params(UniType::Client);
+            for diff in &[full, incremental] {
+                let data = diff.to_bytes().unwrap();
+                let decoded = GenStateDiff::from_bytes(&data).unwrap();
+                assert_eq!((decoded.gen0, decoded.gen1), (diff.gen0, diff.gen1));
+                assert_eq!(decoded.to_bytes().unwrap(), data);
+
+                assert_eq!(rle_client.apply(diff, visibility).unwrap(), Some(diff.gen1));
+                assert_eq!(binary_client.apply(&decoded, visibility).unwrap(), Some(diff.gen1));
+                assert_eq!(rle_client.to_pattern(visibility), binary_client.to_pattern(visibility));
+            }
+        }
     }
 
     #[test]
-    fn calc_size_fail1() {
-        let pat = Pattern("invalidpatternlol".to_owned());
-        let size_result = pat.calc_size();
-        let err = size_result.unwrap_err();
-        assert_eq!(
-            err,
-            ConwayError::InvalidData {
-                reason: "Premature termination at Some(16)".to_owned(),
+    fn round_trip_dying_cells() {
+        let diff = GenStateDiff {
+            gen0:    1,
+            gen1:    2,
+            pattern: Pattern("o2a$\"12c\"z$oAWz!".to_owned()),
+        };
+        let data = diff.to_bytes().unwrap();
+        let decoded = GenStateDiff::from_bytes(&data).unwrap();
+        assert_eq!(decoded.pattern.0, diff.pattern.0);
+        assert_eq!(decoded.to_bytes().unwrap(), data);
+    }
+
+    #[test]
+    fn binary_is_smaller_than_rle() {
+        let s_uni = make_server_universe();
+        let latest = s_uni.latest_gen();
+        let (mut rle_size, mut binary_size) = (0, 0);
+        for &visibility in &[None, Some(0), Some(1)] {
+            for &gen0 in &[0, latest - 8, latest - 1] {
+                let diff = s_uni.diff(gen0, latest, visibility).unwrap();
+                rle_size += diff.pattern.0.len();
+                binary_size += diff.to_bytes().unwrap().len();
             }
+        }
+        assert!(
+            binary_size * 4 <= rle_size,
+            "binary diffs take {} bytes, RLE diffs {}",
+            binary_size,
+            rle_size
         );
     }
+
+    #[test]
+    fn decoded_pattern_keeps_changes_at_row_ends() {
+        let diff = GenStateDiff {
+            gen0:    1,
+            gen1:    2,
+            pattern: Pattern("3\"b$\"A2\"?!".to_owned()),
+        };
+        let decoded = GenStateDiff::from_bytes(&diff.to_bytes().unwrap()).unwrap();
+        assert_eq!(decoded.pattern.0, "3\"b$\"A2\"?!");
+        let empty = GenStateDiff {
+            gen0:    3,
+            gen1:    4,
+            pattern: Pattern("!".to_owned()),
+        };
+        let data = empty.to_bytes().unwrap();
+        assert_eq!(data.len(), 5);
+        assert_eq!(GenStateDiff::from_bytes(&data).unwrap(), empty);
+    }
+
+    #[test]
+    fn invalid_binary_diffs_are_rejected() {
+        let diff = GenStateDiff {
+            gen0:    1,
+            gen1:    2,
+            pattern: Pattern("o$2bA!".to_owned()),
+        };
+        let data = diff.to_bytes().unwrap();
+        let mut bad_version = data.clone();
+        bad_version[0] = 99;
+        let mut bad_symbol = data.clone();
+        bad_symbol.push(31);
+        let mut too_many_cells = data.clone();
+        too_many_cells.push(0xff); // long run of unchanged cells
+        too_many_cells.push(0x7f);
+        // 1 cell wide and 2^40 rows high, all unchanged
+        let too_large = vec![
+            1, 0, 1, 1, 0x80, 0x80, 0x80, 0x80, 0x80, 0x20, 0xe0, 0xf8, 0xff, 0xff, 0xff, 0xff, 0x1f,
+        ];
+        for bad in &[
+            vec![],
+            bad_version,
+            data[..3].to_vec(),
+            bad_symbol,
+            too_many_cells,
+            too_large,
+        ] {
+            match GenStateDiff::from_bytes(bad) {
+                Err(ConwayError::InvalidData { .. }) => {}
+                other => panic!("unexpected result {:?} for {:?}", other, bad),
+            }
+        }
+    }
 }
@@ -15,11 +15,22 @@
  *  You should have received a copy of the GNU General Public License
  *  along with libconway.  If not, see <http://www.gnu.org/licenses/>. */
 
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::{char, cmp, fmt};
 
-use crate::error::{ConwayError, ConwayResult};
-use crate::grids::{BitGrid, BitOperation, CharGrid};
-use crate::rle::{Pattern, NO_OP_CHAR};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::action::{Action, ScheduledAction};
+use crate::activity::{ActivityMap, ActivityTracker};
+use crate::budget::{Budget, BudgetTracker};
+use crate::error::{ConwayError, ConwayResult, PlacementError};
+use crate::grids::{BitGrid, BitOperation, CharGrid, SetOperation};
+use crate::hashlife::HashLife;
+use crate::rle::{Pattern, PatternFile, NO_OP_CHAR};
+use crate::rule::Rule;
+use crate::snapshot::{SnapshotFormat, UniverseSnapshot, SNAPSHOT_VERSION};
 
 /// Builder paradigm to create `Universe` structs with default values.
 pub struct BigBang {
@@ -28,22 +39,54 @@ pub struct BigBang {
     is_server:       bool,
     history:         usize,
     num_players:     usize,
-    player_writable: Vec<Region>,
+    player_writable: Vec<WritableArea>,
+    teams:           Vec<Option<usize>>,
     fog_radius:      usize,
+    rule:            Rule,
+    topology:        Topology,
+    parallel:        bool,
+    activity_window: Option<usize>,
+    budgets:         Vec<Option<Budget>>,
 }
 
 /// Player builder
 pub struct PlayerBuilder {
-    writable_region: Region,
+    writable_area: WritableArea,
+    team:          Option<usize>,
+    budget:        Option<Budget>,
 }
 
 impl PlayerBuilder {
-    /// Returns a new PlayerBuilder.
-    pub fn new(region: Region) -> PlayerBuilder {
+    /// Returns a new PlayerBuilder for a player who can write to `area`, which is usually a
+    /// `Region` or a `WritableArea`.
+    pub fn new<A: Into<WritableArea>>(area: A) -> PlayerBuilder {
         PlayerBuilder {
-            writable_region: region,
+            writable_area: area.into(),
+            team:          None,
+            budget:        None,
         }
     }
+
+    /// Puts the player on team `team`. Players on the same team share their visibility through
+    /// the fog, and don't contest the ownership of cells with each other; see `Universe::next`.
+    /// By default, a player is not on any team.
+    pub fn team(mut self, team: usize) -> PlayerBuilder {
+        self.team = Some(team);
+        self
+    }
+
+    /// Adds `region` to the player's writable area.
+    pub fn add_region(mut self, region: Region) -> PlayerBuilder {
+        self.writable_area.add_region(region);
+        self
+    }
+
+    /// Limits the cells the player can bring to life; see `Universe::set_budget`. By default, a
+    /// player has no budget.
+    pub fn budget(mut self, budget: Budget) -> PlayerBuilder {
+        self.budget = Some(budget);
+        self
+    }
 }
 
 /// This is a builder for `Universe` structs.
@@ -68,7 +111,13 @@ impl BigBang {
             history:         16,
             num_players:     0,
             player_writable: vec![],
+            teams:           vec![],
             fog_radius:      6,
+            rule:            Rule::default(),
+            topology:        Topology::default(),
+            parallel:        false,
+            activity_window: None,
+            budgets:         vec![],
         }
     }
 
@@ -107,7 +156,9 @@ impl BigBang {
     /// does not match the number of players.
     pub fn add_player(mut self, new_player: PlayerBuilder) -> BigBang {
         self.num_players += 1;
-        self.player_writable.push(new_player.writable_region);
+        self.player_writable.push(new_player.writable_area);
+        self.teams.push(new_player.team);
+        self.budgets.push(new_player.budget);
         assert_eq!(self.num_players, self.player_writable.len()); // These should always match up!
         self
     }
@@ -134,52 +185,637 @@ impl BigBang {
         self
     }
 
+    /// Sets the Life-like rule used to compute each generation. Defaults to B3/S23 (Conway's Game
+    /// of Life).
+    pub fn rule(mut self, new_rule: Rule) -> BigBang {
+        self.rule = new_rule;
+        self
+    }
+
+    /// Sets what happens at the edges of the universe. Defaults to `Topology::Torus`.
+    pub fn topology(mut self, new_topology: Topology) -> BigBang {
+        self.topology = new_topology;
+        self
+    }
+
+    /// If true, each generation is computed on multiple threads; see `Universe::set_parallel`.
+    /// Defaults to false.
+    pub fn parallel(mut self, is_parallel: bool) -> BigBang {
+        self.parallel = is_parallel;
+        self
+    }
+
+    /// Keeps track of the age and activity of each cell, counting changes over the last `window`
+    /// generations; see `Universe::set_activity_window`. Not tracked by default.
+    pub fn activity_window(mut self, window: usize) -> BigBang {
+        self.activity_window = Some(window);
+        self
+    }
+
     /// "Gives life to the universe and the first moment of time."
     /// Creates a Universe which can then CGoL process generations.
     ///
     /// # Errors
     ///
-    /// - if `width` or `height` are not positive, or if `width` is not a multiple of 64.
+    /// - if `width` or `height` are not positive.
     /// - if `fog_radius` is not positive.
     /// - if `history` is not positive.
+    /// - if `activity_window` was set to 0.
+    /// - if a player's budget has a rate limit spanning 0 generations.
     pub fn birth(&self) -> ConwayResult<Universe> {
-        let universe = Universe::new(
+        let mut universe = Universe::new(
             self.width,
             self.height,
             self.is_server, // if false, allow receiving generation 1 as GenStateDiff
             self.history,
             self.num_players,             // number of players in the game (player numbers are 0-based)
-            self.player_writable.clone(), // writable region (indexed by player_id)
+            self.player_writable.clone(), // writable area (indexed by player_id)
             self.fog_radius,              // fog radius provides visiblity outside of writable regions
-        );
-        universe
+        )?;
+        universe.rule = self.rule;
+        universe.topology = self.topology;
+        universe.parallel = self.parallel;
+        if self.activity_window == Some(0) {
+            return Err(ConwayError::InvalidData {
+                reason: "activity window must be at least 1".to_owned(),
+            });
+        }
+        universe.set_activity_window(self.activity_window);
+        for (player_id, &opt_budget) in self.budgets.iter().enumerate() {
+            universe.set_budget(player_id, opt_budget)?;
+        }
+        let dying = vec![BitGrid::new(universe.width_in_words, universe.height); self.rule.dying_states()];
+        for gen_state in &mut universe.gen_states {
+            gen_state.dying = dying.clone();
+        }
+        universe.teams = self.teams.clone();
+        for state_index in 0..universe.gen_states.len() {
+            universe.share_team_fog(state_index);
+        }
+        Ok(universe)
+    }
+}
+
+/// Describes how the edges of a `Universe` are connected.
+#[derive(Serialize, Deserialize, Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Topology {
+    /// Left edge is joined to the right edge, and top edge is joined to the bottom edge.
+    Torus,
+    /// Nothing is joined; cells beyond the edges are always dead.
+    Plane,
+    /// Left edge is joined to the right edge; cells beyond the top and bottom edges are always dead.
+    Cylinder,
+    /// Left edge is joined to the right edge, and top edge is joined to the bottom edge with a
+    /// twist: crossing the top or bottom edge mirrors the column (`col` becomes `width - 1 -
+    /// col`).
+    KleinBottle,
+}
+
+impl Default for Topology {
+    fn default() -> Self {
+        Topology::Torus
+    }
+}
+
+impl Topology {
+    /// Returns true if the left edge is joined to the right edge.
+    pub fn wraps_horizontally(self) -> bool {
+        self != Topology::Plane
+    }
+
+    /// Returns true if the top edge is joined to the bottom edge.
+    pub fn wraps_vertically(self) -> bool {
+        self == Topology::Torus || self == Topology::KleinBottle
+    }
+
+    /// Maps a possibly out-of-range `row` into the universe. Returns `Some((row, mirrored))`, where
+    /// `mirrored` is true if the columns of that row are reversed as seen from `row`, or `None` if
+    /// `row` is beyond a non-wrapping edge.
+    fn wrap_row(self, row: isize, height: usize) -> Option<(usize, bool)> {
+        let height = height as isize;
+        if row >= 0 && row < height {
+            return Some((row as usize, false));
+        }
+        if !self.wraps_vertically() {
+            return None;
+        }
+        let crossings = row.div_euclid(height);
+        let mirrored = self == Topology::KleinBottle && crossings % 2 != 0;
+        Some((row.rem_euclid(height) as usize, mirrored))
+    }
+
+    /// Returns true if `region`, which may extend beyond the edges of a universe `height` cells
+    /// high, covers any cells in `row` once edges are joined.
+    pub fn region_contains_row(self, region: Region, row: usize, height: usize) -> bool {
+        let row = row as isize;
+        if !self.wraps_vertically() {
+            return region.top() <= row && row <= region.bottom();
+        }
+        // smallest image of the row that is at or below the region's top edge
+        let image = region.top() + (row - region.top()).rem_euclid(height as isize);
+        image <= region.bottom()
+    }
+
+    /// Maps a possibly out-of-range cell into the universe. Returns `Some((col, row))`, or `None`
+    /// if the cell is beyond a non-wrapping edge.
+    pub fn map_cell(self, col: isize, row: isize, width: usize, height: usize) -> Option<(usize, usize)> {
+        let (row, mirrored) = self.wrap_row(row, height)?;
+        let col = if mirrored { width as isize - 1 - col } else { col };
+        if self.wraps_horizontally() {
+            Some((col.rem_euclid(width as isize) as usize, row))
+        } else if col >= 0 && col < width as isize {
+            Some((col as usize, row))
+        } else {
+            None
+        }
+    }
+
+    /// Returns true if `region`, which may extend beyond the edges of a `width` by `height`
+    /// universe, covers the cell at (`col`, `row`) once edges are joined.
+    pub fn region_contains(self, region: Region, col: usize, row: usize, width: usize, height: usize) -> bool {
+        let (col, row) = (col as isize, row as isize);
+        let (width, height) = (width as isize, height as isize);
+        let (first_crossing, last_crossing) = if self.wraps_vertically() {
+            (
+                (region.top() - row + height - 1).div_euclid(height),
+                (region.bottom() - row).div_euclid(height),
+            )
+        } else {
+            (0, 0)
+        };
+        for crossing in first_crossing..=last_crossing {
+            let virtual_row = row + crossing * height;
+            if virtual_row < region.top() || virtual_row > region.bottom() {
+                continue;
+            }
+            let virtual_col = if self == Topology::KleinBottle && crossing % 2 != 0 {
+                width - 1 - col
+            } else {
+                col
+            };
+            if self.wraps_horizontally() {
+                // smallest image of the column that is at or to the right of the region's left edge
+                let image = region.left() + (virtual_col - region.left()).rem_euclid(width);
+                if image <= region.right() {
+                    return true;
+                }
+            } else if region.left() <= virtual_col && virtual_col <= region.right() {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Performs `op` on the cells from `left` to `right` (inclusive) in `row`, any of which may be
+    /// beyond the edges of `grid`, whose rows are `width` cells wide.
+    fn modify_span(self, grid: &mut BitGrid, width: usize, row: isize, left: isize, right: isize, op: BitOperation) {
+        let width = width as isize;
+        let (row, mirrored) = match self.wrap_row(row, grid.height()) {
+            Some(row_info) => row_info,
+            None => return,
+        };
+        let (left, right) = if mirrored {
+            (width - 1 - right, width - 1 - left)
+        } else {
+            (left, right)
+        };
+        if self.wraps_horizontally() {
+            if right - left + 1 >= width {
+                grid.modify_row_span(row, 0, width as usize - 1, op);
+                return;
+            }
+            let left = left.rem_euclid(width) as usize;
+            let right = right.rem_euclid(width) as usize;
+            if left <= right {
+                grid.modify_row_span(row, left, right, op);
+            } else {
+                grid.modify_row_span(row, left, width as usize - 1, op);
+                grid.modify_row_span(row, 0, right, op);
+            }
+        } else {
+            let left = cmp::max(left, 0);
+            let right = cmp::min(right, width - 1);
+            if left <= right {
+                grid.modify_row_span(row, left as usize, right as usize, op);
+            }
+        }
+    }
+}
+
+/// Reads the rows surrounding a cell for `Universe::next`, taking into account the `Topology` and
+/// the unused bits at the end of each row when the width is not a multiple of 64.
+#[derive(Copy, Clone)]
+struct NeighborReader {
+    topology: Topology,
+    width:    usize,
+    height:   usize,
+    pad_bits: usize, // number of unused bits at the end of the last word of each row
+}
+
+impl NeighborReader {
+    fn new(topology: Topology, width: usize, height: usize) -> Self {
+        let width_in_words = (width - 1) / 64 + 1;
+        NeighborReader {
+            topology,
+            width,
+            height,
+            pad_bits: width_in_words * 64 - width,
+        }
+    }
+
+    /// Returns the row of `grid` which is `delta` rows away from `row_idx`. Rows beyond a
+    /// non-wrapping edge are filled with `outside`. The unused bits at the end of the row are
+    /// filled with the cells on the other side of the right edge (that is, the leftmost cells of
+    /// the row), or with `outside` if the topology does not wrap horizontally.
+    fn row<'a>(&self, grid: &'a BitGrid, row_idx: usize, delta: isize, outside: u64) -> Cow<'a, [u64]> {
+        let (src_row_idx, mirrored) = match self.topology.wrap_row(row_idx as isize + delta, self.height) {
+            Some(row_info) => row_info,
+            None => return Cow::Owned(vec![outside; grid.width_in_words()]),
+        };
+        if !mirrored && self.pad_bits == 0 {
+            return Cow::Borrowed(&grid[src_row_idx][..]);
+        }
+        let mut row: Vec<u64> = if mirrored {
+            let mut row: Vec<u64> = grid[src_row_idx].iter().rev().map(|word| word.reverse_bits()).collect();
+            if self.pad_bits > 0 {
+                // the cells are now at the end of the row; move them back to the start
+                for i in 0..row.len() {
+                    let next_word = row.get(i + 1).copied().unwrap_or(0);
+                    row[i] = (row[i] << self.pad_bits) | (next_word >> (64 - self.pad_bits));
+                }
+            }
+            row
+        } else {
+            grid[src_row_idx].to_vec()
+        };
+        if self.pad_bits > 0 {
+            let used_bits = 64 - self.pad_bits;
+            let last_idx = row.len() - 1;
+            if self.topology.wraps_horizontally() {
+                let first_word = row[0];
+                row[last_idx] |= first_word >> used_bits;
+            } else {
+                row[last_idx] |= outside & (u64::max_value() >> used_bits);
+            }
+        }
+        Cow::Owned(row)
+    }
+
+    /// Returns the word at `col_idx` in a `row` returned by `row()`, where `col_idx` may be one
+    /// word beyond either end of the row. Only the bit nearest the row is meaningful in the words
+    /// beyond either end, since that is all that is needed to compute the next generation. Words
+    /// beyond a non-wrapping edge are `outside`.
+    #[inline]
+    fn word(&self, row: &[u64], col_idx: isize, outside: u64) -> u64 {
+        let width_in_words = row.len() as isize;
+        if col_idx >= 0 && col_idx < width_in_words {
+            row[col_idx as usize]
+        } else if !self.topology.wraps_horizontally() {
+            outside
+        } else if col_idx < 0 {
+            // the rightmost cell of the row must be in the least significant bit
+            row[row.len() - 1] >> self.pad_bits
+        } else {
+            row[0]
+        }
+    }
+
+    /// Mask of the bits in the word at `col_idx` that are cells in the universe.
+    #[inline]
+    fn used_bits_mask(&self, col_idx: usize) -> u64 {
+        if col_idx == (self.width - 1) / 64 {
+            u64::max_value() << self.pad_bits
+        } else {
+            u64::max_value()
+        }
+    }
+}
+
+/// Number of rows in each band of rows that is computed on a single thread in parallel mode.
+const PARALLEL_BAND_HEIGHT: usize = 16;
+
+/// Mutable references to a single row of each `BitGrid` written by `Universe::next`, so that rows
+/// can be computed independently of each other.
+struct NextGenRow<'a> {
+    cells:        &'a mut [u64],
+    wall:         &'a mut [u64],
+    known:        &'a mut [u64],
+    player_cells: Vec<&'a mut [u64]>, // indexed by player_id
+    newly_owned:  Vec<&'a mut [u64]>, // indexed by player_id
+    dying:        Vec<&'a mut [u64]>, // one for each dying state
+}
+
+impl<'a> NextGenRow<'a> {
+    /// Splits `gen_state` and `newly_owned` (one `BitGrid` per player) into rows. Fog is not
+    /// included, since clearing fog affects the rows around a cell.
+    fn split(gen_state: &'a mut GenState, newly_owned: &'a mut [BitGrid]) -> Vec<NextGenRow<'a>> {
+        let height = gen_state.cells.height();
+        let mut player_cells: Vec<Vec<&mut [u64]>> = (0..height).map(|_| vec![]).collect();
+        for player_state in gen_state.player_states.iter_mut() {
+            for (row_idx, row) in player_state.cells.0.iter_mut().enumerate() {
+                player_cells[row_idx].push(row);
+            }
+        }
+        let mut newly_owned_rows: Vec<Vec<&mut [u64]>> = (0..height).map(|_| vec![]).collect();
+        for grid in newly_owned.iter_mut() {
+            for (row_idx, row) in grid.0.iter_mut().enumerate() {
+                newly_owned_rows[row_idx].push(row);
+            }
+        }
+        let mut dying_rows: Vec<Vec<&mut [u64]>> = (0..height).map(|_| vec![]).collect();
+        for plane in gen_state.dying.iter_mut() {
+            for (row_idx, row) in plane.0.iter_mut().enumerate() {
+                dying_rows[row_idx].push(row);
+            }
+        }
+        gen_state
+            .cells
+            .0
+            .iter_mut()
+            .zip(gen_state.wall_cells.0.iter_mut())
+            .zip(gen_state.known.0.iter_mut())
+            .zip(player_cells.into_iter().zip(newly_owned_rows.into_iter()))
+            .zip(dying_rows.into_iter())
+            .map(
+                |((((cells, wall), known), (player_cells, newly_owned)), dying)| NextGenRow {
+                    cells,
+                    wall,
+                    known,
+                    player_cells,
+                    newly_owned,
+                    dying,
+                },
+            )
+            .collect()
+    }
+}
+
+/// Everything besides the current `GenState` that is needed to compute a row of the next
+/// generation.
+struct NextGenContext {
+    reader:     NeighborReader,
+    rule:       Rule,
+    is_conway:  bool,
+    team_index: Vec<usize>, // indexed by player_id; see `Universe::team_indices`
+}
+
+impl NextGenContext {
+    /// Computes row `row_idx` of the generation after `gen_state`, writing it to `next`. The fog
+    /// is left alone; instead, the cells each player acquired are written to `next.newly_owned`.
+    fn next_row(&self, gen_state: &GenState, row_idx: usize, next: &mut NextGenRow) {
+        let reader = &self.reader;
+        let cells = &gen_state.cells;
+        let known = &gen_state.known;
+        let num_players = gen_state.player_states.len();
+        let num_teams = self.team_index.iter().map(|&team| team + 1).max().unwrap_or(0);
+        let mut team_seen = vec![0u64; num_teams];
+        let mut team_owned = vec![0u64; num_teams];
+        let mut team_taken = vec![0u64; num_teams];
+
+        // Cells beyond non-wrapping edges are dead, but known.
+        let cells_row_n = reader.row(cells, row_idx, -1, 0);
+        let cells_row_c = reader.row(cells, row_idx, 0, 0);
+        let cells_row_s = reader.row(cells, row_idx, 1, 0);
+        let wall_row_c = &gen_state.wall_cells[row_idx];
+        let known_row_n = reader.row(known, row_idx, -1, u64::max_value());
+        let known_row_c = reader.row(known, row_idx, 0, u64::max_value());
+        let known_row_s = reader.row(known, row_idx, 1, u64::max_value());
+        let player_rows: Vec<_> = gen_state
+            .player_states
+            .iter()
+            .map(|player_state| {
+                (
+                    reader.row(&player_state.cells, row_idx, -1, 0),
+                    reader.row(&player_state.cells, row_idx, 0, 0),
+                    reader.row(&player_state.cells, row_idx, 1, 0),
+                )
+            })
+            .collect();
+
+        // These will be shifted over at the beginning of the loop
+        let mut cells_nw;
+        let mut cells_w;
+        let mut cells_sw;
+        let mut cells_n = reader.word(&cells_row_n, -1, 0);
+        let mut cells_cen = reader.word(&cells_row_c, -1, 0);
+        let mut cells_s = reader.word(&cells_row_s, -1, 0);
+        let mut cells_ne = cells_row_n[0];
+        let mut cells_e = cells_row_c[0];
+        let mut cells_se = cells_row_s[0];
+        let mut known_nw;
+        let mut known_w;
+        let mut known_sw;
+        let mut known_n = reader.word(&known_row_n, -1, u64::max_value());
+        let mut known_cen = reader.word(&known_row_c, -1, u64::max_value());
+        let mut known_s = reader.word(&known_row_s, -1, u64::max_value());
+        let mut known_ne = known_row_n[0];
+        let mut known_e = known_row_c[0];
+        let mut known_se = known_row_s[0];
+
+        for col_idx in 0..cells.width_in_words() {
+            let (west_idx, east_idx) = (col_idx as isize - 1, col_idx as isize + 1);
+
+            // shift over
+            cells_nw = cells_n;
+            cells_n = cells_ne;
+            cells_w = cells_cen;
+            cells_cen = cells_e;
+            cells_sw = cells_s;
+            cells_s = cells_se;
+            cells_ne = reader.word(&cells_row_n, east_idx, 0);
+            cells_e = reader.word(&cells_row_c, east_idx, 0);
+            cells_se = reader.word(&cells_row_s, east_idx, 0);
+            known_nw = known_n;
+            known_n = known_ne;
+            known_w = known_cen;
+            known_cen = known_e;
+            known_sw = known_s;
+            known_s = known_se;
+            known_ne = reader.word(&known_row_n, east_idx, u64::max_value());
+            known_e = reader.word(&known_row_c, east_idx, u64::max_value());
+            known_se = reader.word(&known_row_s, east_idx, u64::max_value());
+
+            // apply BitGrid changes; dying cells can't come to life
+            let dying_cen = gen_state
+                .dying
+                .iter()
+                .fold(0, |dying_cen, plane| dying_cen | plane[row_idx][col_idx]);
+            let mut cells_cen_next = if self.is_conway {
+                Universe::next_single_gen(
+                    cells_nw, cells_n, cells_ne, cells_w, cells_cen, cells_e, cells_sw, cells_s, cells_se,
+                )
+            } else {
+                self.rule.next_single_gen(
+                    cells_nw, cells_n, cells_ne, cells_w, cells_cen, cells_e, cells_sw, cells_s, cells_se,
+                )
+            };
+            cells_cen_next &= !dying_cen;
+
+            // any known cells with at least one unknown neighbor will become unknown in
+            // the next generation
+            next.known[col_idx] = Universe::contagious_zero(
+                known_nw, known_n, known_ne, known_w, known_cen, known_e, known_sw, known_s, known_se,
+            ) & reader.used_bits_mask(col_idx);
+
+            cells_cen_next &= next.known[col_idx];
+            cells_cen_next &= !wall_row_c[col_idx];
+
+            // assign to the u64 element in the next generation
+            next.cells[col_idx] = cells_cen_next;
+
+            // Live cells that don't survive start dying, and dying cells move on to the next
+            // dying state, or become dead after the last one.
+            if !next.dying.is_empty() {
+                for i in (1..next.dying.len()).rev() {
+                    next.dying[i][col_idx] = gen_state.dying[i - 1][row_idx][col_idx] & next.known[col_idx];
+                }
+                next.dying[0][col_idx] = cells_cen & !cells_cen_next & next.known[col_idx];
+            }
+
+            let mut in_multiple: u64 = 0;
+            let mut seen_before: u64 = 0;
+            for word in team_seen.iter_mut() {
+                *word = 0;
+            }
+            for player_id in 0..num_players {
+                let team = self.team_index[player_id];
+                // Any unknown cell with
+                //
+                // A cell which would have belonged to 2+ players in the next
+                // generation will belong to no one. These are unowned cells.
+                //
+                // Unowned cells follow the same rules of life.
+                //
+                // Any unowned cells are influenced by their neighbors, and if players,
+                // can be acquired by the player, just as long as no two players are
+                // fighting over those cells
+                let (ref player_row_n, ref player_row_c, ref player_row_s) = player_rows[player_id];
+                let player_cell_next = Universe::contagious_one(
+                    reader.word(player_row_n, west_idx, 0),
+                    player_row_n[col_idx],
+                    reader.word(player_row_n, east_idx, 0),
+                    reader.word(player_row_c, west_idx, 0),
+                    player_row_c[col_idx],
+                    reader.word(player_row_c, east_idx, 0),
+                    reader.word(player_row_s, west_idx, 0),
+                    player_row_s[col_idx],
+                    reader.word(player_row_s, east_idx, 0),
+                ) & cells_cen_next;
+                // only players on different teams fight over cells
+                in_multiple |= player_cell_next & seen_before & !team_seen[team];
+                seen_before |= player_cell_next;
+                team_seen[team] |= player_cell_next;
+                next.player_cells[player_id][col_idx] = player_cell_next;
+            }
+            for word in team_owned.iter_mut().chain(team_taken.iter_mut()) {
+                *word = 0;
+            }
+            for player_id in 0..num_players {
+                team_owned[self.team_index[player_id]] |= gen_state.player_states[player_id].cells[row_idx][col_idx];
+            }
+            for player_id in 0..num_players {
+                let team = self.team_index[player_id];
+                let cell_cur = gen_state.player_states[player_id].cells[row_idx][col_idx];
+                let mut cell_next = next.player_cells[player_id][col_idx];
+                cell_next &= !in_multiple; // if a cell would have belonged to multiple players, it belongs to none
+
+                // A cell that would have belonged to several teammates stays with the teammate that
+                // owns it, or else goes to the teammate with the lowest player_id.
+                cell_next &= cell_cur | !team_owned[team];
+                cell_next &= !team_taken[team];
+                team_taken[team] |= cell_next;
+                next.player_cells[player_id][col_idx] = cell_next;
+                next.newly_owned[player_id][col_idx] = cell_next & !cell_cur;
+            }
+        }
+
+        // copy wall to wall_next
+        next.wall.copy_from_slice(wall_row_c);
     }
 }
 
-/// Represents a wrapping universe in Conway's game of life.
+/// What happens to the cells of a player removed with `Universe::remove_player`.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum LeavePolicy {
+    /// The cells stay alive, but no longer belong to any player.
+    Unowned,
+    /// The cells die.
+    Die,
+    /// The cells are replaced by walls.
+    Wall,
+}
+
+/// How `Universe::drop_pattern` combines a pattern with the cells already in the universe.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum BlendMode {
+    /// The live cells of the pattern come to life; the other cells are left alone.
+    Or,
+    /// The cells covered by the pattern are set to the pattern, so its dead cells kill the cells
+    /// under them.
+    Replace,
+    /// The live cells of the pattern toggle the cells under them.
+    Xor,
+}
+
+/// Population counts for one player's cells, or for the cells not owned by any player, as
+/// returned in a `UniverseStats`.
+#[derive(Eq, PartialEq, Copy, Clone, Debug, Default)]
+pub struct CellStats {
+    pub live:             usize,          // number of live cells
+    pub inside_writable:  usize,          // live cells inside the writable region
+    pub outside_writable: usize,          // live cells outside the writable region
+    pub births:           usize,          // cells that came alive since the previous generation
+    pub deaths:           usize,          // cells that died since the previous generation
+    pub bounding_box:     Option<Region>, // smallest region containing the live cells; None if none
+}
+
+/// Statistics about the latest generation of a `Universe`; see `Universe::stats`.
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub struct UniverseStats {
+    pub generation: usize,
+    pub players:    Vec<CellStats>, // indexed by player_id
+    pub unowned:    CellStats,      // live cells that don't belong to any player
+    pub walls:      usize,          // number of wall cells
+}
+
+/// Represents a universe in Conway's game of life. By default, the universe wraps around at its
+/// edges; see `Topology` for the alternatives.
+#[derive(Clone)]
 pub struct Universe {
     width:           usize,
     height:          usize,
-    width_in_words:  usize,         // width in u64 elements, _not_ width in cells!
-    generation:      usize,         // current generation (1-based)
-    num_players:     usize,         // number of players in the game (player numbers are 0-based)
-    state_index:     usize,         // index of GenState for current generation within gen_states
-    gen_states:      Vec<GenState>, // circular buffer of generational states
-    player_writable: Vec<Region>,   // writable region (indexed by player_id)
+    width_in_words:  usize,              // width in u64 elements, _not_ width in cells!
+    generation:      usize,              // current generation (1-based)
+    num_players:     usize,              // number of players in the game (player numbers are 0-based)
+    state_index:     usize,              // index of GenState for current generation within gen_states
+    gen_states:      Vec<GenState>,      // circular buffer of generational states
+    player_writable: Vec<WritableArea>,  // writable area (indexed by player_id)
+    teams:           Vec<Option<usize>>, // team of each player, if any (indexed by player_id)
+    vacant:          Vec<bool>,          // true for the player_ids of removed players, until reused
     fog_radius:      usize,
     fog_circle:      BitGrid,
+    fog_spans:       Vec<(usize, usize)>, // for each row of fog_circle, the columns of the first and last 0 bits
+    rule:            Rule,
+    topology:        Topology,
+    parallel:        bool, // if true, compute each generation on the rayon thread pool
+    activity:        Option<ActivityTracker>, // age and activity of each cell, if tracked
+    scheduled:       Vec<ScheduledAction>, // actions waiting for their generation, in the order scheduled
+    applied:         Vec<ScheduledAction>, // actions applied to generations after the oldest one in the history
+    budgets:         Vec<Vec<Option<BudgetTracker>>>, // budget of each player in each GenState (indexed like gen_states)
 }
 
 // Describes the state of the universe for a particular generation
 // This includes any cells alive, known, and each player's own gen states
 // for this current session
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct GenState {
-    gen_or_none:   Option<usize>, // Some(generation number) (redundant info); if None, this is an unused buffer
-    cells:         BitGrid,       // 1 = cell is known to be Alive
-    wall_cells:    BitGrid,       // 1 = is a wall cell (should this just be fixed for the universe?)
-    known:         BitGrid,       // 1 = cell is known (always 1 if this is server)
+    width:         usize,               // width in cells; the BitGrids may have unused bits beyond this
+    gen_or_none:   Option<usize>,       // Some(generation number) (redundant info); if None, this is an unused buffer
+    cells:         BitGrid,             // 1 = cell is known to be Alive
+    wall_cells:    BitGrid,             // 1 = is a wall cell (should this just be fixed for the universe?)
+    known:         BitGrid,             // 1 = cell is known (always 1 if this is server)
     player_states: Vec<PlayerGenState>, // player-specific info (indexed by player_id)
+    dying:         Vec<BitGrid>,        // cells in each dying state of a Generations rule; index 0 is Dying(1)
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -189,18 +825,26 @@ pub struct GenStateDiff {
     pub pattern: Pattern,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 struct PlayerGenState {
     cells: BitGrid, // cells belonging to this player (if 1 here, must be 1 in GenState cells)
     fog:   BitGrid, // cells that are currently invisible to the player
 }
 
+/// Most players a universe can have, since each player's cells are written as one of the uppercase
+/// letters A through V (see `CellState::to_char`).
+pub const MAX_PLAYERS: usize = 22;
+
+/// Characters for the dying states of Generations rules, starting with `CellState::Dying(1)`.
+pub(crate) const DYING_CHARS: &str = "acdefghijklmnpqrstuvwxyz";
+
 #[derive(Eq, PartialEq, Ord, PartialOrd, Copy, Clone, Debug)]
 pub enum CellState {
     Dead,
     Alive(Option<usize>), // Some(player_number) or alive but not belonging to any player
     Wall,
     Fog,
+    Dying(u8), // in a Generations rule, the number of generations since the cell stopped being alive (1-based)
 }
 
 impl CellState {
@@ -211,13 +855,15 @@ impl CellState {
     ///
     /// # Panics
     ///
-    /// Panics if `player_id` is not less than 23, since we map IDs 0 through 22 to uppercase
-    /// letters A through V. W is not usable since it represents a wall cell.
+    /// Panics if `player_id` is not less than 22, since we map IDs 0 through 21 to uppercase
+    /// letters A through V. W is not usable since it represents a wall cell. Also panics if the
+    /// dying state is not between 1 and 24; these are mapped to the lowercase letters other than
+    /// `b` and `o`.
     pub fn to_char(self) -> char {
         match self {
             CellState::Alive(Some(player_id)) => {
-                if player_id >= 23 {
-                    panic!("Player IDs must be less than 23 to be converted to chars");
+                if player_id >= MAX_PLAYERS {
+                    panic!("Player IDs must be less than {} to be converted to chars", MAX_PLAYERS);
                 }
                 char::from_u32(player_id as u32 + 65).unwrap()
             }
@@ -225,6 +871,12 @@ impl CellState {
             CellState::Dead => 'b',
             CellState::Wall => 'W',
             CellState::Fog => '?',
+            CellState::Dying(n) => {
+                if n == 0 || n as usize > DYING_CHARS.len() {
+                    panic!("Dying state {} cannot be converted to a char", n);
+                }
+                DYING_CHARS.chars().nth(n as usize - 1).unwrap()
+            }
         }
     }
 
@@ -236,18 +888,61 @@ impl CellState {
             'W' => Some(CellState::Wall),
             '?' => Some(CellState::Fog),
             'A'..='V' => Some(CellState::Alive(Some(u32::from(ch) as usize - 65))),
-            _ => None,
+            _ => DYING_CHARS
+                .chars()
+                .position(|c| c == ch)
+                .map(|i| CellState::Dying(i as u8 + 1)),
+        }
+    }
+}
+
+/// Word-at-a-time hash used by `GenState::checksum` (the function of FxHash). Unlike the hashers
+/// in the standard library, its result is fixed, so it is the same on every platform and with
+/// every version of Rust.
+struct Checksum(u64);
+
+impl Checksum {
+    fn write(&mut self, word: u64) {
+        self.0 = (self.0.rotate_left(5) ^ word).wrapping_mul(0x51_7c_c1_b7_27_22_0a_95);
+    }
+
+    /// Hashes the words of `grid`, with the bits that are 1 in `opt_hidden` (if any) cleared.
+    fn write_grid(&mut self, grid: &BitGrid, opt_hidden: Option<&BitGrid>) {
+        for (row_idx, row) in grid.0.iter().enumerate() {
+            for (col_idx, &word) in row.iter().enumerate() {
+                match opt_hidden {
+                    Some(hidden) => self.write(word & !hidden[row_idx][col_idx]),
+                    None => self.write(word),
+                }
+            }
         }
     }
 }
 
 impl GenState {
+    /// Returns the dying state of the cells in `mask` in the word at `word_col` of `row`, or
+    /// `None` if they are not dying.
+    fn dying_state(&self, row: usize, word_col: usize, mask: u64) -> Option<u8> {
+        self.dying
+            .iter()
+            .position(|plane| plane[row][word_col] & mask != 0)
+            .map(|i| i as u8 + 1)
+    }
+
+    /// Makes the cells in `mask` in the word at `word_col` of `row` stop dying.
+    fn clear_dying(&mut self, row: usize, word_col: usize, mask: u64) {
+        for plane in &mut self.dying {
+            plane[row][word_col] &= !mask;
+        }
+    }
+
     /// Sets the state of a cell, with minimal checking.  It doesn't support setting
     /// `CellState::Fog`.
     ///
     /// # Panics
     ///
-    /// Panics if an attempt is made to set an unknown cell.
+    /// Panics if an attempt is made to set an unknown cell, or to set a dying state that the
+    /// rule of the universe doesn't have.
     pub fn set_unchecked(&mut self, col: usize, row: usize, new_state: CellState) {
         let word_col = col / 64;
         let shift = 63 - (col & (64 - 1)); // translate literal col (ex: 134) to bit index in word_col
@@ -258,6 +953,7 @@ impl GenState {
         if known_cell_word & mask == 0 {
             panic!("Tried to set unknown cell at ({}, {})", col, row);
         }
+        self.clear_dying(row, word_col, mask);
 
         // clear all player cell bits, so that this cell is unowned by any player (we'll set
         // ownership further down)
@@ -289,6 +985,14 @@ impl GenState {
                 cells.modify_bits_in_word(row, word_col, mask, BitOperation::Clear);
                 walls.modify_bits_in_word(row, word_col, mask, BitOperation::Set);
             }
+            CellState::Dying(n) => {
+                if n == 0 || n as usize > self.dying.len() {
+                    panic!("Tried to set dying state {}, but the rule has {}", n, self.dying.len());
+                }
+                cells.modify_bits_in_word(row, word_col, mask, BitOperation::Clear);
+                walls.modify_bits_in_word(row, word_col, mask, BitOperation::Clear);
+                self.dying[n as usize - 1].modify_bits_in_word(row, word_col, mask, BitOperation::Set);
+            }
             _ => unimplemented!(),
         }
     }
@@ -296,7 +1000,8 @@ impl GenState {
     /// Copies from `src` BitGrid to this GenState as the player specified by `opt_player_id`,
     /// unless `opt_player_id` is `None`. This is an "or" operation, so any existing alive cells
     /// are retained, though they may change ownership.  Walls, however, are preserved. Fog is
-    /// cleared on a cell-by-cell basis, rather than using fog radius.
+    /// cleared on a cell-by-cell basis, rather than using fog radius. Dying cells that are
+    /// written to come to life.
     ///
     /// IMPORTANT: dst_region should not extend beyond GenState, nor beyond player's writable
     /// region. Caller may ensure this using Region::intersection.
@@ -334,8 +1039,41 @@ impl GenState {
                 let word_col = word_col as usize;
 
                 self.cells[row][word_col] &= !self.wall_cells[row][word_col];
+                let cells_word = self.cells[row][word_col];
+                self.clear_dying(row, word_col, cells_word);
+            }
+        }
+    }
+
+    /// Creates a `HashLife` engine containing the live cells of this generation, which will evolve
+    /// according to `rule`. Ownership, walls and fog are not carried over; see `HashLife` for the
+    /// restrictions of that engine.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ConwayError::InvalidData` if `rule` is not supported by `HashLife`.
+    pub fn to_hash_life(&self, rule: Rule) -> ConwayResult<HashLife> {
+        HashLife::from_bit_grid(&self.cells, rule)
+    }
+
+    /// Replaces the cells of this GenState with the live cells of `life` that fall inside it.
+    /// Live cells are not written on top of walls or unknown cells. Every cell is unowned
+    /// afterwards, since `HashLife` does not track ownership, and no cell is dying. Walls, fog and
+    /// known cells are left as they are.
+    pub fn copy_from_hash_life(&mut self, life: &HashLife) {
+        let mut cells = life.to_bit_grid(Region::new(0, 0, self.width(), self.height()));
+        for row in 0..self.height() {
+            for word_col in 0..cells.width_in_words() {
+                cells[row][word_col] &= self.known[row][word_col] & !self.wall_cells[row][word_col];
             }
         }
+        self.cells = cells;
+        for player_state in &mut self.player_states {
+            player_state.cells.clear();
+        }
+        for plane in &mut self.dying {
+            plane.clear();
+        }
     }
 
     /// Creates a "diff" RLE pattern (contained within GenStateDiff) showing the changes present in
@@ -393,6 +1131,51 @@ impl GenState {
         }
     }
 
+    /// Returns a hash of this generation as seen by the player specified by `visibility`, for
+    /// detecting when a client and the server disagree about a generation. The result only
+    /// depends on the bits of the `BitGrid`s, so it is the same on every platform.
+    ///
+    /// If `visibility.is_none()`, the hash covers the cells, walls, known cells, dying cells, and
+    /// the cells and fog of each player. Otherwise, it covers the player's fog, and the cells,
+    /// walls, dying cells and each player's cells outside of that fog; known cells and other
+    /// players' fog are left out, since a client only has the view of its own player.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `visibility` is out of range.
+    pub fn checksum(&self, visibility: Option<usize>) -> u64 {
+        let mut checksum = Checksum(0);
+        checksum.write(self.width as u64);
+        checksum.write(self.player_states.len() as u64);
+        match visibility {
+            Some(player_id) => {
+                let fog = &self.player_states[player_id].fog;
+                checksum.write_grid(fog, None);
+                checksum.write_grid(&self.cells, Some(fog));
+                checksum.write_grid(&self.wall_cells, Some(fog));
+                for player_state in &self.player_states {
+                    checksum.write_grid(&player_state.cells, Some(fog));
+                }
+                for plane in &self.dying {
+                    checksum.write_grid(plane, Some(fog));
+                }
+            }
+            None => {
+                checksum.write_grid(&self.cells, None);
+                checksum.write_grid(&self.wall_cells, None);
+                checksum.write_grid(&self.known, None);
+                for player_state in &self.player_states {
+                    checksum.write_grid(&player_state.cells, None);
+                    checksum.write_grid(&player_state.fog, None);
+                }
+                for plane in &self.dying {
+                    checksum.write_grid(plane, None);
+                }
+            }
+        }
+        checksum.0
+    }
+
     /// Zeroes out all bit grids. Note: this means fog is cleared for all players.
     pub fn clear(&mut self) {
         let region = Region::new(0, 0, self.width(), self.height());
@@ -405,6 +1188,9 @@ impl GenState {
             p.cells.modify_region(region, BitOperation::Clear);
             p.fog.modify_region(region, BitOperation::Clear);
         }
+        for plane in &mut self.dying {
+            plane.modify_region(region, BitOperation::Clear);
+        }
     }
 
     pub fn copy(&self, dest: &mut GenState) {
@@ -425,13 +1211,16 @@ impl GenState {
                 region,
             );
         }
+        for (plane, dest_plane) in self.dying.iter().zip(dest.dying.iter_mut()) {
+            BitGrid::copy(plane, dest_plane, region);
+        }
     }
 }
 
 impl CharGrid for GenState {
     /// Width in cells
     fn width(&self) -> usize {
-        self.cells.width()
+        self.width
     }
 
     /// Height in cells
@@ -446,18 +1235,29 @@ impl CharGrid for GenState {
         }
         let word_col = col / 64;
         let shift = 63 - (col & (64 - 1));
+        let opt_dying = match CellState::from_char(ch) {
+            Some(CellState::Dying(n)) => Some(n),
+            _ => None,
+        };
         // cells
         match ch {
             'b' | 'W' | '?' => self.cells[row][word_col] &= !(1 << shift),
             'o' | 'A'..='V' => self.cells[row][word_col] |= 1 << shift,
+            _ if opt_dying.is_some() => self.cells[row][word_col] &= !(1 << shift),
             _ => unreachable!(),
         }
         // wall cells
         match ch {
             'W' => self.wall_cells[row][word_col] |= 1 << shift,
             'b' | 'o' | 'A'..='V' | '?' => self.wall_cells[row][word_col] &= !(1 << shift),
+            _ if opt_dying.is_some() => self.wall_cells[row][word_col] &= !(1 << shift),
             _ => unreachable!(),
         }
+        // dying cells
+        self.clear_dying(row, word_col, 1 << shift);
+        if let Some(n) = opt_dying {
+            self.dying[n as usize - 1][row][word_col] |= 1 << shift; // can panic if the rule has fewer dying states
+        }
         // player_states
         if ch == '?' {
             if visibility.is_none() {
@@ -496,7 +1296,7 @@ impl CharGrid for GenState {
         match ch {
             'o' | 'b' | 'A'..='W' | '?' => true,
             NO_OP_CHAR => true,
-            _ => false,
+            _ => DYING_CHARS.contains(ch),
         }
     }
 
@@ -560,9 +1360,17 @@ impl CharGrid for GenState {
         }
         if wall_ch == 'o' {
             return (min_run, CellState::Wall.to_char());
-        } else {
-            return (min_run, CellState::Dead.to_char());
         }
+        for (i, plane) in self.dying.iter().enumerate() {
+            let (dying_run, dying_ch) = plane.get_run(col, row, None);
+            if dying_run < min_run {
+                min_run = dying_run;
+            }
+            if dying_ch == 'o' {
+                return (min_run, CellState::Dying(i as u8 + 1).to_char());
+            }
+        }
+        (min_run, CellState::Dead.to_char())
     }
 }
 
@@ -649,8 +1457,9 @@ impl fmt::Display for Universe {
                 let cell_cen = cells[row_idx][col_idx];
                 let wall_cen = wall[row_idx][col_idx];
                 let known_cen = known[row_idx][col_idx];
-                let mut s = String::with_capacity(64);
-                for shift in (0..64).rev() {
+                let cells_in_word = cmp::min(64, self.width - col_idx * 64);
+                let mut s = String::with_capacity(cells_in_word);
+                for shift in (64 - cells_in_word..64).rev() {
                     if (known_cen >> shift) & 1 == 0 {
                         s.push('?');
                     } else if (cell_cen >> shift) & 1 == 1 {
@@ -704,6 +1513,8 @@ impl Universe {
             let cell = (gen_state.cells[row][word_col] & mask) >> shift;
             if cell == 1 {
                 CellState::Alive(None)
+            } else if let Some(n) = gen_state.dying_state(row, word_col, mask) {
+                CellState::Dying(n)
             } else {
                 CellState::Dead
             }
@@ -717,7 +1528,10 @@ impl Universe {
     ///
     /// Panics if an attempt is made to set an unknown cell.
     pub fn set_unchecked(&mut self, col: usize, row: usize, new_state: CellState) {
-        self.gen_states[self.state_index].set_unchecked(col, row, new_state)
+        self.gen_states[self.state_index].set_unchecked(col, row, new_state);
+        if let CellState::Alive(Some(player_id)) = new_state {
+            self.share_team_fog_cell(player_id, col, row);
+        }
     }
 
     /// Checked set - check for:
@@ -725,6 +1539,7 @@ impl Universe {
     /// * current cell state (can't change wall)
     /// * fog
     /// * if current cell is alive, player_id matches player_id argument
+    /// * if the cell comes to life, the player's budget allows it (see `set_budget`)
     ///
     /// If any of the above checks fail, do nothing.
     ///
@@ -768,7 +1583,16 @@ impl Universe {
             }
         }
 
-        self.set_unchecked(col, row, new_state)
+        let births = match (self.get_cell_state(col, row, None), new_state) {
+            (CellState::Alive(_), _) => 0,
+            (_, CellState::Alive(_)) => 1,
+            _ => 0,
+        };
+        if self.check_budget(player_id, births).is_err() {
+            return;
+        }
+        self.set_unchecked(col, row, new_state);
+        self.spend_budget(player_id, births);
     }
 
     /// Switches any non-dead state to CellState::Dead.
@@ -780,12 +1604,22 @@ impl Universe {
     ///  2. Clear all players' cell
     ///  3. If general cell transitioned Dead->Alive, then set requested player's cell
     ///
+    /// A dying cell just becomes dead.
+    ///
     /// The new value of the cell is returned.
     pub fn toggle_unchecked(&mut self, col: usize, row: usize, opt_player_id: Option<usize>) -> CellState {
         let word_col = col / 64;
         let shift = 63 - (col & (64 - 1));
         let mask = 1 << shift;
 
+        {
+            let gen_state = &mut self.gen_states[self.state_index];
+            if gen_state.dying_state(row, word_col, mask).is_some() {
+                gen_state.clear_dying(row, word_col, mask);
+                return CellState::Dead;
+            }
+        }
+
         let word = {
             let cells = &mut self.gen_states[self.state_index].cells;
             cells.modify_bits_in_word(row, word_col, mask, BitOperation::Toggle);
@@ -807,6 +1641,7 @@ impl Universe {
                 let ref mut player = self.gen_states[self.state_index].player_states[player_id];
                 player.cells.modify_bits_in_word(row, word_col, mask, BitOperation::Set);
                 player.fog.modify_bits_in_word(row, word_col, mask, BitOperation::Clear);
+                self.share_team_fog_cell(player_id, col, row);
             }
 
             CellState::Alive(opt_player_id)
@@ -819,8 +1654,9 @@ impl Universe {
     ///
     /// # Errors
     ///
-    /// * It is a `ConwayError::AccessDenied` error to toggle outside player's writable area, or to
-    /// toggle a wall or an unknown cell.
+    /// * It is a `ConwayError::AccessDenied` error to toggle outside player's writable area, to
+    ///   toggle a wall or an unknown cell, or to bring a cell to life beyond the player's budget
+    ///   (see `set_budget`).
     /// * It is a `ConwayError::InvalidData` error to pass in an invalid player_id.
     pub fn toggle(&mut self, col: usize, row: usize, player_id: usize) -> ConwayResult<CellState> {
         use ConwayError::*;
@@ -846,10 +1682,20 @@ impl Universe {
                 });
             }
         }
-        Ok(self.toggle_unchecked(col, row, Some(player_id)))
+        let births = if self.get_cell_state(col, row, None) == CellState::Dead {
+            1
+        } else {
+            0
+        };
+        self.check_budget(player_id, births)
+            .map_err(|reason| AccessDenied { reason })?;
+        let new_state = self.toggle_unchecked(col, row, Some(player_id));
+        self.spend_budget(player_id, births);
+        Ok(new_state)
     }
 
-    /// Returns Ok(true) if col and row are in writable area for specified player.
+    /// Returns Ok(true) if col and row are in writable area for specified player. Cells outside of
+    /// the universe are never writable.
     ///
     /// # Errors
     ///
@@ -860,8 +1706,11 @@ impl Universe {
                 reason: format!("Unexpected player_id {}", player_id),
             });
         }
-        let in_writable_region = self.player_writable[player_id].contains(col as isize, row as isize);
-        if !in_writable_region {
+        if col >= self.width || row >= self.height {
+            return Ok(false);
+        }
+        let in_writable_area = self.player_writable[player_id].contains(col as isize, row as isize);
+        if !in_writable_area {
             return Ok(false);
         }
 
@@ -873,42 +1722,627 @@ impl Universe {
         Ok(!on_wall_cell)
     }
 
-    /// Instantiate a new blank universe with the given width and height, in cells.
-    /// The universe is at generation 1.
+    /// Returns the budget of `player_id`, or `None` if the player has no budget.
     ///
-    /// **Note**: it is easier to use `BigBang` to build a `Universe`, as that has default values
-    /// that can be overridden as needed.
-    pub fn new(
-        width: usize,
-        height: usize,
-        is_server: bool,
-        history: usize,
-        num_players: usize,
-        player_writable: Vec<Region>,
-        fog_radius: usize,
-    ) -> ConwayResult<Universe> {
-        use ConwayError::*;
-        if height == 0 {
-            return Err(InvalidData {
-                reason: "Height must be positive".to_owned(),
-            });
-        }
+    /// # Panics
+    ///
+    /// Panics if `player_id` is out of range.
+    pub fn budget(&self, player_id: usize) -> Option<Budget> {
+        self.budgets[self.state_index][player_id]
+            .as_ref()
+            .map(|tracker| tracker.budget())
+    }
 
-        let width_in_words = width / 64;
-        if width % 64 != 0 {
-            return Err(InvalidData {
-                reason: "Width must be a multiple of 64".to_owned(),
-            });
-        } else if width == 0 {
-            return Err(InvalidData {
-                reason: "Width must be positive".to_owned(),
-            });
-        }
+    /// Returns the number of cells `player_id` can still bring to life, or `None` if the player
+    /// has no budget.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `player_id` is out of range.
+    pub fn available_cells(&self, player_id: usize) -> Option<usize> {
+        self.budgets[self.state_index][player_id]
+            .as_ref()
+            .map(|tracker| tracker.available())
+    }
 
-        if history == 0 {
-            return Err(InvalidData {
-                reason: "History must be positive".to_owned(),
-            });
+    /// Gives `player_id` a full `budget`, or removes the player's budget if `opt_budget` is
+    /// `None`. A player with a budget can only bring cells to life with `toggle`, `set`,
+    /// `drop_pattern` and scheduled actions while the budget allows it; see `Budget`. The budget
+    /// is refilled as generations are computed by `next` or received with `apply`. Each generation
+    /// in the history keeps the budgets as they were in that generation, so `rewind_to` and `fork`
+    /// restore them. The full budget is given in every generation in the history, so that it is
+    /// not lost by rewinding.
+    ///
+    /// # Errors
+    ///
+    /// It is a `ConwayError::InvalidData` error to pass in an invalid player_id, or a budget with
+    /// a rate limit spanning 0 generations.
+    pub fn set_budget(&mut self, player_id: usize, opt_budget: Option<Budget>) -> ConwayResult<()> {
+        self.check_player_id(player_id)?;
+        if let Some(budget) = opt_budget {
+            budget.check()?;
+        }
+        for budgets in &mut self.budgets {
+            budgets[player_id] = opt_budget.map(BudgetTracker::new);
+        }
+        Ok(())
+    }
+
+    /// Returns the reason if bringing `cells` cells to life at once is over the budget of
+    /// `player_id`.
+    fn check_budget(&mut self, player_id: usize, cells: usize) -> Result<(), String> {
+        let gen = self.generation;
+        match self.budgets[self.state_index][player_id].as_mut() {
+            Some(tracker) if cells > 0 => tracker
+                .check_placement(cells, gen)
+                .map_err(|reason| format!("player {} {}", player_id, reason)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Takes `cells` cells brought to life at once from the budget of `player_id`, after
+    /// `check_budget`.
+    fn spend_budget(&mut self, player_id: usize, cells: usize) {
+        let gen = self.generation;
+        if let Some(tracker) = self.budgets[self.state_index][player_id].as_mut() {
+            if cells > 0 {
+                tracker.place(cells, gen);
+            }
+        }
+    }
+
+    /// Starts the budgets of generational state `state_index` from those of `prev_state_index`,
+    /// refilled for the `generations` generations in between.
+    fn replenish_budgets(&mut self, prev_state_index: usize, state_index: usize, generations: usize) {
+        let mut budgets = self.budgets[prev_state_index].clone();
+        for tracker in budgets.iter_mut().flatten() {
+            tracker.replenish(generations);
+        }
+        self.budgets[state_index] = budgets;
+    }
+
+    /// Writes `pattern` for `player_id` with its top-left cell at (`col`, `row`), combining it with
+    /// the cells already there according to `mode`. Parts of the pattern beyond the edges of the
+    /// universe are wrapped around according to its `Topology`. Dying cells that are written to
+    /// come to life or die. Returns the number of cells that changed.
+    ///
+    /// The pattern is written in full or not at all. Every cell it writes to (the live cells of
+    /// the pattern, or all the cells it covers with `BlendMode::Replace`) must be in the player's
+    /// writable area, known and out of the player's fog, and must not be a wall or a cell of
+    /// another player. If the player has a budget (see `set_budget`), the cells brought to life
+    /// are taken from it; live cells that belong to no player are claimed for free.
+    ///
+    /// # Errors
+    ///
+    /// * It is a `PlacementError::InvalidData` error to pass in an invalid player_id, or a pattern
+    ///   that can't be parsed.
+    /// * It is a `PlacementError::CellsDenied` error if any cells can't be written to; these cells
+    ///   are listed in the error.
+    /// * It is a `PlacementError::OverBudget` error if the player's budget doesn't allow bringing
+    ///   these cells to life.
+    pub fn drop_pattern(
+        &mut self,
+        player_id: usize,
+        col: usize,
+        row: usize,
+        pattern: &Pattern,
+        mode: BlendMode,
+    ) -> Result<usize, PlacementError> {
+        self.check_player_id(player_id)?;
+        let (width, height) = pattern.calc_size()?;
+        if width == 0 || height == 0 {
+            return Ok(0);
+        }
+        let grid = pattern.to_new_bit_grid(width, height)?;
+
+        let mut writes = vec![]; // cells to write, and whether the pattern is alive there
+        let mut denied = vec![];
+        for pat_row in 0..height {
+            for pat_col in 0..width {
+                let is_alive = grid.is_set(pat_col, pat_row);
+                if !is_alive && mode != BlendMode::Replace {
+                    continue;
+                }
+                let (uni_col, uni_row) = ((col + pat_col) as isize, (row + pat_row) as isize);
+                match self.topology.map_cell(uni_col, uni_row, self.width, self.height) {
+                    Some((c, r)) if self.may_place(c, r, player_id) => writes.push((c, r, is_alive)),
+                    Some((c, r)) => denied.push((c as isize, r as isize)),
+                    None => denied.push((uni_col, uni_row)),
+                }
+            }
+        }
+        if !denied.is_empty() {
+            return Err(PlacementError::CellsDenied { cells: denied });
+        }
+        let births = {
+            let gen_state = &self.gen_states[self.state_index];
+            writes
+                .iter()
+                .filter(|&&(c, r, is_alive)| match mode {
+                    BlendMode::Or | BlendMode::Replace => is_alive && !gen_state.cells.is_set(c, r),
+                    BlendMode::Xor => !gen_state.cells.is_set(c, r),
+                })
+                .count()
+        };
+        self.check_budget(player_id, births)
+            .map_err(|reason| PlacementError::OverBudget { reason })?;
+
+        let mut changed = 0;
+        for (c, r, is_alive) in writes {
+            let gen_state = &self.gen_states[self.state_index];
+            let was_alive = gen_state.cells.is_set(c, r);
+            let was_own = gen_state.player_states[player_id].cells.is_set(c, r);
+            let was_dying = gen_state.dying_state(r, c / 64, 1 << (63 - c % 64)).is_some();
+            let comes_alive = match mode {
+                BlendMode::Or | BlendMode::Replace => is_alive,
+                BlendMode::Xor => !was_alive,
+            };
+            if comes_alive {
+                if !was_own {
+                    changed += 1;
+                    self.set_unchecked(c, r, CellState::Alive(Some(player_id)));
+                }
+            } else if was_alive || was_dying {
+                changed += 1;
+                self.set_unchecked(c, r, CellState::Dead);
+            }
+        }
+        self.spend_budget(player_id, births);
+        Ok(changed)
+    }
+
+    /// Kills the live and dying cells in `region` that don't belong to other players, as
+    /// `player_id`. Walls, cells of other players, and cells that are unknown or in the player's
+    /// fog are left alone. Returns the number of cells that changed.
+    ///
+    /// # Errors
+    ///
+    /// * It is a `PlacementError::InvalidData` error to pass in an invalid player_id.
+    /// * It is a `PlacementError::CellsDenied` error if any part of `region` is outside the
+    ///   player's writable area; these cells are listed in the error, and nothing is cleared.
+    pub fn clear_area(&mut self, player_id: usize, region: Region) -> Result<usize, PlacementError> {
+        self.check_player_id(player_id)?;
+        let mut clears = vec![];
+        let mut denied = vec![];
+        for row in region.top()..=region.bottom() {
+            for col in region.left()..=region.right() {
+                match self.topology.map_cell(col, row, self.width, self.height) {
+                    Some((c, r)) if self.player_writable[player_id].contains(c as isize, r as isize) => {
+                        if self.may_place(c, r, player_id) {
+                            clears.push((c, r));
+                        }
+                    }
+                    Some((c, r)) => denied.push((c as isize, r as isize)),
+                    None => denied.push((col, row)),
+                }
+            }
+        }
+        if !denied.is_empty() {
+            return Err(PlacementError::CellsDenied { cells: denied });
+        }
+
+        let mut changed = 0;
+        for (c, r) in clears {
+            let gen_state = &self.gen_states[self.state_index];
+            let was_dying = gen_state.dying_state(r, c / 64, 1 << (63 - c % 64)).is_some();
+            if gen_state.cells.is_set(c, r) || was_dying {
+                changed += 1;
+                self.set_unchecked(c, r, CellState::Dead);
+            }
+        }
+        Ok(changed)
+    }
+
+    fn check_player_id(&self, player_id: usize) -> ConwayResult<()> {
+        if !self.has_player(player_id) {
+            return Err(ConwayError::InvalidData {
+                reason: format!("Unexpected player_id {}", player_id),
+            });
+        }
+        Ok(())
+    }
+
+    /// Returns true if `player_id` may write to the cell in the latest generation: it is in the
+    /// player's writable area, known, out of the player's fog, and neither a wall nor a cell of
+    /// another player.
+    fn may_place(&self, col: usize, row: usize, player_id: usize) -> bool {
+        let gen_state = &self.gen_states[self.state_index];
+        let player_state = &gen_state.player_states[player_id];
+        self.player_writable[player_id].contains(col as isize, row as isize)
+            && gen_state.known.is_set(col, row)
+            && !gen_state.wall_cells.is_set(col, row)
+            && !player_state.fog.is_set(col, row)
+            && gen_state
+                .player_states
+                .iter()
+                .enumerate()
+                .all(|(other_id, other)| other_id == player_id || !other.cells.is_set(col, row))
+    }
+
+    /// Schedules `action` by `player_id` to be applied to generation `gen` as soon as `next`
+    /// computes it. All the actions for a generation are applied together, by player_id and then
+    /// in the order they were scheduled, with the same checks as `drop_pattern`. Unlike with
+    /// `drop_pattern`, the cells that fail these checks are skipped one by one, but an action over
+    /// the player's budget (see `set_budget`) is skipped as a whole. A cell targeted by more than
+    /// one player in the same generation is contested and left unchanged by all of them, so the
+    /// outcome doesn't depend on the order in which the actions of different players arrive.
+    ///
+    /// Actions for generations received with `apply` are dropped, since the diff already includes
+    /// them. Actions stay scheduled when rewinding, and the actions applied to the discarded
+    /// generations are scheduled again (see `rewind_to`). Snapshots include the scheduled actions.
+    ///
+    /// # Errors
+    ///
+    /// It is a `ConwayError::InvalidData` error to pass in an invalid player_id, to schedule an
+    /// action for a generation that has already been computed, to pass in a cell or pattern
+    /// position outside the universe, or a pattern that can't be parsed.
+    pub fn schedule(&mut self, gen: usize, player_id: usize, action: Action) -> ConwayResult<()> {
+        self.check_player_id(player_id)?;
+        if gen <= self.generation {
+            return Err(ConwayError::InvalidData {
+                reason: format!(
+                    "cannot schedule an action for generation {}, since generation {} has been computed",
+                    gen, self.generation
+                ),
+            });
+        }
+        self.action_cells(&action)?;
+        self.scheduled.push(ScheduledAction { gen, player_id, action });
+        Ok(())
+    }
+
+    /// Returns the actions waiting to be applied, in the order they were scheduled.
+    pub fn scheduled_actions(&self) -> &[ScheduledAction] {
+        &self.scheduled
+    }
+
+    /// Drops all scheduled actions.
+    pub fn clear_scheduled_actions(&mut self) {
+        self.scheduled.clear();
+    }
+
+    /// Moves the scheduled actions for generations up to and including `gen` to the applied
+    /// actions, and forgets the applied actions that `rewind_to` can no longer schedule again.
+    fn retire_actions(&mut self, gen: usize) {
+        let (due, later): (Vec<ScheduledAction>, Vec<ScheduledAction>) =
+            self.scheduled.drain(..).partition(|scheduled| scheduled.gen <= gen);
+        self.scheduled = later;
+        self.applied.extend(due);
+        let opt_oldest_gen = self.history().next().map(|(gen, _)| gen);
+        if let Some(oldest_gen) = opt_oldest_gen {
+            self.applied.retain(|applied| applied.gen > oldest_gen);
+        }
+    }
+
+    /// Returns the cells that `action` targets.
+    fn action_cells(&self, action: &Action) -> ConwayResult<Vec<(usize, usize)>> {
+        let check_position = |col: usize, row: usize| {
+            if col >= self.width || row >= self.height {
+                Err(ConwayError::InvalidData {
+                    reason: format!("col={}, row={} is outside the universe", col, row),
+                })
+            } else {
+                Ok(())
+            }
+        };
+        let mut cells = vec![];
+        match *action {
+            Action::Toggle { col, row } => {
+                check_position(col, row)?;
+                cells.push((col, row));
+            }
+            Action::DropPattern { ref pattern, col, row } => {
+                check_position(col, row)?;
+                let (width, height) = pattern.calc_size()?;
+                if width > 0 && height > 0 {
+                    let (uni_width, uni_height, topology) = (self.width, self.height, self.topology);
+                    let grid = pattern.to_new_bit_grid(width, height)?;
+                    cells.extend(grid.iter_set().filter_map(|(pat_col, pat_row)| {
+                        topology.map_cell(
+                            (col + pat_col) as isize,
+                            (row + pat_row) as isize,
+                            uni_width,
+                            uni_height,
+                        )
+                    }));
+                }
+            }
+            Action::ClearArea { region } => {
+                if let Some(region) = region.intersection(self.region()) {
+                    for row in region.top()..=region.bottom() {
+                        for col in region.left()..=region.right() {
+                            cells.push((col as usize, row as usize));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(cells)
+    }
+
+    /// Applies the scheduled actions that are due in the latest generation; see `schedule`.
+    fn apply_scheduled_actions(&mut self) {
+        let gen = self.generation;
+        let mut due: Vec<ScheduledAction> = self
+            .scheduled
+            .iter()
+            .filter(|scheduled| scheduled.gen <= gen)
+            .cloned()
+            .collect();
+        self.retire_actions(gen);
+        if due.is_empty() {
+            return;
+        }
+        due.sort_by_key(|scheduled| scheduled.player_id); // stable, so each player's actions stay in order
+
+        let targets: Vec<Vec<(usize, usize)>> = due
+            .iter()
+            .map(|scheduled| self.action_cells(&scheduled.action).unwrap_or_default())
+            .collect();
+        // the player targeting each cell, or None if several players do
+        let mut claims: HashMap<(usize, usize), Option<usize>> = HashMap::new();
+        for (scheduled, cells) in due.iter().zip(targets.iter()) {
+            for &cell in cells {
+                let claim = claims.entry(cell).or_insert(Some(scheduled.player_id));
+                if *claim != Some(scheduled.player_id) {
+                    *claim = None;
+                }
+            }
+        }
+
+        for (scheduled, cells) in due.iter().zip(targets) {
+            let player_id = scheduled.player_id;
+            let cells: Vec<(usize, usize)> = cells
+                .into_iter()
+                .filter(|&(col, row)| claims[&(col, row)].is_some() && self.may_place(col, row, player_id))
+                .collect();
+            let births = {
+                let gen_state = &self.gen_states[self.state_index];
+                let is_born = |&&(col, row): &&(usize, usize)| match scheduled.action {
+                    Action::Toggle { .. } => {
+                        !gen_state.cells.is_set(col, row)
+                            && gen_state.dying_state(row, col / 64, 1 << (63 - col % 64)).is_none()
+                    }
+                    Action::DropPattern { .. } => !gen_state.cells.is_set(col, row),
+                    Action::ClearArea { .. } => false,
+                };
+                cells.iter().filter(is_born).count()
+            };
+            if self.check_budget(player_id, births).is_err() {
+                continue; // the whole action is skipped
+            }
+            for (col, row) in cells {
+                match scheduled.action {
+                    Action::Toggle { .. } => {
+                        self.toggle_unchecked(col, row, Some(player_id));
+                    }
+                    Action::DropPattern { .. } => self.set_unchecked(col, row, CellState::Alive(Some(player_id))),
+                    Action::ClearArea { .. } => self.set_unchecked(col, row, CellState::Dead),
+                }
+            }
+            self.spend_budget(player_id, births);
+        }
+    }
+
+    /// Returns the team of `player_id`, or `None` if the player is not on a team.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `player_id` is out of range.
+    pub fn team(&self, player_id: usize) -> Option<usize> {
+        self.teams[player_id]
+    }
+
+    /// Returns the player_ids of the players on the same team as `player_id`, including
+    /// `player_id` itself.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `player_id` is out of range.
+    pub fn teammates(&self, player_id: usize) -> Vec<usize> {
+        match self.teams[player_id] {
+            Some(team) => (0..self.num_players)
+                .filter(|&other_id| self.teams[other_id] == Some(team))
+                .collect(),
+            None => vec![player_id],
+        }
+    }
+
+    /// Numbers the teams from 0, giving each player that is not on a team a team of its own.
+    /// Returns the team number of each player (indexed by player_id).
+    fn team_indices(&self) -> Vec<usize> {
+        let mut teams: Vec<usize> = self.teams.iter().filter_map(|&opt_team| opt_team).collect();
+        teams.sort();
+        teams.dedup();
+        self.teams
+            .iter()
+            .enumerate()
+            .map(|(player_id, &opt_team)| match opt_team {
+                Some(team) => teams.binary_search(&team).unwrap(),
+                None => teams.len() + player_id,
+            })
+            .collect()
+    }
+
+    /// Makes teammates share their visibility in generational state `state_index`: a cell is in
+    /// the fog of a player only if it is in the fog of each of the player's teammates.
+    fn share_team_fog(&mut self, state_index: usize) {
+        let teams = &self.teams;
+        let player_states = &mut self.gen_states[state_index].player_states;
+        for (player_id, &opt_team) in teams.iter().enumerate() {
+            let team = match opt_team {
+                Some(team) => team,
+                None => continue,
+            };
+            // only the first player of each team does the work
+            if teams[..player_id].contains(&opt_team) {
+                continue;
+            }
+            let teammates: Vec<usize> = (player_id..teams.len())
+                .filter(|&other_id| teams[other_id] == Some(team))
+                .collect();
+            if teammates.len() < 2 {
+                continue;
+            }
+            let mut fog = player_states[player_id].fog.clone();
+            for &other_id in &teammates[1..] {
+                fog.combine(&player_states[other_id].fog, SetOperation::And);
+            }
+            for &other_id in &teammates {
+                player_states[other_id].fog = fog.clone();
+            }
+        }
+    }
+
+    /// Clears the fog at (`col`, `row`) in the latest generation for the teammates of `player_id`.
+    fn share_team_fog_cell(&mut self, player_id: usize, col: usize, row: usize) {
+        if self.teams[player_id].is_none() {
+            return;
+        }
+        let mask = 1 << (63 - (col & (64 - 1)));
+        for other_id in self.teammates(player_id) {
+            self.gen_states[self.state_index].player_states[other_id]
+                .fog
+                .modify_bits_in_word(row, col / 64, mask, BitOperation::Clear);
+        }
+    }
+
+    /// Adds a player to the running game, who may write to the writable area of `player`. The
+    /// new player has no cells and, in every generation in the history, fog everywhere outside of
+    /// its writable area. The new player gets the lowest player_id left vacant by
+    /// `remove_player`, if any, or else the next player_id. Returns the player_id of the new
+    /// player.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ConwayError::InvalidData` if the writable area is a mask that does not match the
+    /// dimensions of the universe, if the player's budget has a rate limit spanning 0
+    /// generations, or if there is no vacant player_id and the universe already has
+    /// `MAX_PLAYERS` players.
+    pub fn add_player(&mut self, player: PlayerBuilder) -> ConwayResult<usize> {
+        let area = player.writable_area;
+        area.check(self.width, self.height)?;
+        if let Some(budget) = player.budget {
+            budget.check()?;
+        }
+        let opt_vacant_id = self.vacant.iter().position(|&vacant| vacant);
+        if opt_vacant_id.is_none() && self.num_players >= MAX_PLAYERS {
+            return Err(ConwayError::InvalidData {
+                reason: format!("At most {} players are supported", MAX_PLAYERS),
+            });
+        }
+        let mut fog = BitGrid::new(self.width_in_words, self.height);
+        fog.modify_region(self.region(), BitOperation::Set);
+        fog.combine(&area.to_bit_grid(self.width, self.height), SetOperation::AndNot);
+        let player_id = if let Some(player_id) = opt_vacant_id {
+            // the cells of a vacant player_id were cleared by `remove_player`
+            for gen_state in &mut self.gen_states {
+                gen_state.player_states[player_id].fog = fog.clone();
+            }
+            self.player_writable[player_id] = area;
+            self.teams[player_id] = player.team;
+            for budgets in &mut self.budgets {
+                budgets[player_id] = player.budget.map(BudgetTracker::new);
+            }
+            self.vacant[player_id] = false;
+            player_id
+        } else {
+            for gen_state in &mut self.gen_states {
+                gen_state.player_states.push(PlayerGenState {
+                    cells: BitGrid::new(self.width_in_words, self.height),
+                    fog:   fog.clone(),
+                });
+            }
+            self.player_writable.push(area);
+            self.teams.push(player.team);
+            for budgets in &mut self.budgets {
+                budgets.push(player.budget.map(BudgetTracker::new));
+            }
+            self.vacant.push(false);
+            self.num_players += 1;
+            self.num_players - 1
+        };
+        for state_index in 0..self.gen_states.len() {
+            self.share_team_fog(state_index);
+        }
+        Ok(player_id)
+    }
+
+    /// Removes a player from the running game. The player's cells become unowned, die, or turn
+    /// into walls according to `policy`. This is done in every generation in the history, so that
+    /// diffs between any of them stay consistent with a client universe that removes the same
+    /// player with the same policy. The player_ids of the other players don't change, so that
+    /// they still match the player_ids used by a `GameMode` or by the server; `player_id` is left
+    /// vacant until `add_player` gives it to a new player. The player's scheduled actions are
+    /// dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ConwayError::InvalidData` if `player_id` is out of range or already vacant.
+    pub fn remove_player(&mut self, player_id: usize, policy: LeavePolicy) -> ConwayResult<()> {
+        if !self.has_player(player_id) {
+            return Err(ConwayError::InvalidData {
+                reason: format!("Unexpected player_id {}", player_id),
+            });
+        }
+        let mut fog = BitGrid::new(self.width_in_words, self.height);
+        fog.modify_region(self.region(), BitOperation::Set);
+        for gen_state in &mut self.gen_states {
+            let player_state = &mut gen_state.player_states[player_id];
+            match policy {
+                LeavePolicy::Unowned => {}
+                LeavePolicy::Die => gen_state.cells.combine(&player_state.cells, SetOperation::AndNot),
+                LeavePolicy::Wall => {
+                    gen_state.cells.combine(&player_state.cells, SetOperation::AndNot);
+                    gen_state.wall_cells.combine(&player_state.cells, SetOperation::Or);
+                }
+            }
+            player_state.cells.clear();
+            player_state.fog = fog.clone();
+        }
+        self.player_writable[player_id] = WritableArea::Regions(vec![]);
+        self.teams[player_id] = None;
+        for budgets in &mut self.budgets {
+            budgets[player_id] = None;
+        }
+        self.vacant[player_id] = true;
+        self.scheduled.retain(|scheduled| scheduled.player_id != player_id);
+        self.applied.retain(|applied| applied.player_id != player_id);
+        Ok(())
+    }
+
+    /// Instantiate a new blank universe with the given width and height, in cells.
+    /// The universe is at generation 1.
+    ///
+    /// **Note**: it is easier to use `BigBang` to build a `Universe`, as that has default values
+    /// that can be overridden as needed.
+    pub fn new(
+        width: usize,
+        height: usize,
+        is_server: bool,
+        history: usize,
+        num_players: usize,
+        player_writable: Vec<WritableArea>,
+        fog_radius: usize,
+    ) -> ConwayResult<Universe> {
+        use ConwayError::*;
+        if height == 0 {
+            return Err(InvalidData {
+                reason: "Height must be positive".to_owned(),
+            });
+        }
+
+        if width == 0 {
+            return Err(InvalidData {
+                reason: "Width must be positive".to_owned(),
+            });
+        }
+        let width_in_words = (width - 1) / 64 + 1; // the bits beyond `width` in the last word are unused
+
+        if history == 0 {
+            return Err(InvalidData {
+                reason: "History must be positive".to_owned(),
+            });
         }
 
         if fog_radius == 0 {
@@ -917,6 +2351,29 @@ impl Universe {
             });
         }
 
+        if num_players > MAX_PLAYERS {
+            return Err(InvalidData {
+                reason: format!("At most {} players are supported, not {}", MAX_PLAYERS, num_players),
+            });
+        }
+
+        if player_writable.len() != num_players {
+            return Err(InvalidData {
+                reason: format!(
+                    "Expected a writable area for each of the {} players, but got {}",
+                    num_players,
+                    player_writable.len()
+                ),
+            });
+        }
+        for area in &player_writable {
+            area.check(width, height)?;
+        }
+        let writable_masks: Vec<BitGrid> = player_writable
+            .iter()
+            .map(|area| area.to_bit_grid(width, height))
+            .collect();
+
         // Initialize all generational states with the default appropriate bitgrids
         let mut gen_states = Vec::new();
         for i in 0..history {
@@ -931,8 +2388,8 @@ impl Universe {
                 pgs.fog
                     .modify_region(Region::new(0, 0, width, height), BitOperation::Set);
 
-                // clear player fog on writable regions
-                pgs.fog.modify_region(player_writable[player_id], BitOperation::Clear);
+                // clear player fog on writable area
+                pgs.fog.combine(&writable_masks[player_id], SetOperation::AndNot);
 
                 player_states.push(pgs);
             }
@@ -943,20 +2400,18 @@ impl Universe {
             let mut known = BitGrid::new(width_in_words, height);
 
             if is_server && i == 0 {
-                // could use modify_region but it's much cheaper this way
-                for y in 0..height {
-                    for x in 0..width_in_words {
-                        known[y][x] = u64::max_value(); // if server, all cells are known
-                    }
-                }
+                // if server, all cells are known; the unused bits at the end of each row stay clear
+                known.modify_region(Region::new(0, 0, width, height), BitOperation::Set);
             }
 
             gen_states.push(GenState {
+                width:         width,
                 gen_or_none:   if i == 0 && is_server { Some(1) } else { None },
                 cells:         BitGrid::new(width_in_words, height),
                 wall_cells:    BitGrid::new(width_in_words, height),
                 known:         known,
                 player_states: player_states,
+                dying:         vec![],
             });
         }
 
@@ -969,9 +2424,19 @@ impl Universe {
             state_index:     0,
             gen_states:      gen_states,
             player_writable: player_writable,
+            teams:           vec![None; num_players],
+            vacant:          vec![false; num_players],
             // TODO: it's not very rusty to have uninitialized stuff (use Option<FogInfo> instead)
             fog_radius:      fog_radius,      // uninitialized
             fog_circle:      BitGrid(vec![]), // uninitialized
+            fog_spans:       vec![],          // uninitialized
+            rule:            Rule::default(),
+            topology:        Topology::default(),
+            parallel:        false,
+            activity:        None,
+            scheduled:       vec![],
+            applied:         vec![],
+            budgets:         vec![vec![None; num_players]; history],
         };
         uni.generate_fog_circle_bitmap();
         Ok(uni)
@@ -1008,7 +2473,9 @@ impl Universe {
         let center_x = (fog_radius - 1) as isize;
         let center_y = (fog_radius - 1) as isize;
         // algebra!
+        self.fog_spans = Vec::with_capacity(height);
         for y in 0..height {
+            let mut opt_span: Option<(usize, usize)> = None;
             for bit_x in 0..word_width * 64 {
                 let shift = 63 - (bit_x & 63);
                 let mask = 1 << shift;
@@ -1017,9 +2484,96 @@ impl Universe {
                 let y_delta = isize::abs(center_y - y as isize) as usize;
                 if x_delta * x_delta + y_delta * y_delta < fog_radius * fog_radius {
                     self.fog_circle[y][bit_x / 64] &= !mask;
+                    opt_span = Some(opt_span.map_or((bit_x, bit_x), |(left, _)| (left, bit_x)));
                 }
             }
+            // every row of the circle contains at least its center column
+            self.fog_spans.push(opt_span.unwrap());
+        }
+    }
+
+    /// Get the rule used to compute each generation.
+    pub fn rule(&self) -> Rule {
+        self.rule
+    }
+
+    /// Get the topology of this universe.
+    pub fn topology(&self) -> Topology {
+        self.topology
+    }
+
+    /// Returns true if generations are computed on multiple threads.
+    pub fn is_parallel(&self) -> bool {
+        self.parallel
+    }
+
+    /// Turns parallel mode on or off. In parallel mode, `next` splits the universe into bands of
+    /// rows and computes them on the rayon global thread pool, which is shared by all universes in
+    /// the process (configure it with `rayon::ThreadPoolBuilder::build_global`). This only pays off
+    /// for large universes.
+    pub fn set_parallel(&mut self, is_parallel: bool) {
+        self.parallel = is_parallel;
+    }
+
+    /// Returns the number of generations over which cell activity is counted, or `None` if the age
+    /// and activity of cells are not tracked.
+    pub fn activity_window(&self) -> Option<usize> {
+        self.activity.as_ref().map(|tracker| tracker.window())
+    }
+
+    /// Starts keeping track of the age and activity of each cell, counting births and deaths over
+    /// the last `window` generations, or stops if `opt_window` is `None`. Tracking starts afresh
+    /// with all ages and counts at 0, even if it was already on. Only the generations computed by
+    /// `next` or received with `apply` are counted; cells set or toggled in between are seen as
+    /// changes in the following generation. Snapshots include the ages and counts, so a loaded
+    /// universe carries on tracking where the saved one left off.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `opt_window` is `Some(0)`.
+    pub fn set_activity_window(&mut self, opt_window: Option<usize>) {
+        self.activity = opt_window.map(|window| ActivityTracker::new(self.width, self.height, window));
+    }
+
+    /// Returns the age and activity of the cells in `region` (see `ActivityMap`), or `None` if
+    /// they are not tracked or `region` is outside the universe. The parts of `region` beyond the
+    /// edges of the universe are left out. `visibility` is an optional player_id; cells in the fog
+    /// of that player, and cells that are not known, are reported with an age and activity of 0.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `visibility` is not a valid player_id.
+    pub fn activity(&self, region: Region, visibility: Option<usize>) -> Option<ActivityMap> {
+        let tracker = self.activity.as_ref()?;
+        let region = region.intersection(self.region())?;
+        let gen_state = &self.gen_states[self.state_index];
+        let mut visible = gen_state.known.clone();
+        if let Some(player_id) = visibility {
+            visible.combine(&gen_state.player_states[player_id].fog, SetOperation::AndNot);
         }
+        Some(tracker.map(region, &visible))
+    }
+
+    /// The cells of the latest generation, ignoring walls, fog and ownership.
+    pub(crate) fn latest_cells(&self) -> &BitGrid {
+        &self.gen_states[self.state_index].cells
+    }
+
+    /// The cells of the latest generation belonging to `player_id`, ignoring fog.
+    pub(crate) fn latest_player_cells(&self, player_id: usize) -> &BitGrid {
+        &self.gen_states[self.state_index].player_states[player_id].cells
+    }
+
+    /// Returns the number of players in the game. Every player_id is less than this, but some of
+    /// them may be vacant; see `has_player`.
+    pub fn num_players(&self) -> usize {
+        self.num_players
+    }
+
+    /// Returns true if `player_id` is in range and wasn't removed with `remove_player` (or was
+    /// given to a new player by `add_player` since).
+    pub fn has_player(&self, player_id: usize) -> bool {
+        player_id < self.num_players && !self.vacant[player_id]
     }
 
     /// Get the latest generation number (1-based).
@@ -1098,11 +2652,22 @@ impl Universe {
     }
 
     /// Compute the next generation. Returns the new latest generation number.
+    ///
+    /// The actions scheduled for the new generation (see `schedule`) are then applied to it.
+    ///
+    /// A cell that would belong to players on different teams belongs to no one. A cell that
+    /// would belong to several players on the same team stays with the teammate that owns it, or
+    /// else goes to the teammate with the lowest player_id.
+    ///
+    /// In parallel mode (see `set_parallel`), bands of rows are computed on the rayon thread pool,
+    /// and the fog of each player is cleared on a separate thread. The result is the same as in
+    /// serial mode.
     pub fn next(&mut self) -> usize {
         // get the buffers and buffers_next
         assert!(self.gen_states[self.state_index].gen_or_none.unwrap() == self.generation);
         let history = self.gen_states.len();
         let next_state_index = (self.state_index + 1) % history;
+        let team_indices = self.team_indices();
 
         let (gen_state, gen_state_next) = if self.state_index < next_state_index {
             let (p0, p1) = self.gen_states.split_at_mut(next_state_index);
@@ -1113,423 +2678,650 @@ impl Universe {
             (&p1[history - 2], &mut p0[0])
         };
 
+        let ctx = NextGenContext {
+            reader:     NeighborReader::new(self.topology, self.width, self.height),
+            rule:       self.rule,
+            is_conway:  self.rule.is_conway(), // B3/S23 has a faster hand-optimized implementation
+            team_index: team_indices,
+        };
+
+        // Copy fog over to next generation
+        for row_idx in 0..self.height {
+            for player_id in 0..self.num_players {
+                gen_state_next.player_states[player_id].fog[row_idx]
+                    .copy_from_slice(&gen_state.player_states[player_id].fog[row_idx]);
+            }
+        }
+
+        // cells that each player acquired in this generation (indexed by player_id)
+        let (width_in_words, height) = (self.width_in_words, self.height);
+        let mut newly_owned: Vec<BitGrid> = (0..self.num_players)
+            .map(|_| BitGrid::new(width_in_words, height))
+            .collect();
+
         {
-            let cells = &gen_state.cells;
-            let wall = &gen_state.wall_cells;
-            let known = &gen_state.known;
-            let cells_next = &mut gen_state_next.cells;
-            let wall_next = &mut gen_state_next.wall_cells;
-            let known_next = &mut gen_state_next.known;
-
-            // Copy fog over to next generation
-            for row_idx in 0..self.height {
-                for player_id in 0..self.num_players {
-                    gen_state_next.player_states[player_id].fog[row_idx]
-                        .copy_from_slice(&gen_state.player_states[player_id].fog[row_idx]);
+            let mut rows = NextGenRow::split(gen_state_next, &mut newly_owned);
+            if self.parallel {
+                rows.par_chunks_mut(PARALLEL_BAND_HEIGHT)
+                    .enumerate()
+                    .for_each(|(band_idx, band)| {
+                        for (i, row) in band.iter_mut().enumerate() {
+                            ctx.next_row(gen_state, band_idx * PARALLEL_BAND_HEIGHT + i, row);
+                        }
+                    });
+            } else {
+                for (row_idx, row) in rows.iter_mut().enumerate() {
+                    ctx.next_row(gen_state, row_idx, row);
                 }
             }
+        }
 
-            for row_idx in 0..self.height {
-                let n_row_idx = (row_idx + self.height - 1) % self.height;
-                let s_row_idx = (row_idx + 1) % self.height;
-                let cells_row_n = &cells[n_row_idx];
-                let cells_row_c = &cells[row_idx];
-                let cells_row_s = &cells[s_row_idx];
-                let wall_row_c = &wall[row_idx];
-                let known_row_n = &known[n_row_idx];
-                let known_row_c = &known[row_idx];
-                let known_row_s = &known[s_row_idx];
-
-                // These will be shifted over at the beginning of the loop
-                let mut cells_nw;
-                let mut cells_w;
-                let mut cells_sw;
-                let mut cells_n = cells_row_n[self.width_in_words - 1];
-                let mut cells_cen = cells_row_c[self.width_in_words - 1];
-                let mut cells_s = cells_row_s[self.width_in_words - 1];
-                let mut cells_ne = cells_row_n[0];
-                let mut cells_e = cells_row_c[0];
-                let mut cells_se = cells_row_s[0];
-                let mut known_nw;
-                let mut known_w;
-                let mut known_sw;
-                let mut known_n = known_row_n[self.width_in_words - 1];
-                let mut known_cen = known_row_c[self.width_in_words - 1];
-                let mut known_s = known_row_s[self.width_in_words - 1];
-                let mut known_ne = known_row_n[0];
-                let mut known_e = known_row_c[0];
-                let mut known_se = known_row_s[0];
-
-                for col_idx in 0..self.width_in_words {
-                    // shift over
-                    cells_nw = cells_n;
-                    cells_n = cells_ne;
-                    cells_w = cells_cen;
-                    cells_cen = cells_e;
-                    cells_sw = cells_s;
-                    cells_s = cells_se;
-                    cells_ne = cells_row_n[(col_idx + 1) % self.width_in_words];
-                    cells_e = cells_row_c[(col_idx + 1) % self.width_in_words];
-                    cells_se = cells_row_s[(col_idx + 1) % self.width_in_words];
-                    known_nw = known_n;
-                    known_n = known_ne;
-                    known_w = known_cen;
-                    known_cen = known_e;
-                    known_sw = known_s;
-                    known_s = known_se;
-                    known_ne = known_row_n[(col_idx + 1) % self.width_in_words];
-                    known_e = known_row_c[(col_idx + 1) % self.width_in_words];
-                    known_se = known_row_s[(col_idx + 1) % self.width_in_words];
-
-                    // apply BitGrid changes
-                    let mut cells_cen_next = Universe::next_single_gen(
-                        cells_nw, cells_n, cells_ne, cells_w, cells_cen, cells_e, cells_sw, cells_s, cells_se,
+        // clear fog for all cells that turned on in this generation
+        let (fog_spans, fog_radius, topology, width) = (&self.fog_spans, self.fog_radius, self.topology, self.width);
+        let clear_player_fog = |(player_state, newly_owned): (&mut PlayerGenState, &BitGrid)| {
+            for (row_idx, row) in newly_owned.0.iter().enumerate() {
+                for (col_idx, &bits) in row.iter().enumerate() {
+                    Universe::clear_fog(
+                        &mut player_state.fog,
+                        fog_spans,
+                        fog_radius,
+                        topology,
+                        width,
+                        row_idx,
+                        col_idx,
+                        bits,
                     );
+                }
+            }
+        };
+        if self.parallel {
+            gen_state_next
+                .player_states
+                .par_iter_mut()
+                .zip(newly_owned.par_iter())
+                .for_each(clear_player_fog);
+        } else {
+            gen_state_next
+                .player_states
+                .iter_mut()
+                .zip(newly_owned.iter())
+                .for_each(clear_player_fog);
+        }
 
-                    // any known cells with at least one unknown neighbor will become unknown in
-                    // the next generation
-                    known_next[row_idx][col_idx] = Universe::contagious_zero(
-                        known_nw, known_n, known_ne, known_w, known_cen, known_e, known_sw, known_s, known_se,
-                    );
+        // increment generation in appropriate places
+        let prev_state_index = self.state_index;
+        self.generation += 1;
+        self.state_index = next_state_index;
+        gen_state_next.gen_or_none = Some(self.generation);
+        self.share_team_fog(next_state_index);
+        self.replenish_budgets(prev_state_index, next_state_index, 1);
+        self.apply_scheduled_actions();
+        if let Some(tracker) = self.activity.as_mut() {
+            tracker.record(
+                &self.gen_states[prev_state_index].cells,
+                &self.gen_states[next_state_index].cells,
+                1,
+            );
+        }
+        self.generation
+    }
 
-                    cells_cen_next &= known_next[row_idx][col_idx];
-                    cells_cen_next &= !wall_row_c[col_idx];
-
-                    // assign to the u64 element in the next generation
-                    cells_next[row_idx][col_idx] = cells_cen_next;
-
-                    let mut in_multiple: u64 = 0;
-                    let mut seen_before: u64 = 0;
-                    for player_id in 0..self.num_players {
-                        // Any unknown cell with
-                        //
-                        // A cell which would have belonged to 2+ players in the next
-                        // generation will belong to no one. These are unowned cells.
-                        //
-                        // Unowned cells follow the same rules of life.
-                        //
-                        // Any unowned cells are influenced by their neighbors, and if players,
-                        // can be acquired by the player, just as long as no two players are
-                        // fighting over those cells
-                        let player_cell_next = Universe::contagious_one(
-                            gen_state.player_states[player_id].cells[n_row_idx]
-                                [(col_idx + self.width_in_words - 1) % self.width_in_words],
-                            gen_state.player_states[player_id].cells[n_row_idx][col_idx],
-                            gen_state.player_states[player_id].cells[n_row_idx][(col_idx + 1) % self.width_in_words],
-                            gen_state.player_states[player_id].cells[row_idx]
-                                [(col_idx + self.width_in_words - 1) % self.width_in_words],
-                            gen_state.player_states[player_id].cells[row_idx][col_idx],
-                            gen_state.player_states[player_id].cells[row_idx][(col_idx + 1) % self.width_in_words],
-                            gen_state.player_states[player_id].cells[s_row_idx]
-                                [(col_idx + self.width_in_words - 1) % self.width_in_words],
-                            gen_state.player_states[player_id].cells[s_row_idx][col_idx],
-                            gen_state.player_states[player_id].cells[s_row_idx][(col_idx + 1) % self.width_in_words],
-                        ) & cells_cen_next;
-                        in_multiple |= player_cell_next & seen_before;
-                        seen_before |= player_cell_next;
-                        gen_state_next.player_states[player_id].cells[row_idx][col_idx] = player_cell_next;
-                    }
-                    for player_id in 0..self.num_players {
-                        let cell_cur = gen_state.player_states[player_id].cells[row_idx][col_idx];
-                        let mut cell_next = gen_state_next.player_states[player_id].cells[row_idx][col_idx];
-                        cell_next &= !in_multiple; // if a cell would have belonged to multiple players, it belongs to none
-                        gen_state_next.player_states[player_id].cells[row_idx][col_idx] = cell_next;
-
-                        // clear fog for all cells that turned on in this generation
-                        Universe::clear_fog(
-                            &mut gen_state_next.player_states[player_id].fog,
-                            &self.fog_circle,
-                            self.fog_radius,
-                            self.width,
-                            self.height,
-                            row_idx,
-                            col_idx,
-                            cell_next & !cell_cur,
+    /// Clears the fog for the specified bits in the 64-bit word at `center_row_idx` and
+    /// `center_col_idx` using the spans of the fog circle (see `generate_fog_circle_bitmap`
+    /// documentation for more on this). Fog beyond the edges of the universe is cleared on the
+    /// other side, or not at all, depending on `topology`.
+    fn clear_fog(
+        player_fog: &mut BitGrid,
+        fog_spans: &[(usize, usize)],
+        fog_radius: usize,
+        topology: Topology,
+        uni_width: usize,
+        center_row_idx: usize,
+        center_col_idx: usize,
+        bits_to_clear: u64,
+    ) {
+        if bits_to_clear == 0 {
+            return; // nothing to do
+        }
+
+        // The fog circle must be shifted up and to the left by this many cells to be centered.
+        let offset = fog_radius as isize - 1;
+
+        // Each run of adjacent bits in bits_to_clear clears a single span of fog per row of the
+        // fog circle, so there's no need to clear around each bit separately.
+        let mut remaining = bits_to_clear;
+        while remaining != 0 {
+            let run_start = remaining.leading_zeros() as usize; // bit index counting from the left
+            let run_len = (!(remaining << run_start)).leading_zeros() as usize;
+            let run_end = run_start + run_len; // exclusive
+            remaining &= u64::max_value().checked_shr(run_end as u32).unwrap_or(0);
+
+            let run_left = (center_col_idx * 64 + run_start) as isize;
+            let run_right = run_left + run_len as isize - 1;
+            debug!(
+                "clearing fog around row {} and cols range [{}, {}]",
+                center_row_idx, run_left, run_right
+            );
+            for (fog_row_idx, &(span_left, span_right)) in fog_spans.iter().enumerate() {
+                let row = center_row_idx as isize + fog_row_idx as isize - offset;
+                let left = run_left - offset + span_left as isize;
+                let right = run_right - offset + span_right as isize;
+                topology.modify_span(player_fog, uni_width, row, left, right, BitOperation::Clear);
+            }
+        }
+    }
+
+    /// Returns an iterator over every non-dead cell in the universe for the current generation,
+    /// as (`col`, `row`, `cell_state`) in row-major order. `region` is the rectangular area used
+    /// for restricting results; parts of it beyond the edges of the universe wrap around according
+    /// to the universe's `Topology`. `visibility` is an optional player_id; if specified, cells not
+    /// visible to the player are returned as `CellState::Fog`.
+    ///
+    /// Rows outside `region`, and words with only dead cells, are skipped without looking at
+    /// their cells.
+    ///
+    /// # Panics
+    ///
+    /// Does numerous consistency checks on the bitmaps, and panics if inconsistencies are found.
+    pub fn non_dead_cells(&self, region: Region, visibility: Option<usize>) -> NonDeadCells<'_> {
+        let mut iter = NonDeadCells {
+            uni: self,
+            region,
+            visibility,
+            row: 0,
+            word_col: 0,
+            word: 0,
+        };
+        iter.word = iter.candidates();
+        iter
+    }
+
+    /// Returns an iterator over every non-dead cell in the universe for the current generation.
+    /// `visibility` is an optional player_id, allowing filtering based on fog.
+    /// The iterator yields (col, row, cell_state).
+    pub fn non_dead_cells_full(&self, visibility: Option<usize>) -> NonDeadCells<'_> {
+        self.non_dead_cells(self.region(), visibility)
+    }
+
+    /// Iterate over every non-dead cell in the universe for the current generation. `region` is
+    /// the rectangular area used for restricting results; parts of it beyond the edges of the
+    /// universe wrap around according to the universe's `Topology`. `visibility` is an optional
+    /// player_id; if specified, causes cells not visible to the player to be passed as
+    /// `CellState::Fog` to the callback.
+    ///
+    /// Callback receives (`col`, `row`, `cell_state`). See also `non_dead_cells`.
+    ///
+    /// # Panics
+    ///
+    /// Does numerous consistency checks on the bitmaps, and panics if inconsistencies are found.
+    pub fn each_non_dead(
+        &self,
+        region: Region,
+        visibility: Option<usize>,
+        callback: &mut dyn FnMut(usize, usize, CellState),
+    ) {
+        for (col, row, state) in self.non_dead_cells(region, visibility) {
+            callback(col, row, state);
+        }
+    }
+
+    /// Iterate over every non-dead cell in the universe for the current generation.
+    /// `visibility` is an optional player_id, allowing filtering based on fog.
+    /// Callback receives (col, row, cell_state).
+    pub fn each_non_dead_full(&self, visibility: Option<usize>, callback: &mut dyn FnMut(usize, usize, CellState)) {
+        self.each_non_dead(self.region(), visibility, callback);
+    }
+
+    /// Returns the state of the cell at (`col`, `row`) of the current generation, as seen by the
+    /// player specified by `visibility`, after checking the bitmaps for consistency.
+    ///
+    /// # Panics
+    ///
+    /// Panics if inconsistencies are found.
+    fn checked_cell_state(&self, col: usize, row: usize, visibility: Option<usize>) -> CellState {
+        let gen_state = &self.gen_states[self.state_index];
+        let col_idx = col / 64;
+        let shift = 63 - (col & (64 - 1));
+        let c = (gen_state.cells[row][col_idx] >> shift) & 1 == 1;
+        let w = (gen_state.wall_cells[row][col_idx] >> shift) & 1 == 1;
+        let k = (gen_state.known[row][col_idx] >> shift) & 1 == 1;
+        if c && w {
+            panic!("Cannot be both cell and wall at ({}, {})", col, row);
+        }
+        if !k && ((c && !w) || (!c && w)) {
+            panic!("Unspecified invalid state at ({}, {})", col, row);
+        }
+        let mut state = CellState::Wall;
+        if c && !w && k {
+            // It's known and it's a cell; check cells + fog for every player
+            // (expensive step since this is per-bit).
+
+            let mut opt_player_id = None;
+            for player_id in 0..self.num_players {
+                let player_state = &gen_state.player_states[player_id];
+                let pc = (player_state.cells[row][col_idx] >> shift) & 1 == 1;
+                let pf = (player_state.fog[row][col_idx] >> shift) & 1 == 1;
+                if pc && pf {
+                    panic!(
+                        "Player cell and player fog at ({}, {}) for player {}",
+                        col, row, player_id
+                    );
+                }
+                if pc {
+                    if let Some(other_player_id) = opt_player_id {
+                        panic!(
+                            "Cell ({}, {}) belongs to player {} and player {}!",
+                            col, row, other_player_id, player_id
                         );
                     }
+                    opt_player_id = Some(player_id);
                 }
+            }
+            state = CellState::Alive(opt_player_id);
+        } else if !c && !w {
+            // (B) other states
+            state = if !k {
+                CellState::Fog
+            } else if let Some(n) = gen_state.dying_state(row, col_idx, 1 << shift) {
+                CellState::Dying(n)
+            } else {
+                CellState::Dead
+            };
+        }
+        if let Some(player_id) = visibility {
+            let player_state = &gen_state.player_states[player_id];
+            let pc = (player_state.cells[row][col_idx] >> shift) & 1 == 1;
+            let pf = (player_state.fog[row][col_idx] >> shift) & 1 == 1;
+            if !k && pc {
+                panic!("Player can't have cells where unknown, at ({}, {})", col, row);
+            }
+            if w && pc {
+                panic!("Player can't have cells where wall, at ({}, {})", col, row);
+            }
+            if pf {
+                state = CellState::Fog;
+            }
+        }
+        state
+    }
 
-                // copy wall to wall_next
-                wall_next[row_idx].copy_from_slice(wall_row_c);
+    /// Counts the cells of the latest generation, for each player and for the cells not owned by
+    /// any player, as seen by the player specified by `visibility` (cells in that player's fog
+    /// are not counted), or without fog if `visibility.is_none()`.
+    ///
+    /// For a player, `inside_writable` and `outside_writable` refer to that player's writable
+    /// area; for unowned cells, `inside_writable` counts the cells inside any player's writable
+    /// area. A birth is a cell that is alive now but was dead in the previous generation, and a
+    /// death is a cell that was alive in the previous generation but is dead now; ownership is
+    /// looked up in the generation where the cell was alive. Births and deaths are 0 if the
+    /// previous generation is no longer in the history (or this is the first generation).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `visibility` is not a valid player_id.
+    pub fn stats(&self, visibility: Option<usize>) -> UniverseStats {
+        let gen_state = &self.gen_states[self.state_index];
+        let history = self.gen_states.len();
+        let prev_state = &self.gen_states[(self.state_index + history - 1) % history];
+        let opt_prev = if self.generation > 1 && prev_state.gen_or_none == Some(self.generation - 1) {
+            Some(prev_state)
+        } else {
+            None
+        };
+
+        // removes the bits that are in fog for `visibility`
+        let visible = |grid: &BitGrid| -> BitGrid {
+            let mut grid = grid.clone();
+            if let Some(player_id) = visibility {
+                grid.combine(&gen_state.player_states[player_id].fog, SetOperation::AndNot);
+            }
+            grid
+        };
+        let writable_mask = |areas: &[WritableArea]| -> BitGrid {
+            let mut mask = BitGrid::new(self.width_in_words, self.height);
+            for area in areas {
+                mask.combine(&area.to_bit_grid(self.width, self.height), SetOperation::Or);
             }
+            mask
+        };
+        let count = |cells: BitGrid, prev_cells: Option<BitGrid>, writable: BitGrid| -> CellStats {
+            let live = cells.popcount();
+            let mut inside = cells.clone();
+            inside.combine(&writable, SetOperation::And);
+            let inside_writable = inside.popcount();
+            let (births, deaths) = match (opt_prev, prev_cells) {
+                (Some(prev), Some(mut died)) => {
+                    let mut born = cells.clone();
+                    born.combine(&prev.cells, SetOperation::AndNot);
+                    died.combine(&gen_state.cells, SetOperation::AndNot);
+                    (born.popcount(), died.popcount())
+                }
+                _ => (0, 0),
+            };
+            CellStats {
+                live,
+                inside_writable,
+                outside_writable: live - inside_writable,
+                births,
+                deaths,
+                bounding_box: cells.bounding_box(),
+            }
+        };
+
+        let players = (0..self.num_players)
+            .map(|player_id| {
+                count(
+                    visible(&gen_state.player_states[player_id].cells),
+                    opt_prev.map(|prev| visible(&prev.player_states[player_id].cells)),
+                    writable_mask(&self.player_writable[player_id..=player_id]),
+                )
+            })
+            .collect();
+
+        let unowned_in = |state: &GenState| -> BitGrid {
+            let mut unowned = visible(&state.cells);
+            for player_state in &state.player_states {
+                unowned.combine(&player_state.cells, SetOperation::AndNot);
+            }
+            unowned
+        };
+        let unowned = count(
+            unowned_in(gen_state),
+            opt_prev.map(unowned_in),
+            writable_mask(&self.player_writable),
+        );
+
+        UniverseStats {
+            generation: self.generation,
+            players,
+            unowned,
+            walls: visible(&gen_state.wall_cells).popcount(),
         }
+    }
 
-        // increment generation in appropriate places
-        self.generation += 1;
-        self.state_index = next_state_index;
-        gen_state_next.gen_or_none = Some(self.generation);
-        self.generation
+    /// Get a Region of the same size as the universe.
+    pub fn region(&self) -> Region {
+        Region::new(0, 0, self.width, self.height)
     }
 
-    /// Clears the fog for the specified bits in the 64-bit word at `center_row_idx` and
-    /// `center_col_idx` using the fog circle (see `generate_fog_circle_bitmap` documentation for
-    /// more on this).
-    //TODO: unit test with fog_radiuses above and below 64
-    fn clear_fog(
-        player_fog: &mut BitGrid,
-        fog_circle: &BitGrid,
-        fog_radius: usize,
-        uni_width: usize,
-        uni_height: usize,
-        center_row_idx: usize,
-        center_col_idx: usize,
-        bits_to_clear: u64,
-    ) {
-        if bits_to_clear == 0 {
-            return; // nothing to do
-        }
-        debug!("---");
+    /// Creates a `HashLife` engine containing the live cells of the latest generation, evolving
+    /// according to this universe's rule, for fast-forwarding a copy of the universe. Only cells
+    /// are carried over; see `HashLife` for its restrictions.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ConwayError::InvalidData` if the rule is not supported by `HashLife`.
+    pub fn to_hash_life(&self) -> ConwayResult<HashLife> {
+        self.gen_states[self.state_index].to_hash_life(self.rule)
+    }
 
-        // Iterate over every u64 in a rectangular region of `player_fog`, ANDing together the
-        // shifted u64s of `fog_circle` according to `bits_to_clear`, so as to only perform a
-        // single `&=` in `player_fog`.
-        // EXPLANATION OF VAR NAMES: "_idx" indicates this is a word index; otherwise, it's a game
-        // coord.
+    /// Replaces the cells of the latest generation with the live cells of `life` that fall inside
+    /// the universe. The generation number is not changed. See `GenState::copy_from_hash_life`
+    /// for how walls, fog and ownership are handled.
+    pub fn copy_from_hash_life(&mut self, life: &HashLife) {
+        self.gen_states[self.state_index].copy_from_hash_life(life);
+    }
 
-        // Get the highest and lowest bits in bits_to_clear
-        let mut col_of_highest_to_clear = center_col_idx * 64;
-        for shift in (0..64).rev() {
-            if bits_to_clear & (1 << shift) > 0 {
-                break;
-            }
-            col_of_highest_to_clear += 1;
+    /// Creates a `PatternFile` describing the latest generation as seen by the player specified
+    /// by `visibility`, or a fog-less view if `visibility.is_none()`. The header line includes
+    /// this universe's rule. See `PatternFile` for the characters used for walls, fog and
+    /// player-owned cells.
+    pub fn to_pattern_file(&self, visibility: Option<usize>) -> PatternFile {
+        PatternFile::from_grid(&self.gen_states[self.state_index], visibility).with_rule(self.rule)
+    }
+
+    /// Writes the pattern in `file` to the latest generation, starting at the top-left corner,
+    /// as if it were written by the player specified by `visibility` (see
+    /// `GenState::write_at_position`). The rule in the header line, if any, is ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ConwayError::InvalidData` if the pattern is invalid, does not fit in the
+    /// universe, has cells belonging to a player that is not in this universe, has dying cells
+    /// in states that the rule doesn't have, or has fog but `visibility` is `None`. The universe
+    /// is not modified in these cases.
+    pub fn load_pattern_file(&mut self, file: &PatternFile, visibility: Option<usize>) -> ConwayResult<()> {
+        let (width, height) = file.pattern.calc_size()?;
+        if width > self.width || height > self.height {
+            return Err(ConwayError::InvalidData {
+                reason: format!(
+                    "pattern of size {}x{} does not fit in universe of size {}x{}",
+                    width, height, self.width, self.height
+                ),
+            });
         }
-        let mut col_of_lowest_to_clear = center_col_idx * 64 + 63;
-        for shift in 0..64 {
-            if bits_to_clear & (1 << shift) > 0 {
-                break;
+        if let Some(player_id) = visibility {
+            if player_id >= self.num_players {
+                return Err(ConwayError::InvalidData {
+                    reason: format!("player {} does not exist", player_id),
+                });
             }
-            col_of_lowest_to_clear -= 1;
         }
-        debug!(
-            "bits_to_clear: row {} and cols range [{}, {}]",
-            center_row_idx, col_of_highest_to_clear, col_of_lowest_to_clear
-        );
-
-        // Get the bounds in terms of game coordinates (from col_left to col_right, inclusive,
-        // and from row_top to row_bottom, inclusive).
-        let row_top = (uni_height + center_row_idx - (fog_radius - 1)) % uni_height;
-        let row_bottom = (center_row_idx + fog_radius - 1) % uni_height;
-        let col_left = (uni_width + col_of_highest_to_clear - (fog_radius - 1)) % uni_width;
-        let col_right = (col_of_lowest_to_clear + fog_radius - 1) % uni_width;
-        debug!("row_(top,bottom) range is [{}, {}]", row_top, row_bottom);
-        debug!("col_(left,right) range is [{}, {}]", col_left, col_right);
-
-        // Convert cols to col_idxes
-        let col_idx_left = col_left / 64;
-        let col_idx_right = col_right / 64;
-
-        let mut row_idx = row_top;
-        let uni_word_width = uni_width / 64;
-        loop {
-            //debug!("row_idx is {} (out of height {})", row_idx, uni_height);
-            let mut col_idx = col_idx_left;
-            loop {
-                debug!("row {}, col range [{}, {}]", row_idx, col_idx * 64, col_idx * 64 + 63);
-                //debug!("col_idx is {} (out of word_width {}); stopping after {}", col_idx, uni_word_width, col_idx_right);
-                let mut mask = u64::max_value();
-                for shift in (0..64).rev() {
-                    if mask == 0 {
-                        break;
-                    }
-                    if bits_to_clear & (1 << shift) > 0 {
-                        let fog_row_idx = (uni_height + row_idx - center_row_idx + (fog_radius - 1)) % uni_height;
-                        let current_highest_col = col_idx * 64;
-                        let current_lowest_col = col_idx * 64 + 63;
-                        for fog_col_idx in 0..fog_circle.width_in_words() {
-                            let fog_highest_col =
-                                (uni_width + center_col_idx * 64 + (63 - shift) - (fog_radius - 1)) % uni_width;
-                            let fog_lowest_col =
-                                (uni_width + center_col_idx * 64 + (63 - shift) - (fog_radius - 1) + 63) % uni_width;
-                            debug!("  fog col range [{}, {}]", fog_highest_col, fog_lowest_col);
-
-                            if current_highest_col == fog_highest_col && current_lowest_col == fog_lowest_col {
-                                mask &= fog_circle[fog_row_idx][fog_col_idx];
-                                debug!(
-                                    "  mask is now {:016x}, cleared by fog circle R{}, Ci{}, no shift",
-                                    mask, fog_row_idx, fog_col_idx
-                                );
-                            } else if current_highest_col <= fog_lowest_col && fog_lowest_col < current_lowest_col {
-                                // we need to double negate so that shifting results in 1s, not 0s
-                                mask &=
-                                    !(!fog_circle[fog_row_idx][fog_col_idx] << (current_lowest_col - fog_lowest_col));
-                                debug!("  fog word is {:016x}", fog_circle[fog_row_idx][fog_col_idx]);
-                                debug!(
-                                    "  mask is now {:016x}, cleared by fog circle R{}, Ci{}, fog circle << {}",
-                                    mask,
-                                    fog_row_idx,
-                                    fog_col_idx,
-                                    current_lowest_col - fog_lowest_col
-                                );
-                            } else if current_highest_col < fog_highest_col && fog_highest_col <= current_lowest_col {
-                                mask &=
-                                    !(!fog_circle[fog_row_idx][fog_col_idx] >> (fog_highest_col - current_highest_col));
-                                debug!("  fog word is {:016x}", fog_circle[fog_row_idx][fog_col_idx]);
-                                debug!(
-                                    "  mask is now {:016x}, cleared by fog circle R{}, Ci{}, fog circle >> {}",
-                                    mask,
-                                    fog_row_idx,
-                                    fog_col_idx,
-                                    fog_highest_col - current_highest_col
-                                );
-                            }
-                        }
-                    }
+        for ch in file.pattern.0.chars() {
+            match CellState::from_char(ch) {
+                Some(CellState::Alive(Some(player_id))) if player_id >= self.num_players => {
+                    return Err(ConwayError::InvalidData {
+                        reason: format!("pattern has cells belonging to nonexistent player {}", player_id),
+                    });
                 }
-                player_fog[row_idx][col_idx] &= mask;
-
-                if col_idx == col_idx_right {
-                    break;
+                Some(CellState::Fog) if visibility.is_none() => {
+                    return Err(ConwayError::InvalidData {
+                        reason: "pattern has fog but no player was specified".to_owned(),
+                    });
                 }
-                col_idx = (col_idx + 1) % uni_word_width;
-            }
-
-            if row_idx == row_bottom {
-                break;
+                Some(CellState::Dying(n)) if n as usize > self.rule.dying_states() => {
+                    return Err(ConwayError::InvalidData {
+                        reason: format!("pattern has dying state {}, which rule {} doesn't have", n, self.rule),
+                    });
+                }
+                _ => {}
             }
-            row_idx = (row_idx + 1) % uni_height;
         }
+        let mut gen_state = self.gen_states[self.state_index].clone();
+        file.pattern.to_grid(&mut gen_state, visibility)?;
+        self.gen_states[self.state_index] = gen_state;
+        Ok(())
     }
 
-    /// Iterate over every non-dead cell in the universe for the current generation. `region` is
-    /// the rectangular area used for restricting results. `visibility` is an optional player_id;
-    /// if specified, causes cells not visible to the player to be passed as `CellState::Fog` to the
-    /// callback.
+    /// Saves the full state of this universe, including its whole history, the budgets of the
+    /// players, the scheduled actions and the age and activity of the cells, in the specified
+    /// format. The universe can be recreated with `Universe::load_snapshot`. Whether the universe
+    /// is in parallel mode is not saved.
     ///
-    /// Callback receives (`col`, `row`, `cell_state`).
+    /// # Errors
     ///
-    /// # Panics
+    /// Returns `ConwayError::InvalidData` if encoding fails.
+    pub fn save_snapshot(&self, format: SnapshotFormat) -> ConwayResult<Vec<u8>> {
+        UniverseSnapshot {
+            version:         SNAPSHOT_VERSION,
+            width:           self.width,
+            height:          self.height,
+            generation:      self.generation,
+            num_players:     self.num_players,
+            state_index:     self.state_index,
+            gen_states:      self.gen_states.clone(),
+            player_writable: self.player_writable.clone(),
+            teams:           self.teams.clone(),
+            vacant:          self.vacant.clone(),
+            fog_radius:      self.fog_radius,
+            rule:            self.rule,
+            topology:        self.topology,
+            activity:        self.activity.clone(),
+            scheduled:       self.scheduled.clone(),
+            applied:         self.applied.clone(),
+            budgets:         self.budgets.clone(),
+        }
+        .encode(format)
+    }
+
+    /// Recreates a universe from a snapshot created by `Universe::save_snapshot`, in either
+    /// format (the format is detected automatically).
     ///
-    /// Does numerous consistency checks on the bitmaps, and panics if inconsistencies are found.
-    //XXX non_dead_cells_in_region
-    pub fn each_non_dead(
-        &self,
-        region: Region,
-        visibility: Option<usize>,
-        callback: &mut dyn FnMut(usize, usize, CellState),
-    ) {
-        let cells = &self.gen_states[self.state_index].cells;
-        let wall = &self.gen_states[self.state_index].wall_cells;
-        let known = &self.gen_states[self.state_index].known;
-        let opt_player_state = if let Some(player_id) = visibility {
-            Some(&self.gen_states[self.state_index].player_states[player_id])
-        } else {
-            None
+    /// # Errors
+    ///
+    /// Returns `ConwayError::InvalidData` if the snapshot can't be decoded, was saved by an
+    /// incompatible version of this library, or is inconsistent (for example, if a grid does not
+    /// match the dimensions of the universe).
+    pub fn load_snapshot(data: &[u8]) -> ConwayResult<Universe> {
+        use ConwayError::*;
+        let snapshot = UniverseSnapshot::decode(data)?;
+        let invalid = |reason: String| Err(InvalidData { reason });
+
+        if snapshot.width == 0 || snapshot.height == 0 {
+            return invalid(format!(
+                "snapshot has invalid dimensions {}x{}",
+                snapshot.width, snapshot.height
+            ));
+        }
+        if snapshot.fog_radius == 0 {
+            return invalid("snapshot has a fog radius of 0".to_owned());
+        }
+        if snapshot.state_index >= snapshot.gen_states.len() {
+            return invalid(format!(
+                "snapshot state index {} is out of range for history of {}",
+                snapshot.state_index,
+                snapshot.gen_states.len()
+            ));
+        }
+        if snapshot.gen_states[snapshot.state_index].gen_or_none != Some(snapshot.generation) {
+            return invalid(format!(
+                "snapshot does not contain its latest generation {}",
+                snapshot.generation
+            ));
+        }
+        if snapshot.num_players > MAX_PLAYERS {
+            return invalid(format!(
+                "snapshot has {} players, but at most {} are supported",
+                snapshot.num_players, MAX_PLAYERS
+            ));
+        }
+        if snapshot.player_writable.len() != snapshot.num_players {
+            return invalid(format!(
+                "snapshot has {} writable areas for {} players",
+                snapshot.player_writable.len(),
+                snapshot.num_players
+            ));
+        }
+        for area in &snapshot.player_writable {
+            area.check(snapshot.width, snapshot.height)?;
+        }
+        if snapshot.teams.len() != snapshot.num_players || snapshot.vacant.len() != snapshot.num_players {
+            return invalid(format!(
+                "snapshot has {} teams and {} vacancy flags for {} players",
+                snapshot.teams.len(),
+                snapshot.vacant.len(),
+                snapshot.num_players
+            ));
+        }
+        let width_in_words = (snapshot.width - 1) / 64 + 1;
+        let unused_bits_mask = !(u64::max_value() << (width_in_words * 64 - snapshot.width));
+        let grid_is_valid = |grid: &BitGrid| {
+            grid.height() == snapshot.height
+                && grid
+                    .0
+                    .iter()
+                    .all(|row| row.len() == width_in_words && row[width_in_words - 1] & unused_bits_mask == 0)
         };
-        let mut col;
-        for row in 0..self.height {
-            let cells_row = &cells[row];
-            let wall_row = &wall[row];
-            let known_row = &known[row];
-            if (row as isize) >= region.top() && (row as isize) < (region.top() + region.height() as isize) {
-                col = 0;
-                for col_idx in 0..self.width_in_words {
-                    let cells_word = cells_row[col_idx];
-                    let wall_word = wall_row[col_idx];
-                    let known_word = known_row[col_idx];
-                    let opt_player_words;
-                    if let Some(player_state) = opt_player_state {
-                        let player_cells_word = player_state.cells[row][col_idx];
-                        let player_fog_word = player_state.fog[row][col_idx];
-                        opt_player_words = Some((player_cells_word, player_fog_word));
-                    } else {
-                        opt_player_words = None;
-                    }
-                    for shift in (0..64).rev() {
-                        if (col as isize) >= region.left() && (col as isize) < (region.left() + region.width() as isize)
-                        {
-                            let mut state = CellState::Wall;
-                            let c = (cells_word >> shift) & 1 == 1;
-                            let w = (wall_word >> shift) & 1 == 1;
-                            let k = (known_word >> shift) & 1 == 1;
-                            if c && w {
-                                panic!("Cannot be both cell and wall at ({}, {})", col, row);
-                            }
-                            if !k && ((c && !w) || (!c && w)) {
-                                panic!("Unspecified invalid state at ({}, {})", col, row);
-                            }
-                            if c && !w && k {
-                                // It's known and it's a cell; check cells + fog for every player
-                                // (expensive step since this is per-bit).
-
-                                let mut opt_player_id = None;
-                                for player_id in 0..self.num_players {
-                                    let player_state = &self.gen_states[self.state_index].player_states[player_id];
-                                    let pc = (player_state.cells[row][col_idx] >> shift) & 1 == 1;
-                                    let pf = (player_state.fog[row][col_idx] >> shift) & 1 == 1;
-                                    if pc && pf {
-                                        panic!(
-                                            "Player cell and player fog at ({}, {}) for player {}",
-                                            col, row, player_id
-                                        );
-                                    }
-                                    if pc {
-                                        if let Some(other_player_id) = opt_player_id {
-                                            panic!(
-                                                "Cell ({}, {}) belongs to player {} and player {}!",
-                                                col, row, other_player_id, player_id
-                                            );
-                                        }
-                                        opt_player_id = Some(player_id);
-                                    }
-                                }
-                                state = CellState::Alive(opt_player_id);
-                            } else {
-                                // (B) other states
-                                if !c && !w {
-                                    state = if k { CellState::Dead } else { CellState::Fog };
-                                } else if !c && w {
-                                    state = CellState::Wall;
-                                }
-                            }
-                            if let Some((player_cells_word, player_fog_word)) = opt_player_words {
-                                let pc = (player_cells_word >> shift) & 1 == 1;
-                                let pf = (player_fog_word >> shift) & 1 == 1;
-                                if !k && pc {
-                                    panic!("Player can't have cells where unknown, at ({}, {})", col, row);
-                                }
-                                if w && pc {
-                                    panic!("Player can't have cells where wall, at ({}, {})", col, row);
-                                }
-                                if pf {
-                                    state = CellState::Fog;
-                                }
-                            }
-                            if state != CellState::Dead {
-                                callback(col, row, state);
-                            }
-                        }
-                        col += 1;
-                    }
-                }
+        for (i, gen_state) in snapshot.gen_states.iter().enumerate() {
+            let grids_are_valid = gen_state.width == snapshot.width
+                && gen_state.player_states.len() == snapshot.num_players
+                && grid_is_valid(&gen_state.cells)
+                && grid_is_valid(&gen_state.wall_cells)
+                && grid_is_valid(&gen_state.known)
+                && gen_state
+                    .player_states
+                    .iter()
+                    .all(|player_state| grid_is_valid(&player_state.cells) && grid_is_valid(&player_state.fog))
+                && gen_state.dying.len() == snapshot.rule.dying_states()
+                && gen_state.dying.iter().all(|plane| grid_is_valid(plane));
+            if !grids_are_valid {
+                return invalid(format!(
+                    "snapshot generational state {} does not match the dimensions of the universe",
+                    i
+                ));
             }
         }
-    }
-
-    /// Iterate over every non-dead cell in the universe for the current generation.
-    /// `visibility` is an optional player_id, allowing filtering based on fog.
-    /// Callback receives (col, row, cell_state).
-    pub fn each_non_dead_full(&self, visibility: Option<usize>, callback: &mut dyn FnMut(usize, usize, CellState)) {
-        self.each_non_dead(self.region(), visibility, callback);
-    }
+        if let Some(ref tracker) = snapshot.activity {
+            if !tracker.is_valid(snapshot.width, snapshot.height, grid_is_valid) {
+                return invalid("snapshot activity does not match the dimensions of the universe".to_owned());
+            }
+        }
+        for action in snapshot.scheduled.iter().chain(snapshot.applied.iter()) {
+            if action.player_id >= snapshot.num_players {
+                return invalid(format!(
+                    "snapshot has an action for player {} of {} players",
+                    action.player_id, snapshot.num_players
+                ));
+            }
+        }
+        if snapshot.budgets.len() != snapshot.gen_states.len()
+            || snapshot
+                .budgets
+                .iter()
+                .any(|budgets| budgets.len() != snapshot.num_players)
+        {
+            return invalid(format!(
+                "snapshot budgets do not match {} generational states of {} players",
+                snapshot.gen_states.len(),
+                snapshot.num_players
+            ));
+        }
+        for tracker in snapshot.budgets.iter().flatten().flatten() {
+            tracker.check()?;
+        }
 
-    /// Get a Region of the same size as the universe.
-    pub fn region(&self) -> Region {
-        Region::new(0, 0, self.width, self.height)
+        let mut uni = Universe {
+            width:           snapshot.width,
+            height:          snapshot.height,
+            width_in_words:  width_in_words,
+            generation:      snapshot.generation,
+            num_players:     snapshot.num_players,
+            state_index:     snapshot.state_index,
+            gen_states:      snapshot.gen_states,
+            player_writable: snapshot.player_writable,
+            teams:           snapshot.teams,
+            vacant:          snapshot.vacant,
+            fog_radius:      snapshot.fog_radius,
+            fog_circle:      BitGrid(vec![]), // uninitialized
+            fog_spans:       vec![],          // uninitialized
+            rule:            snapshot.rule,
+            topology:        snapshot.topology,
+            parallel:        false,
+            activity:        snapshot.activity,
+            scheduled:       snapshot.scheduled,
+            applied:         snapshot.applied,
+            budgets:         snapshot.budgets,
+        };
+        uni.generate_fog_circle_bitmap();
+        Ok(uni)
     }
 
     /// Copies from `src` BitGrid to this GenState as the player specified by `opt_player_id`,
     /// unless `opt_player_id` is `None`.
     ///
     /// This function is similar to `GenState::copy_from_bit_grid` except that 1) when a `player_id`
-    /// is specified, the specified player's writable region is used, and 2) the latest generation
-    /// is written to.
+    /// is specified, only the cells in the specified player's writable area are copied, and 2) the
+    /// latest generation is written to.
+    ///
+    /// If `dst_region` extends beyond the edges of the universe, the parts of `src` beyond the
+    /// edges are wrapped around or dropped according to the universe's `Topology`.
     ///
     /// Panics if `opt_player_id` is `Some(player_id)` and `player_id` is out of range.
     pub fn copy_from_bit_grid(&mut self, src: &BitGrid, dst_region: Region, opt_player_id: Option<usize>) {
+        if dst_region.intersection(self.region()) != Some(dst_region) {
+            self.copy_from_bit_grid_cell_by_cell(src, dst_region, opt_player_id);
+            return;
+        }
         let region;
         if let Some(player_id) = opt_player_id {
-            if let Some(_region) = dst_region.intersection(self.player_writable[player_id]) {
+            let writable = match self.player_writable[player_id].single_region() {
+                Some(writable) => writable,
+                None => {
+                    self.copy_from_bit_grid_cell_by_cell(src, dst_region, opt_player_id);
+                    return;
+                }
+            };
+            if let Some(_region) = dst_region.intersection(writable) {
                 region = _region;
             } else {
                 // nothing to do because `dst_region` completely outside of player's writable region
@@ -1540,6 +3332,35 @@ impl Universe {
         }
         let latest_gen = &mut self.gen_states[self.state_index];
         latest_gen.copy_from_bit_grid(src, region, opt_player_id);
+        self.share_team_fog(self.state_index);
+    }
+
+    /// Slow path of `copy_from_bit_grid` for when `dst_region` is not entirely inside the
+    /// universe, or the player's writable area is not a single rectangle. Each bit of `src` is
+    /// mapped into the universe separately.
+    fn copy_from_bit_grid_cell_by_cell(&mut self, src: &BitGrid, dst_region: Region, opt_player_id: Option<usize>) {
+        let mut wrapped = BitGrid::new(self.width_in_words, self.height);
+        let (width, height, topology) = (self.width, self.height, self.topology);
+        let opt_writable = opt_player_id.map(|player_id| &self.player_writable[player_id]);
+        src.each_set(|col, row| {
+            if col >= dst_region.width() || row >= dst_region.height() {
+                return;
+            }
+            let virtual_col = dst_region.left() + col as isize;
+            let virtual_row = dst_region.top() + row as isize;
+            if let Some((col, row)) = topology.map_cell(virtual_col, virtual_row, width, height) {
+                if let Some(writable) = opt_writable {
+                    if !writable.contains(col as isize, row as isize) {
+                        return;
+                    }
+                }
+                wrapped.write_at_position(col, row, 'o', None);
+            }
+        });
+        let region = self.region();
+        let latest_gen = &mut self.gen_states[self.state_index];
+        latest_gen.copy_from_bit_grid(&wrapped, region, opt_player_id);
+        self.share_team_fog(self.state_index);
     }
 
     /// Utility function to mutably borrow two separate GenStates from self.gen_states, specified
@@ -1662,6 +3483,15 @@ impl Universe {
         } else {
             0
         };
+        // the latest generation before this update, for tracking cell activity
+        let opt_prev_latest = match (&self.activity, opt_largest_gen_idx) {
+            (Some(_), Some(largest_gen_idx)) => Some((
+                self.gen_states[largest_gen_idx].cells.clone(),
+                opt_largest_gen_value.unwrap(),
+            )),
+            _ => None,
+        };
+
         // 4) If incremental update, then copy from the gen_state for gen0 to what will be the
         //    gen_state for gen1.
         if diff.gen0 > 0 {
@@ -1675,17 +3505,113 @@ impl Universe {
 
         // 5) update self.generation, self.state_index, and self.gen_states[gen1_idx].gen_or_none
         let new_gen = diff.gen1;
+        self.replenish_budgets(self.state_index, gen1_idx, new_gen.saturating_sub(self.generation));
         self.generation = new_gen;
         self.state_index = gen1_idx;
         self.gen_states[gen1_idx].gen_or_none = Some(new_gen);
+        self.retire_actions(new_gen); // the diff includes the actions for these generations
 
         // 6) apply the diff!
         // TODO: wrap the error message rather than just passing it through
         diff.pattern.to_grid(&mut self.gen_states[gen1_idx], visibility)?;
 
+        // 7) count the changes since the previous latest generation
+        if let Some(tracker) = self.activity.as_mut() {
+            let cells = &self.gen_states[gen1_idx].cells;
+            match opt_prev_latest {
+                Some((prev_cells, prev_gen)) => tracker.record(&prev_cells, cells, new_gen - prev_gen),
+                None => tracker.record(&BitGrid::new(self.width_in_words, self.height), cells, 1),
+            }
+        }
+
         Ok(Some(new_gen))
     }
 
+    /// Returns the checksum of generation `gen` as seen by the player specified by `visibility`
+    /// (see `GenState::checksum`), or `None` if `gen` is not in the history. A client can compare
+    /// this with the server's checksum for the same generation and player to detect a desync.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `visibility` is out of range.
+    pub fn checksum(&self, gen: usize, visibility: Option<usize>) -> Option<u64> {
+        self.gen_states
+            .iter()
+            .find(|gen_state| gen_state.gen_or_none == Some(gen))
+            .map(|gen_state| gen_state.checksum(visibility))
+    }
+
+    /// Returns the generations in the history, from oldest to newest, with their states. A client
+    /// may have gaps between generations if it skipped some with `apply`.
+    pub fn history(&self) -> impl Iterator<Item = (usize, &GenState)> {
+        let mut history: Vec<(usize, &GenState)> = self
+            .gen_states
+            .iter()
+            .filter_map(|gen_state| gen_state.gen_or_none.map(|gen| (gen, gen_state)))
+            .collect();
+        history.sort_by_key(|&(gen, _)| gen);
+        history.into_iter()
+    }
+
+    /// Makes generation `gen` the latest generation again, discarding all later generations. This
+    /// can be used to undo the last few generations, or to go back to the generation a late
+    /// change applies to and compute the following generations again. If activity is tracked,
+    /// tracking starts afresh (see `set_activity_window`). The actions that were applied to the
+    /// discarded generations are scheduled again, so that computing the following generations again
+    /// gives the same result unless more actions are scheduled (see `schedule`). The budgets of the
+    /// players are as they were in generation `gen` (see `set_budget`).
+    ///
+    /// # Errors
+    ///
+    /// Returns `ConwayError::InvalidData` if `gen` is not in the history.
+    pub fn rewind_to(&mut self, gen: usize) -> ConwayResult<()> {
+        let state_index = match self
+            .gen_states
+            .iter()
+            .position(|gen_state| gen_state.gen_or_none == Some(gen))
+        {
+            Some(state_index) => state_index,
+            None => {
+                return Err(ConwayError::InvalidData {
+                    reason: format!("generation {} is not in the history", gen),
+                })
+            }
+        };
+        if gen == self.generation {
+            return Ok(());
+        }
+        for gen_state in self.gen_states.iter_mut() {
+            if let Some(other_gen) = gen_state.gen_or_none {
+                if other_gen > gen {
+                    gen_state.gen_or_none = None;
+                }
+            }
+        }
+        self.state_index = state_index;
+        self.generation = gen;
+        let (mut rescheduled, applied): (Vec<ScheduledAction>, Vec<ScheduledAction>) =
+            self.applied.drain(..).partition(|applied| applied.gen > gen);
+        self.applied = applied;
+        rescheduled.append(&mut self.scheduled);
+        self.scheduled = rescheduled;
+        let opt_window = self.activity_window();
+        self.set_activity_window(opt_window);
+        Ok(())
+    }
+
+    /// Returns a copy of this universe in which generation `gen` is the latest generation, leaving
+    /// this universe unchanged. The copy keeps the history up to `gen` and can be run on its own,
+    /// for example to try out a move before making it.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ConwayError::InvalidData` if `gen` is not in the history.
+    pub fn fork(&self, gen: usize) -> ConwayResult<Universe> {
+        let mut uni = self.clone();
+        uni.rewind_to(gen)?;
+        Ok(uni)
+    }
+
     /// If it's possible to generate a diff between the GenStates specified by `gen0` and `gen1`, do
     /// so. Otherwise, return `None`.
     ///
@@ -1722,6 +3648,78 @@ impl Universe {
     }
 }
 
+/// Iterator over the non-dead cells of the current generation of a `Universe`; see
+/// `Universe::non_dead_cells`.
+pub struct NonDeadCells<'a> {
+    uni:        &'a Universe,
+    region:     Region,
+    visibility: Option<usize>,
+    row:        usize,
+    word_col:   usize,
+    word:       u64, // cells of the word at (row, word_col) that may be non-dead and weren't returned yet
+}
+
+impl<'a> NonDeadCells<'a> {
+    /// Returns the cells of the word at (`self.row`, `self.word_col`) that may be non-dead: live,
+    /// wall, unknown and dying cells, and cells in the fog of the player we are looking as. It's 0
+    /// if the row is outside the region.
+    fn candidates(&self) -> u64 {
+        let uni = self.uni;
+        let (row, word_col) = (self.row, self.word_col);
+        if row >= uni.height || !uni.topology.region_contains_row(self.region, row, uni.height) {
+            return 0;
+        }
+        let gen_state = &uni.gen_states[uni.state_index];
+        let mut word =
+            gen_state.cells[row][word_col] | gen_state.wall_cells[row][word_col] | !gen_state.known[row][word_col];
+        for plane in &gen_state.dying {
+            word |= plane[row][word_col];
+        }
+        if let Some(player_id) = self.visibility {
+            word |= gen_state.player_states[player_id].fog[row][word_col];
+        }
+        let used_bits = uni.width - word_col * 64;
+        if used_bits < 64 {
+            word &= !(u64::max_value() >> used_bits); // unused bits at the end of the row
+        }
+        word
+    }
+}
+
+impl<'a> Iterator for NonDeadCells<'a> {
+    type Item = (usize, usize, CellState);
+
+    fn next(&mut self) -> Option<(usize, usize, CellState)> {
+        let uni = self.uni;
+        loop {
+            while self.word == 0 {
+                self.word_col += 1;
+                if self.word_col >= uni.width_in_words {
+                    self.word_col = 0;
+                    self.row += 1;
+                }
+                if self.row >= uni.height {
+                    return None;
+                }
+                self.word = self.candidates();
+            }
+            let offset = self.word.leading_zeros() as usize;
+            self.word &= !(1 << (63 - offset));
+            let (col, row) = (self.word_col * 64 + offset, self.row);
+            if !uni
+                .topology
+                .region_contains(self.region, col, row, uni.width, uni.height)
+            {
+                continue;
+            }
+            let state = uni.checked_cell_state(col, row, self.visibility);
+            if state != CellState::Dead {
+                return Some((col, row, state));
+            }
+        }
+    }
+}
+
 impl CharGrid for Universe {
     fn is_valid(ch: char) -> bool {
         GenState::is_valid(ch)
@@ -1748,7 +3746,7 @@ impl CharGrid for Universe {
 }
 
 /// Rectangular area within a `Universe`.
-#[derive(Eq, PartialEq, Ord, PartialOrd, Copy, Clone, Debug)]
+#[derive(Serialize, Deserialize, Eq, PartialEq, Ord, PartialOrd, Copy, Clone, Debug)]
 pub struct Region {
     left:   isize,
     top:    isize,
@@ -1824,6 +3822,112 @@ impl Region {
     }
 }
 
+/// The cells a player is allowed to write to: a union of rectangles, or an arbitrary set of cells
+/// given as a `BitGrid` mask with the same dimensions as the universe.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum WritableArea {
+    Regions(Vec<Region>),
+    Mask(BitGrid), // 1 = writable
+}
+
+impl From<Region> for WritableArea {
+    fn from(region: Region) -> Self {
+        WritableArea::Regions(vec![region])
+    }
+}
+
+impl From<BitGrid> for WritableArea {
+    fn from(mask: BitGrid) -> Self {
+        WritableArea::Mask(mask)
+    }
+}
+
+impl WritableArea {
+    /// Determines whether the specified cell is part of the area. Regions may extend past the edges
+    /// of a universe, so a universe bounds the cell by its own dimensions before calling this; a
+    /// mask has no bits set beyond the universe's width (see `check`).
+    pub fn contains(&self, col: isize, row: isize) -> bool {
+        match *self {
+            WritableArea::Regions(ref regions) => regions.iter().any(|region| region.contains(col, row)),
+            WritableArea::Mask(ref mask) => {
+                if col < 0 || row < 0 || row as usize >= mask.height() || col as usize >= mask.width() {
+                    return false;
+                }
+                mask.is_set(col as usize, row as usize)
+            }
+        }
+    }
+
+    /// Adds the cells of `region` to the area. For a mask, the parts of `region` outside of the
+    /// mask are ignored.
+    pub fn add_region(&mut self, region: Region) {
+        match *self {
+            WritableArea::Regions(ref mut regions) => regions.push(region),
+            WritableArea::Mask(ref mut mask) => {
+                if let Some(region) = region.intersection(mask.region()) {
+                    mask.modify_region(region, BitOperation::Set);
+                }
+            }
+        }
+    }
+
+    /// Returns a `BitGrid` for a universe of `width` by `height` cells in which the cells of this
+    /// area are 1. Parts of the area outside of the universe are left out.
+    pub fn to_bit_grid(&self, width: usize, height: usize) -> BitGrid {
+        let universe_region = Region::new(0, 0, width, height);
+        let mut grid = BitGrid::new((width - 1) / 64 + 1, height);
+        match *self {
+            WritableArea::Regions(ref regions) => {
+                for region in regions {
+                    if let Some(region) = region.intersection(universe_region) {
+                        grid.modify_region(region, BitOperation::Set);
+                    }
+                }
+            }
+            WritableArea::Mask(ref mask) => grid.combine_in_region(mask, universe_region, SetOperation::Or),
+        }
+        grid
+    }
+
+    /// Returns the region if this area is a single rectangle.
+    fn single_region(&self) -> Option<Region> {
+        match *self {
+            WritableArea::Regions(ref regions) if regions.len() == 1 => Some(regions[0]),
+            _ => None,
+        }
+    }
+
+    /// Checks that a mask has the dimensions of a universe of `width` by `height` cells, and that
+    /// none of the bits past `width` in its last word are set.
+    fn check(&self, width: usize, height: usize) -> ConwayResult<()> {
+        if let WritableArea::Mask(ref mask) = *self {
+            if mask.height() != height || mask.width_in_words() != (width - 1) / 64 + 1 {
+                return Err(ConwayError::InvalidData {
+                    reason: format!(
+                        "writable area mask of {} words by {} rows does not match a {}x{} universe",
+                        mask.width_in_words(),
+                        mask.height(),
+                        width,
+                        height
+                    ),
+                });
+            }
+            if mask.width() > width {
+                let padding = Region::new(width as isize, 0, mask.width() - width, height);
+                if mask.popcount_in_region(padding) > 0 {
+                    return Err(ConwayError::InvalidData {
+                        reason: format!(
+                            "writable area mask has cells beyond the width of a {}x{} universe",
+                            width, height
+                        ),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 pub mod test_helpers {
     use super::*;
@@ -1853,6 +3957,12 @@ pub mod test_helpers {
         bigbang.unwrap()
     }
 
+    /// A `BigBang` for a `width` by `height` universe with `players`, on which tests set whatever
+    /// else they need before calling `birth()`.
+    pub fn test_big_bang(width: usize, height: usize, players: Vec<PlayerBuilder>) -> BigBang {
+        BigBang::new().width(width).height(height).add_players(players)
+    }
+
     pub fn make_gen_state() -> GenState {
         let player0 = PlayerBuilder::new(Region::new(100, 70, 34, 16));
         let player1 = PlayerBuilder::new(Region::new(0, 0, 80, 80));
@@ -1886,6 +3996,27 @@ mod universe_tests {
         assert_eq!(next_center, 0xC000000E00000002);
     }
 
+    #[test]
+    fn next_single_gen_matches_conway_rule() {
+        let rule = Rule::conway();
+        let mut x: u64 = 0x2545F4914F6CDD1D;
+        let mut words = [0u64; 9];
+        for _ in 0..1000 {
+            for word in words.iter_mut() {
+                // xorshift64
+                x ^= x << 13;
+                x ^= x >> 7;
+                x ^= x << 17;
+                *word = x;
+            }
+            let [nw, n, ne, w, cen, e, sw, s, se] = words;
+            assert_eq!(
+                rule.next_single_gen(nw, n, ne, w, cen, e, sw, s, se),
+                Universe::next_single_gen(nw, n, ne, w, cen, e, sw, s, se)
+            );
+        }
+    }
+
     #[test]
     fn set_checked_cannot_set_a_fog_cell() {
         let mut uni = generate_test_universe_with_default_params(UniType::Server);
@@ -2236,10 +4367,10 @@ mod universe_tests {
 
         Universe::clear_fog(
             &mut gen_state_next.player_states[player_id].fog,
-            &uni.fog_circle,
+            &uni.fog_spans,
             uni.fog_radius,
+            uni.topology,
             uni.width,
-            uni.height,
             row_index_outside_of_p0_region,
             col_index_outside_of_p0_region,
             one_bit_to_clear,
@@ -2658,3 +4789,114 @@ mod genstate_tests {
         );
     }
 }
+
+/// Checks that the parallel and serial paths of `Universe::next` agree. These are here because
+/// they compare private parts of Universe.
+#[cfg(test)]
+mod parallel_tests {
+    use super::*;
+    use proptest::prelude::*;
+    use std::str::FromStr;
+
+    /// Builds a server universe full of pseudo-random cells, walls, owners, and unknown cells.
+    fn make_random_universe(
+        width: usize,
+        height: usize,
+        topology: Topology,
+        rule: Rule,
+        num_players: usize,
+        fog_radius: usize,
+        mut seed: u64,
+    ) -> Universe {
+        let mut next_random = move || {
+            // xorshift64
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+        let players = (0..num_players)
+            .map(|_| {
+                let left = (next_random() % width as u64) as isize;
+                let top = (next_random() % height as u64) as isize;
+                PlayerBuilder::new(Region::new(left, top, width - left as usize, height - top as usize))
+            })
+            .collect();
+        let mut uni = BigBang::new()
+            .width(width)
+            .height(height)
+            .history(2)
+            .fog_radius(fog_radius)
+            .topology(topology)
+            .rule(rule)
+            .add_players(players)
+            .birth()
+            .unwrap();
+
+        for row in 0..height {
+            for col in 0..width {
+                let random = next_random() % 16;
+                let state = match random {
+                    0..=3 => CellState::Alive(None),
+                    4..=7 if num_players > 0 => CellState::Alive(Some(random as usize % num_players)),
+                    8 => CellState::Wall,
+                    _ => CellState::Dead,
+                };
+                uni.set_unchecked(col, row, state);
+            }
+        }
+
+        // make a few cells unknown, which spreads in the following generations
+        let gen_state = &mut uni.gen_states[uni.state_index];
+        for _ in 0..3 {
+            let col = (next_random() % width as u64) as usize;
+            let row = (next_random() % height as u64) as usize;
+            let mask = !(1 << (63 - col % 64));
+            gen_state.known[row][col / 64] &= mask;
+            gen_state.cells[row][col / 64] &= mask;
+            gen_state.wall_cells[row][col / 64] &= mask;
+            for player_state in gen_state.player_states.iter_mut() {
+                player_state.cells[row][col / 64] &= mask;
+            }
+        }
+        uni
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn parallel_next_matches_serial_next(
+            width in 1usize..200,
+            height in 1usize..80,
+            topology_idx in 0usize..4,
+            rule_str in prop::sample::select(vec!["B3/S23", "B36/S23", "B2/S"]),
+            num_players in 0usize..4,
+            fog_radius in 1usize..10,
+            seed in 1u64..,
+        ) {
+            let topology = [Topology::Torus, Topology::Plane, Topology::Cylinder, Topology::KleinBottle][topology_idx];
+            let rule = Rule::from_str(rule_str).unwrap();
+            let mut serial = make_random_universe(width, height, topology, rule, num_players, fog_radius, seed);
+            let mut parallel = make_random_universe(width, height, topology, rule, num_players, fog_radius, seed);
+            parallel.set_parallel(true);
+            prop_assert!(parallel.is_parallel());
+            for _ in 0..4 {
+                serial.next();
+                parallel.next();
+                prop_assert_eq!(
+                    &serial.gen_states[serial.state_index],
+                    &parallel.gen_states[parallel.state_index]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn big_bang_sets_parallel() {
+        let uni = BigBang::new().parallel(true).birth().unwrap();
+        assert!(uni.is_parallel());
+        let uni = BigBang::new().birth().unwrap();
+        assert!(!uni.is_parallel());
+    }
+}
@@ -267,7 +267,7 @@ pub struct GameOptions {
     width:           u32,
     height:          u32,
     history:         u16,
-    player_writable: Vec<NetRegion>,
+    player_writable: Vec<NetWritableArea>,
     fog_radius:      u32,
 }
 
@@ -280,6 +280,15 @@ pub struct NetRegion {
     height: u32,
 }
 
+/// Net-safe version of a libconway WritableArea
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub enum NetWritableArea {
+    Regions(Vec<NetRegion>),
+    /// Rows of the mask; each row has one bit per cell, with the leftmost cell in the most
+    /// significant bit of the first word.
+    Mask(Vec<Vec<u64>>),
+}
+
 // TODO: add support
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub struct PlayerInfo {